@@ -0,0 +1,94 @@
+use ggez::audio::{self, SoundSource};
+use ggez::{Context, GameResult};
+
+use orbits::simulation::{Impact, DEFAULT_FRAGMENTATION_THRESHOLD};
+
+// In the resources directory
+const IMPACT_SOUND: &str = "/sounds/impact.wav";
+const AMBIENT_SOUND: &str = "/sounds/ambient.wav";
+
+const MAX_IMPACT_SOUNDS: usize = 4;       // Per frame, loudest first, so a burst of merges isn't deafening
+const QUIETEST_IMPACT: f32 = 0.05;        // Impacts quieter than this (as a fraction of full volume) aren't played
+const AMBIENT_VOLUME: f32 = 0.3;          // Relative to the master volume
+
+// Impact sounds, louder for more energetic collisions, over a quiet ambient loop.
+// Either sound can be missing, in which case it's skipped with a warning.
+pub struct Audio {
+  impact: Option<audio::Source>,
+  ambient: Option<audio::Source>,
+  volume: f32,
+  muted: bool,
+}
+
+impl Audio {
+  pub fn new(ctx: &mut Context, volume: f32, muted: bool) -> Self {
+    let load = |ctx: &mut Context, path: &str| match audio::Source::new(ctx, path) {
+      Ok(source) => Some(source),
+      Err(e) => {
+        println!("WARNING: Could not load sound {}: {}", path, e);
+        None
+      },
+    };
+
+    let mut audio = Self {
+      impact: load(ctx, IMPACT_SOUND),
+      ambient: load(ctx, AMBIENT_SOUND),
+      volume,
+      muted,
+    };
+    if let Some(ambient) = audio.ambient.as_mut() {
+      ambient.set_repeat(true);
+      if let Err(e) = ambient.play(ctx) {
+        println!("WARNING: Could not play ambient sound: {}", e);
+      }
+    }
+    audio.update_ambient_volume();
+    audio
+  }
+
+  pub fn volume(&self) -> f32 {
+    self.volume
+  }
+
+  pub fn is_muted(&self) -> bool {
+    self.muted
+  }
+
+  pub fn set_volume(&mut self, volume: f32) {
+    self.volume = volume.max(0.0).min(1.0);
+    self.update_ambient_volume();
+  }
+
+  pub fn toggle_mute(&mut self) {
+    self.muted = !self.muted;
+    self.update_ambient_volume();
+  }
+
+  fn update_ambient_volume(&mut self) {
+    let volume = if self.muted { 0.0 } else { self.volume * AMBIENT_VOLUME };
+    if let Some(ambient) = self.ambient.as_mut() {
+      ambient.set_volume(volume);
+    }
+  }
+
+  // Plays a sound for the loudest of this frame's impacts. Impacts energetic enough to shatter bodies are loudest.
+  pub fn play_impacts(&mut self, ctx: &Context, impacts: &[Impact]) -> GameResult {
+    if self.muted || impacts.is_empty() { return Ok(()) }
+    let impact_sound = match self.impact.as_mut() {
+      Some(sound) => sound,
+      None => return Ok(()),
+    };
+
+    let mut loudness: Vec<f32> = impacts.iter()
+      .map(|impact| ((1.0 + impact.energy).ln()/(1.0 + DEFAULT_FRAGMENTATION_THRESHOLD).ln()).min(1.0))
+      .filter(|loudness| *loudness >= QUIETEST_IMPACT)
+      .collect();
+    loudness.sort_unstable_by(|a, b| b.total_cmp(a));
+
+    for loudness in loudness.into_iter().take(MAX_IMPACT_SOUNDS) {
+      impact_sound.set_volume(loudness * self.volume);
+      impact_sound.play_detached(ctx)?;
+    }
+    Ok(())
+  }
+}
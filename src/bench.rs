@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+use crate::simulation::World;
+use crate::quadtree::{self, TreeBody};
+
+pub const DEFAULT_BENCH_SIZES: [usize; 3] = [100, 1_000, 10_000];
+const BODY_RADIUS_RANGE: (f32, f32) = (1.0, 3.0);
+const AREA_PER_BODY: f32 = 2500.0;    // Square pixels, so density (and number of collisions) is the same for every size
+
+pub struct BenchConfig {
+  pub sizes: Vec<usize>,
+  pub iterations: usize,    // Timings are averaged over this many runs
+  pub seed: u64,
+}
+
+impl Default for BenchConfig {
+  fn default() -> Self {
+    Self {
+      sizes: DEFAULT_BENCH_SIZES.to_vec(),
+      iterations: 10,
+      seed: 0,
+    }
+  }
+}
+
+// Mean time per call for one world size
+pub struct BenchResult {
+  pub bodies: usize,
+  pub forces: Duration,       // Building the Barnes–Hut tree and finding every body's acceleration
+  pub collisions: Duration,   // Detecting and resolving collisions, starting from a fresh world each time
+  pub step: Duration,         // A whole World::step
+}
+
+impl BenchResult {
+  pub fn report(&self) -> String {
+    format!(
+      "{:>6} bodies: forces {:>10.3?}  collisions {:>10.3?}  step {:>10.3?}",
+      self.bodies, self.forces, self.collisions, self.step
+    )
+  }
+}
+
+// Times the physics core without rendering. Worlds are seeded, so runs with the same config do the same work.
+pub fn run(config: &BenchConfig) -> Vec<BenchResult> {
+  let iterations = config.iterations.max(1);
+  config.sizes.iter()
+    .map(|&n| {
+      let mut forces = Duration::ZERO;
+      let mut collisions = Duration::ZERO;
+      let mut step = Duration::ZERO;
+      let dt = Duration::from_secs_f32(1.0/60.0);
+
+      for _ in 0..iterations {
+        let world = random_world(n, config.seed);
+        let bodies: Vec<TreeBody> = world.planets.values()
+          .map(|pl| {
+            let pl = pl.borrow();
+            TreeBody { position: pl.position, mass: pl.mass, radius: pl.radius }
+          })
+          .collect();
+        let start = Instant::now();
+        let accelerations = quadtree::accelerations(&bodies, world.barnes_hut_theta, world.config.g, world.config.softening, world.wrap_bounds);
+        forces += start.elapsed();
+        assert_eq!(accelerations.len(), bodies.len());
+
+        let mut world = random_world(n, config.seed);
+        let start = Instant::now();
+        world.resolve_collisions();
+        collisions += start.elapsed();
+
+        let mut world = random_world(n, config.seed);
+        let start = Instant::now();
+        world.step(&dt);
+        step += start.elapsed();
+      }
+
+      BenchResult {
+        bodies: n,
+        forces: forces/iterations as u32,
+        collisions: collisions/iterations as u32,
+        step: step/iterations as u32,
+      }
+    })
+    .collect()
+}
+
+// n bodies spread over a square sized to keep the density the same
+fn random_world(n: usize, seed: u64) -> World {
+  let mut world = World::new();
+  world.seed(seed);
+  if n == 0 { return world }
+
+  let side = (n as f32 * AREA_PER_BODY).sqrt();
+  world.add_random_planets(n, (0.0, side), (0.0, side), BODY_RADIUS_RANGE, Some((0.0, 10.0)));
+  world
+}
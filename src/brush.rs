@@ -0,0 +1,136 @@
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::time::Duration;
+
+use nalgebra::{Point2, Vector2};
+
+use orbits::planet::Planet;
+
+pub const DEFAULT_STREAM_RATE: f32 = 20.0;    // Bodies per second
+pub const DEFAULT_RING_COUNT: usize = 12;
+
+pub const GRID_SPACING_RADII: f32 = 4.0;   // Distance between grid brush bodies, in spawn radii
+pub const MAX_GRID_BODIES: usize = 2500;
+
+pub const DEFAULT_BINARY_MASS_RATIO: f32 = 0.5;   // Lighter body's mass over the heavier one's
+pub const MIN_BINARY_SEPARATION_RADII: f32 = 4.0;  // Used when clicking without dragging, in spawn radii
+
+// What left clicking and dragging does, chosen from the toolbar or cycled with B
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Tool {
+  #[serde(alias = "Single")]
+  Launch,   // One body per click, launched with the drag velocity. Dragging from a body launches from its surface.
+  Stream,   // Bodies emitted from the cursor while the mouse is held, moving with the cursor
+  Orbit,    // Dragging from a host spawns a body in circular orbit around it, at the release point
+  Ring,     // Dragging from a host spawns a ring of bodies in circular orbit around it
+  Binary,   // Dragging spawns a pair orbiting each other about the press point, as far apart as the drag is long
+  Grid,     // Dragging a box fills it with evenly spaced stationary bodies
+  Eraser,   // Clicking or dragging over bodies deletes them
+  Select,   // Clicking a body selects it and dragging moves it, dragging elsewhere box selects
+}
+
+impl Default for Tool {
+  fn default() -> Self {
+    Tool::Launch
+  }
+}
+
+impl fmt::Display for Tool {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Tool::Launch => write!(f, "Launch"),
+      Tool::Stream => write!(f, "Stream"),
+      Tool::Orbit => write!(f, "Orbit"),
+      Tool::Ring => write!(f, "Ring"),
+      Tool::Binary => write!(f, "Binary"),
+      Tool::Grid => write!(f, "Grid"),
+      Tool::Eraser => write!(f, "Eraser"),
+      Tool::Select => write!(f, "Select"),
+    }
+  }
+}
+
+impl Tool {
+  pub const ALL: [Tool; 8] = [
+    Tool::Launch, Tool::Stream, Tool::Orbit, Tool::Ring, Tool::Binary, Tool::Grid, Tool::Eraser, Tool::Select,
+  ];
+
+  pub fn next(self) -> Self {
+    match self {
+      Tool::Launch => Tool::Stream,
+      Tool::Stream => Tool::Orbit,
+      Tool::Orbit => Tool::Ring,
+      Tool::Ring => Tool::Binary,
+      Tool::Binary => Tool::Grid,
+      Tool::Grid => Tool::Eraser,
+      Tool::Eraser => Tool::Select,
+      Tool::Select => Tool::Launch,
+    }
+  }
+
+  // Shown when hovering over the toolbar
+  pub fn description(self) -> &'static str {
+    match self {
+      Tool::Launch => "Click to spawn a body, drag to launch it. Drag from a body to launch from its surface.",
+      Tool::Stream => "Hold to emit bodies moving with the cursor",
+      Tool::Orbit => "Drag from a body to spawn one in circular orbit around it (Shift: anticlockwise)",
+      Tool::Ring => "Drag from a body to spawn a ring of bodies orbiting it (Shift: anticlockwise)",
+      Tool::Binary => "Drag from where the pair's centre of mass should be to set how far apart they are (Shift: anticlockwise)",
+      Tool::Grid => "Drag a box to fill it with a grid of bodies",
+      Tool::Eraser => "Click or drag over bodies to delete them",
+      Tool::Select => "Click a body to select it and drag to move it. Drag a box to select a group (Shift: add to it).",
+    }
+  }
+}
+
+// Centres of the grid brush's bodies in the box between two corners, spaced `spacing` apart starting from `corner`.
+// A click without dragging gives just the one point.
+pub fn grid_points(corner: Point2<f32>, opposite: Point2<f32>, spacing: f32) -> Vec<Point2<f32>> {
+  if spacing <= 0.0 { return vec![corner] }
+  let size = opposite - corner;
+  let columns = (size.x.abs()/spacing).floor() as usize + 1;
+  let rows = (size.y.abs()/spacing).floor() as usize + 1;
+  if columns * rows > MAX_GRID_BODIES { return Vec::new() }
+
+  let step = Vector2::new(spacing * size.x.signum(), spacing * size.y.signum());
+  (0..columns)
+    .flat_map(|i| (0..rows).map(move |j| corner + Vector2::new(step.x * i as f32, step.y * j as f32)))
+    .collect()
+}
+
+// Masses of the binary brush's pair, heavier first, splitting total by ratio (lighter/heavier, from 0 to 1)
+pub fn binary_masses(total: f32, ratio: f32) -> (f32, f32) {
+  let ratio = ratio.max(0.0).min(1.0);
+  let heavier = total/(1.0 + ratio);
+  (heavier, total - heavier)
+}
+
+// A stream brush stroke in progress
+pub struct StreamStroke {
+  timer: Duration,
+  pub spawned: Vec<Planet>,   // Everything emitted so far, so the stroke can be undone in one go
+}
+
+impl StreamStroke {
+  pub fn new() -> Self {
+    Self {
+      timer: Duration::ZERO,
+      spawned: Vec::new(),
+    }
+  }
+
+  // Number of bodies due to be emitted after dt, at rate bodies per second
+  pub fn due(&mut self, dt: Duration, rate: f32) -> usize {
+    if rate <= 0.0 { return 0 }
+
+    self.timer += dt;
+    let period = Duration::from_secs_f32(1.0/rate);
+    let mut count = 0;
+    while self.timer >= period {
+      self.timer -= period;
+      count += 1;
+    }
+    count
+  }
+}
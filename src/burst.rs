@@ -0,0 +1,76 @@
+use ggez::graphics::{MeshBuilder, DrawMode};
+use ggez::GameResult;
+
+use nalgebra::{Vector2, Point2};
+use rand::Rng;
+
+use std::f32::consts::PI;
+
+use orbits::simulation::Impact;
+
+const BURST_LIFETIME: f32 = 0.6;          // Seconds
+const BURST_ENERGY_PER_PARTICLE: f32 = 50.0;
+const BURST_PARTICLE_RANGE: (usize, usize) = (4, 64);
+const BURST_SPEED_MULTIPLIER: f32 = 0.5;  // Of the impact speed
+const BURST_MAX_SPEED: f32 = 400.0;
+const BURST_PARTICLE_RADIUS: f32 = 1.0;
+const BURST_COLOR: [f32; 3] = [1.0, 0.7, 0.3];
+
+struct Particle {
+  position: Point2<f32>,
+  velocity: Vector2<f32>,
+}
+
+// One-shot spray of particles from a merge. Particles ignore gravity and fade out over BURST_LIFETIME.
+pub struct ParticleBurst {
+  particles: Vec<Particle>,
+  age: f32,
+}
+
+impl ParticleBurst {
+  // Particle count and speed grow with the impact energy
+  pub fn new(impact: &Impact) -> Self {
+    let count = ((impact.energy/BURST_ENERGY_PER_PARTICLE).sqrt() as usize)
+      .max(BURST_PARTICLE_RANGE.0)
+      .min(BURST_PARTICLE_RANGE.1);
+    // Q = v^2/2 for the smaller body, so this is roughly the impact speed
+    let speed = ((2.0 * impact.energy).sqrt() * BURST_SPEED_MULTIPLIER).min(BURST_MAX_SPEED);
+
+    let mut rng = rand::thread_rng();
+    let particles = (0..count)
+      .map(|_| {
+        let angle = rng.gen_range(-PI..PI);
+        let particle_speed = speed * rng.gen_range(0.3..1.0);
+        Particle {
+          position: impact.position,
+          velocity: impact.velocity + Vector2::new(angle.cos(), angle.sin()) * particle_speed,
+        }
+      })
+      .collect();
+
+    Self {
+      particles,
+      age: 0.0,
+    }
+  }
+
+  pub fn update(&mut self, dt: f32) {
+    self.age += dt;
+    for particle in self.particles.iter_mut() {
+      particle.position += particle.velocity * dt;
+    }
+  }
+
+  pub fn is_dead(&self) -> bool {
+    self.age >= BURST_LIFETIME
+  }
+
+  pub fn draw(&self, mesh: &mut MeshBuilder) -> GameResult {
+    let alpha = (1.0 - self.age/BURST_LIFETIME).max(0.0);
+    let [r, g, b] = BURST_COLOR;
+    for particle in self.particles.iter() {
+      mesh.circle(DrawMode::fill(), particle.position, BURST_PARTICLE_RADIUS, 0.5, [r, g, b, alpha].into())?;
+    }
+    Ok(())
+  }
+}
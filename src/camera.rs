@@ -0,0 +1,108 @@
+use ggez::graphics::{Canvas, Rect};
+
+use nalgebra::{Vector2, Point2};
+
+use orbits::simulation::World;
+
+use crate::SCREEN_DIMS;
+
+const RECENTRE_RATE: f32 = 4.0;   // How quickly the camera closes the gap to its target, per second
+const ZOOM_RANGE: (f32, f32) = (0.05, 20.0);
+
+// What the camera keeps in the centre of the screen
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Focus {
+  Free,             // Camera stays where it is
+  Planet(usize),
+  Barycentre,       // Centre of mass of every body
+}
+
+pub struct Camera {
+  centre: Point2<f32>,              // World position in the centre of the screen
+  pub focus: Focus,
+  last_target: Option<Point2<f32>>, // Target position last update, so the camera can move with it
+  zoom: f32,                        // Screen pixels per world pixel
+  pub shake: Vector2<f32>,          // Screen pixels the view is jolted by this frame, from impacts
+}
+
+impl Camera {
+  pub fn new() -> Self {
+    Self {
+      centre: Point2::new(SCREEN_DIMS.0/2.0, SCREEN_DIMS.1/2.0),
+      focus: Focus::Free,
+      last_target: None,
+      zoom: 1.0,
+      shake: Vector2::new(0.0, 0.0),
+    }
+  }
+
+  // Multiplies the zoom, keeping the world point under screen_point where it is
+  pub fn zoom_at(&mut self, screen_point: Point2<f32>, factor: f32) {
+    let before = self.screen_to_world(screen_point);
+    self.zoom = (self.zoom * factor).max(ZOOM_RANGE.0).min(ZOOM_RANGE.1);
+    self.centre += before - self.screen_to_world(screen_point);
+  }
+
+  // Moves the view by a distance in screen pixels. Stops following anything, as it would pull the view straight back.
+  pub fn pan(&mut self, screen_delta: Vector2<f32>) {
+    self.centre -= screen_delta/self.zoom;
+    self.set_focus(Focus::Free);
+  }
+
+  // Jumps the view to be centred on a world point, e.g. from the minimap
+  pub fn look_at(&mut self, point: Point2<f32>) {
+    self.centre = point;
+    self.set_focus(Focus::Free);
+  }
+
+  pub fn set_focus(&mut self, focus: Focus) {
+    self.focus = focus;
+    self.last_target = None;
+  }
+
+  // Moves with the focused target, while smoothly closing any gap left when the focus was changed.
+  // Falls back to Free if the focused planet no longer exists.
+  pub fn update(&mut self, world: &World, dt: f32) {
+    let target = match self.focus {
+      Focus::Free => None,
+      Focus::Planet(id) => world.planets.get(&id).map(|pl| pl.borrow().position),
+      Focus::Barycentre => world.barycenter().map(|(position, _)| position),
+    };
+
+    if let Some(target) = target {
+      if let Some(last_target) = self.last_target {
+        self.centre += target - last_target;
+      }
+      self.centre += (target - self.centre) * (1.0 - (-RECENTRE_RATE * dt).exp());
+    } else if let Focus::Planet(_) = self.focus {
+      self.focus = Focus::Free;
+    }
+    self.last_target = target;
+  }
+
+  fn top_left(&self) -> Point2<f32> {
+    self.centre - Vector2::new(SCREEN_DIMS.0/2.0, SCREEN_DIMS.1/2.0)/self.zoom
+  }
+
+  pub fn screen_to_world(&self, point: Point2<f32>) -> Point2<f32> {
+    self.top_left() + point.coords/self.zoom
+  }
+
+  // Area of the world on screen
+  pub fn view(&self) -> Rect {
+    let top_left = self.top_left();
+    Rect::new(top_left.x, top_left.y, SCREEN_DIMS.0/self.zoom, SCREEN_DIMS.1/self.zoom)
+  }
+
+  // Following draws are in world coordinates, shaken if there's been an impact
+  pub fn apply(&self, canvas: &mut Canvas) {
+    let mut view = self.view();
+    view.translate(self.shake/self.zoom);
+    canvas.set_screen_coordinates(view);
+  }
+
+  // Following draws are in screen coordinates, for UI
+  pub fn reset(canvas: &mut Canvas) {
+    canvas.set_screen_coordinates(Rect::new(0.0, 0.0, SCREEN_DIMS.0, SCREEN_DIMS.1));
+  }
+}
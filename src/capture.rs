@@ -0,0 +1,47 @@
+use ggez::graphics::{self, Canvas, DrawParam, DrawMode, ImageEncodingFormat, Mesh};
+use ggez::{Context, GameResult};
+
+use crate::SCREEN_DIMS;
+
+// Inside ggez's user data directory, as that's where the filesystem writes to
+pub const CAPTURE_DIR: &str = "/frames";
+
+// Saves every rendered frame as a numbered PNG, e.g. for `ffmpeg -framerate 60 -i frame_%06d.png out.mp4`.
+// While capturing, frames are a fixed length (see MainState::is_deterministic), so the video plays at the sim's speed.
+pub struct FrameCapture {
+  next_frame: u32,
+}
+
+impl FrameCapture {
+  pub fn new(ctx: &mut Context) -> GameResult<Self> {
+    ctx.fs.create_dir(CAPTURE_DIR)?;
+    Ok(Self {
+      next_frame: 0,
+    })
+  }
+
+  pub fn frames_captured(&self) -> u32 {
+    self.next_frame
+  }
+
+  // Writes out the frame drawn so far. Call after the frame's canvas has been finished.
+  pub fn capture(&mut self, ctx: &mut Context) -> GameResult {
+    let frame = ctx.gfx.frame().clone();
+    frame.encode(ctx, ImageEncodingFormat::Png, format!("{}/frame_{:06}.png", CAPTURE_DIR, self.next_frame))?;
+    self.next_frame += 1;
+    Ok(())
+  }
+
+  // Red dot and frame count, drawn on top of the frame after it has been captured so it doesn't end up in the video
+  pub fn draw_indicator(&self, ctx: &mut Context) -> GameResult {
+    let mut canvas = Canvas::from_frame(ctx, None);
+    let dot_position = [SCREEN_DIMS.0 - 110.0, SCREEN_DIMS.1 - 20.0];
+    let dot = Mesh::new_circle(ctx, DrawMode::fill(), dot_position, 6.0, 0.5, [1.0, 0.1, 0.1, 1.0].into())?;
+    canvas.draw(&dot, DrawParam::default());
+    canvas.draw(
+      &graphics::Text::new(format!("REC {}", self.next_frame)),
+      DrawParam::new().dest([dot_position[0] + 12.0, dot_position[1] - 8.0]),
+    );
+    canvas.finish(ctx)
+  }
+}
@@ -0,0 +1,120 @@
+use clap::Parser;
+
+use std::path::PathBuf;
+
+use orbits::headless::HeadlessConfig;
+use orbits::bench::{BenchConfig, DEFAULT_BENCH_SIZES};
+use crate::protocol::DEFAULT_PORT;
+
+// Startup options. Everything is optional: with no arguments the window opens on the first scenario.
+#[derive(Parser, Debug)]
+#[command(name = "orbits", about = "2D gravity sandbox")]
+pub struct Cli {
+  #[arg(long, value_name = "NAME|FILE", conflicts_with = "bodies",
+    help = "Scenario to start with, by name (case doesn't matter), or a save file to load")]
+  pub scenario: Option<String>,
+
+  #[arg(long, value_name = "N", help = "Start with N randomly placed bodies instead of a scenario")]
+  pub bodies: Option<usize>,
+
+  #[arg(long, value_name = "FILE", conflicts_with_all = ["scenario", "bodies"],
+    help = "Start with bodies from a CSV file with columns x, y, vx, vy, mass and/or radius, name and kind")]
+  pub import: Option<PathBuf>,
+
+  #[arg(long, help = "Seed for the random number generator, so runs can be reproduced")]
+  pub seed: Option<u64>,
+
+  #[arg(long, help = "Run without a window, writing every body's state to CSV")]
+  pub headless: bool,
+
+  #[arg(long, default_value_t = HeadlessConfig::default().steps, help = "Steps to run in headless mode")]
+  pub steps: usize,
+
+  #[arg(long, default_value = "./orbits_output.csv", help = "CSV file written in headless mode")]
+  pub output: PathBuf,
+
+  #[arg(long, default_value_t = 1, value_name = "N", help = "Write body states every N steps in headless mode")]
+  pub record_every: usize,
+
+  #[arg(long, alias = "dt", value_name = "SECONDS", value_parser = parse_positive,
+    help = "Advance by this much sim time every frame (or every step headless) instead of the real frame time")]
+  pub fixed_dt: Option<f32>,
+
+  #[arg(long, value_name = "HZ", value_parser = parse_positive,
+    help = "Physics steps per second of sim time, overriding the settings file. Drawing is smoothed between steps.")]
+  pub physics_rate: Option<f32>,
+
+  #[arg(long, value_name = "WxH", value_parser = parse_window_size, help = "Window size, overriding the settings file")]
+  pub window: Option<(f32, f32)>,
+
+  #[arg(long, help = "Start recording a replay straight away, as if F6 was pressed")]
+  pub record: bool,
+
+  #[arg(long, help = "Time the physics for randomly filled worlds and exit")]
+  pub bench: bool,
+
+  #[arg(long, value_delimiter = ',', default_values_t = DEFAULT_BENCH_SIZES, help = "World sizes to benchmark")]
+  pub sizes: Vec<usize>,
+
+  #[arg(long, default_value_t = BenchConfig::default().iterations, help = "Benchmark runs to average over")]
+  pub iterations: usize,
+
+  #[arg(long, help = "Compute gravity on the GPU, if built with the gpu feature")]
+  pub gpu: bool,
+
+  #[arg(long, value_name = "PORT", num_args = 0..=1, default_missing_value = "7878", conflicts_with = "join",
+    help = "Host a shared sandbox others can join, on port 7878 unless given")]
+  pub host: Option<u16>,
+
+  #[arg(long, value_name = "ADDRESS", help = "Join a sandbox hosted with --host, e.g. 192.168.1.5 or 192.168.1.5:7878")]
+  pub join: Option<String>,
+
+  #[cfg(feature = "three_d")]
+  #[arg(long = "3d", help = "Open the 3D world instead")]
+  pub three_d: bool,
+}
+
+impl Cli {
+  pub fn headless_config(&self) -> HeadlessConfig {
+    let default = HeadlessConfig::default();
+    HeadlessConfig {
+      steps: self.steps,
+      dt: self.fixed_dt.unwrap_or(default.dt),
+      output: self.output.clone(),
+      record_every: self.record_every.max(1),
+    }
+  }
+
+  // The host to join, on the default port unless one is given
+  pub fn join_address(&self) -> Option<String> {
+    self.join.as_ref().map(|address| if address.contains(':') {
+      address.clone()
+    } else {
+      format!("{}:{}", address, DEFAULT_PORT)
+    })
+  }
+
+  pub fn bench_config(&self) -> BenchConfig {
+    BenchConfig {
+      sizes: self.sizes.clone(),
+      iterations: self.iterations,
+      seed: self.seed.unwrap_or(BenchConfig::default().seed),
+    }
+  }
+}
+
+// "1600x1000"
+fn parse_window_size(s: &str) -> Result<(f32, f32), String> {
+  let (width, height) = s.split_once(['x', 'X']).ok_or("expected WIDTHxHEIGHT, e.g. 1600x1000")?;
+  let width: f32 = parse_positive(width.trim())?;
+  let height: f32 = parse_positive(height.trim())?;
+  Ok((width, height))
+}
+
+fn parse_positive(s: &str) -> Result<f32, String> {
+  match s.parse::<f32>() {
+    Ok(v) if v > 0.0 && v.is_finite() => Ok(v),
+    Ok(_) => Err(format!("{} must be above zero", s)),
+    Err(e) => Err(format!("{}: {}", s, e)),
+  }
+}
@@ -0,0 +1,62 @@
+use nalgebra::Point2;
+
+use std::collections::HashMap;
+
+const MIN_CELL_SIZE: f32 = 1.0;
+
+// Uniform spatial hash used as the broad phase for collision detection.
+// Each body is put in every cell its bounding box touches, so only bodies sharing a cell need a narrow phase check.
+pub struct SpatialHash {
+  cell_size: f32,
+  cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialHash {
+  // bodies are (position, radius). Indexes in the returned pairs refer to this slice.
+  pub fn new(bodies: &[(Point2<f32>, f32)]) -> Self {
+    // Cells around the size of an average body keep most bodies in one to four cells
+    let mean_diameter = if bodies.is_empty() {
+      MIN_CELL_SIZE
+    } else {
+      bodies.iter().map(|(_, r)| r * 2.0).sum::<f32>()/bodies.len() as f32
+    };
+
+    let mut hash = Self {
+      cell_size: mean_diameter.max(MIN_CELL_SIZE),
+      cells: HashMap::with_capacity(bodies.len()),
+    };
+
+    for (i, (pos, radius)) in bodies.iter().enumerate() {
+      let (min_x, min_y) = hash.cell_of(pos.x - radius, pos.y - radius);
+      let (max_x, max_y) = hash.cell_of(pos.x + radius, pos.y + radius);
+      for x in min_x..=max_x {
+        for y in min_y..=max_y {
+          hash.cells.entry((x, y)).or_insert_with(Vec::new).push(i);
+        }
+      }
+    }
+
+    hash
+  }
+
+  fn cell_of(&self, x: f32, y: f32) -> (i32, i32) {
+    ((x/self.cell_size).floor() as i32, (y/self.cell_size).floor() as i32)
+  }
+
+  // Every pair (i, j) with i < j sharing at least one cell, each pair once, in ascending order.
+  pub fn candidate_pairs(&self) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for bodies in self.cells.values() {
+      for (n, &i) in bodies.iter().enumerate() {
+        for &j in bodies[n+1..].iter() {
+          pairs.push(if i < j { (i, j) } else { (j, i) });
+        }
+      }
+    }
+
+    // Large bodies share several cells with the same neighbours
+    pairs.sort_unstable();
+    pairs.dedup();
+    pairs
+  }
+}
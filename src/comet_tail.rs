@@ -0,0 +1,107 @@
+use ggez::graphics::{MeshBuilder, DrawMode};
+use ggez::GameResult;
+
+use nalgebra::{Vector2, Point2};
+use rand::Rng;
+
+use orbits::simulation::World;
+use orbits::planet::{Planet, BodyKind};
+
+const TAIL_RANGE_RADII: f32 = 40.0;       // Tails start within this many star radii of a star's centre
+const TAIL_MAX_EMISSION_RATE: f32 = 80.0; // Particles per second, right next to the star
+const TAIL_MAX_SPEED: f32 = 60.0;         // Away from the star, relative to the comet
+const TAIL_SPREAD: f32 = 0.15;            // Sideways speed, as a fraction of the outward speed
+const TAIL_LIFETIME: f32 = 1.5;           // Seconds
+const TAIL_PARTICLE_RADIUS: f32 = 0.7;
+const TAIL_COLOR: [f32; 3] = [0.6, 0.85, 1.0];
+
+// Where a comet's tail points and how strongly it emits, from the star heating it most
+pub struct Heating {
+  pub direction: Vector2<f32>,  // Unit vector from the star through the comet
+  pub intensity: f32,           // 0 at the edge of the star's range, 1 at its surface
+}
+
+// The nearest star (relative to its size) within range of the given body, if any
+pub fn heating(world: &World, comet: &Planet) -> Option<Heating> {
+  world.planets.values()
+    .map(|star| star.borrow())
+    .filter(|star| star.kind == BodyKind::Star && star.id != comet.id)
+    .filter_map(|star| {
+      let offset = comet.position - star.position;
+      let distance = offset.magnitude();
+      let range = star.radius * TAIL_RANGE_RADII;
+      if distance <= 0.0 || distance >= range { return None }
+      let intensity = ((range - distance)/(range - star.radius)).min(1.0);
+      Some(Heating { direction: offset/distance, intensity })
+    })
+    .max_by(|a, b| a.intensity.partial_cmp(&b.intensity).unwrap())
+}
+
+struct TailParticle {
+  position: Point2<f32>,
+  velocity: Vector2<f32>,
+  age: f32,
+}
+
+// Particles streaming off an asteroid away from a nearby star. They carry on with the comet's velocity plus an
+// outward push, so the tail points away from the star rather than along the path. Particles ignore gravity.
+#[derive(Default)]
+pub struct CometTail {
+  particles: Vec<TailParticle>,
+  emission_timer: f32,
+  emitting: bool,   // Whether the comet was near a star at the last update
+}
+
+impl CometTail {
+  // comet is None once the body is gone, then the tail just fades out
+  pub fn update(&mut self, dt: f32, comet: Option<(&Planet, Option<Heating>)>) {
+    for particle in self.particles.iter_mut() {
+      particle.age += dt;
+      particle.position += particle.velocity * dt;
+    }
+    self.particles.retain(|particle| particle.age < TAIL_LIFETIME);
+
+    let (comet, heating) = match comet {
+      Some((comet, Some(heating))) => (comet, heating),
+      _ => {
+        self.emission_timer = 0.0;
+        self.emitting = false;
+        return
+      },
+    };
+
+    self.emitting = true;
+    let rate = TAIL_MAX_EMISSION_RATE * heating.intensity;
+    if rate <= 0.0 { return }
+    let period = 1.0/rate;
+    let speed = TAIL_MAX_SPEED * heating.intensity.sqrt();
+    let sideways = Vector2::new(-heating.direction.y, heating.direction.x);
+    let mut rng = rand::thread_rng();
+
+    self.emission_timer += dt;
+    while self.emission_timer > period {
+      self.emission_timer -= period;
+      let spread = rng.gen_range(-TAIL_SPREAD..TAIL_SPREAD);
+      self.particles.push(TailParticle {
+        position: comet.position + heating.direction * comet.radius,
+        velocity: comet.velocity + (heating.direction + sideways * spread) * speed * rng.gen_range(0.5..1.0),
+        age: 0.0,
+      });
+    }
+  }
+
+  // No longer emitting and faded out
+  pub fn is_dead(&self) -> bool {
+    !self.emitting && self.particles.is_empty()
+  }
+
+  // Returns if anything was drawn
+  pub fn draw(&self, mesh: &mut MeshBuilder) -> GameResult<bool> {
+    let [r, g, b] = TAIL_COLOR;
+    for particle in self.particles.iter() {
+      let alpha = (1.0 - particle.age/TAIL_LIFETIME).max(0.0);
+      mesh.circle(DrawMode::fill(), particle.position, TAIL_PARTICLE_RADIUS, 0.5, [r, g, b, alpha].into())?;
+    }
+    Ok(!self.particles.is_empty())
+  }
+}
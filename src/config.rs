@@ -0,0 +1,206 @@
+use serde::{Serialize, Deserialize};
+
+use rgb_hsv::hsv_to_rgb;
+
+use std::fmt;
+
+use crate::G;
+use crate::planet::BodyKind;
+
+pub const G_SI: f32 = 6.674e-11;  // m^3 kg^-1 s^-2
+pub const METRES_PER_AU: f32 = 1.496e11;
+pub const SECONDS_PER_DAY: f32 = 86400.0;
+pub const DEFAULT_SOFTENING: f32 = 1.0;
+
+pub const DEFAULT_DESPAWN_RADIUS: f32 = 50_000.0;
+pub const DEFAULT_TRAIL_COLOR: [f32; 4] = [0.1, 0.4, 1.0, 1.0];
+pub const PALETTE_SIZE: usize = 8;
+pub const DEFAULT_PALETTE: [[f32; 3]; PALETTE_SIZE] = [
+  [0.35, 0.6, 1.0],
+  [0.95, 0.55, 0.3],
+  [0.45, 0.85, 0.5],
+  [0.85, 0.4, 0.7],
+  [0.95, 0.85, 0.4],
+  [0.4, 0.85, 0.9],
+  [0.75, 0.55, 0.95],
+  [0.9, 0.9, 0.9],
+];
+const DENSITY_HUE_RANGE: (f32, f32) = (1000.0, 20000.0);  // Densities coloured blue through to red, on a log scale
+
+// How trails behind bodies look. Only used by frontends, but kept here so it can be set in the config file.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrailConfig {
+  pub color: Option<[f32; 4]>,  // None uses the colour of the planet
+  pub lifetime: f32,            // Seconds before a node fades out completely
+  pub emission_rate: f32,       // Nodes placed per second
+}
+
+impl Default for TrailConfig {
+  fn default() -> Self {
+    Self {
+      color: Some(DEFAULT_TRAIL_COLOR),
+      lifetime: 0.7,
+      emission_rate: 50.0,
+    }
+  }
+}
+
+// What is conserved when bodies merge, which decides the merged body's radius
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum MergeGeometry {
+  #[default]
+  Volume,   // Bodies are spheres: the merged radius comes from the total mass and the mass-weighted density
+  Area,     // Bodies are discs: the merged body covers the same area on screen as both parts did
+}
+
+impl MergeGeometry {
+  pub fn next(self) -> Self {
+    match self {
+      MergeGeometry::Volume => MergeGeometry::Area,
+      MergeGeometry::Area => MergeGeometry::Volume,
+    }
+  }
+}
+
+impl fmt::Display for MergeGeometry {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", match self {
+      MergeGeometry::Volume => "Conserve volume",
+      MergeGeometry::Area => "Conserve area",
+    })
+  }
+}
+
+// Where new planets' colours come from. Stars and asteroids always start in their kind's colour.
+// Once spawned a body keeps its colour, blended by mass when it merges, and it can be changed in the inspector.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum PlanetColoring {
+  #[default]
+  Palette,  // Picked from SimConfig::palette by id, so bodies spawned one after another differ
+  Density,  // Hue from density, from blue for light bodies to red for dense ones
+  Kind,     // All planets the same colour
+}
+
+impl PlanetColoring {
+  pub fn next(self) -> Self {
+    match self {
+      PlanetColoring::Palette => PlanetColoring::Density,
+      PlanetColoring::Density => PlanetColoring::Kind,
+      PlanetColoring::Kind => PlanetColoring::Palette,
+    }
+  }
+}
+
+impl fmt::Display for PlanetColoring {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", match self {
+      PlanetColoring::Palette => "Palette",
+      PlanetColoring::Density => "Density",
+      PlanetColoring::Kind => "Kind",
+    })
+  }
+}
+
+// Physical constants and units of the simulation.
+// Distances are in pixels and time in sim seconds, so g is G expressed in those units.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+  pub g: f32,
+  pub softening: f32,               // Softening length ε in pixels, see tools::grav_force
+  pub metres_per_pixel: f32,        // Real distance one pixel represents, for readouts
+  pub seconds_per_sim_second: f32,  // Real time one sim second represents, for readouts
+  pub time_scale: f32,              // Sim seconds per wall clock second
+  pub trails: TrailConfig,          // Default for trails without their own config
+  pub wrap_size: Option<(f32, f32)>, // If set, the world starts wrapped around at this size (see World::wrap_bounds)
+  pub merge_geometry: MergeGeometry,
+  pub planet_coloring: PlanetColoring,
+  pub palette: [[f32; 3]; PALETTE_SIZE],  // RGB, 0 to 1
+  // Bodies are removed when further than this from the barycentre, so ones flung away stop costing time
+  pub despawn_radius: Option<f32>,
+  // Or when moving faster than this relative to the barycentre while not bound to the other bodies
+  pub despawn_speed: Option<f32>,
+}
+
+impl Default for SimConfig {
+  fn default() -> Self {
+    Self {
+      g: G,
+      softening: DEFAULT_SOFTENING,
+      metres_per_pixel: 1.0,
+      seconds_per_sim_second: 1.0,
+      time_scale: 1.0,
+      trails: TrailConfig::default(),
+      wrap_size: None,
+      merge_geometry: MergeGeometry::default(),
+      planet_coloring: PlanetColoring::default(),
+      palette: DEFAULT_PALETTE,
+      despawn_radius: Some(DEFAULT_DESPAWN_RADIUS),
+      despawn_speed: None,
+    }
+  }
+}
+
+impl SimConfig {
+  // Config where masses are in kg and G is the real value, converted to pixel/sim second units.
+  // G' = G * T^2/L^3 where L is metres per pixel and T is seconds per sim second.
+  pub fn real_units(metres_per_pixel: f32, seconds_per_sim_second: f32) -> Self {
+    // Split up to avoid overflowing f32 at solar system scales
+    let g = G_SI * (seconds_per_sim_second/metres_per_pixel).powi(2)/metres_per_pixel;
+    Self {
+      g,
+      metres_per_pixel,
+      seconds_per_sim_second,
+      ..Self::default()
+    }
+  }
+
+  // Mass in this config's units of a body with the given real mass, so it attracts like the real one would at the
+  // scale set by metres_per_pixel and seconds_per_sim_second, without changing g.
+  pub fn scaled_mass(&self, kg: f32) -> f32 {
+    kg * (Self::real_units(self.metres_per_pixel, self.seconds_per_sim_second).g/self.g)
+  }
+
+  // Colour of a newly spawned body
+  pub fn body_color(&self, kind: BodyKind, id: usize, density: f32) -> [f32; 4] {
+    if kind != BodyKind::Planet { return kind.base_color() }
+    match self.planet_coloring {
+      PlanetColoring::Palette => {
+        let [r, g, b] = self.palette[id % PALETTE_SIZE];
+        [r, g, b, 1.0]
+      },
+      PlanetColoring::Density => {
+        let (low, high) = (DENSITY_HUE_RANGE.0.ln(), DENSITY_HUE_RANGE.1.ln());
+        let t = ((density.max(f32::MIN_POSITIVE).ln() - low)/(high - low)).clamp(0.0, 1.0);
+        let (r, g, b) = hsv_to_rgb((0.66 * (1.0 - t), 0.7, 1.0));
+        [r, g, b, 1.0]
+      },
+      PlanetColoring::Kind => kind.base_color(),
+    }
+  }
+
+  // Human readable distance and time scale, e.g. "1 px = 0.01 AU, 1 sim s = 10 days"
+  pub fn describe_scale(&self) -> String {
+    let distance = match self.metres_per_pixel {
+      m if m >= METRES_PER_AU * 1e-3 => format!("{:.3} AU", m/METRES_PER_AU),
+      m if m >= 1000.0 => format!("{:.3} km", m/1000.0),
+      m => format!("{:.3} m", m),
+    };
+    format!("1 px = {}, 1 sim s = {}", distance, Self::describe_seconds(self.seconds_per_sim_second))
+  }
+
+  // How much real time sim_seconds of sim time represents, e.g. "3.250 years"
+  pub fn describe_duration(&self, sim_seconds: f32) -> String {
+    Self::describe_seconds(sim_seconds * self.seconds_per_sim_second)
+  }
+
+  fn describe_seconds(seconds: f32) -> String {
+    match seconds {
+      s if s >= SECONDS_PER_DAY * 365.25 => format!("{:.3} years", s/(SECONDS_PER_DAY * 365.25)),
+      s if s >= SECONDS_PER_DAY => format!("{:.3} days", s/SECONDS_PER_DAY),
+      s if s >= 3600.0 => format!("{:.3} hours", s/3600.0),
+      s => format!("{:.3} s", s),
+    }
+  }
+}
@@ -0,0 +1,67 @@
+use nalgebra::Vector2;
+
+use crate::simulation::World;
+use crate::tools;
+
+// Conserved quantities of the whole system, for judging integrator quality
+#[derive(Clone, Copy, Debug)]
+pub struct Diagnostics {
+  pub kinetic_energy: f32,
+  pub potential_energy: f32,
+  pub linear_momentum: Vector2<f32>,
+  pub angular_momentum: f32,    // About the origin, plus spin. Scalar since the sim is 2D (z component only).
+}
+
+impl Diagnostics {
+  // Potential energy is summed over every pair, so this is O(n²)
+  pub fn measure(world: &World) -> Self {
+    let planets: Vec<_> = world.planets.values().map(|pl| pl.borrow()).collect();
+
+    let mut kinetic_energy = 0.0;
+    let mut potential_energy = 0.0;
+    let mut linear_momentum = Vector2::new(0.0, 0.0);
+    let mut angular_momentum = 0.0;
+    let softening_squared = world.config.softening.powi(2);
+
+    for (i, pl) in planets.iter().enumerate() {
+      kinetic_energy += 0.5 * pl.mass * pl.velocity.magnitude_squared() + 0.5 * pl.moment_of_inertia() * pl.angular_velocity.powi(2);
+      linear_momentum += pl.velocity * pl.mass;
+      // L = r x p
+      angular_momentum += pl.mass * (pl.position.x * pl.velocity.y - pl.position.y * pl.velocity.x) + pl.spin_angular_momentum();
+
+      for other in planets[i+1..].iter() {
+        // U = -GMm/sqrt(r^2 + ε^2), matching the softened force
+        let dist_vec = tools::minimum_image(other.position - pl.position, world.wrap_bounds);
+        let dist = (dist_vec.magnitude_squared() + softening_squared).sqrt();
+        if dist > 0.0 {
+          potential_energy -= world.config.g * pl.mass * other.mass/dist;
+        }
+      }
+    }
+
+    Self {
+      kinetic_energy,
+      potential_energy,
+      linear_momentum,
+      angular_momentum,
+    }
+  }
+
+  pub fn total_energy(&self) -> f32 {
+    self.kinetic_energy + self.potential_energy
+  }
+
+  // Human readable summary, with drift relative to a baseline measurement
+  pub fn report(&self, baseline: &Diagnostics) -> String {
+    let relative = |now: f32, then: f32| if then != 0.0 { (now - then)/then.abs() * 100.0 } else { 0.0 };
+
+    format!(
+      "KE: {:.4e}\nPE: {:.4e}\nE: {:.4e} (drift {:+.4}%)\np: ({:.3e}, {:.3e}) (drift {:.3e})\nL: {:.4e} (drift {:+.4}%)",
+      self.kinetic_energy,
+      self.potential_energy,
+      self.total_energy(), relative(self.total_energy(), baseline.total_energy()),
+      self.linear_momentum.x, self.linear_momentum.y, (self.linear_momentum - baseline.linear_momentum).magnitude(),
+      self.angular_momentum, relative(self.angular_momentum, baseline.angular_momentum),
+    )
+  }
+}
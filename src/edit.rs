@@ -0,0 +1,43 @@
+use nalgebra::{Vector2, Point2};
+
+pub const NUDGE_DISTANCE: f32 = 1.0;    // Pixels moved per arrow key press
+pub const NUDGE_SPEED: f32 = 1.0;       // Velocity added per shift + arrow key press
+pub const MASS_SCALE_STEP: f32 = 1.1;   // Mass is multiplied/divided by this per +/- press
+pub const VELOCITY_HANDLE_SCALE: f32 = 1.0;   // The handle is this many seconds of travel ahead, matching the velocity arrow
+pub const HANDLE_RADIUS: f32 = 5.0;
+
+// Whether the keyboard is editing the selected planet.
+// Off -> Editing when Enter is pressed with a planet selected, which pauses the simulation.
+// Editing -> Off on Enter again, or when the selection is lost. The pause state from before editing is restored.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum EditMode {
+  #[default]
+  Off,
+  Editing { was_paused: bool },
+}
+
+impl EditMode {
+  pub fn is_editing(&self) -> bool {
+    matches!(self, EditMode::Editing { .. })
+  }
+}
+
+// Dragging a planet with the mouse while paused, to set up initial conditions
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Grab {
+  Position { id: usize, offset: Vector2<f32> },   // offset is from the cursor to the planet's centre
+  Velocity { id: usize },                         // The handle at the tip of the planet's velocity
+}
+
+impl Grab {
+  pub fn id(&self) -> usize {
+    match *self {
+      Grab::Position { id, .. } | Grab::Velocity { id } => id,
+    }
+  }
+}
+
+// Where the velocity handle of a planet is drawn and grabbed
+pub fn velocity_handle(position: Point2<f32>, velocity: Vector2<f32>) -> Point2<f32> {
+  position + velocity * VELOCITY_HANDLE_SCALE
+}
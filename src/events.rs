@@ -0,0 +1,73 @@
+use std::fmt;
+
+const MAX_STORED_EVENTS: usize = 1000;    // So events don't pile up if nothing takes them
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EventKind {
+  Spawned { id: usize, mass: f32 },
+  Merged { into: usize, absorbed: usize, masses: (f32, f32) },   // Masses of into and absorbed just before merging
+  MassTransferred { from: usize, to: usize, mass: f32 },   // In a grazing impact
+  Fragmented { id: usize, pieces: usize },
+  TidallyDisrupted { id: usize, host: usize, pieces: usize },
+  Softened { id: usize, particles: usize },   // Split into bonded particles on impact, see soft_body
+  Removed { id: usize },
+  Despawned { id: usize, reason: DespawnReason },   // Left the system for good, see SimConfig::despawn_radius
+  CrossedBoundary { id: usize },    // Left one edge of a wrapping world and came back at the other
+  Ejected { id: usize },    // Was bound to the rest of the system and now isn't, see World::binding
+  Captured { id: usize },   // The other way round
+  CloseApproach { a: usize, b: usize, distance: f32 },    // Came within a few radii of each other without touching
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DespawnReason {
+  TooFar,     // Beyond the despawn radius
+  Escaping,   // Faster than the despawn speed and not bound to the rest of the system
+}
+
+// Something that happened to a body, and the sim time it happened at
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Event {
+  pub time: f32,
+  pub kind: EventKind,
+}
+
+impl fmt::Display for Event {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "[{:.2}s] ", self.time)?;
+    match self.kind {
+      EventKind::Spawned { id, mass } => write!(f, "Body {} spawned with mass {:.2}", id, mass),
+      EventKind::Merged { into, absorbed, masses } =>
+        write!(f, "Body {} (mass {:.2}) merged into {} (mass {:.2})", absorbed, masses.1, into, masses.0),
+      EventKind::MassTransferred { from, to, mass } => write!(f, "Body {} grazed {}, losing {:.2} mass to it", from, to, mass),
+      EventKind::Fragmented { id, pieces } => write!(f, "Body {} shattered into {} pieces", id, pieces),
+      EventKind::TidallyDisrupted { id, host, pieces } =>
+        write!(f, "Body {} was torn into {} pieces by {}", id, pieces, host),
+      EventKind::Softened { id, particles } => write!(f, "Body {} turned into {} bonded particles", id, particles),
+      EventKind::Removed { id } => write!(f, "Body {} removed", id),
+      EventKind::Despawned { id, reason: DespawnReason::TooFar } => write!(f, "Body {} went too far away and was removed", id),
+      EventKind::Despawned { id, reason: DespawnReason::Escaping } => write!(f, "Body {} escaped the system and was removed", id),
+      EventKind::CrossedBoundary { id } => write!(f, "Body {} crossed the world boundary", id),
+      EventKind::Ejected { id } => write!(f, "Body {} was ejected (no longer bound to the system)", id),
+      EventKind::Captured { id } => write!(f, "Body {} was captured (bound to the system again)", id),
+      EventKind::CloseApproach { a, b, distance } => write!(f, "Bodies {} and {} passed within {:.2} of each other", a, b, distance),
+    }
+  }
+}
+
+// Where the world reports events as they happen. Frontends collect them with World::take_events.
+#[derive(Clone, Default)]
+pub struct EventBus {
+  events: Vec<Event>,
+}
+
+impl EventBus {
+  pub fn emit(&mut self, time: f32, kind: EventKind) {
+    if self.events.len() < MAX_STORED_EVENTS {
+      self.events.push(Event { time, kind });
+    }
+  }
+
+  pub fn drain(&mut self) -> Vec<Event> {
+    std::mem::take(&mut self.events)
+  }
+}
@@ -0,0 +1,88 @@
+use ggez::graphics::{Canvas, DrawParam, Mesh, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+
+use nalgebra::{Point2, Vector2};
+
+use orbits::simulation::World;
+
+use crate::render;
+use crate::SCREEN_DIMS;
+
+const ARROW_COLUMNS: usize = 40;
+const ARROW_REFRESH: f32 = 0.25;      // Seconds between recomputing the field
+const MAX_LENGTH: f32 = 0.8;          // Longest arrow, in grid spacings
+const MIN_LENGTH: f32 = 0.15;         // Shortest, so the direction still shows in weak fields
+const HEAD_SIZE: f32 = 0.3;           // Fraction of the arrow's length
+const LINE_WIDTH: f32 = 1.0;          // On screen
+const ARROW_ALPHA: f32 = 0.8;
+
+// Arrows on a grid over the view, pointing along the net gravitational acceleration at each point. Lengths and colours
+// go with the log of its magnitude, scaled between the weakest and strongest in view, so both the pull near a body
+// and the weak field at a saddle point between bodies show up.
+pub struct FieldArrows {
+  mesh: Option<Mesh>,
+  age: f32,       // Seconds since the field was sampled
+}
+
+impl FieldArrows {
+  pub fn new() -> Self {
+    Self {
+      mesh: None,
+      age: 0.0,
+    }
+  }
+
+  // Only resamples every ARROW_REFRESH seconds, like the heatmap
+  pub fn update(&mut self, ctx: &Context, world: &World, view: Rect, dt: f32) -> GameResult {
+    self.age += dt;
+    if world.planets.is_empty() {
+      self.mesh = None;
+      return Ok(())
+    }
+    if self.mesh.is_some() && self.age < ARROW_REFRESH { return Ok(()) }
+
+    let spacing = view.w/ARROW_COLUMNS as f32;
+    let rows = (view.h/spacing).ceil() as usize;
+    let points: Vec<Point2<f32>> = (0..rows)
+      .flat_map(|row| (0..ARROW_COLUMNS).map(move |column| (column, row)))
+      .map(|(column, row)| Point2::new(view.x + (column as f32 + 0.5) * spacing, view.y + (row as f32 + 0.5) * spacing))
+      .collect();
+
+    let fields: Vec<Vector2<f32>> = world.field_at(&points).into_iter().map(|(_, field)| field).collect();
+    let strengths: Vec<f32> = fields.iter().map(|field| field.magnitude().max(f32::MIN_POSITIVE).ln()).collect();
+    let (min, max) = strengths.iter().fold((f32::MAX, f32::MIN), |(min, max), s| (min.min(*s), max.max(*s)));
+    let range = (max - min).max(f32::EPSILON);
+
+    let width = LINE_WIDTH * view.w/SCREEN_DIMS.0;
+    let mut builder = MeshBuilder::new();
+    let mut any = false;
+    for ((point, field), strength) in points.iter().zip(fields.iter()).zip(strengths.iter()) {
+      let magnitude = field.magnitude();
+      if magnitude <= 0.0 || !magnitude.is_finite() { continue }
+      let t = (strength - min)/range;
+      let length = spacing * (MIN_LENGTH + (MAX_LENGTH - MIN_LENGTH) * t);
+      let direction = field/magnitude;
+      let tail = point - direction * length/2.0;
+      let tip = point + direction * length/2.0;
+      let back = -direction * length * HEAD_SIZE;
+      let side = Vector2::new(-direction.y, direction.x) * length * HEAD_SIZE/2.0;
+
+      let [r, g, b, _] = render::heat_color(t);
+      let color = [r, g, b, ARROW_ALPHA].into();
+      builder.line(&[tail, tip], width, color)?;
+      builder.line(&[tip + back + side, tip, tip + back - side], width, color)?;
+      any = true;
+    }
+
+    self.mesh = if any { Some(Mesh::from_data(ctx, builder.build())) } else { None };
+    self.age = 0.0;
+    Ok(())
+  }
+
+  // In world coordinates
+  pub fn draw(&self, canvas: &mut Canvas) {
+    if let Some(mesh) = &self.mesh {
+      canvas.draw(mesh, DrawParam::default());
+    }
+  }
+}
@@ -0,0 +1,193 @@
+use nalgebra::Vector2;
+use wgpu::util::DeviceExt;
+
+use std::sync::OnceLock;
+
+use crate::quadtree::TreeBody;
+
+const WORKGROUP_SIZE: u32 = 256;    // Must match the shader's TILE
+
+// Every pair summed directly on the GPU. Bodies are loaded a tile at a time into workgroup memory, so each
+// one is read from the storage buffer once per workgroup rather than once per thread.
+const SHADER: &str = r#"
+struct Params {
+  g: f32,
+  softening: f32,
+  wrap_width: f32,
+  wrap_height: f32,
+  count: u32,
+  wrap: u32,
+  pad0: u32,
+  pad1: u32,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> bodies: array<vec4<f32>>;   // x, y, mass, radius
+@group(0) @binding(2) var<storage, read_write> accelerations: array<vec2<f32>>;
+
+const TILE: u32 = 256u;
+var<workgroup> tile: array<vec4<f32>, 256>;
+
+fn minimum_image(d: vec2<f32>) -> vec2<f32> {
+  if (params.wrap == 0u) { return d; }
+  let size = vec2<f32>(params.wrap_width, params.wrap_height);
+  return d - size * round(d/size);
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(local_invocation_id) lid: vec3<u32>) {
+  let i = gid.x;
+  var body = vec4<f32>(0.0);
+  if (i < params.count) { body = bodies[i]; }
+  let eps2 = params.softening * params.softening;
+  var acc = vec2<f32>(0.0);
+
+  for (var start = 0u; start < params.count; start += TILE) {
+    let j = start + lid.x;
+    if (j < params.count) { tile[lid.x] = bodies[j]; } else { tile[lid.x] = vec4<f32>(0.0); }
+    workgroupBarrier();
+
+    for (var k = 0u; k < TILE; k++) {
+      let other = tile[k];
+      let d = minimum_image(other.xy - body.xy);
+      let d2 = dot(d, d);
+      let touching = (body.w + other.w) * (body.w + other.w);
+      // Overlapping bodies don't attract, as on the CPU (see QuadTree::force_on)
+      if (start + k != i && other.z > 0.0 && d2 > touching) {
+        let r2 = d2 + eps2;
+        acc += d * (params.g * other.z/(r2 * sqrt(r2)));
+      }
+    }
+    workgroupBarrier();
+  }
+
+  if (i < params.count) { accelerations[i] = acc; }
+}
+"#;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+  g: f32,
+  softening: f32,
+  wrap_width: f32,
+  wrap_height: f32,
+  count: u32,
+  wrap: u32,
+  _padding: [u32; 2],
+}
+
+// Gravity computed in a compute shader, for worlds too big for the CPU tree to keep up with.
+// Exact rather than Barnes–Hut, so results match the CPU with theta = 0 (up to float rounding).
+pub struct GpuGravity {
+  device: wgpu::Device,
+  queue: wgpu::Queue,
+  pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuGravity {
+  // None if there is no usable GPU
+  pub fn new() -> Option<Self> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+      power_preference: wgpu::PowerPreference::HighPerformance,
+      compatible_surface: None,
+      force_fallback_adapter: false,
+    }))?;
+    let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+      label: Some("orbits gravity"),
+      features: wgpu::Features::empty(),
+      limits: wgpu::Limits::downlevel_defaults(),
+    }, None)).ok()?;
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("gravity shader"),
+      source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("gravity pipeline"),
+      layout: None,
+      module: &module,
+      entry_point: "main",
+    });
+
+    Some(Self { device, queue, pipeline })
+  }
+
+  // Same as quadtree::accelerations. Blocks until the GPU is done.
+  pub fn accelerations(&self, bodies: &[TreeBody], g: f32, softening: f32, wrap_bounds: Option<(f32, f32)>) -> Vec<Vector2<f32>> {
+    if bodies.is_empty() { return Vec::new() }
+
+    let (wrap_width, wrap_height) = wrap_bounds.unwrap_or((0.0, 0.0));
+    let params = Params {
+      g,
+      softening,
+      wrap_width,
+      wrap_height,
+      count: bodies.len() as u32,
+      wrap: wrap_bounds.is_some() as u32,
+      _padding: [0; 2],
+    };
+    let packed: Vec<[f32; 4]> = bodies.iter().map(|b| [b.position.x, b.position.y, b.mass, b.radius]).collect();
+    let output_size = (bodies.len() * std::mem::size_of::<[f32; 2]>()) as wgpu::BufferAddress;
+
+    let params_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("gravity params"),
+      contents: bytemuck::bytes_of(&params),
+      usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let body_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("gravity bodies"),
+      contents: bytemuck::cast_slice(&packed),
+      usage: wgpu::BufferUsages::STORAGE,
+    });
+    let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("gravity accelerations"),
+      size: output_size,
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+    });
+    let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("gravity readback"),
+      size: output_size,
+      usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+
+    let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("gravity bind group"),
+      layout: &self.pipeline.get_bind_group_layout(0),
+      entries: &[
+        wgpu::BindGroupEntry { binding: 0, resource: params_buffer.as_entire_binding() },
+        wgpu::BindGroupEntry { binding: 1, resource: body_buffer.as_entire_binding() },
+        wgpu::BindGroupEntry { binding: 2, resource: output_buffer.as_entire_binding() },
+      ],
+    });
+
+    let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("gravity") });
+    {
+      let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("gravity") });
+      pass.set_pipeline(&self.pipeline);
+      pass.set_bind_group(0, &bind_group, &[]);
+      pass.dispatch_workgroups((bodies.len() as u32 + WORKGROUP_SIZE - 1)/WORKGROUP_SIZE, 1, 1);
+    }
+    encoder.copy_buffer_to_buffer(&output_buffer, 0, &readback_buffer, 0, output_size);
+    self.queue.submit(Some(encoder.finish()));
+
+    let slice = readback_buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |_| ());
+    self.device.poll(wgpu::Maintain::Wait);
+    let accelerations = bytemuck::cast_slice::<u8, [f32; 2]>(&slice.get_mapped_range())
+      .iter()
+      .map(|a| Vector2::new(a[0], a[1]))
+      .collect();
+    readback_buffer.unmap();
+    accelerations
+  }
+}
+
+// Set up on first use and shared by every world, as creating a device is slow
+pub fn shared() -> Option<&'static GpuGravity> {
+  static GPU: OnceLock<Option<GpuGravity>> = OnceLock::new();
+  GPU.get_or_init(GpuGravity::new).as_ref()
+}
@@ -0,0 +1,626 @@
+use ggegui::egui;
+
+use nalgebra::Vector2;
+
+use orbits::planet::BodyKind;
+use orbits::integrator::Integrator;
+use orbits::simulation::{CollisionMode, DEFAULT_RESTITUTION, DEFAULT_FRAGMENTATION_THRESHOLD, DEFAULT_TEST_PARTICLE_MASS};
+use orbits::soft_body::SoftBodyConfig;
+use orbits::config::{TrailConfig, MergeGeometry, PlanetColoring, DEFAULT_TRAIL_COLOR, DEFAULT_DESPAWN_RADIUS};
+
+use serde::{Serialize, Deserialize};
+
+use crate::input::Action;
+use crate::brush::Tool;
+use crate::trail::TrailColoring;
+use crate::edit::MASS_SCALE_STEP;
+use crate::snapshot;
+use crate::{MainState, SPAWN_RADIUS_RANGE, SPAWN_DENSITY_RANGE, TIME_SCALE_STEPS, VECTOR_SCALE_RANGE, LAUNCH_SCALE_RANGE, PHYSICS_RATE_RANGE};
+
+const BODY_KINDS: [BodyKind; 3] = [BodyKind::Star, BodyKind::Planet, BodyKind::Asteroid];
+const TRAIL_COLORINGS: [TrailColoring; 3] = [TrailColoring::Config, TrailColoring::Speed, TrailColoring::Acceleration];
+const MERGE_GEOMETRIES: [MergeGeometry; 2] = [MergeGeometry::Volume, MergeGeometry::Area];
+const DEFAULT_DESPAWN_SPEED: f32 = 500.0;   // When turned on from the panel
+const PLANET_COLORINGS: [PlanetColoring; 3] = [PlanetColoring::Palette, PlanetColoring::Density, PlanetColoring::Kind];
+const INTEGRATORS: [Integrator; 4] = [Integrator::Euler, Integrator::SemiImplicitEuler, Integrator::VelocityVerlet, Integrator::RK4];
+
+// New values for the selected planet, from the body panel
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PlanetEdit {
+  pub id: usize,
+  pub kind: BodyKind,
+  pub mass: f32,
+  pub radius: f32,
+  #[serde(default)]
+  pub density: f32,
+  pub velocity: Vector2<f32>,
+  pub angular_velocity: f32,
+  pub is_static: bool,
+  pub ignores_gravity: bool,
+  pub exerts_no_gravity: bool,
+  pub tracked: bool,
+  pub color: [f32; 4],
+}
+
+impl MainState {
+  // Lays out the panels. Nothing is changed directly, instead the returned actions are applied
+  // through the same path as key presses so they can be recorded.
+  pub fn build_gui(&self, ctx: &egui::Context) -> Vec<Action> {
+    let mut actions = Vec::new();
+
+    if let Some(path) = &self.recovery {
+      egui::Window::new("Restore autosave?")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+          ui.label("orbits didn't close properly last time.");
+          match snapshot::age(path) {
+            Some(age) => ui.label(format!("The last autosave is from {} minutes ago.", age.as_secs()/60)),
+            None => ui.label(format!("The last autosave is {}.", path.display())),
+          };
+          ui.horizontal(|ui| {
+            if ui.button("Restore").clicked() {
+              actions.push(Action::RestoreSnapshot);
+            }
+            if ui.button("Discard").clicked() {
+              actions.push(Action::DismissRecovery);
+            }
+          });
+        });
+    }
+
+    // Toolbar along the top of the screen
+    egui::Window::new("Tools")
+      .title_bar(false)
+      .resizable(false)
+      .anchor(egui::Align2::CENTER_TOP, [0.0, 10.0])
+      .show(ctx, |ui| {
+        ui.horizontal(|ui| {
+          for tool in Tool::ALL {
+            if ui.selectable_label(self.tool == tool, tool.to_string()).on_hover_text(tool.description()).clicked() {
+              actions.push(Action::SetTool(tool));
+            }
+          }
+        });
+      });
+
+    if !self.templates.is_empty() {
+      egui::Window::new("Templates").default_pos([10.0, 600.0]).show(ctx, |ui| {
+        let active = self.active_template().and(self.spawn_template);
+        for (i, template) in self.templates.iter().enumerate() {
+          let selected = active == Some(i);
+          let label = if i < 9 { format!("{}  (Ctrl+{})", template.name, i + 1) } else { template.name.clone() };
+          let response = ui.selectable_label(selected, label)
+            .on_hover_text(format!("{}, mass {:.3e}, radius {:.1}", template.kind, template.mass, template.radius()));
+          if response.clicked() {
+            actions.push(Action::SelectTemplate(if selected { None } else { Some(i) }));
+          }
+        }
+      });
+    }
+
+    egui::Window::new("Spawn").default_pos([10.0, 330.0]).show(ctx, |ui| {
+      match self.tool {
+        Tool::Stream => {
+          let mut rate = self.stream_rate;
+          if ui.add(egui::Slider::new(&mut rate, 1.0..=120.0).text("Bodies/s")).changed() {
+            actions.push(Action::SetStreamRate(rate));
+          }
+        },
+        Tool::Ring => {
+          let mut count = self.ring_count;
+          if ui.add(egui::Slider::new(&mut count, 2..=200).text("Ring bodies")).changed() {
+            actions.push(Action::SetRingCount(count));
+          }
+        },
+        Tool::Binary => {
+          let mut ratio = self.binary_mass_ratio;
+          if ui.add(egui::Slider::new(&mut ratio, 0.01..=1.0).text("Mass ratio")).changed() {
+            actions.push(Action::SetBinaryMassRatio(ratio));
+          }
+        },
+        _ => (),
+      }
+
+      let mut kind = self.spawn_kind;
+      egui::ComboBox::from_label("Kind")
+        .selected_text(kind.to_string())
+        .show_ui(ui, |ui| {
+          for k in BODY_KINDS {
+            ui.selectable_value(&mut kind, k, k.to_string());
+          }
+        });
+      if kind != self.spawn_kind {
+        actions.push(Action::SetSpawnKind(kind));
+      }
+
+      let coloring = self.world.config.planet_coloring;
+      let mut new_coloring = coloring;
+      egui::ComboBox::from_label("Planet colours")
+        .selected_text(coloring.to_string())
+        .show_ui(ui, |ui| {
+          for c in PLANET_COLORINGS {
+            ui.selectable_value(&mut new_coloring, c, c.to_string());
+          }
+        });
+      if new_coloring != coloring {
+        actions.push(Action::SetPlanetColoring(new_coloring));
+      }
+      if coloring == PlanetColoring::Palette {
+        ui.horizontal(|ui| {
+          for (index, color) in self.world.config.palette.iter().enumerate() {
+            let mut color = *color;
+            if ui.color_edit_button_rgb(&mut color).changed() {
+              actions.push(Action::SetPaletteColor { index, color });
+            }
+          }
+        });
+      }
+
+      let mut radius = self.spawn_radius;
+      if ui.add(egui::Slider::new(&mut radius, SPAWN_RADIUS_RANGE.0..=SPAWN_RADIUS_RANGE.1).logarithmic(true).text("Radius")).changed() {
+        actions.push(Action::SetSpawnRadius(radius));
+      }
+
+      let mut trackpad = self.trackpad_mode;
+      if ui.checkbox(&mut trackpad, "Trackpad mode (scroll pans)").changed() {
+        actions.push(Action::ToggleTrackpadMode);
+      }
+
+      let mut density = self.spawn_density;
+      if ui.add(egui::Slider::new(&mut density, SPAWN_DENSITY_RANGE.0..=SPAWN_DENSITY_RANGE.1).logarithmic(true).text("Density")).changed() {
+        actions.push(Action::SetSpawnDensity(density));
+      }
+
+      let mut launch_scale = self.launch_scale;
+      if ui.add(egui::Slider::new(&mut launch_scale, LAUNCH_SCALE_RANGE.0..=LAUNCH_SCALE_RANGE.1).logarithmic(true).text("Launch speed/px")).changed() {
+        actions.push(Action::SetLaunchScale(launch_scale));
+      }
+      let mut snap = self.snap_launch;
+      if ui.checkbox(&mut snap, "Launch at circular orbit speed").changed() {
+        actions.push(Action::ToggleLaunchSnap);
+      }
+    });
+
+    egui::Window::new("Simulation").default_pos([10.0, 450.0]).show(ctx, |ui| {
+      let config = &self.world.config;
+
+      let mut paused = self.paused;
+      if ui.checkbox(&mut paused, "Paused").changed() {
+        actions.push(Action::TogglePause);
+      }
+
+      let mut time_scale = config.time_scale;
+      let (min_scale, max_scale) = (TIME_SCALE_STEPS[0], TIME_SCALE_STEPS[TIME_SCALE_STEPS.len() - 1]);
+      if ui.add(egui::Slider::new(&mut time_scale, min_scale..=max_scale).logarithmic(true).text("Time scale")).changed() {
+        actions.push(Action::SetTimeScale(time_scale));
+      }
+
+      let mut physics_rate = 1.0/self.physics_dt;
+      if ui.add(egui::Slider::new(&mut physics_rate, PHYSICS_RATE_RANGE.0..=PHYSICS_RATE_RANGE.1).logarithmic(true).text("Physics steps/s")).changed() {
+        actions.push(Action::SetPhysicsRate(physics_rate));
+      }
+      let mut extrapolate = self.extrapolate;
+      if ui.checkbox(&mut extrapolate, "Extrapolate motion between steps").on_hover_text(
+        "Draw bodies ahead of the last physics step instead of between the last two, so they aren't drawn a step late"
+      ).changed() {
+        actions.push(Action::ToggleExtrapolation);
+      }
+
+      let mut g = config.g;
+      if ui.add(egui::DragValue::new(&mut g).speed(g * 0.01).prefix("G: ")).changed() && g > 0.0 {
+        actions.push(Action::SetG(g));
+      }
+
+      let mut softening = config.softening;
+      if ui.add(egui::Slider::new(&mut softening, 0.0..=20.0).text("Softening")).changed() {
+        actions.push(Action::SetSoftening(softening));
+      }
+
+      let mut theta = self.world.barnes_hut_theta;
+      if ui.add(egui::Slider::new(&mut theta, 0.0..=1.5).text("Barnes–Hut θ")).changed() {
+        actions.push(Action::SetTheta(theta));
+      }
+
+      let mut integrator = self.world.integrator;
+      egui::ComboBox::from_label("Integrator")
+        .selected_text(integrator.to_string())
+        .show_ui(ui, |ui| {
+          for i in INTEGRATORS {
+            ui.selectable_value(&mut integrator, i, i.to_string());
+          }
+        });
+      if integrator != self.world.integrator {
+        actions.push(Action::SetIntegrator(integrator));
+      }
+      let mut adaptive = self.world.adaptive_timestep;
+      if ui.checkbox(&mut adaptive, "Adaptive timestep").changed() {
+        actions.push(Action::ToggleAdaptiveTimestep);
+      }
+
+      let mut mode = self.world.collision_mode;
+      let restitution = match mode {
+        CollisionMode::Bounce { restitution } => restitution,
+        _ => DEFAULT_RESTITUTION,
+      };
+      egui::ComboBox::from_label("Collisions")
+        .selected_text(mode.to_string())
+        .show_ui(ui, |ui| {
+          for m in [CollisionMode::Merge, CollisionMode::Bounce { restitution }, CollisionMode::None] {
+            ui.selectable_value(&mut mode, m, m.to_string());
+          }
+        });
+      if let CollisionMode::Bounce { restitution } = &mut mode {
+        ui.add(egui::Slider::new(restitution, 0.0..=1.0).text("Restitution"));
+      }
+      if mode == CollisionMode::Merge {
+        let mut geometry = config.merge_geometry;
+        egui::ComboBox::from_label("Merged size")
+          .selected_text(geometry.to_string())
+          .show_ui(ui, |ui| {
+            for g in MERGE_GEOMETRIES {
+              ui.selectable_value(&mut geometry, g, g.to_string());
+            }
+          });
+        if geometry != config.merge_geometry {
+          actions.push(Action::SetMergeGeometry(geometry));
+        }
+      }
+      if mode != self.world.collision_mode {
+        actions.push(Action::SetCollisionMode(mode));
+      }
+
+      if ui.button("Reverse time").on_hover_text("Negate every velocity and spin, so the system retraces its path").clicked() {
+        actions.push(Action::ReverseTime);
+      }
+      let mut cancel_drift = self.world.cancel_drift;
+      if ui.checkbox(&mut cancel_drift, "Barycentric frame").changed() {
+        actions.push(Action::ToggleDriftCancelling);
+      }
+      let mut recentre = self.world.recentre_on.is_some();
+      if ui.checkbox(&mut recentre, "Keep barycentre centred").changed() {
+        actions.push(Action::ToggleRecentring);
+      }
+
+      let mut wrap = self.world.wrap_bounds.is_some();
+      if ui.checkbox(&mut wrap, "Wrap-around world").changed() {
+        actions.push(Action::ToggleWrap);
+      }
+      if let Some((mut width, mut height)) = self.world.wrap_bounds {
+        ui.horizontal(|ui| {
+          ui.add(egui::DragValue::new(&mut width).prefix("w: ").speed(5.0));
+          ui.add(egui::DragValue::new(&mut height).prefix("h: ").speed(5.0));
+        });
+        if Some((width, height)) != self.world.wrap_bounds {
+          actions.push(Action::SetWrapBounds((width, height)));
+        }
+      }
+
+      ui.collapsing("Default trails", |ui| {
+        let mut trails = config.trails;
+        trail_config_ui(ui, &mut trails);
+        if trails != config.trails {
+          actions.push(Action::SetDefaultTrailConfig(trails));
+        }
+
+        let mut coloring = self.trail_coloring;
+        egui::ComboBox::from_label("Colour by")
+          .selected_text(coloring.to_string())
+          .show_ui(ui, |ui| {
+            for c in TRAIL_COLORINGS {
+              ui.selectable_value(&mut coloring, c, c.to_string());
+            }
+          });
+        if coloring != self.trail_coloring {
+          actions.push(Action::SetTrailColoring(coloring));
+        }
+      });
+
+      let mut fragmentation = self.world.fragmentation_threshold.is_some();
+      if ui.checkbox(&mut fragmentation, "Fragmentation").changed() {
+        actions.push(Action::SetFragmentationThreshold(fragmentation.then(|| DEFAULT_FRAGMENTATION_THRESHOLD)));
+      }
+      let mut mass_transfer = self.world.mass_transfer.is_some();
+      if ui.checkbox(&mut mass_transfer, "Mass transfer on grazing impacts").changed() {
+        actions.push(Action::ToggleMassTransfer);
+      }
+      if let Some(mut fraction) = self.world.mass_transfer {
+        if ui.add(egui::Slider::new(&mut fraction, 0.01..=0.9).text("Mass lost per graze")).changed() {
+          actions.push(Action::SetMassTransfer(Some(fraction)));
+        }
+      }
+      let mut tidal_disruption = self.world.tidal_disruption;
+      if ui.checkbox(&mut tidal_disruption, "Tidal disruption (Roche limit)").changed() {
+        actions.push(Action::ToggleTidalDisruption);
+      }
+      let mut soft_bodies = self.world.soft_bodies.is_some();
+      if ui.checkbox(&mut soft_bodies, "Soft bodies (experimental)").on_hover_text("Bodies that hit each other break into particles held together by springs").changed() {
+        actions.push(Action::SetSoftBodies(soft_bodies.then(SoftBodyConfig::default)));
+      }
+      if let Some(config) = self.world.soft_bodies {
+        let mut new_config = config;
+        ui.add(egui::Slider::new(&mut new_config.particles_per_body, 4..=200).text("Particles per body"));
+        ui.add(egui::Slider::new(&mut new_config.stiffness, 10.0..=2000.0).logarithmic(true).text("Stiffness"));
+        ui.add(egui::Slider::new(&mut new_config.break_stretch, 1.05..=3.0).text("Bonds snap at stretch"));
+        if new_config != config {
+          actions.push(Action::SetSoftBodies(Some(new_config)));
+        }
+      }
+
+      ui.horizontal(|ui| {
+        let threshold = self.world.test_particle_mass;
+        let mut test_particles = threshold.is_some();
+        if ui.checkbox(&mut test_particles, "Test particles below").on_hover_text("Lighter bodies are pulled by heavier ones but pull nothing and pass through each other").changed() {
+          actions.push(Action::SetTestParticleMass(test_particles.then(|| DEFAULT_TEST_PARTICLE_MASS)));
+        }
+        if let Some(mut threshold) = threshold {
+          if ui.add(egui::DragValue::new(&mut threshold).speed(threshold * 0.01).clamp_range(1.0..=1.0e9).suffix(" mass")).changed() {
+            actions.push(Action::SetTestParticleMass(Some(threshold)));
+          }
+        }
+      });
+      ui.horizontal(|ui| {
+        let radius = self.world.config.despawn_radius;
+        let mut despawn = radius.is_some();
+        if ui.checkbox(&mut despawn, "Despawn beyond").changed() {
+          actions.push(Action::SetDespawnRadius(despawn.then(|| DEFAULT_DESPAWN_RADIUS)));
+        }
+        if let Some(mut radius) = radius {
+          if ui.add(egui::DragValue::new(&mut radius).speed(radius * 0.01).clamp_range(100.0..=1.0e7).suffix(" px")).changed() {
+            actions.push(Action::SetDespawnRadius(Some(radius)));
+          }
+        }
+      });
+      ui.horizontal(|ui| {
+        let speed = self.world.config.despawn_speed;
+        let mut despawn = speed.is_some();
+        if ui.checkbox(&mut despawn, "Despawn unbound bodies faster than").changed() {
+          actions.push(Action::SetDespawnSpeed(despawn.then(|| DEFAULT_DESPAWN_SPEED)));
+        }
+        if let Some(mut speed) = speed {
+          if ui.add(egui::DragValue::new(&mut speed).speed(speed * 0.01).clamp_range(1.0..=1.0e6).suffix(" px/s")).changed() {
+            actions.push(Action::SetDespawnSpeed(Some(speed)));
+          }
+        }
+      });
+
+      ui.horizontal(|ui| {
+        let mut muted = self.audio.is_muted();
+        if ui.checkbox(&mut muted, "Mute").changed() {
+          actions.push(Action::ToggleMute);
+        }
+        let mut volume = self.audio.volume();
+        if ui.add(egui::Slider::new(&mut volume, 0.0..=1.0).text("Volume")).changed() {
+          actions.push(Action::SetVolume(volume));
+        }
+      });
+      ui.horizontal(|ui| {
+        let mut effects = self.impact_effects;
+        ui.label("Big impacts:");
+        ui.checkbox(&mut effects.shake, "Shake");
+        ui.checkbox(&mut effects.shockwaves, "Shockwave");
+        ui.checkbox(&mut effects.flash, "Flash");
+        if effects != self.impact_effects {
+          actions.push(Action::SetImpactEffects(effects));
+        }
+      });
+
+      let mut vectors = self.show_vector_debug;
+      if ui.checkbox(&mut vectors, "Velocity arrows").changed() {
+        actions.push(Action::ToggleVectorDebug);
+      }
+      if vectors {
+        let mut show_force = self.vector_debug.show_force;
+        if ui.checkbox(&mut show_force, "Net force arrows").changed() {
+          actions.push(Action::ToggleForceVectors);
+        }
+        let mut scale = self.vector_debug.scale;
+        if ui.add(egui::Slider::new(&mut scale, VECTOR_SCALE_RANGE.0..=VECTOR_SCALE_RANGE.1).logarithmic(true).text("Arrow scale")).changed() {
+          actions.push(Action::SetVectorScale(scale));
+        }
+      }
+
+      ui.horizontal(|ui| {
+        ui.label(format!("Tracked samples: {}", self.trajectories.len()));
+        if ui.button("Export CSV").clicked() {
+          actions.push(Action::ExportTrajectories);
+        }
+        if ui.button("Clear").clicked() {
+          actions.push(Action::ClearTrajectories);
+        }
+      });
+    });
+
+    egui::Window::new("Scenarios").default_pos([10.0, 720.0]).default_open(false).show(ctx, |ui| {
+      ui.horizontal(|ui| {
+        if ui.button("Restart").on_hover_text("Rebuild the current scenario").clicked() {
+          actions.push(Action::Restart);
+        }
+        if ui.button("Reset to start").on_hover_text("Load the scenario the app started with").clicked() {
+          actions.push(Action::ResetToStart);
+        }
+        if ui.button("Clear").on_hover_text("Remove every body, leaving trails to fade").clicked() {
+          actions.push(Action::Clear);
+        }
+      });
+      if let Some(path) = &self.last_loaded {
+        if ui.button(format!("Reload {}", path)).clicked() {
+          actions.push(Action::ReloadFile);
+        }
+      }
+      ui.separator();
+      for (i, scenario) in self.scenarios.iter().enumerate() {
+        if ui.button(scenario.name()).clicked() {
+          actions.push(Action::LoadScenario(i));
+        }
+      }
+    });
+
+    if let Some(planet) = self.selected_planet.and_then(|id| self.world.planets.get(&id)) {
+      let planet = planet.borrow();
+      let mut edit = PlanetEdit {
+        id: planet.id,
+        kind: planet.kind,
+        mass: planet.mass,
+        radius: planet.radius,
+        density: planet.density(),
+        velocity: planet.velocity,
+        angular_velocity: planet.angular_velocity,
+        is_static: planet.is_static,
+        ignores_gravity: planet.ignores_gravity,
+        exerts_no_gravity: planet.exerts_no_gravity,
+        tracked: planet.tracked,
+        color: planet.color,
+      };
+
+      egui::Window::new("Selected body").default_pos([1010.0, 300.0]).show(ctx, |ui| {
+        egui::ComboBox::from_label("Kind")
+          .selected_text(edit.kind.to_string())
+          .show_ui(ui, |ui| {
+            for k in BODY_KINDS {
+              ui.selectable_value(&mut edit.kind, k, k.to_string());
+            }
+          });
+        ui.add(egui::DragValue::new(&mut edit.mass).speed(edit.mass * 0.01).prefix("Mass: "))
+          .on_hover_text("Keeps the density, so the radius changes too");
+        ui.add(egui::DragValue::new(&mut edit.radius).speed(0.1).clamp_range(0.1..=500.0).prefix("Radius: "))
+          .on_hover_text("Keeps the density, so the mass changes too");
+        ui.add(egui::DragValue::new(&mut edit.density).speed(edit.density * 0.01).clamp_range(1.0..=1.0e7).prefix("Density: "))
+          .on_hover_text("Keeps the mass, so the radius changes instead");
+        ui.horizontal(|ui| {
+          ui.label("Velocity:");
+          ui.add(egui::DragValue::new(&mut edit.velocity.x).speed(0.5));
+          ui.add(egui::DragValue::new(&mut edit.velocity.y).speed(0.5));
+        });
+        ui.add(egui::DragValue::new(&mut edit.angular_velocity).speed(0.05).prefix("Spin: "));
+        ui.horizontal(|ui| {
+          ui.label("Colour:");
+          ui.color_edit_button_rgba_unmultiplied(&mut edit.color);
+        });
+        ui.checkbox(&mut edit.is_static, "Static (never moves)");
+        ui.checkbox(&mut edit.ignores_gravity, "Ignores gravity");
+        ui.checkbox(&mut edit.exerts_no_gravity, "Exerts no gravity");
+        ui.checkbox(&mut edit.tracked, "Track trajectory");
+        let mut is_frame = self.trail_frame == Some(edit.id);
+        if ui.checkbox(&mut is_frame, "Trails relative to this body").changed() {
+          // Switching straight from another body's frame goes through world space first
+          if self.trail_frame.is_some() && is_frame {
+            actions.push(Action::ToggleTrailFrame);
+          }
+          actions.push(Action::ToggleTrailFrame);
+        }
+        if let Some(trail) = self.planet_trails.get(&edit.id) {
+          let trail_config = trail.borrow().config;
+          let mut custom = trail_config.is_some();
+          ui.checkbox(&mut custom, "Custom trail");
+          let mut new_config = if custom { Some(trail_config.unwrap_or(self.world.config.trails)) } else { None };
+          if let Some(config) = new_config.as_mut() {
+            trail_config_ui(ui, config);
+          }
+          if new_config != trail_config {
+            actions.push(Action::SetTrailConfig { id: edit.id, config: new_config });
+          }
+        }
+
+        ui.separator();
+        if self.stability_job.as_ref().map_or(false, |(id, _, _)| *id == edit.id) {
+          ui.label("Checking stability...");
+        } else if ui.button("Check stability").on_hover_text("Simulates a copy of the world for 10 orbits").clicked() {
+          actions.push(Action::CheckStability);
+        }
+        if let Some(report) = self.stability_report.filter(|report| report.id == edit.id) {
+          ui.label(report.verdict.to_string());
+          if let Some(primary) = report.primary {
+            if report.max_distance > 0.0 {
+              ui.label(format!("Distance from body {}: {:.1} to {:.1}", primary, report.min_distance, report.max_distance));
+            }
+          }
+          ui.label(format!("Simulated {}", self.world.config.describe_duration(report.simulated)));
+        }
+
+        if ui.button("Delete").clicked() {
+          actions.push(Action::DeleteSelected);
+        }
+      });
+
+      let changed = edit.kind != planet.kind || edit.mass != planet.mass || edit.radius != planet.radius ||
+        edit.velocity != planet.velocity || edit.angular_velocity != planet.angular_velocity ||
+        edit.is_static != planet.is_static || edit.ignores_gravity != planet.ignores_gravity ||
+        edit.exerts_no_gravity != planet.exerts_no_gravity || edit.tracked != planet.tracked;
+      if changed && edit.mass > 0.0 {
+        actions.push(Action::EditPlanet(edit));
+      }
+    }
+
+    egui::Window::new("Events").default_pos([1010.0, 560.0]).default_open(false).show(ctx, |ui| {
+      ui.horizontal(|ui| {
+        if ui.button("Save to file").clicked() {
+          actions.push(Action::SaveEventLog);
+        }
+        if ui.button("Clear").clicked() {
+          actions.push(Action::ClearEventLog);
+        }
+      });
+      // Only the visible rows are laid out, as the log can be long
+      let row_height = ui.text_style_height(&egui::TextStyle::Body);
+      egui::ScrollArea::vertical().max_height(200.0).stick_to_bottom(true).show_rows(ui, row_height, self.event_log.len(), |ui, rows| {
+        for event in &self.event_log[rows] {
+          ui.label(event.to_string());
+        }
+      });
+    });
+
+    if !self.group.is_empty() {
+      egui::Window::new("Selected group").default_pos([1010.0, 300.0]).show(ctx, |ui| {
+        let total_mass: f32 = self.group.iter()
+          .filter_map(|id| self.world.planets.get(id))
+          .map(|planet| planet.borrow().mass)
+          .sum();
+        ui.label(format!("{} bodies, total mass {:.1}", self.group.len(), total_mass));
+        // Dragging these adds the change to every body's velocity
+        let mut offset = Vector2::new(0.0, 0.0);
+        ui.horizontal(|ui| {
+          ui.label("Add velocity:");
+          ui.add(egui::DragValue::new(&mut offset.x).speed(0.5));
+          ui.add(egui::DragValue::new(&mut offset.y).speed(0.5));
+        });
+        if offset != Vector2::new(0.0, 0.0) {
+          actions.push(Action::OffsetGroupVelocity { x: offset.x, y: offset.y });
+        }
+        ui.horizontal(|ui| {
+          ui.label("Mass:");
+          if ui.button("Lighter").clicked() {
+            actions.push(Action::ScaleGroupMass(1.0/MASS_SCALE_STEP));
+          }
+          if ui.button("Heavier").clicked() {
+            actions.push(Action::ScaleGroupMass(MASS_SCALE_STEP));
+          }
+        });
+        ui.horizontal(|ui| {
+          if ui.button("Merge").clicked() {
+            actions.push(Action::MergeGroup);
+          }
+          if ui.button("Delete").clicked() {
+            actions.push(Action::DeleteSelected);
+          }
+        });
+      });
+    }
+
+    actions
+  }
+}
+
+fn trail_config_ui(ui: &mut egui::Ui, config: &mut TrailConfig) {
+  let mut planet_color = config.color.is_none();
+  ui.checkbox(&mut planet_color, "Planet colour");
+  if planet_color {
+    config.color = None;
+  } else {
+    let color = config.color.get_or_insert(DEFAULT_TRAIL_COLOR);
+    ui.color_edit_button_rgba_unmultiplied(color);
+  }
+  ui.add(egui::Slider::new(&mut config.lifetime, 0.1..=10.0).logarithmic(true).text("Lifetime (s)"));
+  ui.add(egui::Slider::new(&mut config.emission_rate, 1.0..=120.0).text("Nodes/s"));
+}
@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::simulation::World;
+
+pub struct HeadlessConfig {
+  pub steps: usize,
+  pub dt: f32,
+  pub output: PathBuf,
+  pub record_every: usize,    // Write body states every n steps
+}
+
+impl Default for HeadlessConfig {
+  fn default() -> Self {
+    Self {
+      steps: 10_000,
+      dt: 1.0/120.0,
+      output: PathBuf::from("./orbits_output.csv"),
+      record_every: 1,
+    }
+  }
+}
+
+// Steps the world without rendering, streaming every body's state to a CSV file
+pub fn run(world: &mut World, config: &HeadlessConfig) -> io::Result<()> {
+  let mut out = BufWriter::new(File::create(&config.output)?);
+  writeln!(out, "t,id,x,y,vx,vy,mass")?;
+
+  let dt_duration = Duration::from_secs_f32(config.dt);
+  let record_every = config.record_every.max(1);
+
+  for step in 0..=config.steps {
+    if step % record_every == 0 {
+      write_states(&mut out, world, step as f32 * config.dt)?;
+    }
+    if step < config.steps {
+      world.step(&dt_duration);
+    }
+  }
+
+  out.flush()
+}
+
+fn write_states<W: Write>(out: &mut W, world: &World, t: f32) -> io::Result<()> {
+  let mut ids: Vec<&usize> = world.planets.keys().collect();
+  ids.sort_unstable();
+
+  for id in ids {
+    let pl = world.planets[id].borrow();
+    writeln!(
+      out,
+      "{},{},{},{},{},{},{}",
+      t, pl.id, pl.position.x, pl.position.y, pl.velocity.x, pl.velocity.y, pl.mass
+    )?;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,75 @@
+use ggez::graphics::{Canvas, DrawParam, Image, ImageFormat, Rect};
+use ggez::Context;
+
+use nalgebra::Point2;
+
+use orbits::simulation::World;
+
+use crate::render;
+
+const HEATMAP_COLUMNS: u32 = 96;
+const HEATMAP_ROWS: u32 = 54;
+const HEATMAP_REFRESH: f32 = 0.25;   // Seconds between recomputing the field
+const HEATMAP_ALPHA: f32 = 0.5;
+
+// Gravitational potential sampled on a coarse grid over the view, drawn behind the bodies.
+// Deeper wells are brighter, on a log scale so wells of very different depths all show up.
+pub struct Heatmap {
+  image: Option<Image>,
+  view: Rect,       // Area the image was sampled over
+  age: f32,         // Seconds since the image was sampled
+}
+
+impl Heatmap {
+  pub fn new() -> Self {
+    Self {
+      image: None,
+      view: Rect::default(),
+      age: 0.0,
+    }
+  }
+
+  // Only resamples every HEATMAP_REFRESH seconds. The image stays where it was sampled in the world,
+  // so if the view moves in between, the edges are briefly uncovered rather than the field being wrong.
+  pub fn update(&mut self, ctx: &Context, world: &World, view: Rect, dt: f32) {
+    self.age += dt;
+    if world.planets.is_empty() {
+      self.image = None;
+      return
+    }
+    if self.image.is_some() && self.age < HEATMAP_REFRESH { return }
+
+    let (cell_width, cell_height) = (view.w/HEATMAP_COLUMNS as f32, view.h/HEATMAP_ROWS as f32);
+    let points: Vec<Point2<f32>> = (0..HEATMAP_ROWS)
+      .flat_map(|row| (0..HEATMAP_COLUMNS).map(move |column| (column, row)))
+      .map(|(column, row)| Point2::new(
+        view.x + (column as f32 + 0.5) * cell_width,
+        view.y + (row as f32 + 0.5) * cell_height,
+      ))
+      .collect();
+
+    let depths: Vec<f32> = world.potential_at(&points).into_iter().map(|p| (-p).max(f32::MIN_POSITIVE).ln()).collect();
+    let (min, max) = depths.iter().fold((f32::MAX, f32::MIN), |(min, max), d| (min.min(*d), max.max(*d)));
+    let range = (max - min).max(f32::EPSILON);
+
+    let pixels: Vec<u8> = depths.iter()
+      .flat_map(|depth| {
+        let [r, g, b, _] = render::heat_color((depth - min)/range);
+        [r, g, b, HEATMAP_ALPHA].map(|c| (c * 255.0) as u8)
+      })
+      .collect();
+
+    self.image = Some(Image::from_pixels(ctx, &pixels, ImageFormat::Rgba8UnormSrgb, HEATMAP_COLUMNS, HEATMAP_ROWS));
+    self.view = view;
+    self.age = 0.0;
+  }
+
+  // In world coordinates
+  pub fn draw(&self, canvas: &mut Canvas) {
+    if let Some(image) = &self.image {
+      canvas.draw(image, DrawParam::default()
+        .dest([self.view.x, self.view.y])
+        .scale([self.view.w/HEATMAP_COLUMNS as f32, self.view.h/HEATMAP_ROWS as f32]));
+    }
+  }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::cell::RefCell;
+
+use orbits::planet::Planet;
+use orbits::simulation::World;
+
+use crate::trail::PlanetTrail;
+
+const MAX_HISTORY: usize = 100;
+
+type Planets = HashMap<usize, RefCell<Planet>>;
+type Trails = HashMap<usize, RefCell<PlanetTrail>>;
+
+// An undoable change to the world made by the user
+pub enum Command {
+  Spawn(Planet),                                    // Planet as it was when spawned
+  SpawnMany(Vec<Planet>),                           // Brush strokes and rings
+  Delete { planet: Planet, trail: Option<PlanetTrail> },
+  ReplacePlanets { before: Planets, after: Planets },   // Scenario load, restart or clear
+}
+
+impl Command {
+  fn undo(&self, world: &mut World, trails: &mut Trails) {
+    match self {
+      Command::Spawn(planet) => { world.planets.remove(&planet.id); },
+      Command::SpawnMany(planets) => for planet in planets.iter() {
+        world.planets.remove(&planet.id);
+      },
+      Command::Delete { planet, trail } => {
+        world.restore_planet(planet.clone());
+        if let Some(trail) = trail {
+          trails.entry(planet.id).or_insert_with(|| RefCell::new(trail.clone()));
+        }
+      },
+      Command::ReplacePlanets { before, .. } => world.planets = before.clone(),
+    }
+  }
+
+  fn redo(&self, world: &mut World) {
+    match self {
+      Command::Spawn(planet) => world.restore_planet(planet.clone()),
+      Command::SpawnMany(planets) => for planet in planets.iter() {
+        world.restore_planet(planet.clone());
+      },
+      Command::Delete { planet, .. } => { world.planets.remove(&planet.id); },
+      Command::ReplacePlanets { after, .. } => world.planets = after.clone(),
+    }
+  }
+}
+
+#[derive(Default)]
+pub struct History {
+  undo_stack: Vec<Command>,
+  redo_stack: Vec<Command>,
+}
+
+impl History {
+  pub fn push(&mut self, command: Command) {
+    self.redo_stack.clear();
+    self.undo_stack.push(command);
+    if self.undo_stack.len() > MAX_HISTORY {
+      self.undo_stack.remove(0);
+    }
+  }
+
+  // Returns false if there was nothing to undo
+  pub fn undo(&mut self, world: &mut World, trails: &mut Trails) -> bool {
+    if let Some(command) = self.undo_stack.pop() {
+      command.undo(world, trails);
+      self.redo_stack.push(command);
+      true
+    } else {
+      false
+    }
+  }
+
+  pub fn redo(&mut self, world: &mut World) -> bool {
+    if let Some(command) = self.redo_stack.pop() {
+      command.redo(world);
+      self.undo_stack.push(command);
+      true
+    } else {
+      false
+    }
+  }
+}
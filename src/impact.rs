@@ -0,0 +1,136 @@
+use ggez::graphics::{self, Canvas, DrawMode, DrawParam, Mesh, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+
+use nalgebra::{Vector2, Point2};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use std::f32::consts::PI;
+
+use orbits::simulation::{Impact, DEFAULT_FRAGMENTATION_THRESHOLD};
+
+use crate::SCREEN_DIMS;
+
+const MIN_STRENGTH: f32 = 0.5;          // Weaker impacts (see strength) get no feedback
+const MAX_SHAKE: f32 = 12.0;            // Screen pixels, for the strongest impacts
+const SHAKE_DECAY: f32 = 8.0;           // Per second, exponential
+const SHOCKWAVE_LIFETIME: f32 = 0.7;    // Seconds
+const SHOCKWAVE_RADII: f32 = 12.0;      // Final size, in radii of the merged body, for the strongest impacts
+const SHOCKWAVE_MIN_RADIUS: f32 = 20.0;
+const SHOCKWAVE_WIDTH: f32 = 2.0;
+const SHOCKWAVE_COLOR: [f32; 3] = [1.0, 0.85, 0.6];
+const MAX_FLASH: f32 = 0.35;            // Alpha of the white flash, for the strongest impacts
+const FLASH_DECAY: f32 = 6.0;           // Per second, exponential
+
+// Which kinds of feedback big impacts give. Kept in the settings file.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImpactEffects {
+  pub shake: bool,
+  pub shockwaves: bool,
+  pub flash: bool,
+}
+
+impl Default for ImpactEffects {
+  fn default() -> Self {
+    Self {
+      shake: true,
+      shockwaves: true,
+      flash: true,
+    }
+  }
+}
+
+// How hard an impact was, from 0 to 1 on a log scale of its energy, reaching 1 at the fragmentation threshold
+fn strength(impact: &Impact) -> f32 {
+  ((1.0 + impact.energy).ln()/(1.0 + DEFAULT_FRAGMENTATION_THRESHOLD).ln()).min(1.0)
+}
+
+struct Shockwave {
+  position: Point2<f32>,
+  velocity: Vector2<f32>,   // Moves with the merged body, like bursts
+  max_radius: f32,
+  strength: f32,
+  age: f32,
+}
+
+// Screen shake, expanding rings and a white flash from the hardest impacts, each scaled by the impact's strength
+#[derive(Default)]
+pub struct ImpactFeedback {
+  shake: f32,   // Current amplitude in screen pixels
+  flash: f32,   // Current alpha
+  shockwaves: Vec<Shockwave>,
+}
+
+impl ImpactFeedback {
+  pub fn add(&mut self, impact: &Impact, effects: &ImpactEffects) {
+    let strength = strength(impact);
+    if strength < MIN_STRENGTH { return }
+    // Rescaled so the weakest impacts that get feedback start from nothing
+    let t = (strength - MIN_STRENGTH)/(1.0 - MIN_STRENGTH);
+
+    if effects.shake {
+      self.shake = self.shake.max(MAX_SHAKE * t);
+    }
+    if effects.flash {
+      self.flash = self.flash.max(MAX_FLASH * t);
+    }
+    if effects.shockwaves {
+      let radius = impact.merge.as_ref().map_or(0.0, |merge| merge.bodies.iter().map(|body| body.radius).fold(0.0, f32::max));
+      self.shockwaves.push(Shockwave {
+        position: impact.position,
+        velocity: impact.velocity,
+        max_radius: (radius * SHOCKWAVE_RADII * t).max(SHOCKWAVE_MIN_RADIUS),
+        strength: t,
+        age: 0.0,
+      });
+    }
+  }
+
+  // Shake and flash fade in real time, so they stop while paused. Shockwaves go with the simulation, like bursts.
+  pub fn update(&mut self, real_dt: f32, sim_dt: f32) {
+    self.shake *= (-SHAKE_DECAY * real_dt).exp();
+    self.flash *= (-FLASH_DECAY * real_dt).exp();
+    for shockwave in self.shockwaves.iter_mut() {
+      shockwave.age += sim_dt;
+      shockwave.position += shockwave.velocity * sim_dt;
+    }
+    self.shockwaves.retain(|shockwave| shockwave.age < SHOCKWAVE_LIFETIME);
+  }
+
+  pub fn clear(&mut self) {
+    *self = Self::default();
+  }
+
+  // How far to jolt the view this frame, in screen pixels, in a random direction
+  pub fn shake_offset(&self) -> Vector2<f32> {
+    if self.shake < 0.1 { return Vector2::new(0.0, 0.0) }
+    let angle = rand::thread_rng().gen_range(-PI..PI);
+    Vector2::new(angle.cos(), angle.sin()) * self.shake
+  }
+
+  // In world coordinates. Returns if anything was drawn.
+  pub fn draw_shockwaves(&self, mesh: &mut MeshBuilder) -> GameResult<bool> {
+    let [r, g, b] = SHOCKWAVE_COLOR;
+    for shockwave in self.shockwaves.iter() {
+      let t = shockwave.age/SHOCKWAVE_LIFETIME;
+      let radius = shockwave.max_radius * (1.0 - (1.0 - t).powi(2));   // Fast at first, slowing down
+      let alpha = (1.0 - t) * (0.4 + 0.6 * shockwave.strength);
+      mesh.circle(DrawMode::stroke(SHOCKWAVE_WIDTH), shockwave.position, radius.max(1.0), 0.5, [r, g, b, alpha].into())?;
+    }
+    Ok(!self.shockwaves.is_empty())
+  }
+
+  // Over the whole screen, in screen coordinates
+  pub fn draw_flash(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+    if self.flash < 0.01 { return Ok(()) }
+    let rect = Mesh::new_rectangle(
+      ctx,
+      DrawMode::fill(),
+      Rect::new(0.0, 0.0, SCREEN_DIMS.0, SCREEN_DIMS.1),
+      graphics::Color::new(1.0, 1.0, 1.0, self.flash),
+    )?;
+    canvas.draw(&rect, DrawParam::default());
+    Ok(())
+  }
+}
@@ -0,0 +1,177 @@
+use nalgebra::{Vector2, Point2};
+
+use std::fs;
+use std::path::Path;
+
+use crate::tools;
+use crate::simulation::World;
+use crate::scenarios::Scenario;
+use crate::planet::BodyKind;
+
+pub const CSV_EXTENSION: &str = "csv";
+
+// One row of an imported file
+#[derive(Clone, Debug)]
+pub struct ImportedBody {
+  pub position: Point2<f32>,    // From the centre of the area the scenario is built in
+  pub velocity: Vector2<f32>,
+  pub mass: Option<f32>,        // At least one of mass and radius is set
+  pub radius: Option<f32>,
+  pub name: Option<String>,
+  pub kind: BodyKind,
+}
+
+impl ImportedBody {
+  // Whichever of mass and radius is missing comes from the kind's density
+  fn radius(&self) -> f32 {
+    match (self.radius, self.mass) {
+      (Some(radius), _) => radius,
+      (None, Some(mass)) => tools::inverse_volume_of_sphere(mass/self.kind.density()),
+      (None, None) => 1.0,
+    }
+  }
+}
+
+// Bodies from CSV made elsewhere, e.g. by a script sampling a galaxy's profile. The first line names the columns,
+// in any order: x and y are needed, along with mass or radius (or both). vx, vy, name and kind (star, planet or
+// asteroid) are optional. Blank lines and lines starting with # are skipped. Fields can't contain commas.
+//
+//   x,y,vx,vy,mass,name
+//   0,0,0,0,5e7,Core
+//   120,0,0,2.1,1e3,
+pub fn parse_csv(text: &str) -> Result<Vec<ImportedBody>, String> {
+  let mut lines = text.lines()
+    .enumerate()
+    .map(|(i, line)| (i + 1, line.trim()))
+    .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+  let header: Vec<String> = match lines.next() {
+    Some((_, line)) => split(line).map(|column| column.to_lowercase()).collect(),
+    None => return Ok(Vec::new()),
+  };
+  let column = |name: &str| header.iter().position(|column| column == name);
+  let (x, y) = match (column("x"), column("y")) {
+    (Some(x), Some(y)) => (x, y),
+    _ => return Err("the first line must name the columns, including x and y".to_string()),
+  };
+  let (vx, vy, mass, radius, name, kind) = (column("vx"), column("vy"), column("mass"), column("radius"), column("name"), column("kind"));
+  if mass.is_none() && radius.is_none() {
+    return Err("needs a mass or radius column".to_string())
+  }
+
+  lines
+    .map(|(line_number, line)| {
+      let fields: Vec<&str> = split(line).collect();
+      let field = |index: Option<usize>| index.and_then(|i| fields.get(i).copied()).filter(|field| !field.is_empty());
+      let number = |index: Option<usize>, what: &str| -> Result<Option<f32>, String> {
+        match field(index) {
+          None => Ok(None),
+          Some(field) => match field.parse::<f32>() {
+            Ok(value) if value.is_finite() => Ok(Some(value)),
+            _ => Err(format!("line {}: {} \"{}\" isn't a number", line_number, what, field)),
+          },
+        }
+      };
+
+      let position = match (number(Some(x), "x")?, number(Some(y), "y")?) {
+        (Some(x), Some(y)) => Point2::new(x, y),
+        _ => return Err(format!("line {}: needs x and y", line_number)),
+      };
+      let velocity = Vector2::new(number(vx, "vx")?.unwrap_or(0.0), number(vy, "vy")?.unwrap_or(0.0));
+      let (mass, radius) = (number(mass, "mass")?, number(radius, "radius")?);
+      if mass.is_none() && radius.is_none() {
+        return Err(format!("line {}: needs a mass or radius", line_number))
+      }
+      if mass.map_or(false, |mass| mass <= 0.0) || radius.map_or(false, |radius| radius <= 0.0) {
+        return Err(format!("line {}: mass and radius must be above zero", line_number))
+      }
+      let kind = match field(kind).map(|kind| kind.to_lowercase()) {
+        None => BodyKind::Planet,
+        Some(kind) => match kind.as_str() {
+          "star" => BodyKind::Star,
+          "planet" => BodyKind::Planet,
+          "asteroid" => BodyKind::Asteroid,
+          _ => return Err(format!("line {}: kind must be star, planet or asteroid, not {}", line_number, kind)),
+        },
+      };
+
+      Ok(ImportedBody {
+        position,
+        velocity,
+        mass,
+        radius,
+        name: field(name).map(str::to_string),
+        kind,
+      })
+    })
+    .collect()
+}
+
+// Fields of a line, trimmed and without surrounding quotes
+fn split(line: &str) -> impl Iterator<Item = &str> {
+  line.split(',').map(|field| field.trim().trim_matches('"').trim())
+}
+
+// An imported file as a scenario, named after the file. Positions are measured from the centre of the area, so
+// files centred on the origin land in the middle of the screen.
+pub struct CsvBodies {
+  name: String,
+  bodies: Vec<ImportedBody>,
+}
+
+impl CsvBodies {
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let bodies = parse_csv(&text)?;
+    let name = path.file_stem()
+      .map(|stem| stem.to_string_lossy().replace('_', " "))
+      .unwrap_or_else(|| "Imported bodies".to_string());
+    Ok(Self { name, bodies })
+  }
+
+  pub fn len(&self) -> usize {
+    self.bodies.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.bodies.is_empty()
+  }
+}
+
+impl Scenario for CsvBodies {
+  fn name(&self) -> &str { &self.name }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    let centre = Vector2::new(bounds.0/2.0, bounds.1/2.0);
+    for body in self.bodies.iter() {
+      let id = world.add_body(body.kind, body.position + centre, Some(body.velocity), body.mass, body.radius(), None);
+      if let Some(name) = &body.name {
+        world.planets[&id].borrow_mut().name = Some(name.clone());
+      }
+    }
+  }
+}
+
+// Every CSV file in a directory, sorted by file name. Files that can't be read are skipped with a warning.
+pub fn load_dir(dir: &Path) -> Vec<CsvBodies> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return Vec::new(),    // No files
+  };
+
+  let mut paths: Vec<_> = entries
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| path.extension().map_or(false, |ext| ext == CSV_EXTENSION))
+    .collect();
+  paths.sort();
+
+  paths.iter()
+    .filter_map(|path| match CsvBodies::load(path) {
+      Ok(bodies) => Some(bodies),
+      Err(e) => {
+        println!("WARNING: Could not import bodies from {}: {}", path.display(), e);
+        None
+      },
+    })
+    .collect()
+}
@@ -0,0 +1,321 @@
+use ggez::input::{mouse::MouseButton, keyboard::{KeyCode, KeyMods}};
+use ggez::event::TouchPhase;
+
+use serde::{Serialize, Deserialize};
+
+use orbits::planet::BodyKind;
+use orbits::integrator::Integrator;
+use orbits::simulation::CollisionMode;
+use orbits::config::{TrailConfig, MergeGeometry, PlanetColoring};
+use orbits::soft_body::SoftBodyConfig;
+
+use crate::gui::PlanetEdit;
+use crate::brush::Tool;
+use crate::trail::TrailColoring;
+use crate::impact::ImpactEffects;
+
+// Keys listed in the help overlay. Keys that do the same as one of these (e.g. the numpad's +) are left out.
+const HELP_KEYS: &[KeyCode] = &[
+  KeyCode::Space, KeyCode::R, KeyCode::C, KeyCode::Tab,
+  KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5, KeyCode::Key6, KeyCode::Key7,
+  KeyCode::Key8, KeyCode::Key9, KeyCode::Key0,
+  KeyCode::B, KeyCode::X, KeyCode::K, KeyCode::S, KeyCode::LBracket, KeyCode::RBracket,
+  KeyCode::Z, KeyCode::Y, KeyCode::Delete, KeyCode::Escape, KeyCode::Return,
+  KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right,
+  KeyCode::Equals, KeyCode::Minus, KeyCode::G, KeyCode::O, KeyCode::N, KeyCode::M, KeyCode::F, KeyCode::W, KeyCode::V,
+  KeyCode::L, KeyCode::Q, KeyCode::T, KeyCode::J, KeyCode::P, KeyCode::A, KeyCode::I, KeyCode::D,
+  KeyCode::Period, KeyCode::Comma, KeyCode::H, KeyCode::U, KeyCode::E,
+  KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F9,
+];
+
+// How key combinations are named in the settings file's key_bindings, e.g. "P", "Shift+P" or "Ctrl+Shift+Key1"
+pub fn binding_name(keycode: KeyCode, mods: KeyMods) -> String {
+  let mut name = String::new();
+  for (modifier, prefix) in [(KeyMods::CTRL, "Ctrl+"), (KeyMods::ALT, "Alt+"), (KeyMods::SHIFT, "Shift+")] {
+    if mods.contains(modifier) {
+      name += prefix;
+    }
+  }
+  name + &format!("{:?}", keycode)
+}
+
+// The built-in key combinations and what they do, named as in binding_name. In edit mode, only the combinations
+// that do something different there.
+pub fn built_in_bindings(editing: bool) -> Vec<(String, Action)> {
+  let mut bindings = Vec::new();
+  for &keycode in HELP_KEYS {
+    let mut seen = Vec::new();
+    for mods in [KeyMods::empty(), KeyMods::SHIFT, KeyMods::CTRL, KeyMods::CTRL | KeyMods::SHIFT] {
+      let action = match Action::from_key(keycode, mods, editing) {
+        Some(action) => action,
+        None => continue,
+      };
+      // Modifiers that don't change anything
+      if seen.contains(&action) { continue }
+      seen.push(action);
+      if editing && Action::from_key(keycode, mods, false) == Some(action) { continue }
+      bindings.push((binding_name(keycode, mods), action));
+    }
+  }
+  bindings
+}
+
+// Everything the user can do with the keyboard or GUI. Inputs are mapped to actions so they can be recorded and replayed.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Action {
+  ToggleDiagnostics,
+  ToggleVectorDebug,
+  ToggleForceVectors,
+  ChangeVectorScale { larger: bool },
+  SetVectorScale(f32),
+  ToggleInfoDebug,
+  ToggleInfluenceOverlay,
+  ToggleHeatmap,
+  ToggleWarpGrid,
+  ToggleFieldArrows,
+  ToggleMinimap,
+  ToggleMute,
+  SetVolume(f32),
+  SetImpactEffects(ImpactEffects),
+  ToggleLabels,
+  CycleColorMode,
+  Restart,
+  ResetToStart,   // The scenario the app started with
+  ReverseTime,
+  ReloadFile,     // The save file last loaded
+  Clear,
+  Undo,
+  Redo,
+  DeleteSelected,
+  CheckStability,   // Simulate the selected body's future in the background, see orbits::stability
+  Deselect,
+  CycleIntegrator,
+  ToggleAdaptiveTimestep,
+  CycleCollisionMode,
+  CycleMergeGeometry,
+  SetMergeGeometry(MergeGeometry),
+  ToggleFragmentation,
+  ToggleTidalDisruption,
+  SetSoftBodies(Option<SoftBodyConfig>),
+  ToggleMassTransfer,
+  ChangeG { stronger: bool },
+  ChangeSoftening { larger: bool },
+  ToggleScenarioMenu,
+  LoadScenario(usize),
+  Save,
+  Load,
+  TogglePause,
+  CycleSpawnKind,
+  ToggleFocus { barycentre: bool },
+  CycleTrailStyle { selected_only: bool },
+  CycleTrailColoring,
+  ToggleTrailFrame,
+  ToggleLaunchSnap,
+  SetTrailColoring(TrailColoring),
+  ChangeSpawnSize { bigger: bool, change_density: bool },
+  ChangeTimeScale { faster: bool },
+  ToggleGui,
+  ToggleHelp,
+  Ignore,     // Does nothing, for unbinding a built-in key in the settings file
+  ToggleEraser,
+  #[serde(alias = "CycleSpawnTool")]
+  CycleTool,
+  ToggleDriftCancelling,
+  ToggleRecentring,
+  ToggleWrap,
+  ToggleTrackpadMode,
+  ToggleExtrapolation,
+  ToggleEditMode,
+  ExportTrajectories,
+  ClearTrajectories,
+  SaveEventLog,
+  ClearEventLog,
+
+  // Edit mode, on the selected planet
+  Nudge { x: f32, y: f32, velocity: bool },   // Direction to move, or to push if velocity is true
+  ScaleMass { bigger: bool },
+
+  // On the box selected group of planets
+  MergeGroup,
+  OffsetGroupVelocity { x: f32, y: f32 },
+  ScaleGroupMass(f32),
+
+  // From the GUI
+  SetSpawnKind(BodyKind),
+  SelectTemplate(Option<usize>),   // Index into the spawn templates, None to go back to the spawn settings
+  SetSpawnRadius(f32),
+  SetSpawnDensity(f32),
+  SetLaunchScale(f32),
+  #[serde(alias = "SetSpawnTool")]
+  SetTool(Tool),
+  SetStreamRate(f32),
+  SetRingCount(usize),
+  SetBinaryMassRatio(f32),    // Lighter over heavier mass
+  RestoreSnapshot,    // From the crash recovery prompt
+  DismissRecovery,
+  SetTimeScale(f32),
+  SetPlanetColoring(PlanetColoring),
+  SetPaletteColor { index: usize, color: [f32; 3] },
+  SetPhysicsRate(f32),    // Steps per second of sim time
+  SetG(f32),
+  SetSoftening(f32),
+  SetTheta(f32),
+  SetIntegrator(Integrator),
+  SetCollisionMode(CollisionMode),
+  SetFragmentationThreshold(Option<f32>),
+  SetMassTransfer(Option<f32>),
+  SetTestParticleMass(Option<f32>),
+  SetDespawnRadius(Option<f32>),
+  SetDespawnSpeed(Option<f32>),
+  SetWrapBounds((f32, f32)),
+  EditPlanet(PlanetEdit),
+  SetTrailConfig { id: usize, config: Option<TrailConfig> },   // None goes back to the default
+  SetDefaultTrailConfig(TrailConfig),
+}
+
+impl Action {
+  // editing is true in edit mode, where arrow keys and +/- change the selected planet
+  pub fn from_key(keycode: KeyCode, mods: KeyMods, editing: bool) -> Option<Self> {
+    let shift = mods.contains(KeyMods::SHIFT);
+    let ctrl = mods.contains(KeyMods::CTRL);
+
+    Some(match keycode {
+      KeyCode::Up if editing => Action::Nudge { x: 0.0, y: -1.0, velocity: shift },
+      KeyCode::Down if editing => Action::Nudge { x: 0.0, y: 1.0, velocity: shift },
+      KeyCode::Left if editing => Action::Nudge { x: -1.0, y: 0.0, velocity: shift },
+      KeyCode::Right if editing => Action::Nudge { x: 1.0, y: 0.0, velocity: shift },
+      KeyCode::Equals | KeyCode::Plus | KeyCode::NumpadAdd if editing => Action::ScaleMass { bigger: true },
+      KeyCode::Minus | KeyCode::NumpadSubtract if editing => Action::ScaleMass { bigger: false },
+      KeyCode::Return => Action::ToggleEditMode,
+      KeyCode::E => Action::ToggleDiagnostics,
+      KeyCode::D => if shift { Action::ToggleForceVectors } else { Action::ToggleVectorDebug },
+      KeyCode::Period => Action::ChangeVectorScale { larger: true },
+      KeyCode::Comma => Action::ChangeVectorScale { larger: false },
+      KeyCode::I => Action::ToggleInfoDebug,
+      KeyCode::H => Action::ToggleInfluenceOverlay,
+      KeyCode::U if ctrl => Action::ToggleFieldArrows,
+      KeyCode::U => if shift { Action::ToggleWarpGrid } else { Action::ToggleHeatmap },
+      KeyCode::A => Action::ToggleLabels,
+      KeyCode::P => Action::CycleColorMode,
+      KeyCode::R if ctrl => Action::ReverseTime,
+      KeyCode::R => if shift { Action::ResetToStart } else { Action::Restart },
+      KeyCode::C => Action::Clear,
+      KeyCode::Z if ctrl => if shift { Action::Redo } else { Action::Undo },
+      KeyCode::Y if ctrl => Action::Redo,
+      KeyCode::Delete | KeyCode::Back => Action::DeleteSelected,
+      KeyCode::Escape => Action::Deselect,
+      KeyCode::N => if shift { Action::ToggleAdaptiveTimestep } else { Action::CycleIntegrator },
+      KeyCode::M if ctrl => Action::MergeGroup,
+      KeyCode::M => if shift { Action::CycleMergeGeometry } else { Action::CycleCollisionMode },
+      KeyCode::F if ctrl => Action::ToggleMassTransfer,
+      KeyCode::F => if shift { Action::ToggleTidalDisruption } else { Action::ToggleFragmentation },
+      KeyCode::G => Action::ChangeG { stronger: !shift },
+      KeyCode::O => Action::ChangeSoftening { larger: !shift },
+      KeyCode::Tab => Action::ToggleScenarioMenu,
+      KeyCode::Key0 if ctrl => Action::SelectTemplate(None),
+      KeyCode::Key1 if ctrl => Action::SelectTemplate(Some(0)),
+      KeyCode::Key2 if ctrl => Action::SelectTemplate(Some(1)),
+      KeyCode::Key3 if ctrl => Action::SelectTemplate(Some(2)),
+      KeyCode::Key4 if ctrl => Action::SelectTemplate(Some(3)),
+      KeyCode::Key5 if ctrl => Action::SelectTemplate(Some(4)),
+      KeyCode::Key6 if ctrl => Action::SelectTemplate(Some(5)),
+      KeyCode::Key7 if ctrl => Action::SelectTemplate(Some(6)),
+      KeyCode::Key8 if ctrl => Action::SelectTemplate(Some(7)),
+      KeyCode::Key9 if ctrl => Action::SelectTemplate(Some(8)),
+      KeyCode::Key1 => Action::LoadScenario(0),
+      KeyCode::Key2 => Action::LoadScenario(1),
+      KeyCode::Key3 => Action::LoadScenario(2),
+      KeyCode::Key4 => Action::LoadScenario(3),
+      KeyCode::Key5 => Action::LoadScenario(4),
+      KeyCode::Key6 => Action::LoadScenario(5),
+      KeyCode::Key7 => Action::LoadScenario(6),
+      KeyCode::Key8 => Action::LoadScenario(7),
+      KeyCode::Key9 => Action::LoadScenario(8),
+      KeyCode::F5 => Action::Save,
+      KeyCode::F9 => if shift { Action::ReloadFile } else { Action::Load },
+      KeyCode::Space => Action::TogglePause,
+      KeyCode::K => Action::CycleSpawnKind,
+      KeyCode::S => Action::ToggleLaunchSnap,
+      KeyCode::L => Action::ToggleFocus { barycentre: shift },
+      KeyCode::T => Action::CycleTrailStyle { selected_only: shift },
+      KeyCode::J => if shift { Action::ToggleTrailFrame } else { Action::CycleTrailColoring },
+      KeyCode::RBracket => Action::ChangeSpawnSize { bigger: true, change_density: shift },
+      KeyCode::LBracket => Action::ChangeSpawnSize { bigger: false, change_density: shift },
+      KeyCode::Equals | KeyCode::Plus | KeyCode::NumpadAdd => Action::ChangeTimeScale { faster: true },
+      KeyCode::Minus | KeyCode::NumpadSubtract => Action::ChangeTimeScale { faster: false },
+      KeyCode::F1 => if shift { Action::ToggleGui } else { Action::ToggleHelp },
+      KeyCode::F2 => Action::ToggleMute,
+      KeyCode::F3 => Action::ExportTrajectories,
+      KeyCode::F4 => Action::ToggleMinimap,
+      KeyCode::X => Action::ToggleEraser,
+      KeyCode::W => Action::ToggleWrap,
+      KeyCode::Q => Action::ToggleTrackpadMode,
+      KeyCode::B => Action::CycleTool,
+      KeyCode::V => if shift { Action::ToggleRecentring } else { Action::ToggleDriftCancelling },
+      _ => return None,
+    })
+  }
+}
+
+// Serializable stand-in for ggez's MouseButton
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Button {
+  Left,
+  Right,
+  Middle,
+  Other(u16),
+}
+
+impl From<MouseButton> for Button {
+  fn from(button: MouseButton) -> Self {
+    match button {
+      MouseButton::Left => Button::Left,
+      MouseButton::Right => Button::Right,
+      MouseButton::Middle => Button::Middle,
+      MouseButton::Other(n) => Button::Other(n),
+    }
+  }
+}
+
+impl From<Button> for MouseButton {
+  fn from(button: Button) -> Self {
+    match button {
+      Button::Left => MouseButton::Left,
+      Button::Right => MouseButton::Right,
+      Button::Middle => MouseButton::Middle,
+      Button::Other(n) => MouseButton::Other(n),
+    }
+  }
+}
+
+// A single input, with mouse positions in screen coordinates.
+// Modifier state is stored with the event, as it can't be read back from the keyboard on replay.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum InputEvent {
+  MouseDown { button: Button, x: f32, y: f32, alt: bool },
+  MouseUp { button: Button, x: f32, y: f32, shift: bool },
+  MouseMotion { x: f32, y: f32 },
+  MouseWheel { x: f32, y: f32, cursor_x: f32, cursor_y: f32, shift: bool, ctrl: bool },
+  Touch { phase: Touch, x: f32, y: f32 },
+  Action(Action),
+}
+
+// Serializable stand-in for ggez's TouchPhase
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Touch {
+  Started,
+  Moved,
+  Ended,
+  Cancelled,
+}
+
+impl From<TouchPhase> for Touch {
+  fn from(phase: TouchPhase) -> Self {
+    match phase {
+      TouchPhase::Started => Touch::Started,
+      TouchPhase::Moved => Touch::Moved,
+      TouchPhase::Ended => Touch::Ended,
+      TouchPhase::Cancelled => Touch::Cancelled,
+    }
+  }
+}
@@ -0,0 +1,104 @@
+use nalgebra::{Point, SVector};
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+
+// Numerical method used to step positions and velocities forward in time
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Integrator {
+  Euler,              // x += v dt, v += a dt. Gains energy, orbits spiral outwards.
+  SemiImplicitEuler,  // v += a dt, x += v dt. Symplectic, cheap.
+  VelocityVerlet,     // Symplectic, second order. Two acceleration evaluations per step.
+  RK4,                // Fourth order Runge–Kutta. Not symplectic but very accurate for small dt.
+}
+
+impl Default for Integrator {
+  fn default() -> Self {
+    Integrator::SemiImplicitEuler
+  }
+}
+
+impl fmt::Display for Integrator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Integrator::Euler => "Euler",
+      Integrator::SemiImplicitEuler => "Semi-implicit Euler",
+      Integrator::VelocityVerlet => "Velocity Verlet",
+      Integrator::RK4 => "RK4",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+impl Integrator {
+  pub fn next(self) -> Self {
+    match self {
+      Integrator::Euler => Integrator::SemiImplicitEuler,
+      Integrator::SemiImplicitEuler => Integrator::VelocityVerlet,
+      Integrator::VelocityVerlet => Integrator::RK4,
+      Integrator::RK4 => Integrator::Euler,
+    }
+  }
+
+  // Steps positions and velocities forward by dt. `acceleration` gives the acceleration of every body for a set of positions.
+  // Returns the accelerations at the start of the step (used for debug drawing).
+  // Works in any number of dimensions: D is 2 for World, 3 for space::World3.
+  pub fn step<const D: usize, F>(&self, positions: &mut [Point<f32, D>], velocities: &mut [SVector<f32, D>], dt: f32, acceleration: F) -> Vec<SVector<f32, D>>
+    where F: Fn(&[Point<f32, D>]) -> Vec<SVector<f32, D>>
+  {
+    let a0 = acceleration(positions);
+
+    match self {
+      Integrator::Euler => {
+        for i in 0..positions.len() {
+          positions[i] += velocities[i] * dt;
+          velocities[i] += a0[i] * dt;
+        }
+      },
+      Integrator::SemiImplicitEuler => {
+        for i in 0..positions.len() {
+          velocities[i] += a0[i] * dt;
+          positions[i] += velocities[i] * dt;
+        }
+      },
+      Integrator::VelocityVerlet => {
+        for i in 0..positions.len() {
+          positions[i] += velocities[i] * dt + a0[i] * (0.5 * dt * dt);
+        }
+        let a1 = acceleration(positions);
+        for i in 0..velocities.len() {
+          velocities[i] += (a0[i] + a1[i]) * (0.5 * dt);
+        }
+      },
+      Integrator::RK4 => {
+        let x0 = positions.to_vec();
+        let v0 = velocities.to_vec();
+        let offset = |k_x: &[SVector<f32, D>], h: f32| -> Vec<Point<f32, D>> {
+          x0.iter().zip(k_x.iter()).map(|(x, k)| x + k * h).collect()
+        };
+        let add = |base: &[SVector<f32, D>], k: &[SVector<f32, D>], h: f32| -> Vec<SVector<f32, D>> {
+          base.iter().zip(k.iter()).map(|(b, k)| b + k * h).collect()
+        };
+
+        let k1_x = v0.clone();
+        let k1_v = a0.clone();
+
+        let k2_x = add(&v0, &k1_v, dt/2.0);
+        let k2_v = acceleration(&offset(&k1_x, dt/2.0));
+
+        let k3_x = add(&v0, &k2_v, dt/2.0);
+        let k3_v = acceleration(&offset(&k2_x, dt/2.0));
+
+        let k4_x = add(&v0, &k3_v, dt);
+        let k4_v = acceleration(&offset(&k3_x, dt));
+
+        for i in 0..positions.len() {
+          positions[i] = x0[i] + (k1_x[i] + k2_x[i] * 2.0 + k3_x[i] * 2.0 + k4_x[i]) * (dt/6.0);
+          velocities[i] = v0[i] + (k1_v[i] + k2_v[i] * 2.0 + k3_v[i] * 2.0 + k4_v[i]) * (dt/6.0);
+        }
+      },
+    }
+
+    a0
+  }
+}
@@ -0,0 +1,31 @@
+pub mod tools;
+pub mod planet;
+pub mod quadtree;
+pub mod integrator;
+pub mod simulation;
+pub mod scenarios;
+pub mod diagnostics;
+pub mod headless;
+pub mod config;
+pub mod collision;
+pub mod union_find;
+pub mod bench;
+pub mod tracking;
+pub mod events;
+pub mod soft_body;
+pub mod stability;
+pub mod profile;
+pub mod import;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "three_d")]
+pub mod space;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "python")]
+pub mod python;
+
+use std::f32::consts::PI;
+
+pub const G: f32 = 0.0001;    // Default gravitational constant (see config::SimConfig)
+pub const TWO_PI: f32 = PI * 2.0;
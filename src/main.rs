@@ -1,48 +1,228 @@
-mod tools;
-mod planet;
-
-use ggez::event::{self};
+mod trail;
+mod render;
+mod save;
+mod history;
+mod camera;
+mod input;
+mod replay;
+mod gui;
+mod brush;
+mod burst;
+mod impact;
+mod comet_tail;
+mod edit;
+mod capture;
+mod merge;
+mod heatmap;
+mod warp_grid;
+mod field_arrows;
+mod minimap;
+mod settings;
+mod audio;
+mod cli;
+mod snapshot;
+mod template;
+mod protocol;
+mod net;
+#[cfg(feature = "three_d")]
+mod view3d;
+
+use ggez::event::{self, TouchPhase};
 use ggez::graphics::{self, DrawParam, Mesh, MeshBuilder, Color, Canvas, DrawMode};
 use ggez::{Context, GameResult};
-use ggez::timer;
+use ggegui::Gui;
 use ggez::input::{mouse::MouseButton, keyboard::{KeyCode, KeyMods, KeyInput}};
 
-use nalgebra::{Point2, Vector2};
-
-use rand::prelude::*;
-use rand::rngs::ThreadRng;
-use rand::distributions::Uniform;
+use nalgebra::{Vector2, Point2};
 
 use std::collections::{HashMap, HashSet};
 use std::cell::RefCell;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use std::f32::consts::PI;
 
-use planet::{Planet, PlanetTrail, PLANET_DENSITY};
+use clap::Parser;
+
+use orbits::simulation::{World, DEFAULT_FRAGMENTATION_THRESHOLD, DEFAULT_MASS_TRANSFER};
+use orbits::planet::{Planet, BodyKind};
+use orbits::tools;
+use orbits::diagnostics::Diagnostics;
+use orbits::headless;
+use orbits::bench;
+use orbits::scenarios::{self, Scenario, RandomBodies};
+use orbits::import::{self, CsvBodies};
+use orbits::config::SimConfig;
+use orbits::tracking::TrajectoryLog;
+use orbits::events::Event;
+use orbits::profile::{self, StepTimings};
+use orbits::stability::{self, StabilityConfig, StabilityReport};
+
+use trail::{PlanetTrail, TrailStyle, TrailColoring, TrailSource, TrailPool};
+use save::{SaveState, SAVE_FILE_PATH, CONFIG_FILE_PATH, TRAJECTORY_FILE_PATH, EVENT_LOG_FILE_PATH};
+use snapshot::{Snapshots, SNAPSHOT_DIR};
+use template::{Template, TEMPLATE_FILE};
+use net::{Net, Host, Client};
+use history::{History, Command};
+use camera::{Camera, Focus};
+use input::{Action, InputEvent, Touch};
+use gui::PlanetEdit;
+use brush::{
+  Tool, StreamStroke, DEFAULT_STREAM_RATE, DEFAULT_RING_COUNT, GRID_SPACING_RADII, DEFAULT_BINARY_MASS_RATIO,
+  MIN_BINARY_SEPARATION_RADII,
+};
+use replay::{Replay, Player, REPLAY_FILE_PATH};
+use burst::ParticleBurst;
+use impact::{ImpactFeedback, ImpactEffects};
+use comet_tail::CometTail;
+use merge::MergeAnimation;
+use heatmap::Heatmap;
+use field_arrows::FieldArrows;
+use settings::Settings;
+use audio::Audio;
+use render::{PlanetBatch, ColorMode, VectorDebug};
+use minimap::{Minimap, MinimapTransform};
+use capture::{FrameCapture, CAPTURE_DIR};
+use cli::Cli;
+use edit::{EditMode, Grab, NUDGE_DISTANCE, NUDGE_SPEED, MASS_SCALE_STEP, VELOCITY_HANDLE_SCALE, HANDLE_RADIUS};
 
-pub const G: f32 = 0.0001;    // Gravitational constant
-pub const TWO_PI: f32 = PI * 2.0;
 const SPAWN_PLANET_RADIUS: f32 = 5.0;
+const SPAWN_RADIUS_RANGE: (f32, f32) = (0.5, 100.0);
+const SPAWN_RADIUS_STEP: f32 = 1.1;     // Multiplier per scroll notch/key press
+const SPAWN_DENSITY_STEP: f32 = 1.25;
+const SPAWN_DENSITY_RANGE: (f32, f32) = (100.0, 100_000.0);
 const ACC_DEBUG_VECTOR_MULTIPLIER: f32 = 5.0;
+const VECTOR_SCALE_RANGE: (f32, f32) = (0.05, 20.0);
+const VECTOR_SCALE_STEP: f32 = 1.25;     // Multiplier per key press
 pub const SCREEN_DIMS: (f32, f32) = (1280.0, 860.0);
-const TELEPORT_ON_EDGES: bool = false;       // When edge of window is reached, teleport to other side.
+const TELEPORT_ON_EDGES: bool = false;       // Start with the world wrapped at the window size, unless the config sets a size.
+const MIN_WRAP_SIZE: f32 = 50.0;
+#[cfg(feature = "scripting")]
+const SCRIPT_DIR: &str = "scripts";   // In the resources directory
+const IMPORT_DIR: &str = "bodies";    // CSV files of bodies, in the resources directory
+const MIN_INFLUENCE_RADII: f32 = 2.0;     // Spheres of influence smaller than this many body radii aren't drawn
+const LAUNCH_GAP: f32 = 1.0;    // Space between a launched body and the surface of the planet it's launched from
+const LAUNCH_SCALE_RANGE: (f32, f32) = (0.05, 10.0);   // Launch speed per pixel dragged
+const TRAJECTORY_PREVIEW_STEPS: usize = 400;
+const TRAJECTORY_PREVIEW_DT: f32 = 1.0/60.0;
+const DEFAULT_PHYSICS_RATE: f32 = 120.0;    // Physics steps per second of sim time
+const PHYSICS_RATE_RANGE: (f32, f32) = (10.0, 480.0);
+const MAX_PHYSICS_STEPS_PER_FRAME: u32 = 8;  // Stops spiral of death when physics can't keep up
+const FIXED_FRAME_DT: f32 = 1.0/60.0;   // Frame time while recording or replaying
+const TIME_SCALE_STEPS: [f32; 7] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0];
+const ERASER_CURSOR_RADIUS: f32 = 6.0;
+const ZOOM_STEP: f32 = 1.1;     // Zoom multiplier per scroll notch with ctrl held (or pinch)
+const TRACKPAD_PAN_SPEED: f32 = 20.0;  // Screen pixels panned per unit of scroll in trackpad mode
+const G_STEP: f32 = 1.25;    // Multiplier per key press when changing G live
+const SOFTENING_STEP: f32 = 0.5;   // Added/removed per key press
+const MAX_LOGGED_EVENTS: usize = 5000;  // Oldest events are dropped past this
+const HELP_COLUMNS: usize = 3;
+const HELP_MARGIN: f32 = 30.0;
+const HELP_TEXT_SIZE: f32 = 13.0;
+const TOOLTIP_DELAY: f32 = 0.3;   // Seconds the cursor must rest on a body before its tooltip shows
+const PERIAPSIS_FLASH_TIME: f32 = 0.8;  // Real seconds the marker flashes for when the selected body passes periapsis
+const MIN_FLASH_ECCENTRICITY: f32 = 0.01;  // Periapsis of nearly circular orbits wanders about, so isn't flashed
 
 struct MainState {
-  planet_id_count: usize,
-  planets: HashMap<usize, RefCell<Planet>>,
+  world: World,
   planet_trails: HashMap<usize, RefCell<PlanetTrail>>,
+  trail_pool: TrailPool,
+  bursts: Vec<ParticleBurst>,   // From merges
+  impact_feedback: ImpactFeedback,  // Shake, shockwaves and flashes from the hardest merges
+  impact_effects: ImpactEffects,
+  comet_tails: HashMap<usize, CometTail>,   // Of asteroids near stars, by id
+  merge_animations: Vec<MergeAnimation>,
   mouse_info: MouseInfo,
+  selected_planet: Option<usize>,
+  group: Vec<usize>,    // Planets picked by a box selection, sorted by id
+  use_gpu: bool,        // Kept across new worlds, see World::use_gpu
+  trajectories: TrajectoryLog,
+  event_log: Vec<Event>,    // Shown in the events panel, oldest first
+  scenarios: Vec<Box<dyn Scenario>>,
+  current_scenario: usize,
+  start_scenario: usize,    // Chosen at launch, for ResetToStart
+  last_loaded: Option<String>,    // Save file, for ReloadFile
+  show_scenario_menu: bool,
+  show_help: bool,      // Overlay listing every control
+  spawn_radius: f32,    // Size and density of click-spawned planets
+  spawn_density: f32,
+  spawn_kind: BodyKind,
+  templates: Vec<Template>,
+  spawn_template: Option<usize>,  // Picked from the palette. Only used while the spawn settings are still its own.
+  tool: Tool,         // What left clicking does
+  stream_rate: f32,
+  ring_count: usize,
+  binary_mass_ratio: f32,   // Lighter over heavier mass of binary brush pairs
+  stream: Option<StreamStroke>,
+  trail_style: TrailStyle,
+  trail_coloring: TrailColoring,
+  launch_scale: f32,    // Launch velocity per pixel of drag
+  snap_launch: bool,    // Launch at circular orbit speed around the nearest body, in the drag's direction
+  trail_frame: Option<usize>,   // Body trails are recorded relative to, so orbits around a moving body close up
+  color_mode: ColorMode,
+  show_diagnostics: bool,
+  diagnostics: Option<Diagnostics>,
+  diagnostics_baseline: Option<Diagnostics>,   // Measurement at t=0, reset whenever bodies are added/removed by the user
+  history: History,
+  camera: Camera,
+  seed: Option<u64>,          // Used for recordings, random if not given
+  frame: u64,                 // Frames since the recording/replay started
+  recording: Option<Replay>,
+  player: Option<Player>,
+  capture: Option<FrameCapture>,    // Saving every frame as an image
+  gui: Gui,
+  show_gui: bool,
 
   show_planet_info_debug: bool,
   show_vector_debug: bool,
+  vector_debug: VectorDebug,
+  show_influence: bool,    // Sphere of influence overlay
+  show_heatmap: bool,      // Gravitational potential overlay
+  heatmap: Heatmap,
+  show_warp_grid: bool,    // Background grid pulled towards massive bodies
+  show_field_arrows: bool,  // Gravitational acceleration on a grid
+  field_arrows: FieldArrows,
+  show_minimap: bool,
+  minimap: Minimap,
+  show_labels: bool,
+  hover: Option<(usize, f32)>,    // Body under the cursor, and for how many real seconds
+  last_anomaly: Option<(usize, f32)>,   // Selected body's true anomaly last frame, see watch_periapsis
+  periapsis_flash: Option<(Point2<f32>, f32)>,  // Where the selected body last passed periapsis, and how long ago
+
+  // Last frame's timings, for the debug text
+  step_timings: StepTimings,
+  trail_time: Duration,
+  draw_time: Duration,
+  trackpad_mode: bool,     // Two-finger scrolling pans the camera instead of changing spawn size
+  edit_mode: EditMode,
   dt: f32,
+  fixed_dt: Option<f32>,   // Frame length in sim time from --fixed-dt, instead of the real frame time
+  paused: bool,
+  physics_accumulator: Duration,   // Simulation time not yet stepped by physics
+  physics_dt: f32,        // Fixed physics time step
+  extrapolate: bool,      // Draw bodies ahead of the last step along their motion, instead of between the last two steps
+
+  audio: Audio,
+  settings: Settings,    // As loaded at startup. Saved with the current spawn settings etc. on exit.
+
+  snapshots: Snapshots,
+  recovery: Option<PathBuf>,    // Snapshot to offer back after a crash, until restored or dismissed
+  stability_job: Option<(usize, Receiver<StabilityReport>, Arc<AtomicBool>)>,  // Body being checked, where its report will arrive and its cancel flag
+  stability_report: Option<StabilityReport>,
+  net: Option<Net>,     // Hosting or joined a shared sandbox
 
   // Mesh objects
   body_mesh: Mesh,
+  planet_batch: PlanetBatch,
 }
 
 impl MainState {
-  fn new(ctx: &mut Context) -> GameResult<MainState> {
+  // Starts with an empty world. The caller builds the start scenario or loads a save, so a seeded world is only
+  // built once and matches --headless with the same seed.
+  fn new(ctx: &mut Context, seed: Option<u64>, use_gpu: bool, settings: Settings) -> GameResult<MainState> {
     let body_mesh = Mesh::new_circle(
       ctx,
       DrawMode::fill(),
@@ -52,214 +232,347 @@ impl MainState {
       Color::WHITE,
     )?;
 
-    let mut s = MainState {
-      planet_id_count: 0,
-      planets: HashMap::new(),
+    let mut world = World::new();
+    world.use_gpu = use_gpu;
+    if let Some(seed) = seed {
+      world.seed(seed);
+    }
+    match save::read_config(CONFIG_FILE_PATH) {
+      Ok(config) => world.config = config,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+      Err(e) => println!("WARNING: Could not read config from {}: {}", CONFIG_FILE_PATH, e),
+    }
+    world.wrap_bounds = Self::initial_wrap_bounds(&world.config);
+    world.integrator = settings.integrator;
+
+    #[allow(unused_mut)]
+    let mut scenarios = scenarios::presets();
+    #[cfg(feature = "scripting")]
+    for script in orbits::script::load_dir(&ctx.fs.resources_dir().join(SCRIPT_DIR)) {
+      scenarios.push(Box::new(script));
+    }
+    for bodies in import::load_dir(&ctx.fs.resources_dir().join(IMPORT_DIR)) {
+      scenarios.push(Box::new(bodies));
+    }
+
+    let templates = match template::load(&ctx.fs.resources_dir().join(TEMPLATE_FILE)) {
+      Ok(templates) => templates,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+      Err(e) => {
+        println!("WARNING: Could not read spawn templates from {}: {}", TEMPLATE_FILE, e);
+        Vec::new()
+      },
+    };
+
+    let snapshots = Snapshots::new(SNAPSHOT_DIR, settings.autosave_interval, settings.snapshot_slots);
+    let recovery = snapshots.start();
+
+    Ok(MainState {
+      world,
       planet_trails: HashMap::new(),
+      trail_pool: TrailPool::default(),
+      bursts: Vec::new(),
+      impact_feedback: ImpactFeedback::default(),
+      impact_effects: settings.impact_effects,
+      comet_tails: HashMap::new(),
+      merge_animations: Vec::new(),
       mouse_info: MouseInfo::default(),
+      selected_planet: None,
+      group: Vec::new(),
+      use_gpu,
+      trajectories: TrajectoryLog::new(),
+      event_log: Vec::new(),
+      scenarios,
+      current_scenario: 0,
+      start_scenario: 0,
+      last_loaded: None,
+      show_scenario_menu: false,
+      show_help: false,
+      spawn_radius: settings.spawn_radius,
+      spawn_density: settings.spawn_density.clamp(SPAWN_DENSITY_RANGE.0, SPAWN_DENSITY_RANGE.1),
+      spawn_kind: settings.spawn_kind,
+      templates,
+      spawn_template: None,
+      tool: Tool::default(),
+      stream_rate: DEFAULT_STREAM_RATE,
+      ring_count: DEFAULT_RING_COUNT,
+      binary_mass_ratio: DEFAULT_BINARY_MASS_RATIO,
+      stream: None,
+      trail_style: settings.trail_style,
+      trail_coloring: TrailColoring::Config,
+      launch_scale: settings.launch_scale,
+      snap_launch: false,
+      trail_frame: None,
+      color_mode: ColorMode::default(),
+      show_diagnostics: false,
+      diagnostics: None,
+      diagnostics_baseline: None,
+      history: History::default(),
+      camera: Camera::new(),
+      seed,
+      frame: 0,
+      recording: None,
+      player: None,
+      capture: None,
+      gui: Gui::new(ctx),
+      show_gui: true,
 
       show_planet_info_debug: false,
       show_vector_debug: false,
+      vector_debug: VectorDebug { scale: 1.0, show_force: true },
+      show_influence: false,
+      show_heatmap: false,
+      heatmap: Heatmap::new(),
+      show_warp_grid: false,
+      show_field_arrows: false,
+      field_arrows: FieldArrows::new(),
+      show_minimap: true,
+      minimap: Minimap::new(ctx),
+      show_labels: false,
+      hover: None,
+      last_anomaly: None,
+      periapsis_flash: None,
+      step_timings: StepTimings::default(),
+      trail_time: Duration::ZERO,
+      draw_time: Duration::ZERO,
+      trackpad_mode: false,
+      edit_mode: EditMode::Off,
       dt: 1.0/60.0,
+      fixed_dt: None,
+      paused: false,
+      physics_accumulator: Duration::ZERO,
+      physics_dt: 1.0/settings.physics_rate.clamp(PHYSICS_RATE_RANGE.0, PHYSICS_RATE_RANGE.1),
+      extrapolate: settings.extrapolate,
+
+      audio: Audio::new(ctx, settings.volume, settings.muted),
+      snapshots,
+      recovery,
+      stability_job: None,
+      stability_report: None,
+      net: None,
+      settings,
 
       body_mesh,
-    };
-
-    s.restart();
-
-    Ok(s)
+      planet_batch: PlanetBatch::new(ctx),
+    })
   }
 
   fn restart(&mut self) {
     self.clear();
-    // const GAP: f32 = 100.0;
-    // self.spawn_square_of_planets(
-    //     Point2::new(GAP/2.0, GAP/2.0),
-    //     (SCREEN_DIMS.0/GAP).ceil() as u16,
-    //     (SCREEN_DIMS.1/GAP).ceil() as u16,
-    //     GAP,
-    //     10.0,
-    // );
-
-    // self.add_planet_with_moons(
-    //     [(SCREEN_DIMS.0/2) as f32, (SCREEN_DIMS.1/2) as f32].into(),
-    //     None,
-    //     None,
-    //     50.0,
-    //     500,
-    //     (15.0, 200.0),
-    //     (0.5, 1.5),
-    //     true,
-    // );
-
-    self.add_planet_with_moons(
-      Point2::new(SCREEN_DIMS.0 * 1.0/3.0, SCREEN_DIMS.1/2.0),
-      None,
-      None,
-      50.0,
-      350,
-      (15.0, 100.0),
-      (0.5, 1.5),
-      true,
-    );
-    self.add_planet_with_moons(
-      Point2::new(SCREEN_DIMS.0 * 2.0/3.0, SCREEN_DIMS.1/2.0),
-      None,
-      None,
-      50.0,
-      350,
-      (15.0, 100.0),
-      (0.5, 1.5),
-      true,
-    );
-
-    // const DIV: f32 = 100.0;
-    // self.add_random_planets(
-    //     1000,
-    //     (SCREEN_DIMS.0/DIV, SCREEN_DIMS.0 - SCREEN_DIMS.0/DIV),
-    //     (SCREEN_DIMS.1/DIV, SCREEN_DIMS.1 - SCREEN_DIMS.1/DIV),
-    //     (2.0, 10.0),
-    //     Some((0.0, 1.0)),
-    // );
+    self.scenarios[self.current_scenario].build(&mut self.world, SCREEN_DIMS);
   }
 
-  fn clear(&mut self) {
-    self.planets = HashMap::new();
+  fn load_scenario(&mut self, index: usize) {
+    if index < self.scenarios.len() {
+      self.current_scenario = index;
+      self.show_scenario_menu = false;
+      self.with_history(Self::restart);
+    }
   }
 
-  fn add_planet(&mut self, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection: Option<Duration>) {
-    self.add_planet_raw(Planet::new(
-      self.planet_id_count,
-      position,
-      velocity,
-      mass,
-      radius,
-      spawn_protection,
-    ));
+  // Runs a change that replaces many planets at once, recording it so it can be undone
+  fn with_history<F: FnOnce(&mut Self)>(&mut self, change: F) {
+    let before = self.world.planets.clone();
+    change(self);
+    self.history.push(Command::ReplacePlanets { before, after: self.world.planets.clone() });
   }
 
-  // Spawns a planet with other 
-  fn add_planet_with_moons(
-    &mut self,
-    position: Point2<f32>,
-    velocity: Option<Vector2<f32>>,
-    main_planet_mass: Option<f32>,
-    main_planet_radius: f32,
-    moon_num: usize,
-    moon_orbit_radius_range: (f32, f32),    // Starting from surface of planet
-    moon_body_radius_range: (f32, f32),
-    orbit_direction_clockwise: bool,  // anticlockwise = false, clockwise = true
-  ) {
-    self.add_planet(position, velocity, main_planet_mass, main_planet_radius, None);  // Add main planet
-    let (main_planet_mass, frame_velocity) = {
-      let p = self.planets.get(&(self.planet_id_count - 1)).unwrap().borrow();
-      (p.mass, p.velocity)
-    };
-    
-    let mut rng = rand::thread_rng();
-
-    let orbit_rad_range = Uniform::from(moon_orbit_radius_range.0..moon_orbit_radius_range.1);
-    let angle_range = Uniform::from(0.0..TWO_PI);
-    let size_rad_range = Uniform::from(moon_body_radius_range.0..moon_body_radius_range.1);
-    
-    for _ in 0..moon_num {
-      let orbit_radius = main_planet_radius + orbit_rad_range.sample(&mut rng);
-      let orbit_speed = tools::circular_orbit_speed(main_planet_mass, orbit_radius);
-      let start_angle = angle_range.sample(&mut rng);      // Angle from main planet to moon
-      let start_pos = tools::get_components(orbit_radius, start_angle);   // Position on circle orbit where planet will start
-      let start_velocity = tools::get_components(
-        orbit_speed,
-        if orbit_direction_clockwise {
-          start_angle + PI/2.0
-        } else {
-          start_angle - PI/2.0
-        }
-      );  // 90 degrees to angle with planet
-      let moon_radius = size_rad_range.sample(&mut rng);
-
-      self.add_planet(
-        position + start_pos,
-        Some(start_velocity + frame_velocity),  // Add velocity of main planet
-        None,
-        moon_radius,
-        None,
-      );
+  // Records a newly spawned planet so it can be undone
+  fn record_spawn(&mut self, id: usize) {
+    if let Some(planet) = self.world.planets.get(&id) {
+      self.history.push(Command::Spawn(planet.borrow().clone()));
     }
+    self.diagnostics_baseline = None;
   }
 
-  fn add_planet_raw(&mut self, mut planet: Planet) {
-    planet.id = self.planet_id_count;
+  fn delete_selected(&mut self) {
+    if !self.group.is_empty() {
+      self.delete_group();
+    } else if let Some(id) = self.selected_planet {
+      self.delete_planet(id);
+    }
+  }
 
-    self.planet_trails.insert(
-      self.planet_id_count,
-      RefCell::new(PlanetTrail::new(planet.position))
-    );
+  // Removes a planet, recording it so it can be undone. Its trail is left to fade out on its own.
+  fn delete_planet(&mut self, id: usize) {
+    if self.selected_planet == Some(id) {
+      self.selected_planet = None;
+    }
 
-    self.planets.insert(
-      self.planet_id_count,
-      RefCell::new(planet)
-    );
+    // Through World::remove_planet, so the removal shows up in the event log
+    if let Some(planet) = self.world.planets.get(&id).map(|planet| planet.borrow().clone()) {
+      self.world.remove_planet(id);
+      let trail = self.planet_trails.get(&planet.id).map(|t| t.borrow().clone());
+      self.history.push(Command::Delete { planet, trail });
+      self.diagnostics_baseline = None;
+    }
+  }
 
-    self.planet_id_count += 1;
+  fn undo(&mut self) {
+    if self.history.undo(&mut self.world, &mut self.planet_trails) {
+      self.diagnostics_baseline = None;
+    }
   }
 
-  fn add_random_planets(&mut self, n: usize, x_range: (f32, f32), y_range: (f32, f32), radius_range: (f32, f32), speed_range: Option<(f32, f32)>) {
-    assert!(x_range.1 > x_range.0);
-    assert!(y_range.1 > y_range.0);
-    assert!(radius_range.1 > radius_range.0);
-    assert!(n > 0);
+  fn redo(&mut self) {
+    if self.history.redo(&mut self.world) {
+      self.diagnostics_baseline = None;
+    }
+  }
 
-    let mut rng = rand::thread_rng();
-    
-    for _ in 0..n {
-      let x_pos = rng.gen_range(x_range.0..x_range.1);
-      let y_pos = rng.gen_range(y_range.0..y_range.1);
-      let radius = rng.gen_range(radius_range.0..radius_range.1);
+  fn draw_scenario_menu(&self, canvas: &mut Canvas) {
+    let mut menu = String::from("Scenarios (press number to load):\n");
+    for (i, scenario) in self.scenarios.iter().enumerate() {
+      menu += &format!(
+        "{}{}: {}\n",
+        if i == self.current_scenario { "> " } else { "  " },
+        i + 1,
+        scenario.name()
+      );
+    }
 
-      let velocity = if let Some(speed_range) = speed_range {
-        assert!(speed_range.1 > speed_range.0);
+    let text = graphics::Text::new(menu);
+    canvas.draw(&text, DrawParam::new().dest([SCREEN_DIMS.0/2.0 - 150.0, SCREEN_DIMS.1/2.0 - 100.0]));
+  }
 
-        let speed = rng.gen_range(speed_range.0..speed_range.1);
-        let angle = rng.gen_range(0.0..TWO_PI);
-        Some(tools::get_components(speed, angle))
-      } else {
-        None
-      };
+  // Every key and mouse binding, including the ones from the settings file, in columns over the middle of the screen
+  fn draw_help(&self, canvas: &mut Canvas) {
+    let mut lines = vec!["Controls (F1 to close)".to_string(), String::new()];
+    if !self.settings.key_bindings.is_empty() || !self.settings.mouse_bindings.is_empty() {
+      lines.push("From settings.toml:".to_string());
+      for (key, action) in self.settings.key_bindings.iter() {
+        lines.push(format!("  {}: {:?}", key, action));
+      }
+      for (button, tool) in self.settings.mouse_bindings.iter() {
+        lines.push(format!("  {} mouse: {} tool", button, tool));
+      }
+      lines.push(String::new());
+    }
+    lines.push("Mouse:".to_string());
+    lines.push(format!("  Left: {} tool (Alt: eraser)", self.tool));
+    lines.push("  Right: orbit tool (ring tool while it's active)".to_string());
+    lines.push("  Middle: select tool".to_string());
+    lines.push("  Scroll: spawn size (Shift: density), Ctrl+scroll: zoom".to_string());
+    lines.push(String::new());
+    lines.push("Keys:".to_string());
+    for (key, action) in input::built_in_bindings(false) {
+      lines.push(format!("  {}: {:?}", key, action));
+    }
+    lines.push("  F6: ToggleRecording".to_string());
+    lines.push("  F7: ToggleReplay".to_string());
+    lines.push("  F8: ToggleCapture".to_string());
+    lines.push("While editing (Return):".to_string());
+    for (key, action) in input::built_in_bindings(true) {
+      lines.push(format!("  {}: {:?}", key, action));
+    }
 
-      self.add_planet(
-        Point2::new(x_pos, y_pos),
-        velocity,
-        None,
-        radius,
-        None,
-      );
+    let background = graphics::Rect::new(HELP_MARGIN/2.0, HELP_MARGIN/2.0, SCREEN_DIMS.0 - HELP_MARGIN, SCREEN_DIMS.1 - HELP_MARGIN);
+    canvas.draw(&graphics::Quad, DrawParam::new().dest_rect(background).color([0.0, 0.0, 0.0, 0.85]));
+    let column_width = (SCREEN_DIMS.0 - 2.0 * HELP_MARGIN)/HELP_COLUMNS as f32;
+    let per_column = (lines.len() + HELP_COLUMNS - 1)/HELP_COLUMNS;
+    for (i, column) in lines.chunks(per_column.max(1)).enumerate() {
+      let mut text = graphics::Text::new(column.join("\n"));
+      text.set_scale(HELP_TEXT_SIZE);
+      canvas.draw(&text, DrawParam::new().dest([HELP_MARGIN + i as f32 * column_width, HELP_MARGIN]));
     }
   }
 
-  fn remove_planet(&mut self, id: usize) {
-    if self.planets.remove(&id).is_none() {
-      println!("WARNING: Tried to remove planet {} but it wasn't in the hashmap.", id);
+  fn clear(&mut self) {
+    self.world.clear();
+    self.diagnostics_baseline = None;
+  }
+
+  // Sim time since the scenario started (it stops while paused), and what it represents when real units are set
+  fn sim_time_readout(&self) -> String {
+    let t = self.world.time;
+    let minutes = (t/60.0).floor();
+    let clock = if minutes >= 1.0 {
+      format!("{}m {:04.1}s", minutes as u64, t - minutes * 60.0)
+    } else {
+      format!("{:.1}s", t)
+    };
+    if self.world.config.seconds_per_sim_second == 1.0 {
+      clock
+    } else {
+      format!("{} ({})", clock, self.world.config.describe_duration(t))
     }
   }
 
   fn draw_debug_info(&self, canvas: &mut Canvas) {
+    let binding = self.world.binding();
     let text = graphics::Text::new(
       format!(
-        "{:.3}\nBodies: {}\nPlanet Trails: {}\nTrail Node Count: {}",
+        "{:.3}\nBodies: {} ({} despawned)\nBound: {}, Unbound: {} ({} ejected)\nPlanet Trails: {}\nTrail Node Count: {}\nTrail Buffers: {} ({} free)\nSim Time: {}\nTime Scale: {}x{}{}{}\nG: {:.3e}\nSoftening: {}\nScale: {}\nGravity: {}\nIntegrator: {}{}\nCollisions: {}{}\nFrame: {}\nTool: {}\nSpawn Kind: {}\nSpawn Radius: {:.2}\nSpawn Density: {:.0}\nForces: {:.2} ms\nCollision Detection: {:.2} ms\nCollision Resolution: {:.2} ms\nTrails: {:.2} ms\nDraw: {:.2} ms{}",
         1.0/self.dt,
-        self.planets.len(),
+        self.world.planets.len(),
+        self.world.despawned,
+        binding.bound,
+        binding.unbound,
+        binding.ejections,
         self.planet_trails.len(),
         self.node_count(),
+        self.trail_pool.allocated_count(),
+        self.trail_pool.free_count(),
+        self.sim_time_readout(),
+        self.world.config.time_scale,
+        if self.paused { " (PAUSED)" } else { "" },
+        if self.edit_mode.is_editing() { " (EDITING)" } else { "" },
+        if self.world.reversed { " (REVERSED)" } else { "" },
+        self.world.config.g,
+        self.world.config.softening,
+        self.world.config.describe_scale(),
+        if self.world.use_gpu { "GPU (exact)".to_string() } else { format!("Barnes-Hut (theta {})", self.world.barnes_hut_theta) },
+        self.world.integrator,
+        if self.world.adaptive_timestep { " (adaptive)" } else { "" },
+        self.world.collision_mode,
+        {
+          let effects: Vec<&str> = [
+            (self.world.fragmentation_threshold.is_some(), "fragmenting"),
+            (self.world.mass_transfer.is_some(), "mass transfer"),
+            (self.world.tidal_disruption, "tidal disruption"),
+            (self.world.soft_bodies.is_some(), "soft bodies"),
+            (self.world.test_particle_mass.is_some(), "test particles"),
+          ].iter().filter(|(on, _)| *on).map(|(_, name)| *name).collect();
+          if effects.is_empty() { String::new() } else { format!(" ({})", effects.join(", ")) }
+        },
+        match (self.world.cancel_drift, self.world.recentre_on.is_some()) {
+          (false, false) => "Free",
+          (true, false) => "Barycentric",
+          (false, true) => "Free (recentred)",
+          (true, true) => "Barycentric (recentred)",
+        },
+        self.tool,
+        self.spawn_kind,
+        self.spawn_radius,
+        self.spawn_density,
+        profile::ms(self.step_timings.forces),
+        profile::ms(self.step_timings.collision_detection),
+        profile::ms(self.step_timings.collision_resolution),
+        profile::ms(self.trail_time),
+        profile::ms(self.draw_time),
+        match &self.net {
+          Some(Net::Host(host)) => format!("\nHosting: {} joined", host.client_count()),
+          Some(Net::Client(client)) if client.is_connected() => "\nJoined".to_string(),
+          Some(Net::Client(_)) => "\nDisconnected from host".to_string(),
+          None => String::new(),
+        },
       )
     );
     
     canvas.draw(&text, DrawParam::new().dest([10.0, 10.0]));
+
+    if let (Some(diagnostics), Some(baseline)) = (self.diagnostics, self.diagnostics_baseline) {
+      let text = graphics::Text::new(diagnostics.report(&baseline));
+      canvas.draw(&text, DrawParam::new().dest([10.0, SCREEN_DIMS.1 - 110.0]));
+    }
   }
 
-  pub fn draw_mouse_drag(ctx: &mut Context, canvas: &mut Canvas, mouse_info: &MouseInfo) -> GameResult {
+  // Drag line and the body to be launched, with its launch speed next to the cursor. spawn_pos is where the body
+  // will appear, usually where the drag started.
+  fn draw_mouse_drag(&self, ctx: &mut Context, canvas: &mut Canvas, spawn_pos: Point2<f32>, speed: f32) -> GameResult {
     let line = Mesh::new_line(
       ctx,
-      &[mouse_info.down_pos, mouse_info.current_drag_position],
+      &[self.mouse_info.down_pos, self.mouse_info.current_drag_position],
       2.0,
       [0.0, 1.0, 0.0, 1.0].into(),
     )?;
@@ -268,62 +581,1199 @@ impl MainState {
     let circ_mesh = Mesh::new_circle(
       ctx,
       DrawMode::fill(),
-      mouse_info.down_pos,
-      SPAWN_PLANET_RADIUS,
+      spawn_pos,
+      self.spawn_radius,
       0.1,
       [1.0, 1.0, 1.0, 0.4].into()
     )?;
       
-    canvas.draw(&circ_mesh, DrawParam::default());        
+    canvas.draw(&circ_mesh, DrawParam::default());
+
+    let text = graphics::Text::new(format!("{:.1}{}", speed, if self.snap_launch { " (circular)" } else { "" }));
+    canvas.draw(&text, DrawParam::default().dest(self.mouse_info.current_drag_position + Vector2::new(12.0, 12.0)));
     Ok(())
   }
 
-  fn collide_planets(pl1: &mut Planet, pl2: &Planet) {  // Makes pl1 the new planet
-    // Conservation of momentum
-    let total_mass = pl1.mass + pl2.mass;
-    let total_momentum = pl1.mass * pl1.velocity + pl2.mass * pl2.velocity;
-    pl1.radius = tools::inverse_volume_of_sphere(total_mass/PLANET_DENSITY);
-    // Use centre of mass as new position
-    pl1.position = Point2::new(
-      (pl1.position.x * pl1.mass + pl2.position.x * pl2.mass)/total_mass,
-      (pl1.position.y * pl1.mass + pl2.position.y * pl2.mass)/total_mass
-    );
-    pl1.velocity = total_momentum/total_mass;   // Inelastic collision
-    pl1.mass = total_mass;
-    pl1.update_color(); // Will have changed colour due to increase in mass
+  // Circle showing the orbit a right-click spawned body will follow
+  fn draw_orbit_spawn_preview(&self, ctx: &mut Context, canvas: &mut Canvas, host_id: usize) -> GameResult {
+    if let Some(host) = self.world.planets.get(&host_id) {
+      let host_position = host.borrow().position;
+      let orbit_radius = (self.mouse_info.current_drag_position - host_position).magnitude();
+      if orbit_radius > 1.0 {
+        let orbit = Mesh::new_circle(
+          ctx,
+          DrawMode::stroke(1.0),
+          host_position,
+          orbit_radius,
+          0.5,
+          [0.0, 1.0, 1.0, 0.4].into()
+        )?;
+        canvas.draw(&orbit, DrawParam::default());
+      }
+    }
+    Ok(())
   }
 
-  fn spawn_square_of_planets(
-    &mut self,
-    top_left: Point2<f32>,
-    w: u16,
-    h: u16,
-    gap: f32,
-    rad: f32,
-  ) {
-    for i in 0..w {
-      for j in 0..h {
-        self.add_planet(
-          Point2::new(top_left.x + i as f32 * gap, top_left.y + j as f32 * gap),
-          None,
-          None,
-          rad,
-          None,
-        );
+  // Ghost of the planet that would be spawned, following the cursor
+  // Shows a red cursor instead when erasing
+  fn draw_spawn_ghost(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+    let (radius, color) = if self.tool == Tool::Eraser {
+      (ERASER_CURSOR_RADIUS, [1.0, 0.2, 0.2, 0.8])
+    } else {
+      (self.spawn_radius, [1.0, 1.0, 1.0, 0.3])
+    };
+
+    let ghost = Mesh::new_circle(
+      ctx,
+      DrawMode::stroke(1.0),
+      self.mouse_info.current_drag_position,
+      radius,
+      0.1,
+      color.into()
+    )?;
+    canvas.draw(&ghost, DrawParam::default());
+    Ok(())
+  }
+
+  // Ghosts of the binary brush's pair, joined through their barycentre
+  fn draw_binary_preview(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+    let centre = self.mouse_info.down_pos;
+    let (masses, radii, separation, angle) = self.binary_layout(centre, self.mouse_info.current_drag_position);
+    let total = masses.0 + masses.1;
+    let heavier = centre + tools::get_components(separation * masses.1/total, angle);
+    let lighter = centre - tools::get_components(separation * masses.0/total, angle);
+
+    let mut mesh = MeshBuilder::new();
+    mesh.circle(DrawMode::stroke(1.0), heavier, radii.0, 0.1, [1.0, 1.0, 1.0, 0.3].into())?;
+    mesh.circle(DrawMode::stroke(1.0), lighter, radii.1, 0.1, [1.0, 1.0, 1.0, 0.3].into())?;
+    mesh.line(&[heavier, lighter], 0.5, [0.0, 1.0, 1.0, 0.4].into())?;
+    canvas.draw(&Mesh::from_data(ctx, mesh.build()), DrawParam::default());
+    Ok(())
+  }
+
+  // The box being dragged with the grid brush, and ghosts of the bodies it will spawn
+  fn draw_grid_preview(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+    let (corner, opposite) = (self.mouse_info.down_pos, self.mouse_info.current_drag_position);
+    render::draw_selection_box(corner, opposite, ctx, canvas)?;
+
+    let points = brush::grid_points(corner, opposite, self.spawn_radius * GRID_SPACING_RADII);
+    if points.is_empty() { return Ok(()) }
+    let mut mesh = MeshBuilder::new();
+    for point in points {
+      mesh.circle(DrawMode::stroke(1.0), point, self.spawn_radius, 0.1, [1.0, 1.0, 1.0, 0.3].into())?;
+    }
+    canvas.draw(&Mesh::from_data(ctx, mesh.build()), DrawParam::default());
+    Ok(())
+  }
+
+  // bigger = true to increase. Density is changed instead of radius when shift is held.
+  fn change_spawn_size(&mut self, bigger: bool, change_density: bool) {
+    if change_density {
+      self.spawn_density = (self.spawn_density * if bigger { SPAWN_DENSITY_STEP } else { 1.0/SPAWN_DENSITY_STEP })
+        .clamp(SPAWN_DENSITY_RANGE.0, SPAWN_DENSITY_RANGE.1);
+    } else {
+      self.spawn_radius = (self.spawn_radius * if bigger { SPAWN_RADIUS_STEP } else { 1.0/SPAWN_RADIUS_STEP })
+        .clamp(SPAWN_RADIUS_RANGE.0, SPAWN_RADIUS_RANGE.1);
+    }
+  }
+
+  // Cycles the global trail style, or the selected planet's own trail style if shift is held
+  fn cycle_trail_style(&mut self, selected_only: bool) {
+    if selected_only {
+      if let Some(trail) = self.selected_planet.and_then(|id| self.planet_trails.get(&id)) {
+        let mut trail = trail.borrow_mut();
+        trail.style = Some(trail.style.unwrap_or(self.trail_style).next());
       }
+    } else {
+      self.trail_style = self.trail_style.next();
     }
   }
 
+  fn save(&self) {
+    let state = SaveState {
+      world: &self.world,
+    };
+
+    match state.write_to_file(SAVE_FILE_PATH) {
+      Ok(()) => println!("Saved simulation to {}", SAVE_FILE_PATH),
+      Err(e) => println!("WARNING: Could not save simulation to {}: {}", SAVE_FILE_PATH, e),
+    }
+  }
+
+  fn load(&mut self) {
+    self.load_from(SAVE_FILE_PATH);
+  }
+
+  fn load_from(&mut self, path: &str) {
+    let state = match SaveState::<World>::read_from_file(path) {
+      Ok(state) => state,
+      Err(e) => {
+        println!("WARNING: Could not load simulation from {}: {}", path, e);
+        return
+      }
+    };
+
+    self.world = state.world;
+    self.world.use_gpu = self.use_gpu;
+    self.last_loaded = Some(path.to_string());
+    self.diagnostics_baseline = None;
+    self.clear_trails();    // Trails start fresh
+    self.trail_frame = None;
+    self.bursts.clear();
+    self.impact_feedback.clear();
+    self.comet_tails.clear();
+    self.merge_animations.clear();
+  }
+
+  fn clear_trails(&mut self) {
+    for (_, trail) in self.planet_trails.drain() {
+      self.trail_pool.recycle(trail.into_inner());
+    }
+  }
+
+  // Where trail nodes are measured from: the reference body, or the world origin
+  fn trail_origin(&self) -> Point2<f32> {
+    self.trail_frame
+      .and_then(|id| self.world.planets.get(&id))
+      .map_or(Point2::origin(), |planet| planet.borrow().position)
+  }
+
+  // Records trails relative to the selected body, or back in world space if they already are.
+  // Existing trails are cleared, as their nodes are in the old frame.
+  fn toggle_trail_frame(&mut self) {
+    self.trail_frame = if self.trail_frame.is_some() { None } else { self.selected_planet };
+    self.clear_trails();
+  }
+
   fn update_planet_trails(&mut self, dt_duration: &Duration) {
+    // Back to world space if the reference body is gone
+    if self.trail_frame.map_or(false, |id| !self.world.planets.contains_key(&id)) {
+      self.trail_frame = None;
+      self.clear_trails();
+    }
+    let origin = self.trail_origin();
+
+    // Give new planets a trail
+    for (id, planet) in self.world.planets.iter() {
+      let pool = &mut self.trail_pool;
+      self.planet_trails.entry(*id)
+        .or_insert_with(|| RefCell::new(PlanetTrail::new(&TrailSource::from(&*planet.borrow()).relative_to(origin), pool)));
+    }
+
     for (id, trail) in self.planet_trails.iter_mut() {
       trail.borrow_mut().update(
         dt_duration,
-        if let Some(planet) = self.planets.get(&id) {
-          Some(planet.borrow().position)
-        } else {
-          None
+        self.world.planets.get(&id).map(|planet| TrailSource::from(&*planet.borrow()).relative_to(origin)),
+        &self.world.config.trails,
+        self.world.reversed,
+      );
+    }
+
+    // Comet tails are in world space whatever the trail frame, as they point away from a star
+    let dt = dt_duration.as_secs_f32();
+    for (id, planet) in self.world.planets.iter() {
+      let planet = planet.borrow();
+      if planet.kind == BodyKind::Asteroid && !self.comet_tails.contains_key(id) {
+        if comet_tail::heating(&self.world, &planet).is_some() {
+          self.comet_tails.insert(*id, CometTail::default());
+        }
+      }
+    }
+    for (id, tail) in self.comet_tails.iter_mut() {
+      let planet = self.world.planets.get(id).map(|planet| planet.borrow());
+      let comet = planet.as_ref()
+        .filter(|planet| planet.kind == BodyKind::Asteroid)
+        .map(|planet| (&**planet, comet_tail::heating(&self.world, planet)));
+      tail.update(dt, comet);
+    }
+    self.comet_tails.retain(|_, tail| !tail.is_dead());
+  }
+
+  // Live input. Recorded if a recording is running, and ignored while a replay is playing.
+  fn input(&mut self, event: InputEvent) {
+    if self.player.is_some() { return }
+    if let Some(recording) = self.recording.as_mut() {
+      recording.record(self.frame, event);
+    }
+    self.handle_input(event);
+  }
+
+  fn handle_input(&mut self, event: InputEvent) {
+    match event {
+      InputEvent::MouseDown { button, x, y, alt } => self.mouse_down(button.into(), Point2::new(x, y), alt),
+      InputEvent::MouseUp { button, x, y, shift } => self.mouse_up(button.into(), Point2::new(x, y), shift),
+      InputEvent::MouseMotion { x, y } => self.update_drag_position(Point2::new(x, y)),
+      InputEvent::MouseWheel { x, y, cursor_x, cursor_y, shift, ctrl } => {
+        if ctrl {
+          // Trackpad pinches arrive as scrolling with ctrl held
+          self.camera.zoom_at(Point2::new(cursor_x, cursor_y), ZOOM_STEP.powf(y));
+          self.update_drag_position(Point2::new(cursor_x, cursor_y));
+        } else if self.trackpad_mode {
+          self.camera.pan(Vector2::new(x, y) * TRACKPAD_PAN_SPEED);
+          self.update_drag_position(Point2::new(cursor_x, cursor_y));
+        } else if y != 0.0 {
+          self.change_spawn_size(y > 0.0, shift);
+        }
+      },
+      // A single finger acts as the left mouse button
+      InputEvent::Touch { phase, x, y } => {
+        let pos = Point2::new(x, y);
+        match phase {
+          Touch::Started => {
+            self.handle_input(InputEvent::MouseMotion { x, y });
+            self.mouse_down(MouseButton::Left, pos, false);
+          },
+          Touch::Moved => self.handle_input(InputEvent::MouseMotion { x, y }),
+          Touch::Ended => self.mouse_up(MouseButton::Left, pos, false),
+          // Keep what a stream already emitted, but don't spawn for a cancelled tap or drag
+          Touch::Cancelled if self.stream.is_some() => self.mouse_up(MouseButton::Left, pos, false),
+          Touch::Cancelled => self.mouse_info.down = false,
+        }
+      },
+      InputEvent::Action(action) => self.apply_action(action),
+    }
+  }
+
+  fn update_drag_position(&mut self, screen_pos: Point2<f32>) {
+    self.mouse_info.current_drag_position = self.camera.screen_to_world(screen_pos);
+    if self.mouse_info.down && self.mouse_info.tool == Some(Tool::Eraser) {
+      self.erase_at(self.mouse_info.current_drag_position);
+    }
+    if let (true, Some(grab)) = (self.mouse_info.down, self.mouse_info.grab) {
+      self.move_grabbed(grab, self.mouse_info.current_drag_position);
+    }
+  }
+
+  // What a click at point picks up while paused: the selected planet's velocity handle, then any planet
+  fn grab_at(&self, point: Point2<f32>) -> Option<Grab> {
+    let handle_grabbed = self.selected_planet
+      .and_then(|id| self.world.planets.get(&id))
+      .map_or(false, |planet| {
+        let planet = planet.borrow();
+        (edit::velocity_handle(planet.position, planet.velocity) - point).magnitude() <= HANDLE_RADIUS
+      });
+    if handle_grabbed {
+      return self.selected_planet.map(|id| Grab::Velocity { id })
+    }
+
+    self.world.planet_at(point).map(|id| Grab::Position {
+      id,
+      offset: self.world.planets[&id].borrow().position - point,
+    })
+  }
+
+  fn move_grabbed(&mut self, grab: Grab, cursor: Point2<f32>) {
+    if let Some(planet) = self.world.planets.get(&grab.id()) {
+      let mut planet = planet.borrow_mut();
+      match grab {
+        Grab::Position { offset, .. } => {
+          planet.position = cursor + offset;
+          planet.previous_position = planet.position;
+        },
+        Grab::Velocity { .. } => planet.velocity = (cursor - planet.position)/VELOCITY_HANDLE_SCALE,
+      }
+    }
+    self.after_edit();
+  }
+
+  // The tool a press of button uses. Left uses the active tool (Alt: the eraser). Right-drag is a shortcut for
+  // spawning orbits (or rings, with the ring tool active), and middle-drag always box selects.
+  // Buttons can be given other tools with mouse_bindings in the settings file.
+  fn tool_for(&self, button: MouseButton, alt: bool) -> Option<Tool> {
+    let name = format!("{}{:?}", if alt { "Alt+" } else { "" }, button);
+    if let Some(&tool) = self.settings.mouse_bindings.get(&name) {
+      return Some(tool)
+    }
+    match button {
+      MouseButton::Left if alt => Some(Tool::Eraser),
+      MouseButton::Left => Some(self.tool),
+      MouseButton::Right if self.tool == Tool::Ring => Some(Tool::Ring),
+      MouseButton::Right => Some(Tool::Orbit),
+      MouseButton::Middle => Some(Tool::Select),
+      _ => None,
+    }
+  }
+
+  fn mouse_down(&mut self, button: MouseButton, screen_pos: Point2<f32>, alt: bool) {
+    // Clicking the minimap moves the view there instead
+    if self.show_minimap && button == MouseButton::Left && minimap::screen_rect().contains(screen_pos) {
+      let transform = MinimapTransform::new(&self.world, self.camera.view());
+      self.camera.look_at(transform.map_to_world(screen_pos));
+      self.update_drag_position(screen_pos);
+      return
+    }
+
+    self.mouse_info.down = true;
+    self.mouse_info.button_down = button;
+    self.mouse_info.down_pos = self.camera.screen_to_world(screen_pos);
+    self.mouse_info.tool = self.tool_for(button, alt);
+    self.mouse_info.selecting = false;
+    self.mouse_info.boxing = false;
+    self.mouse_info.launch_from = None;
+    self.mouse_info.orbit_host = None;
+    self.mouse_info.grab = None;
+
+    let tool = match self.mouse_info.tool {
+      Some(tool) => tool,
+      None => return,
+    };
+
+    // Left dragging a planet moves it with the select tool, or with any spawning tool while paused
+    let grab = if button == MouseButton::Left && tool != Tool::Eraser && (self.paused || tool == Tool::Select) {
+      self.grab_at(self.mouse_info.down_pos)
+    } else {
+      None
+    };
+    if let Some(grab) = grab {
+      self.selected_planet = Some(grab.id());
+      self.mouse_info.selecting = true;
+      self.mouse_info.grab = Some(grab);
+      return
+    }
+
+    match tool {
+      Tool::Eraser => self.erase_at(self.mouse_info.down_pos),
+      Tool::Launch | Tool::Stream => {
+        // Clicking on a planet selects it instead of spawning a new one
+        self.selected_planet = self.world.planet_at(self.mouse_info.down_pos);
+        self.mouse_info.selecting = self.selected_planet.is_some();
+        // Dragging out of the planet instead launches a body from it
+        self.mouse_info.launch_from = self.selected_planet.map(|id| {
+          (id, self.mouse_info.down_pos - self.world.planets[&id].borrow().position)
+        });
+        if !self.mouse_info.selecting && tool == Tool::Stream {
+          self.stream = Some(StreamStroke::new());
+        }
+      },
+      // Dragging from a planet spawns bodies orbiting it
+      Tool::Orbit | Tool::Ring => self.mouse_info.orbit_host = self.world.planet_at(self.mouse_info.down_pos),
+      // Draws a box, selecting every planet it touches when released
+      Tool::Select => self.mouse_info.boxing = true,
+      Tool::Grid | Tool::Binary => (),
+    }
+  }
+
+  fn erase_at(&mut self, point: Point2<f32>) {
+    if let Some(id) = self.world.planet_at(point) {
+      self.delete_planet(id);
+    }
+  }
+
+  // shift spawns orbits anticlockwise, and adds box selections to the group
+  fn mouse_up(&mut self, _button: MouseButton, screen_pos: Point2<f32>, shift: bool) {
+    if !self.mouse_info.down { return }   // Went down over the GUI
+    self.mouse_info.down = false;
+    if self.mouse_info.grab.take().is_some() { return }
+    let tool = match self.mouse_info.tool.take() {
+      Some(tool) => tool,
+      None => return,
+    };
+    let release_pos = self.camera.screen_to_world(screen_pos);
+
+    if let Some(stream) = self.stream.take() {
+      if !stream.spawned.is_empty() {
+        self.history.push(Command::SpawnMany(stream.spawned));
+        self.diagnostics_baseline = None;
+      }
+      return
+    }
+
+    match tool {
+      Tool::Eraser => (),
+      Tool::Select => {
+        self.mouse_info.boxing = false;
+        self.box_select(self.mouse_info.down_pos, release_pos, shift);
+      },
+      Tool::Grid => self.spawn_grid(self.mouse_info.down_pos, release_pos),
+      Tool::Binary => self.spawn_binary(self.mouse_info.down_pos, release_pos, !shift),
+      Tool::Ring => if let Some(host_id) = self.mouse_info.orbit_host.take() {
+        self.spawn_ring(host_id, release_pos, !shift);
+      },
+      Tool::Orbit => if let Some(host_id) = self.mouse_info.orbit_host.take() {
+        self.spawn_orbiting(host_id, release_pos, !shift);
+      },
+      Tool::Launch | Tool::Stream => if let Some((position, velocity)) = self.launch(release_pos) {
+        self.mouse_info.launch_from = None;
+        self.spawn_at(position, velocity);
+      } else if !self.mouse_info.selecting {
+        let velocity = self.launch_velocity(self.mouse_info.down_pos, self.mouse_info.down_pos - release_pos, None);
+        self.spawn_at(self.mouse_info.down_pos, velocity);
+      },
+    }
+  }
+
+  // The picked template, if the spawn settings haven't been changed since it was picked
+  fn active_template(&self) -> Option<&Template> {
+    let template = self.templates.get(self.spawn_template?)?;
+    let unchanged = template.kind == self.spawn_kind && template.density == self.spawn_density &&
+      template.radius() == self.spawn_radius;
+    unchanged.then_some(template)
+  }
+
+  fn select_template(&mut self, index: Option<usize>) {
+    let template = match index {
+      Some(i) => match self.templates.get(i) {
+        Some(template) => template.clone(),
+        None => {
+          println!("WARNING: There are only {} spawn templates", self.templates.len());
+          return
         },
+      },
+      None => {
+        self.spawn_template = None;
+        return
+      },
+    };
+    self.spawn_template = index;
+    self.spawn_kind = template.kind;
+    self.spawn_density = template.density;
+    self.spawn_radius = template.radius();
+  }
+
+  // Gives a body just spawned with the spawn settings the active template's colour and trail
+  fn apply_template(&mut self, id: usize) {
+    let template = match self.active_template() {
+      Some(template) => template.clone(),
+      None => return,
+    };
+    let planet = match self.world.planets.get(&id) {
+      Some(planet) => planet,
+      None => return,
+    };
+    if let Some(color) = template.color {
+      planet.borrow_mut().color = color;
+    }
+    if template.trail.is_some() {
+      let origin = self.trail_origin();
+      let pool = &mut self.trail_pool;
+      let trail = self.planet_trails.entry(id)
+        .or_insert_with(|| RefCell::new(PlanetTrail::new(&TrailSource::from(&*planet.borrow()).relative_to(origin), pool)));
+      trail.borrow_mut().config = template.trail;
+    }
+  }
+
+  // A body with the current spawn settings
+  fn spawn_at(&mut self, position: Point2<f32>, velocity: Vector2<f32>) {
+    let id = self.world.add_body(
+      self.spawn_kind,
+      position,
+      Some(velocity),
+      Some(tools::volume_of_sphere(self.spawn_radius) * self.spawn_density),
+      self.spawn_radius,
+      None,
+    );
+    self.apply_template(id);
+    self.record_spawn(id);
+  }
+
+  fn spawn_orbiting(&mut self, host_id: usize, position: Point2<f32>, clockwise: bool) {
+    let spawned = self.world.add_orbiting_planet(
+      host_id,
+      position,
+      tools::volume_of_sphere(self.spawn_radius) * self.spawn_density,
+      self.spawn_radius,
+      clockwise,
+    );
+    if let Some(id) = spawned {
+      self.world.set_kind(id, self.spawn_kind);
+      self.apply_template(id);
+      self.record_spawn(id);
+    }
+  }
+
+  // A ring around the host passing through position
+  fn spawn_ring(&mut self, host_id: usize, position: Point2<f32>, clockwise: bool) {
+    let orbit_radius = match self.world.planets.get(&host_id) {
+      Some(host) => (position - host.borrow().position).magnitude(),
+      None => return,
+    };
+    let ids = self.world.add_ring(
+      host_id,
+      orbit_radius,
+      self.ring_count,
+      tools::volume_of_sphere(self.spawn_radius) * self.spawn_density,
+      self.spawn_radius,
+      clockwise,
+    );
+    let planets = ids.iter()
+      .map(|id| {
+        self.world.set_kind(*id, self.spawn_kind);
+        self.apply_template(*id);
+        self.world.planets[id].borrow().clone()
+      })
+      .collect::<Vec<_>>();
+    if !planets.is_empty() {
+      self.history.push(Command::SpawnMany(planets));
+      self.diagnostics_baseline = None;
+    }
+  }
+
+  // The binary brush's pair for a drag from centre to release: masses and radii (heavier first), separation and
+  // the angle from the centre to the heavier body. Together the pair weighs as much as two spawn bodies.
+  fn binary_layout(&self, centre: Point2<f32>, release: Point2<f32>) -> ((f32, f32), (f32, f32), f32, f32) {
+    let total = 2.0 * tools::volume_of_sphere(self.spawn_radius) * self.spawn_density;
+    let masses = brush::binary_masses(total, self.binary_mass_ratio);
+    let radii = (
+      tools::inverse_volume_of_sphere(masses.0/self.spawn_density),
+      tools::inverse_volume_of_sphere(masses.1/self.spawn_density),
+    );
+    let drag = release - centre;
+    let separation = drag.magnitude().max(MIN_BINARY_SEPARATION_RADII * self.spawn_radius);
+    let angle = if drag.magnitude_squared() > 0.0 { tools::get_angle(drag) } else { 0.0 };
+    (masses, radii, separation, angle)
+  }
+
+  // A bound pair orbiting the point the drag started at, undone in one go
+  fn spawn_binary(&mut self, centre: Point2<f32>, release: Point2<f32>, clockwise: bool) {
+    let (masses, radii, separation, angle) = self.binary_layout(centre, release);
+    let (id1, id2) = match self.world.add_binary(centre, Vector2::new(0.0, 0.0), masses, radii, separation, angle, clockwise) {
+      Some(ids) => ids,
+      None => {
+        println!("WARNING: Binary bodies would overlap, drag further apart");
+        return
+      },
+    };
+    let planets = [id1, id2].iter()
+      .map(|id| {
+        self.world.set_kind(*id, self.spawn_kind);
+        self.apply_template(*id);
+        self.world.planets[id].borrow().clone()
+      })
+      .collect::<Vec<_>>();
+    self.history.push(Command::SpawnMany(planets));
+    self.diagnostics_baseline = None;
+  }
+
+  // Stationary bodies filling the box, undone in one go
+  fn spawn_grid(&mut self, corner: Point2<f32>, opposite: Point2<f32>) {
+    let points = brush::grid_points(corner, opposite, self.spawn_radius * GRID_SPACING_RADII);
+    if points.is_empty() {
+      println!("WARNING: Grid too big, make the spawn radius bigger or the box smaller");
+      return
+    }
+    let mass = tools::volume_of_sphere(self.spawn_radius) * self.spawn_density;
+    let planets = points.into_iter()
+      .map(|position| {
+        let id = self.world.add_body(self.spawn_kind, position, None, Some(mass), self.spawn_radius, None);
+        self.apply_template(id);
+        self.world.planets[&id].borrow().clone()
+      })
+      .collect::<Vec<_>>();
+    self.history.push(Command::SpawnMany(planets));
+    self.diagnostics_baseline = None;
+  }
+
+  // shift adds to the current group
+  fn box_select(&mut self, corner: Point2<f32>, opposite: Point2<f32>, shift: bool) {
+    let picked = self.world.planets_in_rect(corner, opposite);
+    if shift {
+      self.group.extend(picked);
+      self.group.sort_unstable();
+      self.group.dedup();
+    } else {
+      self.group = picked;
+    }
+    if !self.group.is_empty() {
+      self.selected_planet = None;
+    }
+  }
+
+  // Where a body launched from the planet the drag started on would spawn, and its velocity, if the cursor has left
+  // that planet. It moves with the planet, slingshot-style away from the cursor.
+  fn launch(&self, cursor: Point2<f32>) -> Option<(Point2<f32>, Vector2<f32>)> {
+    let (host_id, offset) = self.mouse_info.launch_from?;
+    let host = self.world.planets.get(&host_id)?.borrow();
+    if (cursor - host.position).magnitude() <= host.radius { return None }
+
+    let drag = host.position + offset - cursor;
+    let direction = drag.try_normalize(f32::EPSILON)?;
+    let position = host.position + direction * (host.radius + self.spawn_radius + LAUNCH_GAP);
+    Some((position, self.launch_velocity(position, drag, Some(&host))))
+  }
+
+  // Velocity of a body spawned at position by a drag, relative to the host it's launched from (if any).
+  // The drag is scaled by launch_scale, or with snapping on, only its direction is kept and the speed is
+  // that of a circular orbit around the host, or the nearest body.
+  fn launch_velocity(&self, position: Point2<f32>, drag: Vector2<f32>, host: Option<&Planet>) -> Vector2<f32> {
+    let base = host.map_or(Vector2::new(0.0, 0.0), |host| host.velocity);
+    if self.snap_launch {
+      let nearest = self.world.nearest_planet(position).map(|id| self.world.planets[&id].borrow());
+      let orbited = host.map(|host| (host.position, host.velocity, host.mass))
+        .or_else(|| nearest.map(|pl| (pl.position, pl.velocity, pl.mass)));
+      if let (Some((host_position, host_velocity, host_mass)), Some(direction)) = (orbited, drag.try_normalize(f32::EPSILON)) {
+        let distance = (position - host_position).magnitude();
+        if distance > 0.0 {
+          return host_velocity + direction * tools::circular_orbit_speed(self.world.config.g, host_mass, distance)
+        }
+      }
+    }
+    base + drag * self.launch_scale
+  }
+
+  // Emits the bodies a stream stroke is due, at the cursor and moving with it
+  fn update_stream(&mut self, dt: Duration) {
+    let count = match self.stream.as_mut() {
+      Some(stream) => stream.due(dt, self.stream_rate),
+      None => return,
+    };
+
+    for _ in 0..count {
+      let id = self.world.add_body(
+        self.spawn_kind,
+        self.mouse_info.current_drag_position,
+        Some(self.mouse_info.cursor_velocity),
+        Some(tools::volume_of_sphere(self.spawn_radius) * self.spawn_density),
+        self.spawn_radius,
+        None,
       );
+      self.apply_template(id);
+      let planet = self.world.planets[&id].borrow().clone();
+      if let Some(stream) = self.stream.as_mut() {
+        stream.spawned.push(planet);
+      }
+    }
+  }
+
+  fn apply_action(&mut self, action: Action) {
+    match action {
+      Action::ToggleDiagnostics => self.show_diagnostics = !self.show_diagnostics,
+      Action::ToggleVectorDebug => self.show_vector_debug = !self.show_vector_debug,
+      Action::ToggleForceVectors => self.vector_debug.show_force = !self.vector_debug.show_force,
+      Action::ChangeVectorScale { larger } => {
+        let factor = if larger { VECTOR_SCALE_STEP } else { 1.0/VECTOR_SCALE_STEP };
+        self.apply_action(Action::SetVectorScale(self.vector_debug.scale * factor));
+      },
+      Action::SetVectorScale(scale) => self.vector_debug.scale = scale.max(VECTOR_SCALE_RANGE.0).min(VECTOR_SCALE_RANGE.1),
+      Action::ToggleInfluenceOverlay => self.show_influence = !self.show_influence,
+      Action::ToggleHeatmap => self.show_heatmap = !self.show_heatmap,
+      Action::ToggleWarpGrid => self.show_warp_grid = !self.show_warp_grid,
+      Action::ToggleFieldArrows => self.show_field_arrows = !self.show_field_arrows,
+      Action::ToggleMinimap => self.show_minimap = !self.show_minimap,
+      Action::ToggleMute => self.audio.toggle_mute(),
+      Action::SetVolume(volume) => self.audio.set_volume(volume),
+      Action::SetImpactEffects(effects) => self.impact_effects = effects,
+      Action::ToggleLabels => self.show_labels = !self.show_labels,
+      Action::ToggleExtrapolation => self.extrapolate = !self.extrapolate,
+      Action::ToggleTrackpadMode => self.trackpad_mode = !self.trackpad_mode,
+      Action::CycleColorMode => self.color_mode = self.color_mode.next(),
+      Action::ToggleEditMode => self.toggle_edit_mode(),
+      Action::Nudge { x, y, velocity } => self.nudge_selected(Vector2::new(x, y), velocity),
+      Action::ScaleMass { bigger } => self.scale_selected_mass(if bigger { MASS_SCALE_STEP } else { 1.0/MASS_SCALE_STEP }),
+      Action::ToggleInfoDebug => self.show_planet_info_debug = !self.show_planet_info_debug,
+      Action::Restart => self.with_history(Self::restart),
+      Action::ResetToStart => self.load_scenario(self.start_scenario),
+      Action::ReverseTime => self.world.reverse_time(),
+      Action::ReloadFile => match self.last_loaded.clone() {
+        Some(path) => self.load_from(&path),
+        None => println!("WARNING: No save file loaded yet"),
+      },
+      Action::Clear => self.with_history(Self::clear),
+      Action::Undo => self.undo(),
+      Action::Redo => self.redo(),
+      Action::DeleteSelected => self.delete_selected(),
+      Action::CheckStability => self.check_stability(),
+      Action::Deselect => {
+        self.selected_planet = None;
+        self.group.clear();
+      },
+      Action::ExportTrajectories => self.export_trajectories(),
+      Action::ClearTrajectories => self.trajectories.clear(),
+      Action::SaveEventLog => self.save_event_log(),
+      Action::ClearEventLog => self.event_log.clear(),
+      Action::MergeGroup => self.merge_group(),
+      Action::OffsetGroupVelocity { x, y } => self.offset_group_velocity(Vector2::new(x, y)),
+      Action::ScaleGroupMass(factor) => self.scale_group_mass(factor),
+      Action::CycleIntegrator => self.world.integrator = self.world.integrator.next(),
+      Action::ToggleAdaptiveTimestep => self.world.adaptive_timestep = !self.world.adaptive_timestep,
+      Action::CycleCollisionMode => self.world.collision_mode = self.world.collision_mode.next(),
+      Action::CycleMergeGeometry => self.world.config.merge_geometry = self.world.config.merge_geometry.next(),
+      Action::SetMergeGeometry(geometry) => self.world.config.merge_geometry = geometry,
+      Action::SetPlanetColoring(coloring) => self.world.config.planet_coloring = coloring,
+      Action::SetPaletteColor { index, color } => if let Some(entry) = self.world.config.palette.get_mut(index) {
+        *entry = color;
+      },
+      Action::ToggleFragmentation => self.world.fragmentation_threshold = match self.world.fragmentation_threshold {
+        Some(_) => None,
+        None => Some(DEFAULT_FRAGMENTATION_THRESHOLD),
+      },
+      Action::ToggleTidalDisruption => self.world.tidal_disruption = !self.world.tidal_disruption,
+      Action::SetSoftBodies(config) => self.world.soft_bodies = config,
+      Action::ToggleMassTransfer => self.world.mass_transfer = match self.world.mass_transfer {
+        Some(_) => None,
+        None => Some(DEFAULT_MASS_TRANSFER),
+      },
+      Action::SetMassTransfer(fraction) => self.world.mass_transfer = fraction,
+      Action::ChangeG { stronger } => self.change_g(stronger),
+      Action::ChangeSoftening { larger } => self.change_softening(larger),
+      Action::ToggleScenarioMenu => self.show_scenario_menu = !self.show_scenario_menu,
+      Action::LoadScenario(index) => self.load_scenario(index),
+      Action::Save => self.save(),
+      Action::Load => self.load(),
+      Action::TogglePause => self.paused = !self.paused,
+      Action::CycleSpawnKind => self.cycle_spawn_kind(),
+      Action::ToggleFocus { barycentre } => self.toggle_focus(barycentre),
+      Action::CycleTrailStyle { selected_only } => self.cycle_trail_style(selected_only),
+      Action::CycleTrailColoring => self.trail_coloring = self.trail_coloring.next(),
+      Action::SetTrailColoring(coloring) => self.trail_coloring = coloring,
+      Action::ToggleTrailFrame => self.toggle_trail_frame(),
+      Action::ToggleLaunchSnap => self.snap_launch = !self.snap_launch,
+      Action::SetLaunchScale(scale) => self.launch_scale = scale.max(LAUNCH_SCALE_RANGE.0).min(LAUNCH_SCALE_RANGE.1),
+      Action::ChangeSpawnSize { bigger, change_density } => self.change_spawn_size(bigger, change_density),
+      Action::ChangeTimeScale { faster } => self.change_time_scale(faster),
+      Action::ToggleGui => self.show_gui = !self.show_gui,
+      Action::ToggleHelp => self.show_help = !self.show_help,
+      Action::Ignore => (),
+      Action::ToggleEraser => self.tool = if self.tool == Tool::Eraser { Tool::Launch } else { Tool::Eraser },
+      Action::CycleTool => self.tool = self.tool.next(),
+      Action::ToggleDriftCancelling => {
+        self.world.cancel_drift = !self.world.cancel_drift;
+        self.diagnostics_baseline = None;   // Momentum changes frame
+      },
+      Action::ToggleRecentring => self.world.recentre_on = match self.world.recentre_on {
+        Some(_) => None,
+        None => Some(Point2::new(SCREEN_DIMS.0/2.0, SCREEN_DIMS.1/2.0)),
+      },
+      Action::ToggleWrap => {
+        self.world.wrap_bounds = match self.world.wrap_bounds {
+          Some(_) => None,
+          None => Some(self.world.config.wrap_size.unwrap_or(SCREEN_DIMS)),
+        };
+        self.diagnostics_baseline = None;   // Potential energy changes with the distances
+      },
+      Action::SetTool(tool) => self.tool = tool,
+      Action::SetStreamRate(rate) => self.stream_rate = rate.max(0.0),
+      Action::SetRingCount(count) => self.ring_count = count.max(1),
+      Action::RestoreSnapshot => if let Some(path) = self.recovery.take() {
+        self.load_from(&path.to_string_lossy());
+      },
+      Action::DismissRecovery => self.recovery = None,
+      Action::SetBinaryMassRatio(ratio) => self.binary_mass_ratio = ratio.max(0.01).min(1.0),
+      Action::SetSpawnKind(kind) => {
+        self.spawn_kind = kind;
+        self.spawn_density = kind.density();
+      },
+      Action::SelectTemplate(index) => self.select_template(index),
+      Action::SetSpawnRadius(radius) => self.spawn_radius = radius.max(SPAWN_RADIUS_RANGE.0).min(SPAWN_RADIUS_RANGE.1),
+      Action::SetSpawnDensity(density) => self.spawn_density = density.clamp(SPAWN_DENSITY_RANGE.0, SPAWN_DENSITY_RANGE.1),
+      Action::SetPhysicsRate(rate) => self.set_physics_rate(rate),
+      Action::SetTimeScale(time_scale) => self.world.config.time_scale = time_scale,
+      Action::SetG(g) => self.world.config.g = g,
+      Action::SetSoftening(softening) => self.world.config.softening = softening.max(0.0),
+      Action::SetTheta(theta) => self.world.barnes_hut_theta = theta.max(0.0),
+      Action::SetIntegrator(integrator) => self.world.integrator = integrator,
+      Action::SetCollisionMode(mode) => self.world.collision_mode = mode,
+      Action::SetFragmentationThreshold(threshold) => self.world.fragmentation_threshold = threshold,
+      Action::SetTestParticleMass(mass) => self.world.test_particle_mass = mass,
+      Action::SetDespawnRadius(radius) => self.world.config.despawn_radius = radius,
+      Action::SetDespawnSpeed(speed) => self.world.config.despawn_speed = speed,
+      Action::SetWrapBounds((width, height)) => if self.world.wrap_bounds.is_some() {
+        self.world.wrap_bounds = Some((width.max(MIN_WRAP_SIZE), height.max(MIN_WRAP_SIZE)));
+      },
+      Action::EditPlanet(edit) => self.edit_planet(&edit),
+      Action::SetTrailConfig { id, config } => if let Some(trail) = self.planet_trails.get(&id) {
+        trail.borrow_mut().config = config;
+      },
+      Action::SetDefaultTrailConfig(config) => self.world.config.trails = config,
+    }
+  }
+
+  // Runs stability::analyse on a copy of the world on another thread, so the sim keeps going meanwhile.
+  // Only one check runs at a time. Starting another cancels the one in progress.
+  fn check_stability(&mut self) {
+    if let Some(id) = self.selected_planet {
+      self.cancel_stability();
+      let world = self.world.clone();
+      let (sender, receiver) = mpsc::channel();
+      let cancel = Arc::new(AtomicBool::new(false));
+      let thread_cancel = Arc::clone(&cancel);
+      thread::spawn(move || {
+        if let Some(report) = stability::analyse(world, id, &StabilityConfig::default(), &thread_cancel) {
+          let _ = sender.send(report);
+        }
+      });
+      self.stability_job = Some((id, receiver, cancel));
+      self.stability_report = None;
+    }
+  }
+
+  fn cancel_stability(&mut self) {
+    if let Some((_, _, cancel)) = self.stability_job.take() {
+      cancel.store(true, Ordering::Relaxed);
+    }
+  }
+
+  fn poll_stability(&mut self) {
+    if let Some((_, receiver, _)) = &self.stability_job {
+      match receiver.try_recv() {
+        Ok(report) => {
+          self.stability_report = Some(report);
+          self.stability_job = None;
+        },
+        Err(mpsc::TryRecvError::Empty) => (),
+        Err(mpsc::TryRecvError::Disconnected) => {
+          println!("WARNING: Stability check stopped without a result");
+          self.stability_job = None;
+        },
+      }
+    }
+  }
+
+  fn edit_planet(&mut self, edit: &PlanetEdit) {
+    if let Some(planet) = self.world.planets.get(&edit.id) {
+      let mut planet = planet.borrow_mut();
+      // One field changes at a time from the panel. The others follow so density stays mass/volume.
+      // Edits recorded before density could be edited have none.
+      if edit.density > 0.0 && edit.density != planet.density() {
+        planet.set_density(edit.density);
+      } else if edit.mass != planet.mass {
+        planet.set_mass(edit.mass.max(f32::MIN_POSITIVE));
+      } else if edit.radius != planet.radius {
+        planet.set_radius(edit.radius);
+      }
+      planet.velocity = edit.velocity;
+      planet.angular_velocity = edit.angular_velocity;
+      planet.is_static = edit.is_static;
+      planet.ignores_gravity = edit.ignores_gravity;
+      planet.exerts_no_gravity = edit.exerts_no_gravity;
+      planet.tracked = edit.tracked;
+      planet.color = edit.color;
+      let kind_changed = planet.kind != edit.kind;
+      drop(planet);
+      if kind_changed {
+        self.world.set_kind(edit.id, edit.kind);
+      }
+      self.diagnostics_baseline = None;
+    }
+  }
+
+  fn toggle_edit_mode(&mut self) {
+    match self.edit_mode {
+      EditMode::Off => if self.selected_planet.is_some() {
+        self.edit_mode = EditMode::Editing { was_paused: self.paused };
+        self.paused = true;
+      },
+      EditMode::Editing { .. } => self.stop_editing(),
+    }
+  }
+
+  fn stop_editing(&mut self) {
+    if let EditMode::Editing { was_paused } = self.edit_mode {
+      self.paused = was_paused;
+    }
+    self.edit_mode = EditMode::Off;
+  }
+
+  fn nudge_selected(&mut self, direction: Vector2<f32>, velocity: bool) {
+    let planet = match self.selected_planet.and_then(|id| self.world.planets.get(&id)) {
+      Some(planet) => planet,
+      None => return,
+    };
+    {
+      let mut planet = planet.borrow_mut();
+      if velocity {
+        planet.velocity += direction * NUDGE_SPEED;
+      } else {
+        planet.position += direction * NUDGE_DISTANCE;
+        planet.previous_position = planet.position;   // Don't interpolate the jump
+      }
+    }
+    self.after_edit();
+  }
+
+  fn scale_selected_mass(&mut self, factor: f32) {
+    if let Some(planet) = self.selected_planet.and_then(|id| self.world.planets.get(&id)) {
+      let mut planet = planet.borrow_mut();
+      let mass = planet.mass * factor;
+      planet.set_mass(mass);
+      drop(planet);
+      self.after_edit();
+    }
+  }
+
+  fn export_trajectories(&self) {
+    if self.trajectories.is_empty() {
+      println!("No trajectories to export, tick \"Track trajectory\" for a body first");
+      return
+    }
+    match self.trajectories.write_csv(TRAJECTORY_FILE_PATH) {
+      Ok(()) => println!("Wrote {} trajectory samples to {}", self.trajectories.len(), TRAJECTORY_FILE_PATH),
+      Err(e) => println!("WARNING: Could not write trajectories to {}: {}", TRAJECTORY_FILE_PATH, e),
+    }
+  }
+
+  fn save_event_log(&self) {
+    let lines: Vec<String> = self.event_log.iter().map(|event| event.to_string()).collect();
+    match std::fs::write(EVENT_LOG_FILE_PATH, lines.join("\n") + "\n") {
+      Ok(()) => println!("Wrote {} events to {}", self.event_log.len(), EVENT_LOG_FILE_PATH),
+      Err(e) => println!("WARNING: Could not write events to {}: {}", EVENT_LOG_FILE_PATH, e),
+    }
+  }
+
+  // Group operations are recorded as one change, so a single undo reverts the whole group
+  fn delete_group(&mut self) {
+    let group = std::mem::take(&mut self.group);
+    self.with_history(|state| {
+      for id in group.iter() {
+        if state.world.planets.contains_key(id) {
+          state.world.remove_planet(*id);
+        }
+      }
+    });
+    if self.selected_planet.map_or(false, |id| group.contains(&id)) {
+      self.selected_planet = None;
+    }
+    self.after_edit();
+  }
+
+  fn offset_group_velocity(&mut self, offset: Vector2<f32>) {
+    if self.group.is_empty() { return }
+    self.with_history(|state| {
+      for id in state.group.iter() {
+        if let Some(planet) = state.world.planets.get(id) {
+          planet.borrow_mut().velocity += offset;
+        }
+      }
+    });
+    self.after_edit();
+  }
+
+  fn scale_group_mass(&mut self, factor: f32) {
+    if self.group.is_empty() { return }
+    self.with_history(|state| {
+      for id in state.group.iter() {
+        if let Some(planet) = state.world.planets.get(id) {
+          let mut planet = planet.borrow_mut();
+          let mass = planet.mass * factor;
+          planet.set_mass(mass);
+        }
+      }
+    });
+    self.after_edit();
+  }
+
+  // Combines the group into one body, conserving momentum as in a collision, and selects it
+  fn merge_group(&mut self) {
+    if self.group.len() < 2 { return }
+    let group = std::mem::take(&mut self.group);
+    let mut merged = None;
+    self.with_history(|state| merged = state.world.merge_planets(&group));
+    self.selected_planet = merged;
+    self.after_edit();
+  }
+
+  // Forces are only recomputed by stepping, so do it here to show the new vectors while paused
+  fn after_edit(&mut self) {
+    if self.paused {
+      self.world.update_forces();
+    }
+    self.diagnostics_baseline = None;
+  }
+
+  fn initial_wrap_bounds(config: &SimConfig) -> Option<(f32, f32)> {
+    config.wrap_size.or(if TELEPORT_ON_EDGES { Some(SCREEN_DIMS) } else { None })
+  }
+
+  // Resets the world and all frontend state to a known starting point, so a session can be reproduced
+  fn begin_session(&mut self, seed: u64, scenario: usize, config: SimConfig) {
+    self.world = World::new();
+    self.world.use_gpu = self.use_gpu;
+    self.world.config = config;
+    self.world.seed(seed);
+    self.world.wrap_bounds = Self::initial_wrap_bounds(&config);
+
+    self.clear_trails();
+    self.bursts.clear();
+    self.impact_feedback.clear();
+    self.comet_tails.clear();
+    self.merge_animations.clear();
+    self.mouse_info = MouseInfo::default();
+    self.stream = None;
+    self.tool = Tool::default();
+    self.stream_rate = DEFAULT_STREAM_RATE;
+    self.ring_count = DEFAULT_RING_COUNT;
+    self.binary_mass_ratio = DEFAULT_BINARY_MASS_RATIO;
+    self.selected_planet = None;
+    self.group.clear();
+    self.trail_frame = None;
+    self.trajectories.clear();
+    self.event_log.clear();
+    self.edit_mode = EditMode::Off;
+    self.current_scenario = scenario;
+    self.show_scenario_menu = false;
+    self.spawn_radius = SPAWN_PLANET_RADIUS;
+    self.spawn_kind = BodyKind::Planet;
+    self.spawn_density = self.spawn_kind.density();
+    self.history = History::default();
+    self.camera = Camera::new();
+    self.paused = false;
+    self.physics_accumulator = Duration::ZERO;
+    self.frame = 0;
+
+    self.restart();
+  }
+
+  fn toggle_recording(&mut self) {
+    if self.player.is_some() { return }
+
+    if let Some(recording) = self.recording.take() {
+      match recording.write_to_file(REPLAY_FILE_PATH) {
+        Ok(()) => println!("Saved replay to {}", REPLAY_FILE_PATH),
+        Err(e) => println!("WARNING: Could not save replay to {}: {}", REPLAY_FILE_PATH, e),
+      }
+    } else {
+      let seed = self.seed.unwrap_or_else(rand::random);
+      let config = self.world.config;
+      self.begin_session(seed, self.current_scenario, config);
+      self.recording = Some(Replay::new(seed, self.current_scenario, config, 1.0/self.physics_dt));
+    }
+  }
+
+  fn toggle_replay(&mut self) {
+    if self.recording.is_some() { return }
+
+    if self.player.take().is_none() {
+      match Replay::read_from_file(REPLAY_FILE_PATH) {
+        Ok(replay) => {
+          self.set_physics_rate(replay.physics_rate);
+          self.begin_session(replay.seed, replay.scenario, replay.config);
+          self.player = Some(Player::new(replay));
+        },
+        Err(e) => println!("WARNING: Could not load replay from {}: {}", REPLAY_FILE_PATH, e),
+      }
+    }
+  }
+
+  fn set_physics_rate(&mut self, rate: f32) {
+    self.physics_dt = 1.0/rate.clamp(PHYSICS_RATE_RANGE.0, PHYSICS_RATE_RANGE.1);
+  }
+
+  // How far past the previous physics step to draw bodies, in steps. Interpolating draws between the last two steps,
+  // so bodies are shown up to a step late. Extrapolating continues the last step's motion instead, which is up to
+  // date but can overshoot when a body turns sharply.
+  fn render_alpha(&self) -> f32 {
+    let alpha = self.physics_accumulator.as_secs_f32()/self.physics_dt;
+    if self.extrapolate { alpha + 1.0 } else { alpha }
+  }
+
+  // Flashes a marker where the selected body passes periapsis, seen as its true anomaly going from negative (on the way
+  // in) to positive. The jump from π to -π at apoapsis doesn't count.
+  fn watch_periapsis(&mut self, real_dt: f32) {
+    if let Some((_, age)) = self.periapsis_flash.as_mut() {
+      *age += real_dt;
+    }
+    if self.periapsis_flash.map_or(false, |(_, age)| age > PERIAPSIS_FLASH_TIME) {
+      self.periapsis_flash = None;
+    }
+
+    let orbit = self.selected_planet.and_then(|id| self.world.osculating_orbit(id).map(|orbit| (id, orbit)));
+    let (id, host_id, elements) = match orbit {
+      Some((id, (host_id, elements))) => (id, host_id, elements),
+      None => {
+        self.last_anomaly = None;
+        return
+      },
+    };
+    if let Some((last_id, last)) = self.last_anomaly {
+      if last_id == id && elements.eccentricity > MIN_FLASH_ECCENTRICITY && last < 0.0 && elements.true_anomaly >= 0.0 && elements.true_anomaly - last < PI {
+        let host = self.world.planets[&host_id].borrow().position;
+        self.periapsis_flash = Some((host + tools::get_components(elements.periapsis, elements.argument_of_periapsis), 0.0));
+      }
+    }
+    self.last_anomaly = Some((id, elements.true_anomaly));
+  }
+
+  // Tracks how long the cursor has rested on the same body, for its tooltip. Not while dragging or over the GUI.
+  fn update_hover(&mut self, ctx: &Context, real_dt: f32) {
+    let cursor = ctx.mouse.position();
+    let (x, y) = to_screen_coords(ctx, cursor.x, cursor.y);
+    let over = if self.mouse_info.down || self.gui_wants_pointer() || self.show_help {
+      None
+    } else {
+      self.world.planet_at(self.camera.screen_to_world(Point2::new(x, y)))
+    };
+    self.hover = match (over, self.hover) {
+      (Some(id), Some((hovered, time))) if id == hovered => Some((id, time + real_dt)),
+      (Some(id), _) => Some((id, 0.0)),
+      (None, _) => None,
+    };
+  }
+
+  fn hover_tooltip(&self) -> Option<String> {
+    let (id, _) = self.hover.filter(|(_, time)| *time >= TOOLTIP_DELAY)?;
+    let planet = self.world.planets.get(&id)?.borrow();
+    let attractor = self.world.dominant_attractor(id)
+      .and_then(|host_id| self.world.planets.get(&host_id))
+      .map_or("none".to_string(), |host| host.borrow().label());
+    Some(format!(
+      "{}\nMass: {:.3}\nSpeed: {:.2}\nAttractor: {}",
+      planet.name.as_ref().map_or(format!("{} {}", planet.kind, planet.id), |name| format!("{} ({} {})", name, planet.kind, planet.id)),
+      planet.mass,
+      planet.velocity.magnitude(),
+      attractor,
+    ))
+  }
+
+  fn gui_wants_pointer(&self) -> bool {
+    self.show_gui && self.gui.ctx().wants_pointer_input()
+  }
+
+  // Recording or replaying. Frames are a fixed length so physics steps line up exactly.
+  fn is_deterministic(&self) -> bool {
+    self.recording.is_some() || self.player.is_some() || self.capture.is_some()
+  }
+
+  fn toggle_capture(&mut self, ctx: &mut Context) {
+    if let Some(capture) = self.capture.take() {
+      println!("Captured {} frames to {} in {}", capture.frames_captured(), CAPTURE_DIR, ctx.fs.user_data_dir().display());
+    } else {
+      match FrameCapture::new(ctx) {
+        Ok(capture) => self.capture = Some(capture),
+        Err(e) => println!("WARNING: Could not start capturing frames: {}", e),
+      }
+    }
+  }
+
+  // Locks the camera onto the selected planet (or the barycentre with shift), or frees it if already locked
+  fn toggle_focus(&mut self, barycentre: bool) {
+    let focus = if barycentre {
+      Focus::Barycentre
+    } else {
+      match self.selected_planet {
+        Some(id) => Focus::Planet(id),
+        None => Focus::Free,
+      }
+    };
+
+    self.camera.set_focus(if self.camera.focus == focus { Focus::Free } else { focus });
+  }
+
+  fn change_softening(&mut self, larger: bool) {
+    let softening = &mut self.world.config.softening;
+    *softening = if larger { *softening + SOFTENING_STEP } else { (*softening - SOFTENING_STEP).max(0.0) };
+  }
+
+  // Density is reset to the new kind's default
+  fn cycle_spawn_kind(&mut self) {
+    self.spawn_kind = self.spawn_kind.next();
+    self.spawn_density = self.spawn_kind.density();
+  }
+
+  // Steps time scale up or down through TIME_SCALE_STEPS
+  fn change_time_scale(&mut self, faster: bool) {
+    let time_scale = &mut self.world.config.time_scale;
+    *time_scale = if faster {
+      TIME_SCALE_STEPS.iter().copied().find(|&s| s > *time_scale)
+    } else {
+      TIME_SCALE_STEPS.iter().rev().copied().find(|&s| s < *time_scale)
+    }.unwrap_or(*time_scale);
+  }
+
+  // Scales the gravitational constant live. Existing orbits will no longer be balanced.
+  fn change_g(&mut self, stronger: bool) {
+    if stronger {
+      self.world.config.g *= G_STEP;
+    } else {
+      self.world.config.g /= G_STEP;
     }
   }
 
@@ -339,97 +1789,274 @@ impl MainState {
 
 impl event::EventHandler for MainState {
   fn update(&mut self, ctx: &mut Context) -> GameResult {
-    let dt_duration = ctx.time.delta();
-    self.dt = dt_duration.as_secs_f32();
+    self.dt = ctx.time.delta().as_secs_f32();
 
-    // For holding planets that have collided
-    let mut collided_planets: Vec<usize> = Vec::with_capacity(self.planets.len()/2);
-    let mut planets_to_remove: Vec<usize> = Vec::with_capacity(self.planets.len()/2);
-    
-    // Remove dead particle emitters
-    self.planet_trails.retain(|_, trail| !trail.borrow().is_dead());
-
-    let keys: Vec<&usize> = self.planets.keys().collect();
-    let len = self.planets.len();
-
-    if len > 0 {
-      // Update planets
-      for (_, pl) in self.planets.iter() {
-        pl.borrow_mut().update(self.dt, &dt_duration);
-      }
-
-      for i in 0..len-1 {
-        let already_collided = collided_planets.contains(&i);
-        if !already_collided {
-          let pl1 = self.planets.get(keys[i]).expect("Couldn't get planet 1");
-          for j in i+1..len {
-            let already_collided = collided_planets.contains(&j);
-            if !already_collided {
-              let pl2 = self.planets.get(keys[j]).expect("Couldn't get planet 2");
-  
-              let (colliding, dist_vec, square_distance, protection) = {
-                let bpl1 = pl1.borrow();
-                let bpl2 = pl2.borrow();
-                let dist_vec = bpl2.position - bpl1.position;
-                let min_dist = bpl1.radius + bpl2.radius;
-                let square_dist = dist_vec.x.powi(2) + dist_vec.y.powi(2);
-                (
-                  // AABB then circle collision
-                  dist_vec.x.abs() <= min_dist && dist_vec.y.abs() <= min_dist && square_dist <= min_dist.powi(2),
-                  dist_vec,
-                  square_dist,
-                  bpl1.has_spawn_protection() || bpl2.has_spawn_protection()
-                )
-              };
-      
-              // Check for collision even if they have spawn protection, since I do not want to apply grav
-              // force when planets are inside of each other (as they become very speedy).
-              // protection is true if either planets have spawn protection
-              if colliding && !protection {
-                Self::collide_planets(&mut pl1.borrow_mut(), &pl2.borrow());
-                collided_planets.push(*keys[i]);
-                collided_planets.push(*keys[j]);
-                planets_to_remove.push(*keys[j])
-              } else if !colliding {
-                tools::newtonian_grav(&mut pl1.borrow_mut(), &mut pl2.borrow_mut(), square_distance, dist_vec);
-              }
-            }
-          }
+    if let Some(player) = self.player.as_mut() {
+      let events = player.events_for(self.frame);
+      let finished = player.is_finished();
+      for event in events {
+        self.handle_input(event);
+      }
+      if finished {
+        println!("Replay finished");
+        self.player = None;
+      }
+    }
+
+    self.update_hover(ctx, self.dt);
+
+    if self.show_gui {
+      let actions = self.build_gui(&self.gui.ctx());
+      for action in actions {
+        self.input(InputEvent::Action(action));
+      }
+    }
+    self.gui.update(ctx);
+
+    let frame_dt = if self.is_deterministic() {
+      Duration::from_secs_f32(FIXED_FRAME_DT)
+    } else if let Some(dt) = self.fixed_dt {
+      Duration::from_secs_f32(dt)
+    } else {
+      ctx.time.delta()
+    };
+
+    // Simulation time step. Trails are updated with the same duration so they stay in sync when paused/scaled.
+    let dt_duration = if self.paused {
+      Duration::ZERO
+    } else {
+      frame_dt.mul_f32(self.world.config.time_scale)
+    };
+
+    // Remove dead particle emitters, keeping their buffers for new trails
+    let dead: Vec<usize> = self.planet_trails.iter()
+      .filter(|(_, trail)| trail.borrow().is_dead())
+      .map(|(id, _)| *id)
+      .collect();
+    for id in dead {
+      if let Some(trail) = self.planet_trails.remove(&id) {
+        self.trail_pool.recycle(trail.into_inner());
+      }
+    }
+
+    // Fixed physics time step, so results don't depend on frame rate
+    self.physics_accumulator += dt_duration;
+    let physics_dt = Duration::from_secs_f32(self.physics_dt);
+    let mut steps = 0;
+    if matches!(self.net, Some(Net::Client(_))) {
+      self.physics_accumulator = Duration::ZERO;   // The host steps the world
+    }
+    while self.physics_accumulator >= physics_dt {
+      self.world.step(&physics_dt);
+      self.scenarios[self.current_scenario].tick(&mut self.world, self.physics_dt);
+      self.trajectories.record(&self.world, self.physics_dt);
+      self.physics_accumulator -= physics_dt;
+
+      steps += 1;
+      if steps >= MAX_PHYSICS_STEPS_PER_FRAME {
+        self.physics_accumulator = Duration::ZERO;
+        break
+      }
+    }
+
+    self.poll_stability();
+
+    match self.net.as_mut() {
+      Some(Net::Host(host)) => {
+        let spawned = host.update(&mut self.world, frame_dt.as_secs_f32());
+        for id in spawned {
+          self.record_spawn(id);
         }
-        
+      },
+      Some(Net::Client(client)) => client.update(&mut self.world, frame_dt.as_secs_f32()),
+      None => (),
+    }
+
+    self.step_timings = self.world.take_timings();
+
+    // Not while a crash is waiting to be recovered, so its snapshot isn't overwritten
+    if self.recovery.is_none() && self.snapshots.due() {
+      if let Err(e) = self.snapshots.write(&self.world) {
+        println!("WARNING: Could not autosave to {}: {}", SNAPSHOT_DIR, e);
       }
     }
 
-    self.planets.retain(|id, _| !planets_to_remove.contains(id));
+    if self.show_diagnostics {
+      let diagnostics = Diagnostics::measure(&self.world);
+      self.diagnostics_baseline.get_or_insert(diagnostics);
+      self.diagnostics = Some(diagnostics);
+    } else {
+      self.diagnostics = None;
+    }
+
+    // Deselect planets that have been merged into another
+    if let Some(id) = self.selected_planet {
+      if !self.world.planets.contains_key(&id) {
+        self.selected_planet = None;
+      }
+    }
+    let world = &self.world;
+    self.group.retain(|id| world.planets.contains_key(id));
+    if self.selected_planet.is_none() && self.edit_mode.is_editing() {
+      self.stop_editing();
+    }
+    self.watch_periapsis(frame_dt.as_secs_f32());
+
+    // Cursor velocity, for bodies emitted by the stream brush
+    let frame_secs = frame_dt.as_secs_f32();
+    if frame_secs > 0.0 {
+      self.mouse_info.cursor_velocity = (self.mouse_info.current_drag_position - self.mouse_info.last_cursor_position)/frame_secs;
+    }
+    self.mouse_info.last_cursor_position = self.mouse_info.current_drag_position;
+    self.update_stream(frame_dt);
+
+    // A launch drag stays stuck to its planet
+    if let (true, Some((host_id, offset))) = (self.mouse_info.down, self.mouse_info.launch_from) {
+      match self.world.planets.get(&host_id) {
+        Some(host) => self.mouse_info.down_pos = host.borrow().position + offset,
+        None => self.mouse_info.launch_from = None,
+      }
+    }
 
     // Update trails
+    let start = Instant::now();
     self.update_planet_trails(&dt_duration);
+    self.trail_time = start.elapsed();
+
+    self.event_log.append(&mut self.world.take_events());
+    if self.event_log.len() > MAX_LOGGED_EVENTS {
+      self.event_log.drain(..self.event_log.len() - MAX_LOGGED_EVENTS);
+    }
+
+    // Bursts and animations from this frame's merges
+    let effect_dt = dt_duration.as_secs_f32();
+    let impacts = self.world.take_impacts();
+    if let Err(e) = self.audio.play_impacts(ctx, &impacts) {
+      println!("WARNING: Could not play impact sound: {}", e);
+    }
+    for impact in impacts {
+      self.bursts.push(ParticleBurst::new(&impact));
+      self.impact_feedback.add(&impact, &self.impact_effects);
+      if let Some(merge) = impact.merge {
+        // A planet merging again restarts its animation
+        self.merge_animations.retain(|animation| animation.into != merge.into);
+        self.merge_animations.push(MergeAnimation::new(&merge));
+      }
+    }
+    for burst in self.bursts.iter_mut() {
+      burst.update(effect_dt);
+    }
+    self.bursts.retain(|burst| !burst.is_dead());
+    self.impact_feedback.update(frame_dt.as_secs_f32(), effect_dt);
+    for animation in self.merge_animations.iter_mut() {
+      animation.update(effect_dt);
+    }
+    let planets = &self.world.planets;
+    self.merge_animations.retain(|animation| !animation.is_finished() && planets.contains_key(&animation.into));
+
+    self.camera.update(&self.world, frame_dt.as_secs_f32());
+    self.camera.shake = self.impact_feedback.shake_offset();
+    if self.show_heatmap {
+      self.heatmap.update(ctx, &self.world, self.camera.view(), frame_dt.as_secs_f32());
+    }
+    if self.show_field_arrows {
+      self.field_arrows.update(ctx, &self.world, self.camera.view(), frame_dt.as_secs_f32())?;
+    }
+    self.frame += 1;
 
     Ok(())
   }
 
   fn draw(&mut self, ctx: &mut Context) -> GameResult {
+    let start = Instant::now();
     let mut canvas = graphics::Canvas::from_frame(ctx, Color::BLACK);
+    self.camera.apply(&mut canvas);
+
+    if self.show_heatmap {
+      self.heatmap.draw(&mut canvas);
+    }
+    if self.show_warp_grid {
+      warp_grid::draw(&self.world, self.camera.view(), ctx, &mut canvas)?;
+    }
+    if self.show_field_arrows {
+      self.field_arrows.draw(&mut canvas);
+    }
+
+    if let Some(bounds) = self.world.wrap_bounds {
+      render::draw_world_bounds(bounds, ctx, &mut canvas)?;
+    }
+
+    let launch = self.launch(self.mouse_info.current_drag_position)
+      .filter(|_| self.mouse_info.down && self.mouse_info.button_down == MouseButton::Left);
 
-    if self.mouse_info.down && self.mouse_info.button_down == MouseButton::Left &&
+    if let Some((position, velocity)) = launch {
+      let relative_speed = self.mouse_info.launch_from
+        .and_then(|(id, _)| self.world.planets.get(&id))
+        .map_or(velocity.magnitude(), |host| (velocity - host.borrow().velocity).magnitude());
+      self.draw_mouse_drag(ctx, &mut canvas, position, relative_speed)?;
+
+      let path = self.world.predict_trajectory(position, velocity, TRAJECTORY_PREVIEW_STEPS, TRAJECTORY_PREVIEW_DT);
+      render::draw_trajectory(&path, ctx, &mut canvas)?;
+    } else if self.mouse_info.down && self.mouse_info.tool == Some(Tool::Launch) && !self.mouse_info.selecting &&
+      self.stream.is_none() &&
       (self.mouse_info.down_pos.x - self.mouse_info.current_drag_position.x).powi(2) +
       (self.mouse_info.down_pos.y - self.mouse_info.current_drag_position.y).powi(2) >= 4.0
     {
-      Self::draw_mouse_drag(ctx, &mut canvas, &self.mouse_info)?;
+      let velocity = self.launch_velocity(self.mouse_info.down_pos, self.mouse_info.down_pos - self.mouse_info.current_drag_position, None);
+      self.draw_mouse_drag(ctx, &mut canvas, self.mouse_info.down_pos, velocity.magnitude())?;
+
+      let path = self.world.predict_trajectory(
+        self.mouse_info.down_pos,
+        velocity,
+        TRAJECTORY_PREVIEW_STEPS,
+        TRAJECTORY_PREVIEW_DT,
+      );
+      render::draw_trajectory(&path, ctx, &mut canvas)?;
+    } else if let (true, Some(host_id)) = (self.mouse_info.down, self.mouse_info.orbit_host) {
+      self.draw_orbit_spawn_preview(ctx, &mut canvas, host_id)?;
+      self.draw_spawn_ghost(ctx, &mut canvas)?;
+    } else if !self.mouse_info.down {
+      self.draw_spawn_ghost(ctx, &mut canvas)?;
     }
 
     // Draw particles
     {
       let mut lines_mesh_builder = MeshBuilder::new();
       let mut can_draw = false;
+      let origin = self.trail_origin().coords;
   
       for (_, trail) in self.planet_trails.iter() {
         // Draw builds the mesh, returns a bool.
         // If this bool is true then there's something to draw.
-        if trail.borrow().draw(&mut lines_mesh_builder)? {
+        if trail.borrow().draw(&mut lines_mesh_builder, self.trail_style, &self.world.config.trails, self.trail_coloring, origin)? {
+          can_draw = true;
+        }
+      }
+
+      for burst in self.bursts.iter() {
+        burst.draw(&mut lines_mesh_builder)?;
+        can_draw = true;
+      }
+      if self.impact_feedback.draw_shockwaves(&mut lines_mesh_builder)? {
+        can_draw = true;
+      }
+
+      for tail in self.comet_tails.values() {
+        if tail.draw(&mut lines_mesh_builder)? {
           can_draw = true;
         }
       }
+
+      if self.show_influence {
+        for (id, planet) in self.world.planets.iter() {
+          let planet = planet.borrow();
+          // Only bodies with room for something to orbit them
+          if let Some(radius) = self.world.sphere_of_influence(*id).filter(|r| *r > planet.radius * MIN_INFLUENCE_RADII) {
+            render::draw_sphere_of_influence(&planet, radius, &mut lines_mesh_builder)?;
+            can_draw = true;
+          }
+        }
+      }
       
       if can_draw {     // Prevents lyon error when building mesh
         let line_mesh = Mesh::from_data(ctx, lines_mesh_builder.build());
@@ -437,72 +2064,237 @@ impl event::EventHandler for MainState {
       }
     }
 
-    for (_, planet) in self.planets.iter() {
-      planet.borrow().draw(
-        ctx,
-        &mut canvas,
-        &self.body_mesh,
-        self.show_planet_info_debug,
-        self.show_vector_debug,
-      )?;
+    let alpha = self.render_alpha();
+    {
+      // Planets still merging are drawn by their animation instead
+      let merging: HashSet<usize> = self.merge_animations.iter().map(|animation| animation.into).collect();
+      let borrowed: Vec<_> = self.world.planets.values()
+        .map(|pl| pl.borrow())
+        .filter(|pl| !merging.contains(&pl.id))
+        .collect();
+      let planets: Vec<&Planet> = borrowed.iter().map(|pl| &**pl).collect();
+      self.planet_batch.draw(&planets, alpha, &self.body_mesh, self.color_mode, &mut canvas);
+
+      for animation in self.merge_animations.iter() {
+        if let Some(merged) = self.world.planets.get(&animation.into) {
+          animation.draw(&merged.borrow(), alpha, &self.body_mesh, &mut canvas);
+        }
+      }
+    }
+
+    for (id, planet) in self.world.planets.iter() {
+      // Always show the vectors of the planet being edited
+      let editing = self.edit_mode.is_editing() && self.selected_planet == Some(*id);
+      if self.show_planet_info_debug || self.show_vector_debug || editing {
+        render::draw_planet_debug(
+          &planet.borrow(),
+          ctx,
+          &mut canvas,
+          alpha,
+          self.show_planet_info_debug,
+          (self.show_vector_debug || editing).then(|| self.vector_debug),
+        )?;
+      }
+      if self.show_labels {
+        render::draw_label(&planet.borrow(), alpha, &mut canvas);
+      }
+    }
+
+    // Dragging the handle sets the selected planet's velocity while paused
+    if let (true, Some(planet)) = (self.paused, self.selected_planet.and_then(|id| self.world.planets.get(&id))) {
+      let planet = planet.borrow();
+      let position = planet.interpolated_position(alpha);
+      render::draw_velocity_handle(position, edit::velocity_handle(position, planet.velocity), HANDLE_RADIUS, ctx, &mut canvas)?;
+    }
+
+    let orbit = self.selected_planet.and_then(|id| self.world.osculating_orbit(id));
+    if let Some((host_id, elements)) = orbit {
+      let host_position = self.world.planets[&host_id].borrow().position;
+      render::draw_orbit(&elements, host_position, ctx, &mut canvas)?;
+      render::draw_apsides(&elements, host_position, ctx, &mut canvas)?;
+    }
+    if let Some((position, age)) = self.periapsis_flash {
+      render::draw_periapsis_flash(position, age/PERIAPSIS_FLASH_TIME, ctx, &mut canvas)?;
+    }
+    let selected = self.selected_planet.and_then(|id| self.world.planets.get(&id));
+    if let Some(planet) = selected {
+      render::draw_selection_ring(&planet.borrow(), ctx, &mut canvas)?;
+    }
+    for planet in self.group.iter().filter_map(|id| self.world.planets.get(id)) {
+      render::draw_selection_ring(&planet.borrow(), ctx, &mut canvas)?;
+    }
+    if self.mouse_info.down && self.mouse_info.tool == Some(Tool::Grid) {
+      self.draw_grid_preview(ctx, &mut canvas)?;
+    }
+    if self.mouse_info.down && self.mouse_info.tool == Some(Tool::Binary) {
+      self.draw_binary_preview(ctx, &mut canvas)?;
+    }
+    if self.mouse_info.down && self.mouse_info.boxing {
+      render::draw_selection_box(self.mouse_info.down_pos, self.mouse_info.current_drag_position, ctx, &mut canvas)?;
+    }
+
+    // UI from here on
+    Camera::reset(&mut canvas);
+    self.impact_feedback.draw_flash(ctx, &mut canvas)?;
+
+    if let (Some((host_id, elements)), Some(id)) = (orbit, self.selected_planet) {
+      let planet = self.world.planets[&id].borrow();
+      let host = self.world.planets[&host_id].borrow();
+      let distance = (planet.position - host.position).magnitude();
+      let escape_speed = tools::escape_velocity(self.world.config.g, host.mass + planet.mass, distance);
+      let sphere_of_influence = tools::sphere_of_influence(distance, planet.mass, host.mass);
+      render::draw_orbit_info(&elements, escape_speed, sphere_of_influence, &mut canvas);
+    }
+    if let Some(planet) = selected {
+      render::draw_inspector(&planet.borrow(), &mut canvas);
+    }
+
+    if self.show_scenario_menu {
+      self.draw_scenario_menu(&mut canvas);
+    }
+    if self.show_help {
+      self.draw_help(&mut canvas);
     }
 
     self.draw_debug_info(&mut canvas);
-    canvas.finish(ctx)
+    if let Some(tooltip) = self.hover_tooltip() {
+      let cursor = ctx.mouse.position();
+      let (x, y) = to_screen_coords(ctx, cursor.x, cursor.y);
+      render::draw_tooltip(&tooltip, Point2::new(x, y), ctx, &mut canvas);
+    }
+    if self.show_minimap {
+      self.minimap.draw(&self.world, self.camera.view(), ctx, &mut canvas)?;
+    }
+    if self.show_gui {
+      canvas.draw(&self.gui, DrawParam::default());
+    }
+    canvas.finish(ctx)?;
+    self.draw_time = start.elapsed();
+
+    let mut failed = false;
+    if let Some(capture) = self.capture.as_mut() {
+      if let Err(e) = capture.capture(ctx) {
+        println!("WARNING: Could not capture frame, stopping: {}", e);
+        failed = true;
+      } else {
+        capture.draw_indicator(ctx)?;
+      }
+    }
+    if failed {
+      self.capture = None;
+    }
+    Ok(())
   }
 
-  fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
-    self.mouse_info.down = true;
-    self.mouse_info.button_down = button;
-    self.mouse_info.down_pos = Point2::new(x, y);
+  fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
+    if self.gui_wants_pointer() { return Ok(()) }
+    let alt = ctx.keyboard.is_mod_active(KeyMods::ALT);
+    let (x, y) = to_screen_coords(ctx, x, y);
+    self.input(InputEvent::MouseDown { button: button.into(), x, y, alt });
     Ok(())
   }
 
-  fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
-    self.mouse_info.down = false;
+  fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
+    let shift = ctx.keyboard.is_mod_active(KeyMods::SHIFT);
+    let (x, y) = to_screen_coords(ctx, x, y);
+    self.input(InputEvent::MouseUp { button: button.into(), x, y, shift });
+    Ok(())
+  }
 
-    if button == MouseButton::Left {
-      self.add_planet(
-        self.mouse_info.down_pos,
-        Some(self.mouse_info.down_pos - Point2::new(x, y)),
-        None,
-        SPAWN_PLANET_RADIUS,
-        None,
-      );
+  fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) -> GameResult {
+    let (x, y) = to_screen_coords(ctx, x, y);
+    self.input(InputEvent::MouseMotion { x, y });
+    Ok(())
+  }
+
+  fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult {
+    self.gui.input.mouse_wheel_event(x * 10.0, y * 10.0);
+    if (x != 0.0 || y != 0.0) && !self.gui_wants_pointer() {
+      let shift = ctx.keyboard.is_mod_active(KeyMods::SHIFT);
+      let ctrl = ctx.keyboard.is_mod_active(KeyMods::CTRL);
+      let cursor = ctx.mouse.position();
+      let (cursor_x, cursor_y) = to_screen_coords(ctx, cursor.x, cursor.y);
+      self.input(InputEvent::MouseWheel { x, y, cursor_x, cursor_y, shift, ctrl });
+    }
+    Ok(())
+  }
+
+  fn touch_event(&mut self, ctx: &mut Context, phase: TouchPhase, x: f64, y: f64) -> GameResult {
+    if !self.gui_wants_pointer() {
+      let (x, y) = to_screen_coords(ctx, x as f32, y as f32);
+      self.input(InputEvent::Touch { phase: phase.into(), x, y });
     }
     Ok(())
   }
 
-  fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) -> GameResult {
-    self.mouse_info.current_drag_position = Point2::new(x, y);
+  fn quit_event(&mut self, _ctx: &mut Context) -> GameResult<bool> {
+    let settings = Settings {
+      spawn_radius: self.spawn_radius,
+      spawn_density: self.spawn_density,
+      spawn_kind: self.spawn_kind,
+      trail_style: self.trail_style,
+      launch_scale: self.launch_scale,
+      integrator: self.world.integrator,
+      physics_rate: 1.0/self.physics_dt,
+      extrapolate: self.extrapolate,
+      impact_effects: self.impact_effects,
+      volume: self.audio.volume(),
+      muted: self.audio.is_muted(),
+      ..self.settings.clone()
+    };
+    if let Err(e) = settings.save() {
+      println!("WARNING: Could not save settings: {}", e);
+    }
+    self.snapshots.finish();
+    Ok(false)
+  }
+
+  fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult {
+    self.gui.input.text_input_event(character);
     Ok(())
   }
 
   fn key_down_event(
     &mut self,
-    _ctx: &mut Context,
+    ctx: &mut Context,
     input: KeyInput,
     _repeat: bool,
   ) -> GameResult {
+    if self.show_gui && self.gui.ctx().wants_keyboard_input() { return Ok(()) }   // Typing in a GUI field
+
     if let Some(keycode) = input.keycode {
       match keycode {
-        KeyCode::D => self.show_vector_debug = !self.show_vector_debug,
-        KeyCode::I => self.show_planet_info_debug = !self.show_planet_info_debug,
-        KeyCode::R => self.restart(),
-        KeyCode::C => self.clear(),
-        _ => (),
+        // Recording and replay controls aren't recorded themselves
+        KeyCode::F6 => self.toggle_recording(),
+        KeyCode::F7 => self.toggle_replay(),
+        KeyCode::F8 => self.toggle_capture(ctx),
+        // Bindings with modifiers first, then the bare key, which catches it whatever the modifiers
+        _ => if let Some(&action) = self.settings.key_bindings.get(&input::binding_name(keycode, input.mods))
+          .or_else(|| self.settings.key_bindings.get(&format!("{:?}", keycode)))
+        {
+          self.input(InputEvent::Action(action));
+        } else if let Some(action) = Action::from_key(keycode, input.mods, self.edit_mode.is_editing()) {
+          self.input(InputEvent::Action(action));
+        },
       }
     }
     Ok(())
   }
 }
 
-
 struct MouseInfo {
   down: bool,
   button_down: MouseButton,
   down_pos: Point2<f32>,
   current_drag_position: Point2<f32>,
+  tool: Option<Tool>,   // What the current press does, see MainState::tool_for
+  selecting: bool,    // Mouse went down on a planet
+  boxing: bool,       // Dragging a box to select everything in it
+  last_cursor_position: Point2<f32>,
+  cursor_velocity: Vector2<f32>,
+  orbit_host: Option<usize>,  // Planet a right-click drag started on
+  launch_from: Option<(usize, Vector2<f32>)>,  // Planet a left drag started on, and the offset from its centre
+  grab: Option<Grab>,         // Planet being dragged around while paused
 }
 
 impl Default for MouseInfo {
@@ -512,15 +2304,93 @@ impl Default for MouseInfo {
       button_down: MouseButton::Left,
       down_pos: Point2::new(0.0, 0.0),
       current_drag_position: Point2::new(1.0, 0.0),
+      tool: None,
+      selecting: false,
+      boxing: false,
+      launch_from: None,
+      grab: None,
+      last_cursor_position: Point2::new(1.0, 0.0),
+      cursor_velocity: Vector2::new(0.0, 0.0),
+      orbit_host: None,
     }
   }
 }
 
+// --gpu computes gravity on the GPU, if the gpu feature is on and there is one. Otherwise stays on the CPU.
+fn enable_gpu(requested: bool) -> bool {
+  if !requested { return false }
+  if World::gpu_available() {
+    println!("Computing gravity on the GPU");
+    true
+  } else {
+    println!("WARNING: No GPU available (or built without the gpu feature), computing gravity on the CPU");
+    false
+  }
+}
+
+// What the world starts with, from --scenario, --bodies and --import
+enum Start {
+  Scenario(usize),
+  SaveFile(String),
+}
+
+// --bodies and --import add a scenario to the list. Unknown names and unreadable files warn and fall back to the
+// first scenario.
+fn choose_start(cli: &Cli, scenarios: &mut Vec<Box<dyn Scenario>>) -> Start {
+  if let Some(bodies) = cli.bodies {
+    scenarios.push(Box::new(RandomBodies::new(bodies)));
+    return Start::Scenario(scenarios.len() - 1)
+  }
+  if let Some(path) = &cli.import {
+    match CsvBodies::load(path) {
+      Ok(bodies) => {
+        println!("Imported {} bodies from {}", bodies.len(), path.display());
+        scenarios.push(Box::new(bodies));
+        return Start::Scenario(scenarios.len() - 1)
+      },
+      Err(e) => {
+        println!("WARNING: Could not import bodies from {}, starting with {}: {}", path.display(), scenarios[0].name(), e);
+        return Start::Scenario(0)
+      },
+    }
+  }
+
+  match &cli.scenario {
+    None => Start::Scenario(0),
+    Some(name) => if let Some(index) = scenarios::find(scenarios, name) {
+      Start::Scenario(index)
+    } else if std::path::Path::new(name).is_file() {
+      Start::SaveFile(name.clone())
+    } else {
+      println!("WARNING: No scenario or save file called \"{}\", starting with {}", name, scenarios[0].name());
+      Start::Scenario(0)
+    },
+  }
+}
+
+// Window positions are scaled to SCREEN_DIMS, which everything is drawn in whatever the window size
+fn to_screen_coords(ctx: &Context, x: f32, y: f32) -> (f32, f32) {
+  let (width, height) = ctx.gfx.drawable_size();
+  (x * SCREEN_DIMS.0/width, y * SCREEN_DIMS.1/height)
+}
+
 pub fn main() -> GameResult {
   use std::path;
   use std::env;
   use ggez::conf::{WindowMode, WindowSetup, NumSamples};
 
+  let cli = Cli::parse();
+  let use_gpu = enable_gpu(cli.gpu);
+
+  if cli.bench {
+    let config = cli.bench_config();
+    println!("Benchmarking {:?} bodies, {} iterations each", config.sizes, config.iterations);
+    for result in bench::run(&config) {
+      println!("{}", result.report());
+    }
+    return Ok(())
+  }
+
   let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
     let mut path = path::PathBuf::from(manifest_dir);
     path.push("resources");
@@ -529,11 +2399,58 @@ pub fn main() -> GameResult {
     path::PathBuf::from("./resources")
   };
 
+  if cli.headless {
+    let config = cli.headless_config();
+    let mut world = World::new();
+    world.use_gpu = use_gpu;
+    if let Some(seed) = cli.seed {
+      world.seed(seed);
+    }
+    match save::read_config(CONFIG_FILE_PATH) {
+      Ok(config) => world.config = config,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+      Err(e) => println!("WARNING: Could not read config from {}: {}", CONFIG_FILE_PATH, e),
+    }
+
+    #[allow(unused_mut)]
+    let mut scenarios = scenarios::presets();
+    #[cfg(feature = "scripting")]
+    for script in orbits::script::load_dir(&resource_dir.join(SCRIPT_DIR)) {
+      scenarios.push(Box::new(script));
+    }
+    for bodies in import::load_dir(&resource_dir.join(IMPORT_DIR)) {
+      scenarios.push(Box::new(bodies));
+    }
+    match choose_start(&cli, &mut scenarios) {
+      Start::Scenario(index) => scenarios[index].build(&mut world, SCREEN_DIMS),
+      Start::SaveFile(path) => match SaveState::<World>::read_from_file(&path) {
+        Ok(state) => {
+          world = state.world;
+          world.use_gpu = use_gpu;
+        },
+        Err(e) => {
+          println!("WARNING: Could not load simulation from {}: {}", path, e);
+          return Ok(())
+        },
+      },
+    }
+
+    println!("Running {} steps headless, writing to {}", config.steps, config.output.display());
+    headless::run(&mut world, &config)?;
+    return Ok(())
+  }
+
+  let settings = Settings::load().unwrap_or_else(|e| {
+    println!("WARNING: Could not read settings, using defaults: {}", e);
+    Settings::default()
+  });
+  let window_size = cli.window.unwrap_or(settings.window_size);
+
   let cb = ggez::ContextBuilder::new("Planets", "ggez")
     .add_resource_path(resource_dir)
     .window_mode(
       WindowMode::default()
-        .dimensions(SCREEN_DIMS.0, SCREEN_DIMS.1)
+        .dimensions(window_size.0, window_size.1)
     )
     .window_setup(
       WindowSetup::default()
@@ -541,6 +2458,51 @@ pub fn main() -> GameResult {
     );
 
   let (mut ctx, event_loop) = cb.build()?;
-  let state = MainState::new(&mut ctx)?;
+  #[cfg(feature = "three_d")]
+  if cli.three_d {
+    return event::run(ctx, event_loop, view3d::View3State::new())
+  }
+
+  snapshot::install_panic_hook();
+  let mut state = MainState::new(&mut ctx, cli.seed, use_gpu, settings)?;
+  state.fixed_dt = cli.fixed_dt;
+  if let Some(rate) = cli.physics_rate {
+    state.set_physics_rate(rate);
+  }
+  match choose_start(&cli, &mut state.scenarios) {
+    Start::Scenario(index) => {
+      state.current_scenario = index;
+      state.start_scenario = index;
+      state.restart();
+      if cli.record {
+        state.toggle_recording();
+      }
+    },
+    Start::SaveFile(path) => {
+      state.load_from(&path);
+      if cli.record {
+        println!("WARNING: Replays start from a scenario, not a save file, so --record was ignored");
+      }
+    },
+  }
+
+  if let Some(port) = cli.host {
+    match Host::new(port) {
+      Ok(host) => {
+        println!("Hosting on port {}", port);
+        state.net = Some(Net::Host(host));
+      },
+      Err(e) => println!("WARNING: Could not host on port {}: {}", port, e),
+    }
+  } else if let Some(address) = cli.join_address() {
+    match Client::join(&address, &mut state.world) {
+      Ok(client) => {
+        println!("Joined {}", address);
+        state.clear_trails();
+        state.net = Some(Net::Client(client));
+      },
+      Err(e) => println!("WARNING: Could not join {}: {}", address, e),
+    }
+  }
   event::run(ctx, event_loop, state)
 }
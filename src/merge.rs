@@ -0,0 +1,58 @@
+use ggez::graphics::{Canvas, DrawParam, Mesh};
+
+use nalgebra::Vector2;
+
+use orbits::planet::Planet;
+use orbits::simulation::{Merge, MergedBody};
+
+const MERGE_ANIMATION_TIME: f32 = 0.25;   // Sim seconds
+
+// Shows two bodies sliding together into the planet they merged into, instead of it appearing instantly.
+// Only visual: the simulation has already merged them, and the merged planet is hidden while this plays.
+pub struct MergeAnimation {
+  pub into: usize,
+  bodies: [MergedBody; 2],
+  age: f32,
+}
+
+impl MergeAnimation {
+  pub fn new(merge: &Merge) -> Self {
+    Self {
+      into: merge.into,
+      bodies: merge.bodies,
+      age: 0.0,
+    }
+  }
+
+  pub fn update(&mut self, dt: f32) {
+    self.age += dt;
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.age >= MERGE_ANIMATION_TIME
+  }
+
+  // Both bodies move from where they would have been towards the merged planet. The larger grows into it while
+  // the smaller shrinks away.
+  pub fn draw(&self, merged: &Planet, alpha: f32, body_mesh: &Mesh, canvas: &mut Canvas) {
+    let t = (self.age/MERGE_ANIMATION_TIME).min(1.0);
+    let progress = t * t * (3.0 - 2.0 * t);   // Ease in and out
+    let target = merged.interpolated_position(alpha);
+    let larger = if self.bodies[0].radius >= self.bodies[1].radius { 0 } else { 1 };
+
+    for (k, body) in self.bodies.iter().enumerate() {
+      let drifted = body.position + body.velocity * self.age;
+      let position = drifted + (target - drifted) * progress;
+      let radius = if k == larger {
+        body.radius + (merged.radius - body.radius) * progress
+      } else {
+        body.radius * (1.0 - progress)
+      };
+
+      canvas.draw(body_mesh, DrawParam::new()
+                               .scale(Vector2::new(radius, radius))
+                               .dest(position)
+                               .color(body.color));
+    }
+  }
+}
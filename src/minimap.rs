@@ -0,0 +1,95 @@
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, InstanceArray, Mesh, Rect};
+use ggez::{Context, GameResult};
+
+use nalgebra::{Point2, Vector2};
+
+use orbits::simulation::World;
+
+use crate::SCREEN_DIMS;
+
+const SIZE: (f32, f32) = (220.0, 150.0);    // On screen
+const MARGIN: f32 = 10.0;     // From the bottom right corner of the screen
+const PADDING: f32 = 0.05;    // Empty space around the bodies, as a fraction of the area shown
+const DOT_SIZE: f32 = 2.0;      // Smallest dot, in screen pixels
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const BORDER_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.4];
+const VIEW_COLOR: [f32; 4] = [0.0, 1.0, 1.0, 0.8];
+
+// Where the minimap is on screen
+pub fn screen_rect() -> Rect {
+  Rect::new(SCREEN_DIMS.0 - SIZE.0 - MARGIN, SCREEN_DIMS.1 - SIZE.1 - MARGIN, SIZE.0, SIZE.1)
+}
+
+// Maps the area of the world holding every body and the view onto the minimap, keeping its aspect ratio
+#[derive(Clone, Copy, Debug)]
+pub struct MinimapTransform {
+  world_centre: Point2<f32>,
+  map_centre: Point2<f32>,
+  scale: f32,     // Minimap pixels per world pixel
+}
+
+impl MinimapTransform {
+  pub fn new(world: &World, view: Rect) -> Self {
+    let mut min = Point2::new(view.x, view.y);
+    let mut max = Point2::new(view.x + view.w, view.y + view.h);
+    for pl in world.planets.values() {
+      let pl = pl.borrow();
+      min = Point2::new(min.x.min(pl.position.x - pl.radius), min.y.min(pl.position.y - pl.radius));
+      max = Point2::new(max.x.max(pl.position.x + pl.radius), max.y.max(pl.position.y + pl.radius));
+    }
+
+    let map = screen_rect();
+    let size = max - min;
+    let scale = (map.w/size.x).min(map.h/size.y) * (1.0 - 2.0 * PADDING);
+    Self {
+      world_centre: nalgebra::center(&min, &max),
+      map_centre: Point2::new(map.x + map.w/2.0, map.y + map.h/2.0),
+      scale,
+    }
+  }
+
+  pub fn world_to_map(&self, point: Point2<f32>) -> Point2<f32> {
+    self.map_centre + (point - self.world_centre) * self.scale
+  }
+
+  pub fn map_to_world(&self, point: Point2<f32>) -> Point2<f32> {
+    self.world_centre + (point - self.map_centre)/self.scale
+  }
+}
+
+// Every body as a dot, batched into one draw, and the view as a rectangle. Drawn in screen coordinates.
+// The instance buffer is kept between frames.
+pub struct Minimap {
+  dots: InstanceArray,
+}
+
+impl Minimap {
+  pub fn new(ctx: &Context) -> Self {
+    Self { dots: InstanceArray::new(ctx, None) }
+  }
+
+  pub fn draw(&mut self, world: &World, view: Rect, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+    let map = screen_rect();
+    let transform = MinimapTransform::new(world, view);
+
+    canvas.draw(&Mesh::new_rectangle(ctx, DrawMode::fill(), map, BACKGROUND_COLOR.into())?, DrawParam::default());
+    canvas.draw(&Mesh::new_rectangle(ctx, DrawMode::stroke(1.0), map, BORDER_COLOR.into())?, DrawParam::default());
+
+    self.dots.clear();
+    for pl in world.planets.values() {
+      let pl = pl.borrow();
+      // Big bodies get bigger dots, so the view's size can be compared with them
+      let size = (pl.radius * transform.scale * 2.0).max(DOT_SIZE);
+      let position = transform.world_to_map(pl.position) - Vector2::new(size, size)/2.0;
+      self.dots.push(DrawParam::new().dest(position).scale(Vector2::new(size, size)).color(Color::from(pl.color)));
+    }
+    if !self.dots.instances().is_empty() {
+      canvas.draw(&self.dots, DrawParam::default());
+    }
+
+    let top_left = transform.world_to_map(Point2::new(view.x, view.y));
+    let view_rect = Rect::new(top_left.x, top_left.y, view.w * transform.scale, view.h * transform.scale);
+    canvas.draw(&Mesh::new_rectangle(ctx, DrawMode::stroke(1.0), view_rect, VIEW_COLOR.into())?, DrawParam::default());
+    Ok(())
+  }
+}
@@ -0,0 +1,222 @@
+use nalgebra::Point2;
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, ErrorKind};
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+use orbits::simulation::World;
+use orbits::planet::Planet;
+use crate::protocol::{Peer, HostMessage, ClientMessage, BROADCAST_RATE};
+
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);   // For the host's welcome
+
+// Shared sandbox. The host steps the only real simulation and sends what changed to every client at BROADCAST_RATE.
+// Clients draw the bodies moving smoothly between updates and send anything spawned locally to the host.
+pub enum Net {
+  Host(Host),
+  Client(Client),
+}
+
+pub struct Host {
+  listener: TcpListener,
+  clients: Vec<Peer>,
+  sent: HashSet<usize>,   // Bodies clients know about
+  broadcast_timer: f32,
+}
+
+impl Host {
+  pub fn new(port: u16) -> io::Result<Self> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    listener.set_nonblocking(true)?;
+    Ok(Self {
+      listener,
+      clients: Vec::new(),
+      sent: HashSet::new(),
+      broadcast_timer: 0.0,
+    })
+  }
+
+  pub fn client_count(&self) -> usize {
+    self.clients.len()
+  }
+
+  // Welcomes new clients and adds the bodies they spawned to the world. Returns the new ids.
+  pub fn update(&mut self, world: &mut World, dt: f32) -> Vec<usize> {
+    loop {
+      match self.listener.accept() {
+        Ok((stream, _)) => match Peer::new(stream).and_then(|mut peer| {
+          peer.send(&HostMessage::Welcome(world.clone()))?;
+          Ok(peer)
+        }) {
+          Ok(peer) => {
+            println!("{} joined", peer.address());
+            self.clients.push(peer);
+          },
+          Err(e) => println!("WARNING: Could not welcome a client: {}", e),
+        },
+        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+        Err(e) => {
+          println!("WARNING: Could not accept a client: {}", e);
+          break
+        },
+      }
+    }
+
+    let mut spawned = Vec::new();
+    self.clients.retain_mut(|peer| match peer.receive::<ClientMessage>() {
+      Ok(messages) => {
+        for message in messages {
+          match message {
+            ClientMessage::Spawn(planets) => for planet in planets {
+              if is_valid(&planet) {
+                spawned.push(world.spawn_planet(planet));
+              } else {
+                println!("WARNING: Ignored an invalid body from {}", peer.address());
+              }
+            },
+          }
+        }
+        true
+      },
+      Err(e) => {
+        println!("{} left: {}", peer.address(), e);
+        false
+      },
+    });
+
+    self.broadcast_timer += dt;
+    if self.broadcast_timer >= 1.0/BROADCAST_RATE {
+      self.broadcast_timer = 0.0;
+      self.broadcast(world);
+    }
+    spawned
+  }
+
+  fn broadcast(&mut self, world: &World) {
+    let planets: Vec<Planet> = world.planets.iter()
+      .filter(|(id, planet)| !planet.borrow().is_static || !self.sent.contains(id))
+      .map(|(_, planet)| planet.borrow().clone())
+      .collect();
+    let removed: Vec<usize> = self.sent.iter()
+      .filter(|id| !world.planets.contains_key(id))
+      .copied()
+      .collect();
+    self.sent = world.planets.keys().copied().collect();
+
+    let delta = HostMessage::Delta { time: world.time, planets, removed };
+    self.clients.retain_mut(|peer| match peer.send(&delta).and_then(|_| peer.flush()) {
+      Ok(()) => true,
+      Err(e) => {
+        println!("{} left: {}", peer.address(), e);
+        false
+      },
+    });
+  }
+}
+
+// Whether a body from a client could go in the world: a finite position and velocity, and some mass and size
+fn is_valid(planet: &Planet) -> bool {
+  planet.position.iter().chain(planet.velocity.iter()).all(|x| x.is_finite()) &&
+    planet.mass.is_finite() && planet.mass > 0.0 &&
+    planet.radius.is_finite() && planet.radius > 0.0
+}
+
+pub struct Client {
+  host: Peer,
+  known: HashSet<usize>,    // Bodies from the host. Any others were spawned here, and are sent to the host.
+  motion: HashMap<usize, (Point2<f32>, Point2<f32>)>,   // Where each body was drawn and where the host says it is
+  blend: f32,     // How far between the two, 0 to 1
+  connected: bool,
+}
+
+impl Client {
+  // Waits for the host's world, which replaces the given one. Gives up after JOIN_TIMEOUT.
+  pub fn join(address: &str, world: &mut World) -> io::Result<Self> {
+    let mut host = Peer::connect(address)?;
+    let start = Instant::now();
+    let welcome = loop {
+      if let Some(message) = host.receive::<HostMessage>()?.into_iter().next() {
+        break message
+      }
+      if start.elapsed() > JOIN_TIMEOUT {
+        return Err(io::Error::new(ErrorKind::TimedOut, "the host didn't send its world in time"))
+      }
+      std::thread::sleep(Duration::from_millis(10));
+    };
+    match welcome {
+      HostMessage::Welcome(host_world) => {
+        let use_gpu = world.use_gpu;
+        *world = host_world;
+        world.use_gpu = use_gpu;
+      },
+      HostMessage::Delta { .. } => return Err(io::Error::new(ErrorKind::InvalidData, "expected a welcome from the host")),
+    }
+
+    Ok(Self {
+      host,
+      known: world.planets.keys().copied().collect(),
+      motion: HashMap::new(),
+      blend: 1.0,
+      connected: true,
+    })
+  }
+
+  pub fn is_connected(&self) -> bool {
+    self.connected
+  }
+
+  // Sends local spawns, applies the host's updates and moves bodies towards where the host last put them.
+  // The world isn't stepped on a client, so this is all that moves it.
+  pub fn update(&mut self, world: &mut World, dt: f32) {
+    if !self.connected { return }
+    if let Err(e) = self.exchange(world) {
+      println!("WARNING: Lost connection to the host: {}", e);
+      self.connected = false;
+      return
+    }
+
+    self.blend = (self.blend + dt * BROADCAST_RATE).min(1.0);
+    for (id, (from, to)) in self.motion.iter() {
+      if let Some(planet) = world.planets.get(id) {
+        planet.borrow_mut().position = from + (to - from) * self.blend;
+      }
+    }
+  }
+
+  fn exchange(&mut self, world: &mut World) -> io::Result<()> {
+    let local: Vec<usize> = world.planets.keys().filter(|id| !self.known.contains(id)).copied().collect();
+    if !local.is_empty() {
+      let planets = local.iter()
+        .filter_map(|id| world.planets.remove(id))
+        .map(|planet| planet.into_inner())
+        .collect();
+      self.host.send(&ClientMessage::Spawn(planets))?;
+    }
+    self.host.flush()?;
+
+    for message in self.host.receive::<HostMessage>()? {
+      match message {
+        HostMessage::Welcome(_) => (),
+        HostMessage::Delta { time, planets, removed } => {
+          world.time = time;
+          for id in removed {
+            world.remove_planet(id);
+            self.known.remove(&id);
+            self.motion.remove(&id);
+          }
+          for planet in planets {
+            let id = planet.id;
+            let target = planet.position;
+            let drawn = world.planets.get(&id).map_or(target, |current| current.borrow().position);
+            self.known.insert(id);
+            world.restore_planet(planet);
+            self.motion.insert(id, (drawn, target));
+          }
+          self.blend = 0.0;
+        },
+      }
+    }
+    Ok(())
+  }
+}
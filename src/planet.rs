@@ -1,65 +1,173 @@
-use ggez::graphics::{self, MeshBuilder, Mesh, DrawMode, Color, DrawParam, Canvas};
-use ggez::{Context, GameResult};
-use ggez::timer;
-
 use nalgebra::{Vector2, Point2};
-use rgb_hsv::hsv_to_rgb;
+use serde::{Serialize, Deserialize};
 
-use std::time::{Duration, Instant};
-use std::collections::VecDeque;
+use std::time::Duration;
+use std::fmt;
 
 use crate::tools;
-use crate::{SCREEN_DIMS, TELEPORT_ON_EDGES, ACC_DEBUG_VECTOR_MULTIPLIER};
+use crate::TWO_PI;
 
 pub const PLANET_DENSITY: f32 = 5000.0;
-const PLANET_RADIUS_COLORING_LOOP: f32 = 5.0;  // Planets are rainbow and colour repeats every 10
+pub const STAR_DENSITY: f32 = 10000.0;
+pub const ASTEROID_DENSITY: f32 = 2500.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum BodyKind {
+  Star,
+  Planet,
+  Asteroid,   // Fragments from high energy impacts are asteroids
+}
+
+impl Default for BodyKind {
+  fn default() -> Self {
+    BodyKind::Planet
+  }
+}
+
+impl fmt::Display for BodyKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      BodyKind::Star => write!(f, "Star"),
+      BodyKind::Planet => write!(f, "Planet"),
+      BodyKind::Asteroid => write!(f, "Asteroid"),
+    }
+  }
+}
+
+impl BodyKind {
+  pub fn next(self) -> Self {
+    match self {
+      BodyKind::Star => BodyKind::Planet,
+      BodyKind::Planet => BodyKind::Asteroid,
+      BodyKind::Asteroid => BodyKind::Star,
+    }
+  }
+
+  pub fn density(self) -> f32 {
+    match self {
+      BodyKind::Star => STAR_DENSITY,
+      BodyKind::Planet => PLANET_DENSITY,
+      BodyKind::Asteroid => ASTEROID_DENSITY,
+    }
+  }
+
+  // Kind of the body left after two bodies merge. Anything merging into a star stays a star,
+  // and asteroids only stay asteroids when merging with other asteroids.
+  pub fn merged_with(self, other: BodyKind) -> Self {
+    match (self, other) {
+      (BodyKind::Star, _) | (_, BodyKind::Star) => BodyKind::Star,
+      (BodyKind::Asteroid, BodyKind::Asteroid) => BodyKind::Asteroid,
+      _ => BodyKind::Planet,
+    }
+  }
 
+  pub fn base_color(self) -> [f32; 4] {
+    match self {
+      BodyKind::Star => [1.0, 0.85, 0.45, 1.0],
+      BodyKind::Planet => [1.0, 1.0, 1.0, 1.0],
+      BodyKind::Asteroid => [0.6, 0.55, 0.5, 1.0],
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Planet {
   pub id: usize,
   pub position: Point2<f32>,
+  pub previous_position: Point2<f32>,   // Position before the last step, for render interpolation
   pub velocity: Vector2<f32>,
   pub mass: f32,
   pub radius: f32,
   pub resultant_force: Vector2<f32>,
-  color: Color,
+  pub color: [f32; 4],
+  #[serde(default)]
+  pub kind: BodyKind,
+  #[serde(default)]
+  pub angular_velocity: f32,    // Spin in radians per second, positive is clockwise on screen
+  #[serde(default)]
+  pub rotation: f32,            // Current spin angle, for drawing
+  #[serde(default)]
+  pub name: Option<String>,     // Shown in labels and the inspector
+  #[serde(default)]
+  pub is_static: bool,          // Never moves, as if infinitely heavy. Still attracts other bodies.
+  #[serde(default)]
+  pub ignores_gravity: bool,    // Isn't pulled by other bodies, so keeps its velocity
+  #[serde(default)]
+  pub exerts_no_gravity: bool,  // Doesn't pull on other bodies
+  #[serde(default)]
+  pub tracked: bool,            // Trajectory is recorded, see tracking::TrajectoryLog
+  #[serde(default)]
+  pub particle: bool,           // Part of a soft body, see soft_body
   spawn_protection_timer: Option<Duration>,
 }
 
 impl Planet {
   pub fn new(id: usize, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection_timer: Option<Duration>) -> Planet {
-    let mut n = Planet {
+    Self::new_with_kind(BodyKind::Planet, id, position, velocity, mass, radius, spawn_protection_timer)
+  }
+
+  // Mass comes from the radius and density, m = ρV
+  pub fn with_density(kind: BodyKind, id: usize, position: Point2<f32>, velocity: Option<Vector2<f32>>, radius: f32, density: f32, spawn_protection_timer: Option<Duration>) -> Planet {
+    Self::new_with_kind(kind, id, position, velocity, Some(Self::mass_from_radius(radius, density)), radius, spawn_protection_timer)
+  }
+
+  // If mass is None it comes from the kind's density
+  pub fn new_with_kind(kind: BodyKind, id: usize, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection_timer: Option<Duration>) -> Planet {
+    Planet {
       id,
       position,
+      previous_position: position,
       velocity: velocity.unwrap_or_else(|| Vector2::new(0.0, 0.0)),
-      mass: mass.unwrap_or_else(|| Self::mass_from_radius(radius, PLANET_DENSITY)),
+      mass: mass.unwrap_or_else(|| Self::mass_from_radius(radius, kind.density())),
       radius,
       resultant_force: Vector2::new(0.0, 0.0),
-      color: Color::WHITE,
+      color: kind.base_color(),
+      kind,
+      angular_velocity: 0.0,
+      rotation: 0.0,
+      name: None,
+      is_static: false,
+      ignores_gravity: false,
+      exerts_no_gravity: false,
+      tracked: false,
+      particle: false,
       spawn_protection_timer,
-    };
+    }
+  }
 
-    n
+  // 1/mass, or 0 for static planets, which nothing can push
+  pub fn inverse_mass(&self) -> f32 {
+    if self.is_static { 0.0 } else { 1.0/self.mass }
   }
 
-  pub fn update(&mut self, dt: f32, dt_duration: &Duration) {
-    let acceleration = self.resultant_force/self.mass;  // F = ma, F/m = a
-    self.velocity += acceleration * dt;
-    self.position += self.velocity * dt;
+  // Mass as seen by the gravity calculation
+  pub fn gravitational_mass(&self) -> f32 {
+    if self.exerts_no_gravity { 0.0 } else { self.mass }
+  }
 
-    if TELEPORT_ON_EDGES {
+  // Called after the integrator has moved the planet. Returns true if it wrapped around the edge of the world.
+  pub fn update(&mut self, dt_duration: &Duration, wrap_bounds: Option<(f32, f32)>) -> bool {
+    let mut wrapped = false;
+    if let Some(bounds) = wrap_bounds {   // When edge of bounds is reached, teleport to other side.
+      let before_wrap = self.position;
       if self.position.x < -self.radius {
-        self.position.x = SCREEN_DIMS.0 + self.radius;
-      } else if self.position.x > SCREEN_DIMS.0 + self.radius {
+        self.position.x = bounds.0 + self.radius;
+      } else if self.position.x > bounds.0 + self.radius {
         self.position.x = -self.radius;
       }
       if self.position.y < -self.radius {
-        self.position.y = SCREEN_DIMS.1 + self.radius;
-      } else if self.position.y > SCREEN_DIMS.1 + self.radius {
+        self.position.y = bounds.1 + self.radius;
+      } else if self.position.y > bounds.1 + self.radius {
         self.position.y = -self.radius;
       }
+
+      if self.position != before_wrap {   // Don't interpolate across the screen
+        self.previous_position = self.position;
+        wrapped = true;
+      }
     }
-    
-    self.resultant_force = Vector2::new(0.0, 0.0);
+
+    self.rotation = (self.rotation + self.angular_velocity * dt_duration.as_secs_f32()).rem_euclid(TWO_PI);
 
     if let Some(spawn_timer) = self.spawn_protection_timer.as_mut() {
       if !(*spawn_timer < *dt_duration) {
@@ -68,66 +176,32 @@ impl Planet {
         self.spawn_protection_timer = None;
       }
     }
+    wrapped
   }
 
-  pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas,
-  						body_mesh: &Mesh, text_debug: bool,
-  						vector_debug: bool) -> GameResult {
-    canvas.draw(body_mesh, DrawParam::new()
-    												 .scale(Vector2::new(self.radius, self.radius))
-    												 .dest(self.position)
-    												 .color(self.color));
-
-    if text_debug {
-      const DEBUG_TEXT_SCALE: f32 = 0.7;
-
-      let debug_text = graphics::Text::new(
-        format!("ID: {}\nMass: {}\nRad: {}",
-          self.id,
-          self.mass,
-          self.radius
-        )
-      );
-
-      canvas.draw(
-        &debug_text,
-        DrawParam::new()
-          .scale(Vector2::new(DEBUG_TEXT_SCALE, DEBUG_TEXT_SCALE))
-          .dest(Point2::new(self.position.x + self.radius, self.position.y - self.radius))
-      );
-    }
+  // Position between the previous and current step. alpha = 0 is previous, 1 is current.
+  pub fn interpolated_position(&self, alpha: f32) -> Point2<f32> {
+    self.previous_position + (self.position - self.previous_position) * alpha
+  }
 
-    if vector_debug {
-      // Draw velocity vector
-      if self.velocity.magnitude_squared() > 1.0 {    // Make sure larger than 1 pixel first
-        let line_mesh = Mesh::new_line(
-          ctx,
-          &[self.position, self.position + self.velocity],
-          1.0,
-          [0.0, 1.0, 0.0, 1.0].into()
-        )?;
-        canvas.draw(&line_mesh, DrawParam::default());
-      }
+  pub fn moment_of_inertia(&self) -> f32 {
+    tools::moment_of_inertia(self.mass, self.radius)
+  }
 
-      // Draw force vector
-      if self.resultant_force.magnitude_squared() > 1.0/ACC_DEBUG_VECTOR_MULTIPLIER {
-        let line_mesh = Mesh::new_line(
-          ctx,
-          &[self.position,
-            self.position + self.resultant_force * ACC_DEBUG_VECTOR_MULTIPLIER/self.mass],
-          1.0,
-          [1.0, 0.0, 0.0, 1.0].into()
-        )?;
-        canvas.draw(&line_mesh, DrawParam::default());
-      }
-    }
+  // Spin angular momentum, Iω
+  pub fn spin_angular_momentum(&self) -> f32 {
+    self.moment_of_inertia() * self.angular_velocity
+  }
 
-    Ok(())
+  // Name if it has one, otherwise its kind and id
+  pub fn label(&self) -> String {
+    self.name.clone().unwrap_or_else(|| format!("{} {}", self.kind, self.id))
   }
 
-  pub fn update_color(&mut self) {
-    let (r, g, b) = hsv_to_rgb((self.radius/PLANET_RADIUS_COLORING_LOOP % 1.0, 1.0, 1.0));
-    self.color = [r, g, b, 1.0].into();
+  // Changes kind, keeping mass and radius. The colour becomes the kind's, see World::set_kind to follow the config.
+  pub fn set_kind(&mut self, kind: BodyKind) {
+    self.kind = kind;
+    self.color = kind.base_color();
   }
 
   fn mass_from_radius(radius: f32, density: f32) -> f32 {
@@ -140,121 +214,32 @@ impl Planet {
     tools::inverse_volume_of_sphere(mass/density)
   }
 
-  pub fn has_spawn_protection(&self) -> bool {
-    self.spawn_protection_timer.is_some()
-  }
-}
-
-const PLANET_TRAIL_NODE_PLACEMENT_PERIOD: u64 = 20;
-const PLANET_TRAIL_NODE_LIFETIME: f32 = 0.7;
-
-pub struct PlanetTrail {
-  nodes: VecDeque<PlanetTrailNode>,
-  node_placement_timer: Duration,
-  has_parent: bool,
-}
-
-impl PlanetTrail {
-  pub fn new(start_pos: Point2<f32>) -> Self {
-    let mut nodes = VecDeque::with_capacity(36);
-    nodes.push_front(PlanetTrailNode::from(start_pos));
-
-    Self {
-      nodes,
-      node_placement_timer: Duration::new(0, 0),
-      has_parent: true,
-    }
-  }
-
-  pub fn update(&mut self, dt_duration: &Duration, parent_pos: Option<Point2<f32>>) {
-    self.kill_dead_nodes();
-
-    if let Some(parent_pos) = parent_pos {
-      self.has_parent = true;
-      self.node_placement_timer += *dt_duration;
-
-      let period = Duration::from_millis(PLANET_TRAIL_NODE_PLACEMENT_PERIOD);
-      if self.node_placement_timer > period {
-        // Place new node
-        self.add_node(parent_pos);
-        self.node_placement_timer -= period;
-      }
-    } else {
-      self.has_parent = false;
-    }
-  }
-
-  pub fn draw(&self, mesh: &mut MeshBuilder) -> GameResult<bool> {    // Returns if any line segments drawn
-    let len = self.node_count();
-    let mut draw_segments = 0;
-    if len > 1 {
-      for i in 0..len-1 {
-        if (self.nodes[i].pos.x - self.nodes[i + 1].pos.x).powi(2) +
-          (self.nodes[i].pos.y - self.nodes[i + 1].pos.y).powi(2) <
-          (SCREEN_DIMS.0.min(SCREEN_DIMS.1)/2.0).powi(2)  // Make sure line length is less than half the minimum screen dimensions.
-        {
-          draw_segments += 1;
-          // Change transpacency depending on how long the node has been alive.
-          let mut alpha = 1.0 - (Instant::now().duration_since(self.nodes[i].time_created).as_secs_f32() /
-                     PLANET_TRAIL_NODE_LIFETIME);
-          alpha = alpha.max(0.0).powi(2);
-  
-          mesh.line(
-            &[self.nodes[i].pos, self.nodes[i + 1].pos],
-            1.0,
-            [0.1, 0.4, 1.0, alpha].into()
-          )?;
-        }
-      }
-    }
-
-    Ok(draw_segments > 0)
+  // Actual density, which can differ from the kind's after edits. Always mass/volume, so the setters below keep
+  // the three consistent by changing one of the others.
+  pub fn density(&self) -> f32 {
+    self.mass/tools::volume_of_sphere(self.radius)
   }
 
-  fn kill_dead_nodes(&mut self) {
-    while let Some(node) = self.nodes.front() {
-      if Instant::now().duration_since(node.time_created).as_secs_f32() >= PLANET_TRAIL_NODE_LIFETIME {
-        self.nodes.pop_front();
-      } else {
-        break
-      }
-    }
+  // Keeps the density, so the radius grows or shrinks with the mass
+  pub fn set_mass(&mut self, mass: f32) {
+    let density = self.density();
+    self.mass = mass;
+    self.radius = Self::radius_from_mass(mass, density);
   }
 
-  pub fn node_count(&self) -> usize {
-    self.nodes.len()
+  // Keeps the density, so the mass follows the volume
+  pub fn set_radius(&mut self, radius: f32) {
+    let density = self.density();
+    self.radius = radius;
+    self.mass = Self::mass_from_radius(radius, density);
   }
 
-  pub fn is_dead(&self) -> bool {
-    self.nodes.is_empty() && !self.has_parent
+  // Keeps the mass, so gravity is unchanged and the body shrinks as it gets denser
+  pub fn set_density(&mut self, density: f32) {
+    self.radius = Self::radius_from_mass(self.mass, density);
   }
 
-  pub fn add_node(&mut self, pos: Point2<f32>) {
-    // Make sure distance from last node is a sufficient distance so that line can be drawn without errors
-    let can_place = {
-      if let Some(last_node) = self.nodes.back() {
-        self.nodes.is_empty() || ((pos.x - last_node.pos.x).powi(2) + (pos.y - last_node.pos.y).powi(2)) > 0.1
-      } else {
-        false
-      }
-    };
-
-    if can_place {
-      self.nodes.push_back(PlanetTrailNode::from(pos));
-    }
+  pub fn has_spawn_protection(&self) -> bool {
+    self.spawn_protection_timer.is_some()
   }
 }
-
-struct PlanetTrailNode {
-  pos: Point2<f32>,
-  time_created: Instant,
-}
-
-impl From<Point2<f32>> for PlanetTrailNode {
-  fn from(pos: Point2<f32>) -> Self {
-    Self {
-      pos,
-      time_created: Instant::now(),
-    }
-  }
-}
\ No newline at end of file
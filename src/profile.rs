@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+// Time spent in each part of the physics, added up over every step since the last World::take_timings.
+// Measured on every step, as Instant::now is cheap next to the work it times.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StepTimings {
+  pub forces: Duration,                 // Building the tree (or the GPU pass) and finding accelerations
+  pub collision_detection: Duration,    // Broad phase, finding pairs close enough to check
+  pub collision_resolution: Duration,   // Checking those pairs, then merging, bouncing or splitting
+}
+
+impl StepTimings {
+  pub fn total(&self) -> Duration {
+    self.forces + self.collision_detection + self.collision_resolution
+  }
+}
+
+// Milliseconds, for readouts
+pub fn ms(duration: Duration) -> f32 {
+  duration.as_secs_f32() * 1000.0
+}
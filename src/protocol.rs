@@ -0,0 +1,102 @@
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+use std::io::{self, Read, Write, ErrorKind};
+use std::net::TcpStream;
+
+use orbits::simulation::World;
+use orbits::planet::Planet;
+
+pub const DEFAULT_PORT: u16 = 7878;
+pub const BROADCAST_RATE: f32 = 20.0;   // State updates per second from the host
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;  // Bytes. Peers sending longer lines are dropped.
+
+// Host to client
+#[derive(Serialize, Deserialize)]
+pub enum HostMessage {
+  Welcome(World),   // Sent once on joining. Bodies then change through deltas.
+  Delta {
+    time: f32,
+    planets: Vec<Planet>,   // New and moving bodies. Static bodies are only sent when new.
+    removed: Vec<usize>,
+  },
+}
+
+// Client to host. Clients don't step the simulation, they only ask the host to change it.
+#[derive(Serialize, Deserialize)]
+pub enum ClientMessage {
+  Spawn(Vec<Planet>),   // Ids are ignored, the host gives them new ones
+}
+
+// One end of a connection, sending and receiving newline separated RON messages without blocking
+pub struct Peer {
+  stream: TcpStream,
+  incoming: Vec<u8>,
+  outgoing: Vec<u8>,
+}
+
+impl Peer {
+  pub fn new(stream: TcpStream) -> io::Result<Self> {
+    stream.set_nonblocking(true)?;
+    stream.set_nodelay(true)?;
+    Ok(Self {
+      stream,
+      incoming: Vec::new(),
+      outgoing: Vec::new(),
+    })
+  }
+
+  pub fn connect(address: &str) -> io::Result<Self> {
+    Self::new(TcpStream::connect(address)?)
+  }
+
+  // Queued until flush
+  pub fn send<T: Serialize>(&mut self, message: &T) -> io::Result<()> {
+    let line = ron::ser::to_string(message).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    self.outgoing.extend_from_slice(line.as_bytes());
+    self.outgoing.push(b'\n');
+    Ok(())
+  }
+
+  // Writes as much of the queue as the socket takes now
+  pub fn flush(&mut self) -> io::Result<()> {
+    while !self.outgoing.is_empty() {
+      match self.stream.write(&self.outgoing) {
+        Ok(0) => return Err(ErrorKind::WriteZero.into()),
+        Ok(n) => { self.outgoing.drain(..n); },
+        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+        Err(e) if e.kind() == ErrorKind::Interrupted => (),
+        Err(e) => return Err(e),
+      }
+    }
+    Ok(())
+  }
+
+  // Every whole message received so far. Errors if the other end has gone or sent something unreadable.
+  pub fn receive<T: DeserializeOwned>(&mut self) -> io::Result<Vec<T>> {
+    let mut buffer = [0; 4096];
+    loop {
+      match self.stream.read(&mut buffer) {
+        Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+        Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+        Err(e) if e.kind() == ErrorKind::Interrupted => (),
+        Err(e) => return Err(e),
+      }
+    }
+
+    let mut messages = Vec::new();
+    while let Some(end) = self.incoming.iter().position(|byte| *byte == b'\n') {
+      let line: Vec<u8> = self.incoming.drain(..=end).collect();
+      let text = std::str::from_utf8(&line[..end]).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+      messages.push(ron::from_str(text).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?);
+    }
+    if self.incoming.len() > MAX_MESSAGE_LEN {
+      return Err(io::Error::new(ErrorKind::InvalidData, "message too long"))
+    }
+    Ok(messages)
+  }
+
+  pub fn address(&self) -> String {
+    self.stream.peer_addr().map_or_else(|_| "unknown".to_string(), |address| address.to_string())
+  }
+}
@@ -0,0 +1,165 @@
+use pyo3::prelude::*;
+use pyo3::exceptions::PyValueError;
+use numpy::PyArray2;
+
+use nalgebra::{Point2, Vector2};
+
+use std::time::Duration;
+
+use crate::simulation::World;
+use crate::planet::BodyKind;
+use crate::diagnostics::Diagnostics;
+use crate::scenarios;
+
+const DEFAULT_BOUNDS: (f32, f32) = (1600.0, 1000.0);  // Area scenarios are built in, the same as the default window
+
+// Columns of World.states()
+const STATE_COLUMNS: [&str; 8] = ["id", "x", "y", "vx", "vy", "mass", "radius", "spin"];
+
+// Python bindings, built with the python feature (e.g. with maturin). The same World as the game, without rendering:
+//
+//   import orbits
+//   world = orbits.World(seed=1)
+//   world.load_scenario("two-body orbit")
+//   world.step(1/120, steps=1000)
+//   states = world.states()   # numpy array, one row per body, columns as in orbits.STATE_COLUMNS
+#[pyclass(name = "World")]
+pub struct PyWorld {
+  world: World,
+}
+
+#[pymethods]
+impl PyWorld {
+  #[new]
+  #[pyo3(signature = (seed=None))]
+  fn new(seed: Option<u64>) -> Self {
+    let mut world = World::new();
+    if let Some(seed) = seed {
+      world.seed(seed);
+    }
+    Self { world }
+  }
+
+  // Adds a preset scenario's bodies, by name (case doesn't matter)
+  #[pyo3(signature = (name, width=DEFAULT_BOUNDS.0, height=DEFAULT_BOUNDS.1))]
+  fn load_scenario(&mut self, name: &str, width: f32, height: f32) -> PyResult<()> {
+    let presets = scenarios::presets();
+    let index = scenarios::find(&presets, name)
+      .ok_or_else(|| PyValueError::new_err(format!("no scenario called {}", name)))?;
+    presets[index].build(&mut self.world, (width, height));
+    Ok(())
+  }
+
+  // Returns the new body's id. Mass defaults to the kind's density times the volume.
+  #[pyo3(signature = (x, y, vx=0.0, vy=0.0, mass=None, radius=1.0, kind="planet"))]
+  fn add_body(&mut self, x: f32, y: f32, vx: f32, vy: f32, mass: Option<f32>, radius: f32, kind: &str) -> PyResult<usize> {
+    let kind = match kind.to_lowercase().as_str() {
+      "star" => BodyKind::Star,
+      "planet" => BodyKind::Planet,
+      "asteroid" => BodyKind::Asteroid,
+      _ => return Err(PyValueError::new_err(format!("kind must be star, planet or asteroid, not {}", kind))),
+    };
+    if radius <= 0.0 || mass.map_or(false, |mass| mass <= 0.0) {
+      return Err(PyValueError::new_err("mass and radius must be above zero"))
+    }
+    Ok(self.world.add_body(kind, Point2::new(x, y), Some(Vector2::new(vx, vy)), mass, radius, None))
+  }
+
+  fn remove_body(&mut self, id: usize) {
+    self.world.remove_planet(id);
+  }
+
+  // Advances by steps steps of dt sim seconds each. The GIL is released meanwhile.
+  #[pyo3(signature = (dt, steps=1))]
+  fn step(&mut self, py: Python, dt: f32, steps: usize) -> PyResult<()> {
+    if !(dt > 0.0 && dt.is_finite()) {
+      return Err(PyValueError::new_err("dt must be above zero"))
+    }
+    let dt = Duration::from_secs_f32(dt);
+    let world = &mut self.world;
+    py.allow_threads(|| {
+      for _ in 0..steps {
+        world.step(&dt);
+      }
+    });
+    Ok(())
+  }
+
+  // Every body's state as an N x 8 float64 array, sorted by id. Columns are STATE_COLUMNS.
+  fn states<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<f64>> {
+    let mut ids: Vec<&usize> = self.world.planets.keys().collect();
+    ids.sort_unstable();
+    let rows: Vec<Vec<f64>> = ids.into_iter()
+      .map(|id| {
+        let pl = self.world.planets[id].borrow();
+        [pl.id as f32, pl.position.x, pl.position.y, pl.velocity.x, pl.velocity.y, pl.mass, pl.radius, pl.angular_velocity]
+          .iter()
+          .map(|v| *v as f64)
+          .collect()
+      })
+      .collect();
+    if rows.is_empty() {
+      return Ok(PyArray2::zeros(py, [0, STATE_COLUMNS.len()], false))
+    }
+    Ok(PyArray2::from_vec2(py, &rows)?)
+  }
+
+  fn ids(&self) -> Vec<usize> {
+    let mut ids: Vec<usize> = self.world.planets.keys().copied().collect();
+    ids.sort_unstable();
+    ids
+  }
+
+  // Kinetic, potential and total energy, momentum and angular momentum, as in the game's diagnostics
+  fn diagnostics(&self) -> (f32, f32, f32, (f32, f32), f32) {
+    let d = Diagnostics::measure(&self.world);
+    (d.kinetic_energy, d.potential_energy, d.total_energy(), (d.linear_momentum.x, d.linear_momentum.y), d.angular_momentum)
+  }
+
+  fn __len__(&self) -> usize {
+    self.world.planets.len()
+  }
+
+  #[getter]
+  fn time(&self) -> f32 {
+    self.world.time
+  }
+
+  #[getter]
+  fn g(&self) -> f32 {
+    self.world.config.g
+  }
+
+  #[setter]
+  fn set_g(&mut self, g: f32) {
+    self.world.config.g = g;
+  }
+
+  #[getter]
+  fn softening(&self) -> f32 {
+    self.world.config.softening
+  }
+
+  #[setter]
+  fn set_softening(&mut self, softening: f32) {
+    self.world.config.softening = softening.max(0.0);
+  }
+
+  #[getter]
+  fn theta(&self) -> f32 {
+    self.world.barnes_hut_theta
+  }
+
+  #[setter]
+  fn set_theta(&mut self, theta: f32) {
+    self.world.barnes_hut_theta = theta.max(0.0);
+  }
+}
+
+#[pymodule]
+fn orbits(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_class::<PyWorld>()?;
+  m.add("STATE_COLUMNS", STATE_COLUMNS.to_vec())?;
+  m.add("SCENARIOS", scenarios::presets().iter().map(|scenario| scenario.name().to_string()).collect::<Vec<_>>())?;
+  Ok(())
+}
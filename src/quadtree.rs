@@ -0,0 +1,256 @@
+use nalgebra::{Vector2, Point2};
+use rayon::prelude::*;
+
+use crate::tools;
+
+const MAX_DEPTH: u32 = 32;    // Stops infinite subdivision when bodies share a position
+
+// A body as seen by the tree. Index refers to the slice the tree was built from.
+#[derive(Clone, Copy)]
+pub struct TreeBody {
+  pub position: Point2<f32>,
+  pub mass: f32,
+  pub radius: f32,
+}
+
+struct Node {
+  centre: Point2<f32>,
+  half_width: f32,
+  mass: f32,
+  mass_weighted_pos: Vector2<f32>,  // Sum of position * mass, divided by mass to get the centre of mass
+  bodies: Vec<usize>,               // Only populated for leaves
+  children: Option<[usize; 4]>,     // Indexes into QuadTree::nodes
+}
+
+impl Node {
+  fn new(centre: Point2<f32>, half_width: f32) -> Self {
+    Self {
+      centre,
+      half_width,
+      mass: 0.0,
+      mass_weighted_pos: Vector2::new(0.0, 0.0),
+      bodies: Vec::new(),
+      children: None,
+    }
+  }
+
+  fn centre_of_mass(&self) -> Point2<f32> {
+    Point2::from(self.mass_weighted_pos/self.mass)
+  }
+
+  fn contains(&self, pos: &Point2<f32>) -> bool {
+    (pos.x - self.centre.x).abs() <= self.half_width && (pos.y - self.centre.y).abs() <= self.half_width
+  }
+
+  fn quadrant_of(&self, pos: &Point2<f32>) -> usize {
+    let mut q = 0;
+    if pos.x >= self.centre.x { q += 1; }
+    if pos.y >= self.centre.y { q += 2; }
+    q
+  }
+}
+
+// Barnes–Hut quadtree. Nodes far enough away (width/distance < theta) are treated as a single
+// body at their centre of mass, so force calculation is O(n log n) instead of O(n²).
+// If the world wraps, distances use the nearest image of each body, and a node is only approximated
+// when all of it lies within half a world of the body (so it has one well defined nearest image).
+pub struct QuadTree<'a> {
+  nodes: Vec<Node>,
+  bodies: &'a [TreeBody],
+  theta: f32,
+  g: f32,
+  softening: f32,
+  wrap_bounds: Option<(f32, f32)>,
+}
+
+impl<'a> QuadTree<'a> {
+  pub fn new(bodies: &'a [TreeBody], theta: f32, g: f32, softening: f32, wrap_bounds: Option<(f32, f32)>) -> Self {
+    // Find square bounding box containing all bodies
+    let (mut min, mut max) = (Point2::new(f32::MAX, f32::MAX), Point2::new(f32::MIN, f32::MIN));
+    for b in bodies.iter() {
+      min.x = min.x.min(b.position.x);
+      min.y = min.y.min(b.position.y);
+      max.x = max.x.max(b.position.x);
+      max.y = max.y.max(b.position.y);
+    }
+    let half_width = ((max.x - min.x).max(max.y - min.y)/2.0).max(1.0);
+
+    let mut tree = Self {
+      nodes: Vec::with_capacity(bodies.len() * 2),
+      bodies,
+      theta,
+      g,
+      softening,
+      wrap_bounds,
+    };
+
+    if !bodies.is_empty() {
+      tree.nodes.push(Node::new(Point2::from((min.coords + max.coords)/2.0), half_width));
+      for i in 0..bodies.len() {
+        tree.insert(0, i, 0);
+      }
+    }
+
+    tree
+  }
+
+  fn insert(&mut self, node_id: usize, body_id: usize, depth: u32) {
+    let body = self.bodies[body_id];
+    {
+      let node = &mut self.nodes[node_id];
+      node.mass += body.mass;
+      node.mass_weighted_pos += body.position.coords * body.mass;
+    }
+
+    if let Some(children) = self.nodes[node_id].children {
+      let q = self.nodes[node_id].quadrant_of(&body.position);
+      self.insert(children[q], body_id, depth + 1);
+    } else if self.nodes[node_id].bodies.is_empty() || depth >= MAX_DEPTH {
+      self.nodes[node_id].bodies.push(body_id);
+    } else {
+      // Leaf already has a body, so split it and push both down
+      self.subdivide(node_id);
+      let existing: Vec<usize> = self.nodes[node_id].bodies.drain(..).collect();
+      let children = self.nodes[node_id].children.unwrap();
+      for id in existing.into_iter().chain(std::iter::once(body_id)) {
+        let q = self.nodes[node_id].quadrant_of(&self.bodies[id].position);
+        self.insert(children[q], id, depth + 1);
+      }
+    }
+  }
+
+  fn subdivide(&mut self, node_id: usize) {
+    let (centre, half_width) = (self.nodes[node_id].centre, self.nodes[node_id].half_width);
+    let quarter = half_width/2.0;
+    let first = self.nodes.len();
+
+    for q in 0..4 {
+      let offset = Vector2::new(
+        if q & 1 == 0 { -quarter } else { quarter },
+        if q & 2 == 0 { -quarter } else { quarter },
+      );
+      self.nodes.push(Node::new(centre + offset, quarter));
+    }
+
+    self.nodes[node_id].children = Some([first, first + 1, first + 2, first + 3]);
+  }
+
+  // Resultant gravitational force on the body at body_id.
+  // Bodies overlapping the target are skipped, as they are about to collide (and forces explode).
+  pub fn force_on(&self, body_id: usize) -> Vector2<f32> {
+    let mut force = Vector2::new(0.0, 0.0);
+    if !self.nodes.is_empty() {
+      self.accumulate_force(0, &self.bodies[body_id], Some(body_id), &mut force);
+    }
+    force
+  }
+
+  // Gravitational acceleration a test particle (which has no effect on the bodies) would feel at a position
+  pub fn acceleration_at(&self, position: Point2<f32>, radius: f32) -> Vector2<f32> {
+    let mut force = Vector2::new(0.0, 0.0);
+    if !self.nodes.is_empty() {
+      let test_body = TreeBody { position, mass: 1.0, radius };
+      self.accumulate_force(0, &test_body, None, &mut force);
+    }
+    force
+  }
+
+  // Gravitational potential at the body at body_id due to every other body, -Σ Gm/sqrt(r² + ε²), using the same
+  // approximation as force_on. Overlapping bodies count here.
+  pub fn potential_on(&self, body_id: usize) -> f32 {
+    if self.nodes.is_empty() { return 0.0 }
+    self.accumulate_potential(0, &self.bodies[body_id], body_id)
+  }
+
+  fn accumulate_potential(&self, node_id: usize, body: &TreeBody, exclude: usize) -> f32 {
+    let node = &self.nodes[node_id];
+    if node.mass <= 0.0 { return 0.0 }
+    let softening_squared = self.softening * self.softening;
+
+    if let Some(children) = node.children {
+      let centre_offset = tools::minimum_image(node.centre - body.position, self.wrap_bounds);
+      let dist_vec = centre_offset + (node.centre_of_mass() - node.centre);
+      let square_dist = dist_vec.magnitude_squared();
+      let width = node.half_width * 2.0;
+
+      let well_separated = self.wrap_bounds.map_or(true, |(w, h)| {
+        centre_offset.x.abs() + node.half_width < w/2.0 && centre_offset.y.abs() + node.half_width < h/2.0
+      });
+
+      if !node.contains(&body.position) && well_separated && width * width < self.theta * self.theta * square_dist {
+        -self.g * node.mass/(square_dist + softening_squared).sqrt()
+      } else {
+        children.iter().map(|child| self.accumulate_potential(*child, body, exclude)).sum()
+      }
+    } else {
+      node.bodies.iter()
+        .filter(|&&id| id != exclude)
+        .map(|other_id| {
+          let other = &self.bodies[*other_id];
+          let dist_vec = tools::minimum_image(other.position - body.position, self.wrap_bounds);
+          let dist = (dist_vec.magnitude_squared() + softening_squared).sqrt();
+          if dist > 0.0 { -self.g * other.mass/dist } else { 0.0 }
+        })
+        .sum()
+    }
+  }
+
+  fn accumulate_force(&self, node_id: usize, body: &TreeBody, exclude: Option<usize>, force: &mut Vector2<f32>) {
+    let node = &self.nodes[node_id];
+    if node.mass <= 0.0 { return }
+
+    if let Some(children) = node.children {
+      // Offset to the nearest image of the node, then to its centre of mass within it
+      let centre_offset = tools::minimum_image(node.centre - body.position, self.wrap_bounds);
+      let dist_vec = centre_offset + (node.centre_of_mass() - node.centre);
+      let square_dist = dist_vec.magnitude_squared();
+      let width = node.half_width * 2.0;
+
+      let well_separated = self.wrap_bounds.map_or(true, |(w, h)| {
+        centre_offset.x.abs() + node.half_width < w/2.0 && centre_offset.y.abs() + node.half_width < h/2.0
+      });
+
+      if !node.contains(&body.position) && well_separated && width * width < self.theta * self.theta * square_dist {
+        // Far enough away to approximate
+        *force += tools::grav_force(self.g, self.softening, body.mass, node.mass, square_dist, dist_vec);
+      } else {
+        for child in children.iter() {
+          self.accumulate_force(*child, body, exclude, force);
+        }
+      }
+    } else {
+      for other_id in node.bodies.iter().filter(|&&id| Some(id) != exclude) {
+        let other = &self.bodies[*other_id];
+        let dist_vec = tools::minimum_image(other.position - body.position, self.wrap_bounds);
+        let square_dist = dist_vec.magnitude_squared();
+        if square_dist > (body.radius + other.radius).powi(2) {
+          *force += tools::grav_force(self.g, self.softening, body.mass, other.mass, square_dist, dist_vec);
+        }
+      }
+    }
+  }
+}
+
+// Gravitational acceleration of every body, in the same order as `bodies`.
+// The tree is only read once built, so each body's force is computed in parallel into its own slot.
+// Bodies with no mass don't attract anything, but are still attracted like a test particle. They're left out of
+// the tree, so thousands of them cost about as much as thousands of lookups rather than thousands of pairs each.
+pub fn accelerations(bodies: &[TreeBody], theta: f32, g: f32, softening: f32, wrap_bounds: Option<(f32, f32)>) -> Vec<Vector2<f32>> {
+  let attracting: Vec<usize> = (0..bodies.len()).filter(|i| bodies[*i].mass > 0.0).collect();
+  let tree_bodies: Vec<TreeBody> = attracting.iter().map(|i| bodies[*i]).collect();
+  let tree = QuadTree::new(&tree_bodies, theta, g, softening, wrap_bounds);
+
+  // Index of each body in the tree, if it's in it
+  let mut in_tree = vec![None; bodies.len()];
+  for (tree_index, i) in attracting.iter().enumerate() {
+    in_tree[*i] = Some(tree_index);
+  }
+
+  (0..bodies.len())
+    .into_par_iter()
+    .map(|i| match in_tree[i] {
+      Some(tree_index) => tree.force_on(tree_index)/bodies[i].mass,
+      None => tree.acceleration_at(bodies[i].position, bodies[i].radius),
+    })
+    .collect()
+}
@@ -0,0 +1,435 @@
+use ggez::graphics::{self, Mesh, MeshBuilder, DrawParam, DrawMode, Canvas, InstanceArray, BlendMode};
+use ggez::{Context, GameResult};
+
+use nalgebra::{Vector2, Point2};
+
+use std::fmt;
+use std::f32::consts::PI;
+
+use orbits::planet::{Planet, BodyKind};
+use orbits::tools::{self, OrbitalElements};
+
+use crate::{ACC_DEBUG_VECTOR_MULTIPLIER, SCREEN_DIMS};
+
+// How planets are coloured
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColorMode {
+  #[default]
+  Kind,   // Each body's own colour (see config::PlanetColoring)
+  Mass,   // Dark red for the lightest body through to white hot for the heaviest, with the heaviest glowing
+}
+
+impl ColorMode {
+  pub fn next(self) -> Self {
+    match self {
+      ColorMode::Kind => ColorMode::Mass,
+      ColorMode::Mass => ColorMode::Kind,
+    }
+  }
+}
+
+impl fmt::Display for ColorMode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", match self {
+      ColorMode::Kind => "Kind",
+      ColorMode::Mass => "Mass",
+    })
+  }
+}
+
+// Draws every planet as instances of one unit circle mesh, so there is one draw call per layer rather than per planet.
+// The instance buffers are kept between frames to avoid reallocating them.
+pub struct PlanetBatch {
+  glows: InstanceArray,     // Under the bodies
+  additive_glows: InstanceArray,  // Added to what's underneath, so overlapping glows brighten
+  bodies: InstanceArray,
+  markers: InstanceArray,   // On top of the bodies
+}
+
+impl PlanetBatch {
+  pub fn new(ctx: &Context) -> Self {
+    Self {
+      glows: InstanceArray::new(ctx, None),
+      additive_glows: InstanceArray::new(ctx, None),
+      bodies: InstanceArray::new(ctx, None),
+      markers: InstanceArray::new(ctx, None),
+    }
+  }
+
+  // alpha is how far between the previous and current physics step to draw the planets
+  pub fn draw(&mut self, planets: &[&Planet], alpha: f32, body_mesh: &Mesh, color_mode: ColorMode, canvas: &mut Canvas) {
+    self.glows.clear();
+    self.additive_glows.clear();
+    self.bodies.clear();
+    self.markers.clear();
+
+    // Log scale, as masses range over many orders of magnitude
+    let (min_log_mass, max_log_mass) = planets.iter()
+      .map(|pl| pl.mass.ln())
+      .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), m| (min.min(m), max.max(m)));
+    let log_mass_range = (max_log_mass - min_log_mass).max(f32::EPSILON);
+
+    for planet in planets.iter() {
+      let position = planet.interpolated_position(alpha);
+      let color = match color_mode {
+        ColorMode::Kind => {
+          if planet.kind == BodyKind::Star {
+            push_glow(planet, position, planet.color, GLOW_ALPHA, &mut self.glows);
+          }
+          planet.color
+        },
+        ColorMode::Mass => {
+          let heat = (planet.mass.ln() - min_log_mass)/log_mass_range;
+          let color = heat_color(heat);
+          if heat > MASS_GLOW_START {
+            let glow_alpha = GLOW_ALPHA * (heat - MASS_GLOW_START)/(1.0 - MASS_GLOW_START);
+            push_glow(planet, position, color, glow_alpha, &mut self.additive_glows);
+          }
+          color
+        },
+      };
+      self.bodies.push(DrawParam::new()
+                         .scale(Vector2::new(planet.radius, planet.radius))
+                         .dest(position)
+                         .color(color));
+      push_spin_marker(planet, position, color, &mut self.markers);
+    }
+
+    if !self.additive_glows.instances().is_empty() {
+      canvas.set_blend_mode(BlendMode::ADD);
+      canvas.draw_instanced_mesh(body_mesh.clone(), &self.additive_glows, DrawParam::default());
+      canvas.set_blend_mode(BlendMode::ALPHA);
+    }
+    for instances in [&self.glows, &self.bodies, &self.markers] {
+      if instances.instances().is_empty() { continue }
+      canvas.draw_instanced_mesh(body_mesh.clone(), instances, DrawParam::default());
+    }
+  }
+}
+
+// How velocity/force arrows are drawn
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VectorDebug {
+  pub scale: f32,         // Multiplies arrow lengths
+  pub show_force: bool,   // Net force (as acceleration) as well as velocity
+}
+
+// Info text and velocity/force arrows for a planet, drawn on top of the batch
+pub fn draw_planet_debug(planet: &Planet, ctx: &mut Context, canvas: &mut Canvas,
+                         alpha: f32, text_debug: bool, vector_debug: Option<VectorDebug>) -> GameResult {
+  let position = planet.interpolated_position(alpha);
+
+  if text_debug {
+    const DEBUG_TEXT_SCALE: f32 = 0.7;
+
+    let debug_text = graphics::Text::new(
+      format!("ID: {}\nMass: {}\nRad: {}",
+        planet.id,
+        planet.mass,
+        planet.radius
+      )
+    );
+
+    canvas.draw(
+      &debug_text,
+      DrawParam::new()
+        .scale(Vector2::new(DEBUG_TEXT_SCALE, DEBUG_TEXT_SCALE))
+        .dest(Point2::new(position.x + planet.radius, position.y - planet.radius))
+    );
+  }
+
+  if let Some(vectors) = vector_debug {
+    let mut mesh = MeshBuilder::new();
+    let mut drawn = push_arrow(position, planet.velocity * vectors.scale, [0.0, 1.0, 0.0, 1.0], &mut mesh)?;
+    if vectors.show_force {
+      let acceleration = planet.resultant_force * ACC_DEBUG_VECTOR_MULTIPLIER/planet.mass;
+      drawn |= push_arrow(position, acceleration * vectors.scale, [1.0, 0.0, 0.0, 1.0], &mut mesh)?;
+    }
+    if drawn {
+      canvas.draw(&Mesh::from_data(ctx, mesh.build()), DrawParam::default());
+    }
+  }
+
+  Ok(())
+}
+
+// Line from start along vector with a head at the end. Returns false if the arrow is too short to draw (under 1 pixel).
+fn push_arrow(start: Point2<f32>, vector: Vector2<f32>, color: [f32; 4], mesh: &mut MeshBuilder) -> GameResult<bool> {
+  const HEAD_LENGTH: f32 = 5.0;
+  const HEAD_HALF_WIDTH: f32 = 2.5;
+
+  let length = vector.magnitude();
+  if length <= 1.0 { return Ok(false) }
+
+  let end = start + vector;
+  let direction = vector/length;
+  let normal = Vector2::new(-direction.y, direction.x);
+  let head_length = HEAD_LENGTH.min(length/2.0);
+  let head_base = end - direction * head_length;
+
+  mesh.line(&[start, head_base], 1.0, color.into())?;
+  mesh.triangles(
+    &[end, head_base + normal * HEAD_HALF_WIDTH, head_base - normal * HEAD_HALF_WIDTH],
+    color.into(),
+  )?;
+  Ok(true)
+}
+
+// Line from a planet to the handle that sets its velocity, in world coordinates
+pub fn draw_velocity_handle(position: Point2<f32>, handle: Point2<f32>, radius: f32, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  const HANDLE_COLOR: [f32; 4] = [0.3, 1.0, 0.3, 0.8];
+
+  let mut mesh = MeshBuilder::new();
+  if (handle - position).magnitude_squared() > 1.0 {
+    mesh.line(&[position, handle], 1.0, HANDLE_COLOR.into())?;
+  }
+  mesh.circle(DrawMode::stroke(1.5), handle, radius, 0.1, HANDLE_COLOR.into())?;
+  canvas.draw(&Mesh::from_data(ctx, mesh.build()), DrawParam::default());
+  Ok(())
+}
+
+const PANEL_WIDTH: f32 = 260.0;   // Width of the inspector panel on the right of the screen
+
+// Planet's name to the right of it, in world coordinates. Skipped for planets too small to tell apart.
+pub fn draw_label(planet: &Planet, alpha: f32, canvas: &mut Canvas) {
+  const MIN_RADIUS: f32 = 3.0;
+  const LABEL_SCALE: f32 = 0.8;
+
+  if planet.radius < MIN_RADIUS { return }
+
+  let position = planet.interpolated_position(alpha);
+  let text = graphics::Text::new(planet.label());
+  canvas.draw(
+    &text,
+    DrawParam::new()
+      .scale(Vector2::new(LABEL_SCALE, LABEL_SCALE))
+      .dest(Point2::new(position.x + planet.radius + 3.0, position.y - 6.0))
+      .color([0.8, 0.8, 0.8, 0.8]),
+  );
+}
+
+// Ring around the selected planet, in world coordinates
+pub fn draw_selection_ring(planet: &Planet, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  const RING_PADDING: f32 = 4.0;
+
+  let ring = Mesh::new_circle(
+    ctx,
+    DrawMode::stroke(1.5),
+    planet.position,
+    planet.radius + RING_PADDING,
+    0.1,
+    [1.0, 1.0, 0.0, 0.8].into(),
+  )?;
+  canvas.draw(&ring, DrawParam::default());
+  Ok(())
+}
+
+// Box being dragged out to select a group, between two opposite corners in world coordinates
+pub fn draw_selection_box(corner: Point2<f32>, opposite: Point2<f32>, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  let (min, max) = (corner.inf(&opposite), corner.sup(&opposite));
+  let rect = graphics::Rect::new(min.x, min.y, max.x - min.x, max.y - min.y);
+  let mut mesh = MeshBuilder::new();
+  mesh.rectangle(DrawMode::fill(), rect, [1.0, 1.0, 0.0, 0.1].into())?;
+  mesh.rectangle(DrawMode::stroke(1.0), rect, [1.0, 1.0, 0.0, 0.6].into())?;
+  canvas.draw(&Mesh::from_data(ctx, mesh.build()), DrawParam::default());
+  Ok(())
+}
+
+// Panel with the selected planet's live stats, in screen coordinates
+pub fn draw_inspector(planet: &Planet, canvas: &mut Canvas) {
+  let text = graphics::Text::new(
+    format!(
+      "{}\nMass: {:.3}\nRadius: {:.3}\nSpin: {:.3} rad/s\nPosition: ({:.1}, {:.1})\nVelocity: ({:.2}, {:.2})\nSpeed: {:.2}\nForce: ({:.3}, {:.3})",
+      planet.name.as_ref().map_or(format!("{} {}", planet.kind, planet.id), |name| format!("{} ({} {})", name, planet.kind, planet.id)),
+      planet.mass,
+      planet.radius,
+      planet.angular_velocity,
+      planet.position.x, planet.position.y,
+      planet.velocity.x, planet.velocity.y,
+      planet.velocity.magnitude(),
+      planet.resultant_force.x, planet.resultant_force.y,
+    )
+  );
+  canvas.draw(&text, DrawParam::new().dest([SCREEN_DIMS.0 - PANEL_WIDTH, 10.0]));
+}
+
+// Small box of text next to the cursor, in screen coordinates. Kept on screen near the edges.
+pub fn draw_tooltip(text: &str, cursor: Point2<f32>, ctx: &Context, canvas: &mut Canvas) {
+  const OFFSET: f32 = 16.0;     // From the cursor, so it doesn't cover the body
+  const PADDING: f32 = 4.0;
+  const TEXT_SIZE: f32 = 13.0;
+
+  let mut text = graphics::Text::new(text);
+  text.set_scale(TEXT_SIZE);
+  let size = text.dimensions(ctx).map_or(Vector2::new(0.0, 0.0), |rect| Vector2::new(rect.w, rect.h));
+  let x = (cursor.x + OFFSET).min(SCREEN_DIMS.0 - size.x - 2.0 * PADDING);
+  let y = (cursor.y + OFFSET).min(SCREEN_DIMS.1 - size.y - 2.0 * PADDING);
+
+  let background = graphics::Rect::new(x, y, size.x + 2.0 * PADDING, size.y + 2.0 * PADDING);
+  canvas.draw(&graphics::Quad, DrawParam::new().dest_rect(background).color([0.0, 0.0, 0.0, 0.75]));
+  canvas.draw(&text, DrawParam::new().dest([x + PADDING, y + PADDING]));
+}
+
+// Dark spot on the surface that turns with the planet, so spin is visible
+fn push_spin_marker(planet: &Planet, position: Point2<f32>, color: [f32; 4], instances: &mut InstanceArray) {
+  const MIN_RADIUS: f32 = 4.0;      // Too small to see on planets smaller than this
+  const MARKER_SIZE: f32 = 0.2;     // Fraction of the planet's radius
+  const MARKER_DISTANCE: f32 = 0.65;
+
+  if planet.radius < MIN_RADIUS { return }
+
+  let [r, g, b, a] = color;
+  let offset = Vector2::new(planet.rotation.cos(), planet.rotation.sin()) * planet.radius * MARKER_DISTANCE;
+  let scale = planet.radius * MARKER_SIZE;
+  instances.push(DrawParam::new()
+                   .scale(Vector2::new(scale, scale))
+                   .dest(position + offset)
+                   .color([r * 0.4, g * 0.4, b * 0.4, a]));
+}
+
+const GLOW_ALPHA: f32 = 0.12;       // Alpha of each glow layer, they stack towards the centre
+const MASS_GLOW_START: f32 = 0.5;   // In mass colouring, bodies heavier than this fraction of the log mass range glow
+
+// Faint layered halo around a body, drawn underneath it
+fn push_glow(planet: &Planet, position: Point2<f32>, color: [f32; 4], alpha: f32, instances: &mut InstanceArray) {
+  const GLOW_LAYERS: u32 = 4;
+  const GLOW_EXTENT: f32 = 1.0;     // Outermost layer radius, as a multiple of the body's radius beyond its surface
+
+  let [r, g, b, _] = color;
+  for layer in (1..=GLOW_LAYERS).rev() {
+    let scale = planet.radius * (1.0 + GLOW_EXTENT * layer as f32/GLOW_LAYERS as f32);
+    instances.push(DrawParam::new()
+                     .scale(Vector2::new(scale, scale))
+                     .dest(position)
+                     .color([r, g, b, alpha]));
+  }
+}
+
+// Dark red at 0, through orange and yellow, to white at 1
+pub fn heat_color(heat: f32) -> [f32; 4] {
+  [
+    (0.35 + 1.3 * heat).min(1.0),
+    (1.6 * heat - 0.3).max(0.0).min(1.0),
+    (2.5 * heat - 1.5).max(0.0).min(1.0),
+    1.0,
+  ]
+}
+
+// Osculating orbit of the selected body around its attractor, in world coordinates
+pub fn draw_orbit(elements: &OrbitalElements, host_position: Point2<f32>, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  const ORBIT_POINTS: usize = 200;
+
+  let max_radius = SCREEN_DIMS.0.max(SCREEN_DIMS.1) * 2.0;
+  let path: Vec<Point2<f32>> = tools::orbit_path(elements, ORBIT_POINTS, max_radius)
+    .into_iter()
+    .map(|offset| host_position + offset)
+    .collect();
+
+  if path.len() > 1 {
+    let line = Mesh::new_line(ctx, &path, 1.0, [1.0, 1.0, 0.0, 0.4].into())?;
+    canvas.draw(&line, DrawParam::default());
+  }
+  Ok(())
+}
+
+// Markers at the closest and furthest points of the selected body's orbit, labelled with their distances from the
+// attractor, in world coordinates. Open orbits only have a periapsis.
+pub fn draw_apsides(elements: &OrbitalElements, host_position: Point2<f32>, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  const MARKER_RADIUS: f32 = 3.0;
+  const LABEL_SCALE: f32 = 0.7;
+  const COLOR: [f32; 4] = [1.0, 1.0, 0.0, 0.8];
+
+  let periapsis = host_position + tools::get_components(elements.periapsis, elements.argument_of_periapsis);
+  let apoapsis = elements.apoapsis.map(|distance| (host_position + tools::get_components(distance, elements.argument_of_periapsis + PI), distance));
+
+  let mut mesh = MeshBuilder::new();
+  for (position, label, distance) in [(periapsis, "Pe", elements.periapsis)].into_iter().chain(apoapsis.map(|(position, distance)| (position, "Ap", distance))) {
+    mesh.circle(DrawMode::stroke(1.0), position, MARKER_RADIUS, 0.5, COLOR.into())?;
+    let text = graphics::Text::new(format!("{} {:.1}", label, distance));
+    canvas.draw(
+      &text,
+      DrawParam::new()
+        .scale(Vector2::new(LABEL_SCALE, LABEL_SCALE))
+        .dest(Point2::new(position.x + MARKER_RADIUS + 2.0, position.y - 5.0))
+        .color(COLOR),
+    );
+  }
+  let mesh = Mesh::from_data(ctx, mesh.build());
+  canvas.draw(&mesh, DrawParam::default());
+  Ok(())
+}
+
+// Ring growing and fading out where the selected body passed periapsis. t goes from 0 to 1 over the flash.
+pub fn draw_periapsis_flash(position: Point2<f32>, t: f32, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  const START_RADIUS: f32 = 3.0;
+  const END_RADIUS: f32 = 15.0;
+
+  let ring = Mesh::new_circle(
+    ctx,
+    DrawMode::stroke(1.5),
+    position,
+    START_RADIUS + (END_RADIUS - START_RADIUS) * t,
+    0.5,
+    [1.0, 1.0, 0.6, (1.0 - t).max(0.0)].into(),
+  )?;
+  canvas.draw(&ring, DrawParam::default());
+  Ok(())
+}
+
+// Shape of the selected body's orbit, below the inspector panel.
+// escape_speed is the speed needed to escape the attractor from the body's current distance.
+pub fn draw_orbit_info(elements: &OrbitalElements, escape_speed: f32, sphere_of_influence: f32, canvas: &mut Canvas) {
+  let text = graphics::Text::new(
+    format!(
+      "Eccentricity: {:.3}\nSemi-major axis: {}\nPeriapsis: {:.1}\nApoapsis: {}\nArg. of periapsis: {:.1}°\nPeriod: {}\nTime to periapsis: {}\nEscape speed: {:.2}\nSphere of influence: {:.1}",
+      elements.eccentricity,
+      if elements.eccentricity < 1.0 { format!("{:.1}", elements.semi_major_axis) } else { "-".to_string() },
+      elements.periapsis,
+      elements.apoapsis.map_or("-".to_string(), |a| format!("{:.1}", a)),
+      elements.argument_of_periapsis.to_degrees().rem_euclid(360.0),
+      elements.period.map_or("-".to_string(), |t| format!("{:.2}s", t)),
+      elements.time_to_periapsis.map_or("-".to_string(), |t| format!("{:.2}s", t)),
+      escape_speed,
+      sphere_of_influence,
+    )
+  );
+  canvas.draw(&text, DrawParam::new().dest([SCREEN_DIMS.0 - PANEL_WIDTH, 140.0]));
+}
+
+// Outline of a wrap-around world, in world coordinates
+pub fn draw_world_bounds(bounds: (f32, f32), ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  let rect = Mesh::new_rectangle(
+    ctx,
+    DrawMode::stroke(1.0),
+    graphics::Rect::new(0.0, 0.0, bounds.0, bounds.1),
+    [0.3, 0.3, 0.3, 1.0].into(),
+  )?;
+  canvas.draw(&rect, DrawParam::default());
+  Ok(())
+}
+
+// Faint circle showing the region a body dominates, in world coordinates
+pub fn draw_sphere_of_influence(planet: &Planet, radius: f32, mesh: &mut MeshBuilder) -> GameResult {
+  let [r, g, b, _] = planet.color;
+  mesh.circle(DrawMode::stroke(1.0), planet.position, radius, 0.5, [r, g, b, 0.35].into())?;
+  Ok(())
+}
+
+// Dotted line along a predicted path
+pub fn draw_trajectory(path: &[Point2<f32>], ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  const DOT_SPACING: usize = 4;   // Draw a dot every n points
+  const DOT_RADIUS: f32 = 1.0;
+
+  if path.len() < DOT_SPACING { return Ok(()) }
+
+  let mut mesh_builder = MeshBuilder::new();
+  for (i, point) in path.iter().step_by(DOT_SPACING).enumerate() {
+    // Fade out further along the path
+    let alpha = 1.0 - (i * DOT_SPACING) as f32/path.len() as f32;
+    mesh_builder.circle(DrawMode::fill(), *point, DOT_RADIUS, 0.5, [1.0, 1.0, 1.0, alpha * 0.8].into())?;
+  }
+
+  let mesh = Mesh::from_data(ctx, mesh_builder.build());
+  canvas.draw(&mesh, DrawParam::default());
+  Ok(())
+}
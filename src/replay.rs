@@ -0,0 +1,83 @@
+use serde::{Serialize, Deserialize};
+
+use std::fs;
+use std::io;
+
+use orbits::config::SimConfig;
+
+use crate::input::InputEvent;
+use crate::DEFAULT_PHYSICS_RATE;
+
+pub const REPLAY_FILE_PATH: &str = "./orbits.replay";
+
+// A recorded session. Replaying starts from the same seed, scenario and config, and feeds
+// the events back in on the frames they happened, with a fixed frame time.
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+  pub seed: u64,
+  pub scenario: usize,
+  pub config: SimConfig,
+  #[serde(default = "default_physics_rate")]
+  pub physics_rate: f32,    // Steps per second of sim time, which changes the results
+  pub events: Vec<(u64, InputEvent)>,   // Frame number and what happened
+}
+
+impl Replay {
+  pub fn new(seed: u64, scenario: usize, config: SimConfig, physics_rate: f32) -> Self {
+    Self {
+      seed,
+      scenario,
+      config,
+      physics_rate,
+      events: Vec::new(),
+    }
+  }
+
+  pub fn record(&mut self, frame: u64, event: InputEvent) {
+    self.events.push((frame, event));
+  }
+
+  pub fn write_to_file(&self, path: &str) -> io::Result<()> {
+    let data = ron::ser::to_string(self)
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, data)
+  }
+
+  pub fn read_from_file(path: &str) -> io::Result<Self> {
+    let data = fs::read_to_string(path)?;
+    ron::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+  }
+}
+
+// Replays recorded before the physics rate could be changed
+fn default_physics_rate() -> f32 {
+  DEFAULT_PHYSICS_RATE
+}
+
+// Steps through a replay's events in order
+pub struct Player {
+  replay: Replay,
+  next_event: usize,
+}
+
+impl Player {
+  pub fn new(replay: Replay) -> Self {
+    Self {
+      replay,
+      next_event: 0,
+    }
+  }
+
+  // Events recorded on or before the given frame that haven't been played yet
+  pub fn events_for(&mut self, frame: u64) -> Vec<InputEvent> {
+    let start = self.next_event;
+    while self.next_event < self.replay.events.len() && self.replay.events[self.next_event].0 <= frame {
+      self.next_event += 1;
+    }
+    self.replay.events[start..self.next_event].iter().map(|(_, event)| *event).collect()
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.next_event >= self.replay.events.len()
+  }
+}
@@ -0,0 +1,39 @@
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+use std::fs;
+use std::io;
+
+use orbits::config::SimConfig;
+
+pub const SAVE_FILE_PATH: &str = "./orbits_save.ron";
+pub const CONFIG_FILE_PATH: &str = "./orbits_config.ron";
+pub const TRAJECTORY_FILE_PATH: &str = "./orbits_trajectories.csv";
+pub const EVENT_LOG_FILE_PATH: &str = "./orbits_events.log";
+
+// Everything needed to restore a simulation, including its SimConfig. Trails are not saved, they start fresh on load.
+// W is a World when loading, and &World when saving (to avoid cloning every planet).
+#[derive(Serialize, Deserialize)]
+pub struct SaveState<W> {
+  pub world: W,
+}
+
+impl<W: Serialize> SaveState<W> {
+  pub fn write_to_file(&self, path: &str) -> io::Result<()> {
+    let data = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, data)
+  }
+}
+
+impl<W: DeserializeOwned> SaveState<W> {
+  pub fn read_from_file(path: &str) -> io::Result<Self> {
+    let data = fs::read_to_string(path)?;
+    ron::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+  }
+}
+
+// Reads the simulation config. Fields missing from the file take their default values.
+pub fn read_config(path: &str) -> io::Result<SimConfig> {
+  let data = fs::read_to_string(path)?;
+  ron::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
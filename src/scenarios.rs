@@ -0,0 +1,509 @@
+use nalgebra::{Vector2, Point2};
+use rand::Rng;
+
+use crate::tools;
+use crate::simulation::{World, CollisionMode};
+use crate::planet::BodyKind;
+use crate::config::{METRES_PER_AU, SECONDS_PER_DAY};
+
+// A named starting configuration. Implement this to add your own presets.
+pub trait Scenario {
+  fn name(&self) -> &str;
+  // Adds the scenario's bodies to the world. bounds is the (width, height) of the area to fill, starting at (0, 0).
+  fn build(&self, world: &mut World, bounds: (f32, f32));
+  // Called after every physics step while the scenario is loaded, for scenarios that keep changing the world.
+  // world.time is the sim time since the scenario was built, so see reached for doing something at a set time.
+  fn tick(&self, _world: &mut World, _dt: f32) {}
+}
+
+// Whether the step of length dt that just finished passed sim time t, so something scheduled for t happens once
+// whatever the frame rate, time scale or pauses
+pub fn reached(world: &World, dt: f32, t: f32) -> bool {
+  world.time >= t && world.time - dt < t
+}
+
+pub fn presets() -> Vec<Box<dyn Scenario>> {
+  vec![
+    Box::new(TwinMoonSystems),
+    Box::new(TwoBodyOrbit),
+    Box::new(FigureEight),
+    Box::new(LagrangeTriangle),
+    Box::new(ResonantPair),
+    Box::new(PlanetGrid),
+    Box::new(BinaryStarWithMoons),
+    Box::new(AccretionDisk::default()),
+    Box::new(SolarSystem),
+    Box::new(RocheRing::default()),
+  ]
+}
+
+// Index of the scenario called `name`, ignoring case
+pub fn find(scenarios: &[Box<dyn Scenario>], name: &str) -> Option<usize> {
+  scenarios.iter().position(|scenario| scenario.name().eq_ignore_ascii_case(name.trim()))
+}
+
+fn centre_of(bounds: (f32, f32)) -> Point2<f32> {
+  Point2::new(bounds.0/2.0, bounds.1/2.0)
+}
+
+// Two large planets side by side, each with a cloud of moons
+pub struct TwinMoonSystems;
+
+impl Scenario for TwinMoonSystems {
+  fn name(&self) -> &str { "Twin moon systems" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    for x in [bounds.0 * 1.0/3.0, bounds.0 * 2.0/3.0] {
+      world.add_planet_with_moons(
+        Point2::new(x, bounds.1/2.0),
+        None,
+        None,
+        50.0,
+        350,
+        (15.0, 100.0),
+        (0.5, 1.5),
+        true,
+      );
+    }
+  }
+}
+
+// A planet in a circular orbit around a much heavier one, with no net momentum
+pub struct TwoBodyOrbit;
+
+impl Scenario for TwoBodyOrbit {
+  fn name(&self) -> &str { "Two-body orbit" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    const HOST_RADIUS: f32 = 40.0;
+    const ORBIT_RADIUS: f32 = 250.0;
+    const MOON_RADIUS: f32 = 8.0;
+
+    let centre = centre_of(bounds);
+    let host_id = world.add_planet(centre, None, None, HOST_RADIUS, None);
+    let host_mass = world.planets[&host_id].borrow().mass;
+
+    let speed = tools::circular_orbit_speed(world.config.g, host_mass, ORBIT_RADIUS);
+    let moon_id = world.add_planet(
+      centre + Vector2::new(ORBIT_RADIUS, 0.0),
+      Some(Vector2::new(0.0, speed)),
+      None,
+      MOON_RADIUS,
+      None,
+    );
+
+    // Give the host the opposite momentum so the pair doesn't drift
+    let moon_momentum = {
+      let moon = world.planets[&moon_id].borrow();
+      moon.velocity * moon.mass
+    };
+    world.planets[&moon_id].borrow_mut().name = Some("Moon".to_string());
+    let mut host = world.planets[&host_id].borrow_mut();
+    host.velocity = -moon_momentum/host.mass;
+    host.name = Some("Primary".to_string());
+  }
+}
+
+// Chenciner & Montgomery's periodic three-body figure-eight (with G = m = 1), scaled to screen units
+pub struct FigureEight;
+
+impl FigureEight {
+  const LENGTH_SCALE: f32 = 250.0;   // Pixels per unit length
+  const BODY_MASS: f32 = 5.0e9;
+  const BODY_RADIUS: f32 = 8.0;
+  const PERIOD: f32 = 6.3259;        // In units of time, where a unit is LENGTH_SCALE/velocity_scale
+
+  fn velocity_scale(g: f32) -> f32 {
+    (g * Self::BODY_MASS/Self::LENGTH_SCALE).sqrt()
+  }
+
+  // One trip round the figure-eight, in sim seconds
+  pub fn period(g: f32) -> f32 {
+    Self::PERIOD * Self::LENGTH_SCALE/Self::velocity_scale(g)
+  }
+}
+
+impl Scenario for FigureEight {
+  fn name(&self) -> &str { "Three-body figure-eight" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    let velocity_scale = Self::velocity_scale(world.config.g);
+    let centre = centre_of(bounds);
+
+    let x1 = Vector2::new(-0.97000436, 0.24308753);
+    let v3 = Vector2::new(-0.93240737, -0.86473146);
+    let bodies = [
+      (x1, -v3/2.0),
+      (-x1, -v3/2.0),
+      (Vector2::new(0.0, 0.0), v3),
+    ];
+
+    for (pos, vel) in bodies.iter() {
+      world.add_planet(
+        centre + pos * Self::LENGTH_SCALE,
+        Some(vel * velocity_scale),
+        Some(Self::BODY_MASS),
+        Self::BODY_RADIUS,
+        None,
+      );
+    }
+  }
+}
+
+// Lagrange's equilateral solution: three bodies at the corners of an equilateral triangle rotate rigidly about their
+// centre of mass, at ω² = G(m1 + m2 + m3)/s³ for side s. It's only stable when one mass dominates
+// (Routh's criterion), so this is a star, a planet and a Trojan asteroid sitting at the planet's L4 point.
+pub struct LagrangeTriangle;
+
+impl Scenario for LagrangeTriangle {
+  fn name(&self) -> &str { "Lagrange triangle" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    const SIDE: f32 = 300.0;
+    const STAR_MASS: f32 = 2.0e10;
+    // Mass, radius and name
+    const BODIES: [(f32, f32, &str); 3] = [
+      (STAR_MASS, 25.0, "Star"),
+      (STAR_MASS/100.0, 6.0, "Planet"),
+      (STAR_MASS/1.0e5, 2.0, "Trojan"),
+    ];
+
+    let centre = centre_of(bounds);
+    let corners: Vec<Vector2<f32>> = (0..3)
+      .map(|i| tools::get_components(SIDE/3f32.sqrt(), crate::TWO_PI * i as f32/3.0))
+      .collect();
+    let total_mass: f32 = BODIES.iter().map(|(mass, _, _)| mass).sum();
+    let centre_of_mass = corners.iter().zip(BODIES.iter())
+      .map(|(corner, (mass, _, _))| corner * *mass)
+      .sum::<Vector2<f32>>()/total_mass;
+    let angular_velocity = (world.config.g * total_mass/SIDE.powi(3)).sqrt();
+
+    for (corner, (mass, radius, name)) in corners.iter().zip(BODIES.iter()) {
+      let offset = corner - centre_of_mass;
+      let id = world.add_planet(
+        centre + offset,
+        Some(Vector2::new(-offset.y, offset.x) * angular_velocity),
+        Some(*mass),
+        *radius,
+        None,
+      );
+      world.planets[&id].borrow_mut().name = Some(name.to_string());
+    }
+  }
+}
+
+// Two light planets on circular orbits in a 2:1 period ratio (Kepler's third law puts the outer one 2^(2/3) times
+// further out), like Io and Europa
+pub struct ResonantPair;
+
+impl Scenario for ResonantPair {
+  fn name(&self) -> &str { "1:2 resonance" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    const STAR_MASS: f32 = 2.0e10;
+    const INNER_ORBIT: f32 = 180.0;
+    const PLANET_MASS: f32 = STAR_MASS/1.0e4;
+
+    let centre = centre_of(bounds);
+    let star_id = world.add_body(BodyKind::Star, centre, None, Some(STAR_MASS), 25.0, None);
+    world.planets[&star_id].borrow_mut().name = Some("Star".to_string());
+
+    for (orbit_radius, angle, name) in [(INNER_ORBIT, 0.0, "Inner"), (INNER_ORBIT * 2f32.powf(2.0/3.0), std::f32::consts::PI, "Outer")] {
+      let speed = tools::circular_orbit_speed(world.config.g, STAR_MASS, orbit_radius);
+      let id = world.add_planet(
+        centre + tools::get_components(orbit_radius, angle),
+        Some(tools::get_components(speed, angle + std::f32::consts::FRAC_PI_2)),
+        Some(PLANET_MASS),
+        5.0,
+        None,
+      );
+      world.planets[&id].borrow_mut().name = Some(name.to_string());
+    }
+
+    world.to_barycentric_frame();
+  }
+}
+
+// Evenly spaced stationary planets filling the area
+pub struct PlanetGrid;
+
+impl Scenario for PlanetGrid {
+  fn name(&self) -> &str { "Planet grid" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    const GAP: f32 = 100.0;
+    world.spawn_square_of_planets(
+      Point2::new(GAP/2.0, GAP/2.0),
+      (bounds.0/GAP).ceil() as u16,
+      (bounds.1/GAP).ceil() as u16,
+      GAP,
+      10.0,
+    );
+  }
+}
+
+// Two equal stars orbiting their barycentre, each with its own moons
+pub struct BinaryStarWithMoons;
+
+impl Scenario for BinaryStarWithMoons {
+  fn name(&self) -> &str { "Binary star with moons" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    const SEPARATION: f32 = 500.0;
+    const STAR_RADIUS: f32 = 35.0;
+    const STAR_MASS: f32 = 1.5e10;
+
+    // Each star orbits at radius d/2: v^2/(d/2) = GM/d^2  ->  v = sqrt(GM/2d)
+    let speed = (world.config.g * STAR_MASS/(2.0 * SEPARATION)).sqrt();
+    let centre = centre_of(bounds);
+
+    for (side, name) in [(-1.0, "Star A"), (1.0, "Star B")] {
+      let star_id = world.add_planet_with_moons(
+        centre + Vector2::new(side * SEPARATION/2.0, 0.0),
+        Some(Vector2::new(0.0, side * speed)),
+        Some(STAR_MASS),
+        STAR_RADIUS,
+        120,
+        (10.0, 60.0),
+        (0.5, 1.5),
+        true,
+      );
+      let mut star = world.planets[&star_id].borrow_mut();
+      star.set_kind(BodyKind::Star);
+      star.name = Some(name.to_string());
+    }
+  }
+}
+
+// Many small bodies in a disk around a heavy central body, on roughly Keplerian orbits.
+// The random kicks from dispersion make orbits cross, so bodies collide and clump into moons.
+pub struct AccretionDisk {
+  pub bodies: usize,
+  pub central_mass: f32,
+  pub central_radius: f32,
+  pub disk_radius_range: (f32, f32),    // From the centre of the central body
+  pub body_radius_range: (f32, f32),
+  pub dispersion: f32,                  // Random velocity added to each body, as a fraction of its circular orbit speed
+  pub clockwise: bool,
+}
+
+impl Default for AccretionDisk {
+  fn default() -> Self {
+    Self {
+      bodies: 600,
+      central_mass: 2.0e10,
+      central_radius: 30.0,
+      disk_radius_range: (80.0, 350.0),
+      body_radius_range: (0.5, 1.5),
+      dispersion: 0.05,
+      clockwise: true,
+    }
+  }
+}
+
+impl Scenario for AccretionDisk {
+  fn name(&self) -> &str { "Accretion disk" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    let centre = centre_of(bounds);
+    let central_id = world.add_planet(centre, None, Some(self.central_mass), self.central_radius, None);
+    world.planets[&central_id].borrow_mut().set_kind(BodyKind::Star);
+
+    let (inner, outer) = self.disk_radius_range;
+    for _ in 0..self.bodies {
+      let (orbit_radius, angle, radius, kick) = {
+        let rng = world.rng();
+        // Uniform in area, so the disk isn't crowded towards the middle
+        let orbit_radius = rng.gen_range(inner * inner..outer * outer).sqrt();
+        let angle = rng.gen_range(0.0..crate::TWO_PI);
+        let radius = rng.gen_range(self.body_radius_range.0..self.body_radius_range.1);
+        let kick = Vector2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+        (orbit_radius, angle, radius, kick)
+      };
+
+      let speed = tools::circular_orbit_speed(world.config.g, self.central_mass, orbit_radius);
+      let direction = if self.clockwise { angle + std::f32::consts::FRAC_PI_2 } else { angle - std::f32::consts::FRAC_PI_2 };
+      world.add_body(
+        BodyKind::Asteroid,
+        centre + tools::get_components(orbit_radius, angle),
+        Some(tools::get_components(speed, direction) + kick * speed * self.dispersion),
+        None,
+        radius,
+        None,
+      );
+    }
+  }
+}
+
+// A ring of hundreds of small asteroids inside a planet's Roche limit, where they can't clump into a moon. Collisions
+// bounce rather than merge, so the ring stays debris: collisions damp out the particles' random motion so it settles
+// thinner, and the shepherd moon outside clears gaps at its orbital resonances.
+pub struct RocheRing {
+  pub particles: usize,
+  pub host_radius: f32,
+  pub ring_range: (f32, f32),     // Inner and outer edge, as fractions of the host's Roche limit for asteroids
+  pub particle_radius: f32,
+  pub restitution: f32,
+  pub moon_orbit: Option<f32>,    // Orbit radius of the shepherd moon, in Roche limits. None for no moon.
+  pub clockwise: bool,
+}
+
+impl Default for RocheRing {
+  fn default() -> Self {
+    Self {
+      particles: 500,
+      host_radius: 60.0,
+      ring_range: (0.75, 0.95),
+      particle_radius: 0.6,
+      restitution: 0.5,
+      moon_orbit: Some(1.6),
+      clockwise: true,
+    }
+  }
+}
+
+impl Scenario for RocheRing {
+  fn name(&self) -> &str { "Roche ring" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    world.collision_mode = CollisionMode::Bounce { restitution: self.restitution };
+
+    let centre = centre_of(bounds);
+    let host_id = world.add_body(BodyKind::Planet, centre, None, None, self.host_radius, None);
+    let roche = tools::roche_limit(self.host_radius, BodyKind::Planet.density(), BodyKind::Asteroid.density());
+    world.add_debris_ring(
+      host_id,
+      (roche * self.ring_range.0, roche * self.ring_range.1),
+      self.particles,
+      self.particle_radius,
+      self.clockwise,
+    );
+
+    if let Some(moon_orbit) = self.moon_orbit {
+      let moon_radius = self.host_radius * 0.1;
+      let mass = tools::volume_of_sphere(moon_radius) * BodyKind::Planet.density();
+      let position = centre + Vector2::new(roche * moon_orbit, 0.0);
+      world.add_orbiting_planet(host_id, position, mass, moon_radius, self.clockwise);
+    }
+  }
+}
+
+// The Sun, the eight planets and the two largest asteroids on circular orbits at their real semi-major axes, with real
+// masses scaled to the sim's units so periods match reality at the scale below (a year is 36.5 sim seconds).
+// Sets the config's unit scale, which readouts use. Sizes are exaggerated, or nothing would be visible.
+// Neptune is 3000 px out, so zoom out to see the outer planets.
+pub struct SolarSystem;
+
+impl Scenario for SolarSystem {
+  fn name(&self) -> &str { "Solar system" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    const PIXELS_PER_AU: f32 = 100.0;
+    const DAYS_PER_SIM_SECOND: f32 = 10.0;
+    const SUN_MASS: f32 = 1.989e30;
+    const SUN_RADIUS: f32 = 12.0;
+    const GOLDEN_ANGLE: f32 = 2.399963;   // Spreads the starting angles evenly, without lining planets up
+
+    // Name, kind, mass (kg), semi-major axis (AU), drawn radius (px)
+    const BODIES: [(&str, BodyKind, f32, f32, f32); 10] = [
+      ("Mercury", BodyKind::Planet, 3.301e23, 0.387, 1.5),
+      ("Venus", BodyKind::Planet, 4.867e24, 0.723, 2.5),
+      ("Earth", BodyKind::Planet, 5.972e24, 1.0, 2.5),
+      ("Mars", BodyKind::Planet, 6.417e23, 1.524, 2.0),
+      ("Vesta", BodyKind::Asteroid, 2.59e20, 2.362, 0.8),
+      ("Ceres", BodyKind::Asteroid, 9.39e20, 2.768, 1.0),
+      ("Jupiter", BodyKind::Planet, 1.898e27, 5.203, 6.0),
+      ("Saturn", BodyKind::Planet, 5.683e26, 9.537, 5.0),
+      ("Uranus", BodyKind::Planet, 8.681e25, 19.19, 4.0),
+      ("Neptune", BodyKind::Planet, 1.024e26, 30.07, 4.0),
+    ];
+
+    world.config.metres_per_pixel = METRES_PER_AU/PIXELS_PER_AU;
+    world.config.seconds_per_sim_second = DAYS_PER_SIM_SECOND * SECONDS_PER_DAY;
+
+    let centre = centre_of(bounds);
+    let sun_mass = world.config.scaled_mass(SUN_MASS);
+    let sun_id = world.add_body(BodyKind::Star, centre, None, Some(sun_mass), SUN_RADIUS, None);
+    world.planets[&sun_id].borrow_mut().name = Some("Sun".to_string());
+
+    for (i, (name, kind, mass, semi_major_axis, radius)) in BODIES.iter().enumerate() {
+      let orbit_radius = semi_major_axis * PIXELS_PER_AU;
+      let angle = i as f32 * GOLDEN_ANGLE;
+      let speed = tools::circular_orbit_speed(world.config.g, sun_mass, orbit_radius);
+      let id = world.add_body(
+        *kind,
+        centre + tools::get_components(orbit_radius, angle),
+        Some(tools::get_components(speed, angle + std::f32::consts::FRAC_PI_2)),
+        Some(world.config.scaled_mass(*mass)),
+        *radius,
+        None,
+      );
+      world.planets[&id].borrow_mut().name = Some(name.to_string());
+    }
+
+    // The Sun wobbles around the barycentre rather than the whole system drifting away
+    world.to_barycentric_frame();
+  }
+}
+
+// Bodies scattered over the whole area with random sizes and small random velocities. Used for --bodies.
+pub struct RandomBodies {
+  pub bodies: usize,
+  pub radius_range: (f32, f32),
+  pub speed_range: (f32, f32),
+}
+
+impl RandomBodies {
+  pub fn new(bodies: usize) -> Self {
+    Self {
+      bodies,
+      radius_range: (1.0, 5.0),
+      speed_range: (0.0, 10.0),
+    }
+  }
+}
+
+impl Scenario for RandomBodies {
+  fn name(&self) -> &str { "Random bodies" }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    if self.bodies == 0 { return }
+    world.add_random_planets(self.bodies, (0.0, bounds.0), (0.0, bounds.1), self.radius_range, Some(self.speed_range));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::diagnostics::Diagnostics;
+
+  use std::time::Duration;
+
+  const PERIODS: f32 = 3.0;
+  const MAX_ENERGY_DRIFT: f32 = 0.01;   // Fraction of the starting energy
+
+  // The default integrator keeps the figure-eight going: nothing merges, escapes or gains or loses much energy
+  #[test]
+  fn figure_eight_stays_together() {
+    let mut world = World::new();
+    FigureEight.build(&mut world, (1000.0, 1000.0));
+    let start_energy = Diagnostics::measure(&world).total_energy();
+    let max_distance = 2.0 * FigureEight::LENGTH_SCALE;
+
+    let dt = Duration::from_secs_f32(1.0/120.0);
+    let steps = (PERIODS * FigureEight::period(world.config.g)/dt.as_secs_f32()) as usize;
+    for _ in 0..steps {
+      world.step(&dt);
+      assert_eq!(world.planets.len(), 3);
+
+      let (barycenter, _) = world.barycenter().unwrap();
+      for pl in world.planets.values() {
+        assert!((pl.borrow().position - barycenter).magnitude() < max_distance);
+      }
+    }
+
+    assert_eq!(world.binding().unbound, 0);
+    let drift = (Diagnostics::measure(&world).total_energy() - start_energy)/start_energy.abs();
+    assert!(drift.abs() < MAX_ENERGY_DRIFT, "energy drifted by {}", drift);
+  }
+}
@@ -0,0 +1,243 @@
+use nalgebra::{Vector2, Point2};
+use rhai::{Engine, AST, Scope, FuncArgs, FLOAT, INT};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::simulation::World;
+use crate::scenarios::Scenario;
+
+pub const SCRIPT_EXTENSION: &str = "rhai";
+
+// Changes a script asked for, applied to the world once the script function returns
+enum Command {
+  Spawn { position: Point2<f32>, velocity: Vector2<f32>, radius: f32, mass: Option<f32> },
+  SetVelocity { id: usize, velocity: Vector2<f32> },
+  Remove(usize),
+}
+
+#[derive(Clone, Copy)]
+struct BodyState {
+  position: Point2<f32>,
+  velocity: Vector2<f32>,
+  mass: f32,
+}
+
+// What script functions can see and change. Scripts can't hold the world itself, so they read a snapshot
+// and queue commands instead.
+#[derive(Default)]
+struct Shared {
+  bodies: HashMap<usize, BodyState>,
+  g: f32,
+  time: f32,          // Sim time of the world
+  next_id: usize,     // Id the next spawned planet will get, so spawn_planet can return it straight away
+  commands: Vec<Command>,
+  scheduled: Vec<(f32, String)>,    // Sim times and functions to call then, from schedule
+}
+
+impl Shared {
+  fn snapshot(&mut self, world: &World) {
+    self.bodies = world.planets.iter()
+      .map(|(id, pl)| {
+        let pl = pl.borrow();
+        (*id, BodyState { position: pl.position, velocity: pl.velocity, mass: pl.mass })
+      })
+      .collect();
+    self.g = world.config.g;
+    self.time = world.time;
+    self.next_id = world.next_planet_id();
+  }
+
+  fn apply(&mut self, world: &mut World) {
+    for command in self.commands.drain(..) {
+      match command {
+        Command::Spawn { position, velocity, radius, mass } => {
+          world.add_planet(position, Some(velocity), mass, radius, None);
+        },
+        Command::SetVelocity { id, velocity } => if let Some(pl) = world.planets.get(&id) {
+          pl.borrow_mut().velocity = velocity;
+        },
+        Command::Remove(id) => world.remove_planet(id),
+      }
+    }
+  }
+}
+
+// A scenario written in Rhai, loaded at runtime. Scripts can define:
+//   fn setup(width, height)   called when the scenario is loaded
+//   fn on_tick(t, dt)         called after every physics step, with the sim time since setup
+// and call spawn_planet, set_velocity, remove_planet and the getters registered in Script::engine.
+// schedule(t, "name") calls the function called name (taking no arguments) once the sim time reaches t.
+// Functions can't see variables outside themselves, but can read top level constants with global::NAME.
+pub struct Script {
+  name: String,
+  engine: Engine,
+  ast: AST,
+  shared: Rc<RefCell<Shared>>,
+  failed: RefCell<bool>,    // Stops calling on_tick after an error, rather than printing it every step
+}
+
+impl Script {
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let shared = Rc::new(RefCell::new(Shared::default()));
+    let engine = Self::engine(&shared);
+    let ast = engine.compile(&source).map_err(|e| e.to_string())?;
+    let name = path.file_stem()
+      .map(|stem| stem.to_string_lossy().replace('_', " "))
+      .unwrap_or_else(|| "Script".to_string());
+
+    Ok(Self {
+      name,
+      engine,
+      ast,
+      shared,
+      failed: RefCell::new(false),
+    })
+  }
+
+  fn engine(shared: &Rc<RefCell<Shared>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let s = shared.clone();
+    engine.register_fn("spawn_planet", move |x: FLOAT, y: FLOAT, vx: FLOAT, vy: FLOAT, radius: FLOAT| -> INT {
+      spawn(&s, x, y, vx, vy, radius, None)
+    });
+    let s = shared.clone();
+    engine.register_fn("spawn_planet", move |x: FLOAT, y: FLOAT, vx: FLOAT, vy: FLOAT, radius: FLOAT, mass: FLOAT| -> INT {
+      spawn(&s, x, y, vx, vy, radius, Some(mass as f32))
+    });
+    let s = shared.clone();
+    engine.register_fn("set_velocity", move |id: INT, vx: FLOAT, vy: FLOAT| {
+      s.borrow_mut().commands.push(Command::SetVelocity { id: id as usize, velocity: Vector2::new(vx as f32, vy as f32) });
+    });
+    let s = shared.clone();
+    engine.register_fn("remove_planet", move |id: INT| {
+      s.borrow_mut().commands.push(Command::Remove(id as usize));
+    });
+
+    let s = shared.clone();
+    engine.register_fn("planet_exists", move |id: INT| s.borrow().bodies.contains_key(&(id as usize)));
+    let s = shared.clone();
+    engine.register_fn("planet_x", move |id: INT| body(&s, id).map_or(0.0, |b| b.position.x as FLOAT));
+    let s = shared.clone();
+    engine.register_fn("planet_y", move |id: INT| body(&s, id).map_or(0.0, |b| b.position.y as FLOAT));
+    let s = shared.clone();
+    engine.register_fn("planet_vx", move |id: INT| body(&s, id).map_or(0.0, |b| b.velocity.x as FLOAT));
+    let s = shared.clone();
+    engine.register_fn("planet_vy", move |id: INT| body(&s, id).map_or(0.0, |b| b.velocity.y as FLOAT));
+    let s = shared.clone();
+    engine.register_fn("planet_mass", move |id: INT| body(&s, id).map_or(0.0, |b| b.mass as FLOAT));
+    let s = shared.clone();
+    engine.register_fn("heaviest_planet", move || -> INT {
+      s.borrow().bodies.iter()
+        .max_by(|(_, a), (_, b)| a.mass.partial_cmp(&b.mass).unwrap())
+        .map_or(-1, |(id, _)| *id as INT)
+    });
+    let s = shared.clone();
+    engine.register_fn("g", move || s.borrow().g as FLOAT);
+    let s = shared.clone();
+    engine.register_fn("sim_time", move || s.borrow().time as FLOAT);
+    let s = shared.clone();
+    engine.register_fn("schedule", move |t: FLOAT, name: &str| {
+      s.borrow_mut().scheduled.push((t as f32, name.to_string()));
+    });
+
+    engine
+  }
+
+  fn has_fn(&self, name: &str) -> bool {
+    self.ast.iter_functions().any(|f| f.name == name)
+  }
+
+  // Calls a script function with a fresh snapshot of the world, then applies what it asked for
+  fn call(&self, world: &mut World, name: &str, args: impl FuncArgs) -> Result<(), String> {
+    self.shared.borrow_mut().snapshot(world);
+    let result = self.engine.call_fn::<rhai::Dynamic>(&mut Scope::new(), &self.ast, name, args);
+    // Apply anything queued before an error too, as the script expected it to happen
+    self.shared.borrow_mut().apply(world);
+    result.map(|_| ()).map_err(|e| e.to_string())
+  }
+}
+
+fn spawn(shared: &Rc<RefCell<Shared>>, x: FLOAT, y: FLOAT, vx: FLOAT, vy: FLOAT, radius: FLOAT, mass: Option<f32>) -> INT {
+  let mut shared = shared.borrow_mut();
+  let id = shared.next_id;
+  shared.next_id += 1;
+  shared.commands.push(Command::Spawn {
+    position: Point2::new(x as f32, y as f32),
+    velocity: Vector2::new(vx as f32, vy as f32),
+    radius: radius as f32,
+    mass,
+  });
+  id as INT
+}
+
+fn body(shared: &Rc<RefCell<Shared>>, id: INT) -> Option<BodyState> {
+  shared.borrow().bodies.get(&(id as usize)).copied()
+}
+
+impl Scenario for Script {
+  fn name(&self) -> &str { &self.name }
+
+  fn build(&self, world: &mut World, bounds: (f32, f32)) {
+    *self.failed.borrow_mut() = false;
+    self.shared.borrow_mut().scheduled.clear();
+    if !self.has_fn("setup") { return }
+
+    if let Err(e) = self.call(world, "setup", (bounds.0 as FLOAT, bounds.1 as FLOAT)) {
+      println!("WARNING: Script \"{}\" failed in setup: {}", self.name, e);
+    }
+  }
+
+  fn tick(&self, world: &mut World, dt: f32) {
+    if *self.failed.borrow() { return }
+
+    // Scheduled calls first, earliest first. They can schedule more, which wait for a later step.
+    let mut due: Vec<(f32, String)> = {
+      let mut shared = self.shared.borrow_mut();
+      let (due, later) = shared.scheduled.drain(..).partition(|(t, _)| *t <= world.time);
+      shared.scheduled = later;
+      due
+    };
+    due.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    for (_, name) in due {
+      if let Err(e) = self.call(world, &name, ()) {
+        println!("WARNING: Script \"{}\" failed in scheduled call to {}: {}", self.name, name, e);
+      }
+    }
+
+    if !self.has_fn("on_tick") { return }
+    if let Err(e) = self.call(world, "on_tick", (world.time as FLOAT, dt as FLOAT)) {
+      println!("WARNING: Script \"{}\" failed in on_tick, disabling it: {}", self.name, e);
+      *self.failed.borrow_mut() = true;
+    }
+  }
+}
+
+// Every script in a directory, sorted by file name. Scripts that fail to compile are skipped with a warning.
+pub fn load_dir(dir: &Path) -> Vec<Script> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return Vec::new(),    // No scripts
+  };
+
+  let mut paths: Vec<_> = entries
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| path.extension().map_or(false, |ext| ext == SCRIPT_EXTENSION))
+    .collect();
+  paths.sort();
+
+  paths.iter()
+    .filter_map(|path| match Script::load(path) {
+      Ok(script) => Some(script),
+      Err(e) => {
+        println!("WARNING: Could not load script {}: {}", path.display(), e);
+        None
+      },
+    })
+    .collect()
+}
@@ -0,0 +1,97 @@
+use serde::{Serialize, Deserialize};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use orbits::planet::BodyKind;
+use orbits::integrator::Integrator;
+
+use crate::input::Action;
+use crate::brush::Tool;
+use crate::trail::TrailStyle;
+use crate::impact::ImpactEffects;
+use crate::snapshot::{DEFAULT_AUTOSAVE_INTERVAL, DEFAULT_SNAPSHOT_SLOTS};
+use crate::{SCREEN_DIMS, SPAWN_PLANET_RADIUS, DEFAULT_PHYSICS_RATE};
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+// User preferences, kept between runs in the platform's config directory (e.g. ~/.config/orbits on Linux).
+// Unlike SimConfig, these only change how the app starts, not the physics. Missing fields take their defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+  pub window_size: (f32, f32),
+  pub spawn_radius: f32,
+  pub spawn_density: f32,
+  pub spawn_kind: BodyKind,
+  pub trail_style: TrailStyle,
+  pub launch_scale: f32,  // Launch speed per pixel dragged
+  pub integrator: Integrator,
+  pub physics_rate: f32,  // Physics steps per second of sim time
+  pub extrapolate: bool,  // Draw bodies ahead of the last physics step, rather than between the last two
+  pub impact_effects: ImpactEffects,  // Feedback from the hardest merges
+  pub volume: f32,      // 0 to 1
+  pub muted: bool,
+  pub autosave_interval: f32,   // Seconds between snapshots, 0 to turn autosaving off
+  pub snapshot_slots: usize,    // Snapshots kept before the oldest is overwritten
+  // Extra key bindings, from a key's name (as in ggez's KeyCode, e.g. "P" or "Key1", optionally with "Ctrl+", "Alt+"
+  // and "Shift+" in that order in front) to the action it does. These take priority over the built-in keys, and
+  // binding a key to "Ignore" turns it off. A bare key name catches the key whatever modifiers are held.
+  pub key_bindings: BTreeMap<String, Action>,
+  // Tools for mouse buttons ("Left", "Right" or "Middle", optionally with "Alt+" in front), instead of the built-in ones
+  pub mouse_bindings: BTreeMap<String, Tool>,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      window_size: SCREEN_DIMS,
+      spawn_radius: SPAWN_PLANET_RADIUS,
+      spawn_density: BodyKind::Planet.density(),
+      spawn_kind: BodyKind::Planet,
+      trail_style: TrailStyle::Line,
+      launch_scale: 1.0,
+      integrator: Integrator::default(),
+      physics_rate: DEFAULT_PHYSICS_RATE,
+      extrapolate: false,
+      impact_effects: ImpactEffects::default(),
+      volume: 0.7,
+      muted: false,
+      autosave_interval: DEFAULT_AUTOSAVE_INTERVAL,
+      snapshot_slots: DEFAULT_SNAPSHOT_SLOTS,
+      key_bindings: BTreeMap::new(),
+      mouse_bindings: BTreeMap::new(),
+    }
+  }
+}
+
+impl Settings {
+  // None if the platform has no config directory
+  pub fn path() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "orbits").map(|dirs| dirs.config_dir().join(SETTINGS_FILE))
+  }
+
+  // Defaults if there is no settings file yet
+  pub fn load() -> io::Result<Self> {
+    let path = match Self::path() {
+      Some(path) => path,
+      None => return Ok(Self::default()),
+    };
+    match fs::read_to_string(&path) {
+      Ok(data) => toml::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(e) => Err(e),
+    }
+  }
+
+  pub fn save(&self) -> io::Result<()> {
+    let path = Self::path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let data = toml::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, data)
+  }
+}
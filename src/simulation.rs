@@ -0,0 +1,1418 @@
+use nalgebra::{Vector2, Point2};
+use rand::prelude::*;
+use rand::distributions::Uniform;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use std::collections::{HashMap, HashSet};
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
+use std::f32::consts::PI;
+use std::fmt;
+
+use crate::tools;
+use crate::planet::{Planet, BodyKind};
+use crate::quadtree::{self, QuadTree, TreeBody};
+use crate::integrator::Integrator;
+use crate::config::{SimConfig, MergeGeometry};
+use crate::collision::SpatialHash;
+use crate::union_find::DisjointSet;
+use crate::soft_body::{self, SoftBodyConfig, Bond};
+use crate::events::{Event, EventBus, EventKind, DespawnReason};
+use crate::profile::StepTimings;
+use crate::TWO_PI;
+
+pub const DEFAULT_RESTITUTION: f32 = 0.8;
+pub const DEFAULT_FRAGMENTATION_THRESHOLD: f32 = 4000.0;  // Specific impact energy (see tools::specific_impact_energy)
+const FRAGMENT_COUNT: usize = 5;
+const MIN_FRAGMENT_RADIUS: f32 = 0.3;      // Merge instead if fragments would be smaller than this
+const FRAGMENT_RESTITUTION: f32 = 0.5;     // Fraction of the rebound speed fragments keep
+const FRAGMENT_SPREAD: f32 = PI/4.0;       // Max angle either side of the rebound direction
+const FRAGMENT_SPAWN_PROTECTION: Duration = Duration::from_millis(300);
+const ROCHE_MASS_RATIO: f32 = 0.01;       // Only bodies at most this fraction of the host's mass are torn apart
+const ROCHE_FRAGMENT_COUNT: usize = 6;
+const ROCHE_SEARCH_RADII: f32 = 3.0;       // Roche limits are looked for within this many radii of each body
+pub const DEFAULT_MASS_TRANSFER: f32 = 0.2;   // Fraction of the lighter body's mass lost in a graze
+const GRAZE_OBLIQUITY: f32 = PI/3.0;      // Impacts more oblique than this graze rather than merge, when mass transfer is on
+const GRAZE_RESTITUTION: f32 = 0.3;
+const TIDAL_SHEAR: f32 = 0.05;             // Fragments spread along the orbit at this fraction of the speed relative to the host
+const ADAPTIVE_ETA: f32 = 0.05;            // Fraction of a body's free-fall time over its own size to step by
+const MAX_SUBSTEPS: u32 = 64;              // Adaptive steps never get shorter than dt/MAX_SUBSTEPS
+const MAX_STORED_IMPACTS: usize = 1000;     // So impacts don't pile up if nothing takes them
+const BINDING_CHECK_INTERVAL: f32 = 1.0;    // Sim seconds between classifying bodies as bound or unbound
+const CLOSE_APPROACH_RADII: f32 = 3.0;      // Bodies closer than this many times their summed radii are having a close approach
+pub const DEFAULT_TEST_PARTICLE_MASS: f32 = 1.0e5;   // About an asteroid of radius 2
+pub const DEFAULT_BARNES_HUT_THETA: f32 = 0.5;  // 0 = exact (all pairs), larger = faster but less accurate
+
+// How many bodies are bound to the rest of the system, as of the last check (see World::binding)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BindingStats {
+  pub bound: usize,
+  pub unbound: usize,
+  pub ejections: usize,   // Bodies going from bound to unbound since the world was cleared
+}
+
+// Where and how hard two bodies hit when merging, for frontends to show effects
+#[derive(Clone, Copy, Debug)]
+pub struct Impact {
+  pub position: Point2<f32>,
+  pub velocity: Vector2<f32>,   // Of the centre of mass of the pair
+  pub energy: f32,              // Specific impact energy, see tools::specific_impact_energy
+  pub merge: Option<Merge>,     // None if the bodies broke apart instead
+}
+
+// Two bodies that became one, as they were just before merging
+#[derive(Clone, Copy, Debug)]
+pub struct Merge {
+  pub into: usize,    // Id of the merged planet
+  pub bodies: [MergedBody; 2],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MergedBody {
+  pub position: Point2<f32>,
+  pub velocity: Vector2<f32>,
+  pub radius: f32,
+  pub color: [f32; 4],
+}
+
+impl From<&Planet> for MergedBody {
+  fn from(planet: &Planet) -> Self {
+    Self {
+      position: planet.position,
+      velocity: planet.velocity,
+      radius: planet.radius,
+      color: planet.color,
+    }
+  }
+}
+
+// What happens when two bodies touch
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum CollisionMode {
+  Merge,                        // Perfectly inelastic, bodies combine into one
+  Bounce { restitution: f32 },  // 1 = elastic, 0 = bodies stop relative to each other along the impact normal
+  None,                         // Bodies pass through each other
+}
+
+impl Default for CollisionMode {
+  fn default() -> Self {
+    CollisionMode::Merge
+  }
+}
+
+impl fmt::Display for CollisionMode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CollisionMode::Merge => write!(f, "Merge"),
+      CollisionMode::Bounce { restitution } => write!(f, "Bounce (e = {})", restitution),
+      CollisionMode::None => write!(f, "None"),
+    }
+  }
+}
+
+impl CollisionMode {
+  pub fn next(self) -> Self {
+    match self {
+      CollisionMode::Merge => CollisionMode::Bounce { restitution: DEFAULT_RESTITUTION },
+      CollisionMode::Bounce { .. } => CollisionMode::None,
+      CollisionMode::None => CollisionMode::Merge,
+    }
+  }
+}
+
+// Renderer-agnostic n-body simulation. Bodies are stored by id, ids are never reused.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct World {
+  planet_id_count: usize,
+  pub planets: HashMap<usize, RefCell<Planet>>,
+  #[serde(default)]
+  pub config: SimConfig,
+  pub barnes_hut_theta: f32,
+  #[serde(default)]
+  pub integrator: Integrator,
+  #[serde(default)]
+  pub adaptive_timestep: bool,  // Split steps up during close encounters, see World::adaptive_dt
+  #[serde(default)]
+  pub collision_mode: CollisionMode,
+  #[serde(default)]
+  pub fragmentation_threshold: Option<f32>,   // Impacts above this break the smaller body apart when merging. None = always merge.
+  #[serde(default)]
+  pub mass_transfer: Option<f32>,   // If set, grazing impacts move this fraction of the lighter body's mass to the heavier instead of merging
+  #[serde(default)]
+  pub tidal_disruption: bool,   // Break up small bodies that pass inside the Roche limit of a much heavier one
+  #[serde(default)]
+  pub soft_bodies: Option<SoftBodyConfig>,  // Experimental. If set, merging bodies split into bonded particles instead.
+  #[serde(default)]
+  pub test_particle_mass: Option<f32>,  // If set, lighter bodies are test particles: pulled by heavier bodies but pulling nothing, and not hitting each other
+  #[serde(default)]
+  bonds: Vec<Bond>,     // Between soft body particles
+  #[serde(default)]
+  pub wrap_bounds: Option<(f32, f32)>,  // If set, the world is a torus: bodies leaving (0, 0) -> bounds teleport to the other side, and gravity acts across the edges
+  #[serde(default)]
+  pub cancel_drift: bool,     // Keep velocities in the barycentric frame, so total momentum stays zero
+  #[serde(default)]
+  pub recentre_on: Option<Point2<f32>>,   // If set, the barycentre is moved here after every step
+  #[serde(default)]
+  pub reversed: bool,     // Whether time has been reversed an odd number of times, see reverse_time
+  #[serde(skip)]
+  pub use_gpu: bool,      // Compute gravity on the GPU when it's available, see World::gpu_available
+  #[serde(default)]
+  pub time: f32,          // Sim seconds stepped so far
+  #[serde(default)]
+  pub despawned: usize,   // Bodies removed for leaving the system, see despawn_escaped
+  #[serde(default)]
+  ejections: usize,       // See BindingStats
+  #[serde(skip)]
+  bound: HashMap<usize, bool>,  // Whether each body was bound at the last check
+  #[serde(skip)]
+  binding_timer: f32,     // Sim seconds since the last check
+  #[serde(skip)]
+  close_pairs: HashSet<(usize, usize)>,   // Bodies having a close approach as of the last step, so each approach is logged once
+  #[serde(skip)]
+  impacts: Vec<Impact>,   // Merges since the last take_impacts
+  #[serde(skip)]
+  events: EventBus,       // Events since the last take_events
+  #[serde(skip)]
+  timings: StepTimings,   // Since the last take_timings
+  #[serde(skip, default = "StdRng::from_entropy")]
+  rng: StdRng,    // All randomness goes through this, so seeded worlds are reproducible
+}
+
+impl Default for World {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl World {
+  pub fn new() -> Self {
+    Self {
+      planet_id_count: 0,
+      planets: HashMap::new(),
+      config: SimConfig::default(),
+      barnes_hut_theta: DEFAULT_BARNES_HUT_THETA,
+      integrator: Integrator::default(),
+      adaptive_timestep: false,
+      collision_mode: CollisionMode::default(),
+      fragmentation_threshold: Some(DEFAULT_FRAGMENTATION_THRESHOLD),
+      mass_transfer: None,
+      tidal_disruption: false,
+      soft_bodies: None,
+      test_particle_mass: None,
+      bonds: Vec::new(),
+      wrap_bounds: None,
+      cancel_drift: false,
+      recentre_on: None,
+      reversed: false,
+      use_gpu: false,
+      time: 0.0,
+      despawned: 0,
+      ejections: 0,
+      bound: HashMap::new(),
+      binding_timer: 0.0,
+      close_pairs: HashSet::new(),
+      impacts: Vec::new(),
+      events: EventBus::default(),
+      timings: StepTimings::default(),
+      rng: StdRng::from_entropy(),
+    }
+  }
+
+  // Makes spawning and fragmentation reproducible. Stepping is deterministic regardless of the seed.
+  pub fn seed(&mut self, seed: u64) {
+    self.rng = StdRng::seed_from_u64(seed);
+  }
+
+  // For scenarios that place bodies randomly, so they follow the seed too
+  pub fn rng(&mut self) -> &mut StdRng {
+    &mut self.rng
+  }
+
+  // Removes every body and starts the clock again
+  pub fn clear(&mut self) {
+    self.planets = HashMap::new();
+    self.bonds.clear();
+    self.time = 0.0;
+    self.despawned = 0;
+    self.ejections = 0;
+    self.bound.clear();
+    self.binding_timer = 0.0;
+    self.close_pairs.clear();
+  }
+
+  // Returns the id of the new planet
+  pub fn add_planet(&mut self, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection: Option<Duration>) -> usize {
+    self.add_body(BodyKind::Planet, position, velocity, mass, radius, spawn_protection)
+  }
+
+  // Same as add_planet, but for any kind of body. If mass is None it comes from the kind's density.
+  pub fn add_body(&mut self, kind: BodyKind, position: Point2<f32>, velocity: Option<Vector2<f32>>, mass: Option<f32>, radius: f32, spawn_protection: Option<Duration>) -> usize {
+    let id = self.add_planet_raw(Planet::new_with_kind(
+      kind,
+      self.planet_id_count,
+      position,
+      velocity,
+      mass,
+      radius,
+      spawn_protection,
+    ));
+    let mass = {
+      let mut planet = self.planets[&id].borrow_mut();
+      planet.color = self.config.body_color(kind, id, planet.density());
+      planet.mass
+    };
+    self.events.emit(self.time, EventKind::Spawned { id, mass });
+    id
+  }
+
+  // Changes a body's kind, keeping mass and radius, and recolours it as a new body of that kind would be
+  pub fn set_kind(&self, id: usize, kind: BodyKind) {
+    if let Some(planet) = self.planets.get(&id) {
+      let mut planet = planet.borrow_mut();
+      planet.set_kind(kind);
+      planet.color = self.config.body_color(kind, id, planet.density());
+    }
+  }
+
+  // Id the next added planet will get
+  pub fn next_planet_id(&self) -> usize {
+    self.planet_id_count
+  }
+
+  pub fn add_planet_raw(&mut self, mut planet: Planet) -> usize {
+    let id = self.planet_id_count;
+    planet.id = id;
+
+    self.planets.insert(
+      id,
+      RefCell::new(planet)
+    );
+
+    self.planet_id_count += 1;
+    id
+  }
+
+  // Adds a body made elsewhere, e.g. sent by a network client, as a new spawn with a fresh id
+  pub fn spawn_planet(&mut self, planet: Planet) -> usize {
+    let mass = planet.mass;
+    let id = self.add_planet_raw(planet);
+    self.events.emit(self.time, EventKind::Spawned { id, mass });
+    id
+  }
+
+  // Puts back a planet that was previously in this world, keeping its id
+  pub fn restore_planet(&mut self, planet: Planet) {
+    self.planet_id_count = self.planet_id_count.max(planet.id + 1);
+    self.planets.insert(planet.id, RefCell::new(planet));
+  }
+
+  // Spawns a planet with other smaller planets orbiting it. Returns the id of the main planet.
+  pub fn add_planet_with_moons(
+    &mut self,
+    position: Point2<f32>,
+    velocity: Option<Vector2<f32>>,
+    main_planet_mass: Option<f32>,
+    main_planet_radius: f32,
+    moon_num: usize,
+    moon_orbit_radius_range: (f32, f32),    // Starting from surface of planet
+    moon_body_radius_range: (f32, f32),
+    orbit_direction_clockwise: bool,  // anticlockwise = false, clockwise = true
+  ) -> usize {
+    let main_id = self.add_planet(position, velocity, main_planet_mass, main_planet_radius, None);  // Add main planet
+    let (main_planet_mass, frame_velocity) = {
+      let p = self.planets[&main_id].borrow();
+      (p.mass, p.velocity)
+    };
+
+    let orbit_rad_range = Uniform::from(moon_orbit_radius_range.0..moon_orbit_radius_range.1);
+    let angle_range = Uniform::from(0.0..TWO_PI);
+    let size_rad_range = Uniform::from(moon_body_radius_range.0..moon_body_radius_range.1);
+
+    for _ in 0..moon_num {
+      let orbit_radius = main_planet_radius + orbit_rad_range.sample(&mut self.rng);
+      let orbit_speed = tools::circular_orbit_speed(self.config.g, main_planet_mass, orbit_radius);
+      let start_angle = angle_range.sample(&mut self.rng);      // Angle from main planet to moon
+      let start_pos = tools::get_components(orbit_radius, start_angle);   // Position on circle orbit where planet will start
+      let start_velocity = tools::get_components(
+        orbit_speed,
+        if orbit_direction_clockwise {
+          start_angle + PI/2.0
+        } else {
+          start_angle - PI/2.0
+        }
+      );  // 90 degrees to angle with planet
+      let moon_radius = size_rad_range.sample(&mut self.rng);
+
+      self.add_planet(
+        position + start_pos,
+        Some(start_velocity + frame_velocity),  // Add velocity of main planet
+        None,
+        moon_radius,
+        None,
+      );
+    }
+
+    main_id
+  }
+
+  // Spawns a planet at position in a circular orbit around the host, moving with the host.
+  // Returns None if the host doesn't exist or the position is inside it.
+  pub fn add_orbiting_planet(&mut self, host_id: usize, position: Point2<f32>, mass: f32, radius: f32, clockwise: bool) -> Option<usize> {
+    let (host_position, host_velocity, host_mass, host_radius) = {
+      let host = self.planets.get(&host_id)?.borrow();
+      (host.position, host.velocity, host.mass, host.radius)
+    };
+
+    let offset = position - host_position;
+    let orbit_radius = offset.magnitude();
+    if orbit_radius <= host_radius + radius { return None }
+
+    // Two-body circular orbit: relative speed is sqrt(G(M + m)/r)
+    let speed = tools::circular_orbit_speed(self.config.g, host_mass + mass, orbit_radius);
+    let angle = tools::get_angle(offset);
+    let velocity = tools::get_components(speed, if clockwise { angle + PI/2.0 } else { angle - PI/2.0 });
+
+    Some(self.add_planet(position, Some(host_velocity + velocity), Some(mass), radius, None))
+  }
+
+  // Spawns two planets in circular orbit about their barycentre at centre, separation apart along angle and moving
+  // together with velocity. Returns None if they would overlap.
+  pub fn add_binary(
+    &mut self,
+    centre: Point2<f32>,
+    velocity: Vector2<f32>,
+    masses: (f32, f32),
+    radii: (f32, f32),
+    separation: f32,
+    angle: f32,
+    clockwise: bool,
+  ) -> Option<(usize, usize)> {
+    if separation <= radii.0 + radii.1 { return None }
+
+    let total = masses.0 + masses.1;
+    let (speed1, speed2) = tools::binary_orbit_speeds(self.config.g, masses.0, masses.1, separation);
+    let direction = if clockwise { angle + PI/2.0 } else { angle - PI/2.0 };
+    // Heavier bodies sit closer to the barycentre: r1 = d m2/(m1 + m2)
+    let offset1 = tools::get_components(separation * masses.1/total, angle);
+    let offset2 = tools::get_components(separation * masses.0/total, angle + PI);
+
+    let id1 = self.add_planet(centre + offset1, Some(velocity + tools::get_components(speed1, direction)), Some(masses.0), radii.0, None);
+    let id2 = self.add_planet(centre + offset2, Some(velocity + tools::get_components(speed2, direction + PI)), Some(masses.1), radii.1, None);
+    Some((id1, id2))
+  }
+
+  // Spawns count planets evenly spaced on a circle around the host, each in circular orbit.
+  // Returns the ids of those that could be placed.
+  pub fn add_ring(&mut self, host_id: usize, orbit_radius: f32, count: usize, mass: f32, radius: f32, clockwise: bool) -> Vec<usize> {
+    let host_position = match self.planets.get(&host_id) {
+      Some(host) => host.borrow().position,
+      None => return Vec::new(),
+    };
+
+    (0..count)
+      .filter_map(|k| {
+        let angle = k as f32 * TWO_PI/count as f32;
+        let position = host_position + tools::get_components(orbit_radius, angle);
+        self.add_orbiting_planet(host_id, position, mass, radius, clockwise)
+      })
+      .collect()
+  }
+
+  // Spawns count asteroids scattered through the annulus inner..outer around the host, each in circular orbit
+  // (ignoring the others' pull), so they form a thin flat ring. Returns the ids of those that could be placed.
+  pub fn add_debris_ring(&mut self, host_id: usize, radius_range: (f32, f32), count: usize, particle_radius: f32, clockwise: bool) -> Vec<usize> {
+    let host_position = match self.planets.get(&host_id) {
+      Some(host) => host.borrow().position,
+      None => return Vec::new(),
+    };
+    let (inner, outer) = radius_range;
+    let mass = tools::volume_of_sphere(particle_radius) * BodyKind::Asteroid.density();
+
+    (0..count)
+      .filter_map(|_| {
+        // Uniform in area
+        let orbit_radius = self.rng.gen_range(inner * inner..=outer * outer).sqrt();
+        let angle = self.rng.gen_range(0.0..TWO_PI);
+        let position = host_position + tools::get_components(orbit_radius, angle);
+        let id = self.add_orbiting_planet(host_id, position, mass, particle_radius, clockwise)?;
+        self.set_kind(id, BodyKind::Asteroid);
+        Some(id)
+      })
+      .collect()
+  }
+
+  pub fn add_random_planets(&mut self, n: usize, x_range: (f32, f32), y_range: (f32, f32), radius_range: (f32, f32), speed_range: Option<(f32, f32)>) {
+    assert!(x_range.1 > x_range.0);
+    assert!(y_range.1 > y_range.0);
+    assert!(radius_range.1 > radius_range.0);
+    assert!(n > 0);
+
+    for _ in 0..n {
+      let x_pos = self.rng.gen_range(x_range.0..x_range.1);
+      let y_pos = self.rng.gen_range(y_range.0..y_range.1);
+      let radius = self.rng.gen_range(radius_range.0..radius_range.1);
+
+      let velocity = if let Some(speed_range) = speed_range {
+        assert!(speed_range.1 > speed_range.0);
+
+        let speed = self.rng.gen_range(speed_range.0..speed_range.1);
+        let angle = self.rng.gen_range(0.0..TWO_PI);
+        Some(tools::get_components(speed, angle))
+      } else {
+        None
+      };
+
+      self.add_planet(
+        Point2::new(x_pos, y_pos),
+        velocity,
+        None,
+        radius,
+        None,
+      );
+    }
+  }
+
+  pub fn spawn_square_of_planets(
+    &mut self,
+    top_left: Point2<f32>,
+    w: u16,
+    h: u16,
+    gap: f32,
+    rad: f32,
+  ) {
+    for i in 0..w {
+      for j in 0..h {
+        self.add_planet(
+          Point2::new(top_left.x + i as f32 * gap, top_left.y + j as f32 * gap),
+          None,
+          None,
+          rad,
+          None,
+        );
+      }
+    }
+  }
+
+  pub fn remove_planet(&mut self, id: usize) {
+    if self.planets.remove(&id).is_none() {
+      println!("WARNING: Tried to remove planet {} but it wasn't in the hashmap.", id);
+    } else {
+      self.events.emit(self.time, EventKind::Removed { id });
+    }
+  }
+
+  // Returns the smallest planet under the given point, if any
+  pub fn planet_at(&self, point: Point2<f32>) -> Option<usize> {
+    self.planets.iter()
+      .map(|(id, pl)| (id, pl.borrow()))
+      .filter(|(_, pl)| (pl.position - point).magnitude_squared() <= pl.radius.powi(2))
+      .min_by(|(_, a), (_, b)| a.radius.partial_cmp(&b.radius).unwrap())
+      .map(|(id, _)| *id)
+  }
+
+  // The planet whose surface is closest to the given point
+  pub fn nearest_planet(&self, point: Point2<f32>) -> Option<usize> {
+    self.planets.iter()
+      .map(|(id, pl)| (id, pl.borrow()))
+      .map(|(id, pl)| (id, (pl.position - point).magnitude() - pl.radius))
+      .min_by(|(_, a), (_, b)| a.total_cmp(b))
+      .map(|(id, _)| *id)
+  }
+
+  // Bodies touching the axis-aligned box between two opposite corners, sorted by id
+  pub fn planets_in_rect(&self, corner: Point2<f32>, opposite: Point2<f32>) -> Vec<usize> {
+    let (min, max) = (corner.inf(&opposite), corner.sup(&opposite));
+    let mut ids: Vec<usize> = self.planets.iter()
+      .filter(|(_, pl)| {
+        let pl = pl.borrow();
+        // Closest point in the box to the centre
+        let closest = Point2::new(pl.position.x.max(min.x).min(max.x), pl.position.y.max(min.y).min(max.y));
+        (pl.position - closest).magnitude_squared() <= pl.radius.powi(2)
+      })
+      .map(|(id, _)| *id)
+      .collect();
+    ids.sort_unstable();
+    ids
+  }
+
+  // Merges the given bodies into the first one still in the world, as if they had all collided with it.
+  // Returns the id of the merged body.
+  pub fn merge_planets(&mut self, ids: &[usize]) -> Option<usize> {
+    let mut ids = ids.iter().filter(|id| self.planets.contains_key(id));
+    let into = *ids.next()?;
+    let others: Vec<usize> = ids.copied().filter(|id| *id != into).collect();
+    for id in others {
+      if let Some(other) = self.planets.remove(&id) {
+        let masses = (self.planets[&into].borrow().mass, other.borrow().mass);
+        self.events.emit(self.time, EventKind::Merged { into, absorbed: id, masses });
+        Self::collide_planets(&mut self.planets[&into].borrow_mut(), &other.borrow(), self.config.merge_geometry);
+      }
+    }
+    Some(into)
+  }
+
+  // The body exerting the strongest pull on the given planet, among those heavier than it
+  pub fn dominant_attractor(&self, id: usize) -> Option<usize> {
+    let planet = self.planets.get(&id)?.borrow();
+    self.planets.iter()
+      .filter(|(other_id, _)| **other_id != id)
+      .map(|(other_id, other)| (other_id, other.borrow()))
+      .filter(|(_, other)| other.mass > planet.mass)
+      .map(|(other_id, other)| (other_id, other.mass/(other.position - planet.position).magnitude_squared()))
+      .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+      .map(|(other_id, _)| *other_id)
+  }
+
+  // Osculating orbit of a planet around its dominant attractor.
+  // Returns the attractor's id and the orbital elements, or None if there is no attractor or the orbit is degenerate.
+  pub fn osculating_orbit(&self, id: usize) -> Option<(usize, tools::OrbitalElements)> {
+    let host_id = self.dominant_attractor(id)?;
+    let planet = self.planets[&id].borrow();
+    let host = self.planets[&host_id].borrow();
+
+    let mu = self.config.g * (host.mass + planet.mass);
+    tools::orbital_elements(mu, planet.position - host.position, planet.velocity - host.velocity)
+      .map(|elements| (host_id, elements))
+  }
+
+  // Gravitational potential per unit mass at each point, -Σ GM/sqrt(r^2 + ε^2) as in Diagnostics.
+  // Sums over every body for each point, so keep the number of points small.
+  pub fn potential_at(&self, points: &[Point2<f32>]) -> Vec<f32> {
+    self.field_at(points).into_iter().map(|(potential, _)| potential).collect()
+  }
+
+  // Potential and gravitational field (acceleration of a test mass) at each point, see tools::sample_field.
+  // Like potential_at, this is a direct sum, so keep the number of points small.
+  pub fn field_at(&self, points: &[Point2<f32>]) -> Vec<(f32, Vector2<f32>)> {
+    let bodies: Vec<(Point2<f32>, f32)> = self.planets.values()
+      .map(|pl| {
+        let pl = pl.borrow();
+        (pl.position, self.attracting_mass(&pl))
+      })
+      .collect();
+    let (g, softening, wrap_bounds) = (self.config.g, self.config.softening, self.wrap_bounds);
+
+    points.par_iter()
+      .map(|point| tools::sample_field(g, softening, &bodies, *point, wrap_bounds))
+      .collect()
+  }
+
+  // Sphere of influence of a planet at its current distance from its dominant attractor.
+  // None if nothing is heavier than it, as then it dominates everywhere.
+  pub fn sphere_of_influence(&self, id: usize) -> Option<f32> {
+    let host_id = self.dominant_attractor(id)?;
+    let planet = self.planets[&id].borrow();
+    let host = self.planets[&host_id].borrow();
+    Some(tools::sphere_of_influence((planet.position - host.position).magnitude(), planet.mass, host.mass))
+  }
+
+  // Path a new body would take if launched from start with the given velocity, treating the
+  // current bodies as fixed in place. Stops early if the path runs into a body.
+  pub fn predict_trajectory(&self, start: Point2<f32>, velocity: Vector2<f32>, steps: usize, dt: f32) -> Vec<Point2<f32>> {
+    let bodies: Vec<TreeBody> = self.planets.values()
+      .map(|pl| {
+        let pl = pl.borrow();
+        TreeBody { position: pl.position, mass: self.attracting_mass(&pl), radius: pl.radius }
+      })
+      .collect();
+    let tree = QuadTree::new(&bodies, self.barnes_hut_theta, self.config.g, self.config.softening, self.wrap_bounds);
+
+    let mut path = Vec::with_capacity(steps + 1);
+    let (mut position, mut velocity) = (start, velocity);
+    path.push(position);
+
+    for _ in 0..steps {
+      velocity += tree.acceleration_at(position, 0.0) * dt;
+      position += velocity * dt;
+      path.push(position);
+
+      if self.planet_at(position).is_some() { break }
+    }
+
+    path
+  }
+
+  // Advances the simulation by dt: merges colliding planets, then integrates
+  pub fn step(&mut self, dt_duration: &Duration) {
+    if self.planets.is_empty() { return }
+
+    if self.adaptive_timestep {
+      self.adaptive_step(dt_duration);
+    } else {
+      self.substep(dt_duration);
+    }
+    self.despawn_escaped();
+
+    if self.cancel_drift {
+      self.to_barycentric_frame();
+    }
+    if let Some(point) = self.recentre_on {
+      self.recentre(point);
+    }
+    self.time += dt_duration.as_secs_f32();
+
+    self.binding_timer += dt_duration.as_secs_f32();
+    if self.binding_timer >= BINDING_CHECK_INTERVAL {
+      self.binding_timer = 0.0;
+      self.classify_binding();
+    }
+  }
+
+  // Bound and unbound body counts as of the last check, and ejections so far
+  pub fn binding(&self) -> BindingStats {
+    let bound = self.bound.values().filter(|bound| **bound).count();
+    BindingStats { bound, unbound: self.bound.len() - bound, ejections: self.ejections }
+  }
+
+  // Sorts bodies into bound and unbound by the sign of their specific energy relative to the rest of the system:
+  // kinetic energy in the barycentre's frame plus the potential due to every other body. Bodies going from bound to
+  // unbound count as ejections. Bodies first seen unbound (e.g. launched that way) don't. Static bodies and wrapping
+  // worlds, where nothing can escape, aren't classified.
+  fn classify_binding(&mut self) {
+    let (com_velocity, wrapping) = (self.barycenter().map(|(_, velocity)| velocity), self.wrap_bounds.is_some());
+    let com_velocity = match com_velocity {
+      Some(velocity) if !wrapping => velocity,
+      _ => {
+        self.bound.clear();
+        return
+      },
+    };
+
+    // Copied out of the RefCells so the potentials can be found in parallel, from the same tree gravity uses
+    let (states, bodies): (Vec<(usize, Vector2<f32>, bool)>, Vec<TreeBody>) = self.planets.iter()
+      .map(|(id, pl)| {
+        let pl = pl.borrow();
+        ((*id, pl.velocity, pl.is_static), TreeBody { position: pl.position, mass: self.attracting_mass(&pl), radius: pl.radius })
+      })
+      .unzip();
+    let tree = QuadTree::new(&bodies, self.barnes_hut_theta, self.config.g, self.config.softening, None);
+
+    let mut classified: Vec<(usize, bool)> = states.par_iter()
+      .enumerate()
+      .filter(|(_, (_, _, is_static))| !is_static)
+      .map(|(i, (id, velocity, _))| {
+        let energy = 0.5 * (velocity - com_velocity).magnitude_squared() + tree.potential_on(i);
+        (*id, energy <= 0.0)
+      })
+      .collect();
+    classified.sort_unstable_by_key(|(id, _)| *id);   // So events come in the same order every run
+
+    let mut bound = HashMap::with_capacity(classified.len());
+    for (id, is_bound) in classified {
+      match self.bound.get(&id) {
+        Some(true) if !is_bound => {
+          self.ejections += 1;
+          self.events.emit(self.time, EventKind::Ejected { id });
+        },
+        Some(false) if is_bound => self.events.emit(self.time, EventKind::Captured { id }),
+        _ => (),
+      }
+      bound.insert(id, is_bound);
+    }
+    self.bound = bound;
+  }
+
+  fn substep(&mut self, dt_duration: &Duration) {
+    self.resolve_collisions();
+    if self.tidal_disruption {
+      self.disrupt_within_roche_limits();
+    }
+    self.apply_bonds(dt_duration.as_secs_f32());
+    self.integrate(dt_duration.as_secs_f32(), dt_duration);
+  }
+
+  // Covers dt in as many substeps as adaptive_dt asks for, so close encounters are integrated accurately while
+  // bodies far apart still take one step per frame.
+  fn adaptive_step(&mut self, dt_duration: &Duration) {
+    // Positions at the start, so drawing interpolates across the whole step rather than the last substep
+    let start_positions: HashMap<usize, Point2<f32>> = self.planets.iter()
+      .map(|(id, pl)| (*id, pl.borrow().position))
+      .collect();
+
+    let dt = dt_duration.as_secs_f32();
+    let min_dt = dt/MAX_SUBSTEPS as f32;
+    let mut remaining = dt;
+    while remaining > min_dt * 1e-3 && !self.planets.is_empty() {
+      let substep_dt = self.adaptive_dt().max(min_dt).min(remaining);
+      self.substep(&Duration::from_secs_f32(substep_dt));
+      remaining -= substep_dt;
+    }
+
+    if self.wrap_bounds.is_none() {   // Wrapped bodies are not interpolated (see Planet::update)
+      for (id, position) in start_positions {
+        if let Some(pl) = self.planets.get(&id) {
+          pl.borrow_mut().previous_position = position;
+        }
+      }
+    }
+  }
+
+  // Removes bodies beyond config.despawn_radius from the barycentre, or faster than config.despawn_speed relative to
+  // it with more kinetic energy than the rest of the system's potential can hold on to. Nothing escapes a wrapping world.
+  fn despawn_escaped(&mut self) {
+    if self.wrap_bounds.is_some() { return }
+    let (radius, speed) = (self.config.despawn_radius, self.config.despawn_speed);
+    if radius.is_none() && speed.is_none() { return }
+    let (com, com_velocity) = match self.barycenter() {
+      Some(barycenter) => barycenter,
+      None => return,
+    };
+
+    let mut ids: Vec<usize> = self.planets.keys().copied().collect();
+    ids.sort_unstable();
+    // Attractors for every escape check, built once. Only needed with a despawn speed.
+    let bodies: Vec<TreeBody> = ids.iter()
+      .map(|id| {
+        let pl = self.planets[id].borrow();
+        TreeBody { position: pl.position, mass: self.attracting_mass(&pl), radius: pl.radius }
+      })
+      .collect();
+    let tree = speed.map(|_| QuadTree::new(&bodies, self.barnes_hut_theta, self.config.g, self.config.softening, None));
+
+    let despawned: Vec<(usize, DespawnReason)> = ids.iter().enumerate()
+      .filter_map(|(i, id)| {
+        let pl = self.planets[id].borrow();
+        if pl.is_static { return None }
+        if radius.map_or(false, |radius| (pl.position - com).magnitude_squared() > radius * radius) {
+          return Some((*id, DespawnReason::TooFar))
+        }
+        let relative_speed = (pl.velocity - com_velocity).magnitude();
+        if let (Some(speed), Some(tree)) = (speed, &tree) {
+          // Potential due to everything else
+          if relative_speed > speed && 0.5 * relative_speed * relative_speed + tree.potential_on(i) > 0.0 {
+            return Some((*id, DespawnReason::Escaping))
+          }
+        }
+        None
+      })
+      .collect();
+
+    for (id, reason) in despawned {
+      self.planets.remove(&id);
+      self.despawned += 1;
+      self.events.emit(self.time, EventKind::Despawned { id, reason });
+    }
+  }
+
+  // Step length that resolves every body's motion: η * sqrt(l/|a|), where l is the larger of its radius and the
+  // softening length. Uses the forces from the last step.
+  fn adaptive_dt(&self) -> f32 {
+    self.planets.values()
+      .map(|pl| {
+        let pl = pl.borrow();
+        let acceleration = pl.resultant_force.magnitude()/pl.mass;
+        let length = pl.radius.max(self.config.softening);
+        if acceleration > 0.0 { ADAPTIVE_ETA * (length/acceleration).sqrt() } else { f32::INFINITY }
+      })
+      .fold(f32::INFINITY, f32::min)
+  }
+
+  // Whether a body is light enough to be a test particle, see test_particle_mass
+  pub fn is_test_particle(&self, planet: &Planet) -> bool {
+    self.test_particle_mass.map_or(false, |threshold| planet.mass < threshold)
+  }
+
+  // Mass as seen by the gravity calculation, zero for test particles
+  fn attracting_mass(&self, planet: &Planet) -> f32 {
+    if self.is_test_particle(planet) { 0.0 } else { planet.gravitational_mass() }
+  }
+
+  // Recomputes every body's resultant force without moving anything, e.g. after editing while paused
+  pub fn update_forces(&mut self) {
+    let mut ids: Vec<usize> = self.planets.keys().copied().collect();
+    ids.sort_unstable();
+    let bodies: Vec<TreeBody> = ids.iter()
+      .map(|id| {
+        let pl = self.planets[id].borrow();
+        TreeBody { position: pl.position, mass: self.attracting_mass(&pl), radius: pl.radius }
+      })
+      .collect();
+
+    let accelerations = quadtree::accelerations(&bodies, self.barnes_hut_theta, self.config.g, self.config.softening, self.wrap_bounds);
+    for (i, id) in ids.iter().enumerate() {
+      let mut pl = self.planets[id].borrow_mut();
+      pl.resultant_force = accelerations[i] * pl.mass;
+    }
+  }
+
+  // Impacts from merges since this was last called. Not collected unless called regularly.
+  pub fn take_impacts(&mut self) -> Vec<Impact> {
+    std::mem::take(&mut self.impacts)
+  }
+
+  // Whether use_gpu does anything: needs the gpu feature and a GPU. Sets the GPU up on the first call.
+  pub fn gpu_available() -> bool {
+    #[cfg(feature = "gpu")]
+    return crate::gpu::shared().is_some();
+    #[cfg(not(feature = "gpu"))]
+    return false;
+  }
+
+  // How long the parts of every step since the last call took
+  pub fn take_timings(&mut self) -> StepTimings {
+    std::mem::take(&mut self.timings)
+  }
+
+  // Spawns, merges, removals and so on since the last call, oldest first
+  pub fn take_events(&mut self) -> Vec<Event> {
+    self.events.drain()
+  }
+
+  pub fn barycenter(&self) -> Option<(Point2<f32>, Vector2<f32>)> {
+    let planets: Vec<_> = self.planets.values().map(|pl| pl.borrow()).collect();
+    tools::barycenter(planets.iter().map(|pl| &**pl))
+  }
+
+  // Negates every velocity and spin, so the system runs back the way it came. Stepping on from here is the same as
+  // stepping backwards in time. Velocity Verlet is time-symmetric, so it retraces its path up to rounding until
+  // something irreversible like a merge happens; the other integrators drift off it, Euler fastest.
+  pub fn reverse_time(&mut self) {
+    for planet in self.planets.values() {
+      let mut planet = planet.borrow_mut();
+      planet.velocity = -planet.velocity;
+      planet.angular_velocity = -planet.angular_velocity;
+    }
+    self.reversed = !self.reversed;
+  }
+
+  // Subtracts the centre of mass velocity from every planet, so the system as a whole stops drifting
+  pub fn to_barycentric_frame(&mut self) {
+    if let Some((_, velocity)) = self.barycenter() {
+      for pl in self.planets.values() {
+        pl.borrow_mut().velocity -= velocity;
+      }
+    }
+  }
+
+  // Moves every planet so the barycentre is at point
+  pub fn recentre(&mut self, point: Point2<f32>) {
+    if let Some((barycenter, _)) = self.barycenter() {
+      let offset = point - barycenter;
+      for pl in self.planets.values() {
+        let mut pl = pl.borrow_mut();
+        pl.position += offset;
+        pl.previous_position += offset;
+      }
+    }
+  }
+
+  // Also logs close approaches, which come out of the same broad phase
+  pub(crate) fn resolve_collisions(&mut self) {
+    let start = Instant::now();
+
+    // Sorted so collisions resolve in the same order every run (HashMap order is random)
+    let mut keys: Vec<usize> = self.planets.keys().copied().collect();
+    keys.sort_unstable();
+    let len = keys.len();
+
+    // Merging bodies touching each other directly or through a chain, so a pile-up becomes one body in one step
+    let mut touching = DisjointSet::new(len);
+    let mut planets_to_remove: Vec<usize> = Vec::with_capacity(len/2);
+    let mut new_planets: Vec<Planet> = Vec::new();
+    let mut to_split: Vec<usize> = Vec::new();    // Hit with soft bodies on, so broken into particles
+
+    // Broad phase: only planets sharing a spatial hash cell can be touching, or close. Boxes are grown to cover
+    // close approaches too.
+    let bounds: Vec<(Point2<f32>, f32)> = keys.iter()
+      .map(|id| {
+        let pl = self.planets[id].borrow();
+        (pl.position, pl.radius * CLOSE_APPROACH_RADII)
+      })
+      .collect();
+    let mut close_pairs = HashSet::new();
+    let candidates = SpatialHash::new(&bounds).candidate_pairs();
+    let detected = Instant::now();
+    self.timings.collision_detection += detected - start;
+
+    for (i, j) in candidates {
+      let pl1 = &self.planets[&keys[i]];
+      let pl2 = &self.planets[&keys[j]];
+
+      let (colliding, protection) = {
+        let bpl1 = pl1.borrow();
+        let bpl2 = pl2.borrow();
+        let both_test_particles = self.is_test_particle(&bpl1) && self.is_test_particle(&bpl2);
+        let distance = (bpl2.position - bpl1.position).magnitude();
+        if !both_test_particles && distance <= (bpl1.radius + bpl2.radius) * CLOSE_APPROACH_RADII {
+          let pair = (keys[i], keys[j]);
+          if !tools::check_collision(&bpl1, &bpl2) && !self.close_pairs.contains(&pair) {
+            self.events.emit(self.time, EventKind::CloseApproach { a: pair.0, b: pair.1, distance });
+          }
+          close_pairs.insert(pair);
+        }
+        (
+          tools::check_collision(&bpl1, &bpl2) && !both_test_particles,
+          bpl1.has_spawn_protection() || bpl2.has_spawn_protection()
+        )
+      };
+
+      // Gravity is not applied between overlapping planets (see QuadTree::force_on), as they become very speedy.
+      // protection is true if either planets have spawn protection
+      if colliding && !protection {
+        match self.collision_mode {
+          // Soft bodies bounce off each other instead, their bonds doing the rest
+          CollisionMode::Merge => match self.soft_bodies {
+            Some(config) if soft_body::can_split(&pl1.borrow(), &config) && soft_body::can_split(&pl2.borrow(), &config) => {
+              for pl in [pl1, pl2] {
+                let pl = pl.borrow();
+                if !pl.particle { to_split.push(pl.id) }
+              }
+              tools::bounce(&mut pl1.borrow_mut(), &mut pl2.borrow_mut(), config.restitution);
+            },
+            _ => touching.union(i, j),
+          },
+          CollisionMode::Bounce { restitution } => {
+            tools::bounce(&mut pl1.borrow_mut(), &mut pl2.borrow_mut(), restitution);
+          },
+          CollisionMode::None => (),
+        }
+      }
+    }
+
+    self.close_pairs = close_pairs;
+
+    for group in touching.groups() {
+      if let [i, j] = group[..] {
+        self.collide_pair(keys[i], keys[j], true, &mut planets_to_remove, &mut new_planets);
+      } else {
+        // Three or more at once all merge into the first, without breaking up or grazing
+        for k in group[1..].iter() {
+          self.collide_pair(keys[group[0]], keys[*k], false, &mut planets_to_remove, &mut new_planets);
+        }
+      }
+    }
+
+    self.planets.retain(|id, _| !planets_to_remove.contains(id));
+    for planet in new_planets {
+      self.add_planet_raw(planet);
+    }
+
+    to_split.sort_unstable();
+    to_split.dedup();
+    for id in to_split {
+      self.split_into_particles(id);
+    }
+    self.timings.collision_resolution += detected.elapsed();
+  }
+
+  // Swaps a body for bonded particles, see soft_body::split. Does nothing if it's gone or can't be split.
+  fn split_into_particles(&mut self, id: usize) {
+    let config = match self.soft_bodies {
+      Some(config) => config,
+      None => return,
+    };
+    let split = match self.planets.get(&id) {
+      Some(planet) => soft_body::split(&planet.borrow(), &config),
+      None => return,
+    };
+    let (particles, spacing) = match split {
+      Some(split) => split,
+      None => return,
+    };
+
+    self.planets.remove(&id);
+    let placed: Vec<(usize, Point2<f32>)> = particles.into_iter()
+      .map(|particle| {
+        let position = particle.position;
+        (self.add_planet_raw(particle), position)
+      })
+      .collect();
+    self.bonds.append(&mut soft_body::bond_neighbours(&placed, spacing));
+    self.events.emit(self.time, EventKind::Softened { id, particles: placed.len() });
+  }
+
+  // Springs between soft body particles. Bonds to particles that are gone, or stretched until they snap, are dropped.
+  fn apply_bonds(&mut self, dt: f32) {
+    if self.bonds.is_empty() { return }
+    let config = self.soft_bodies.unwrap_or_default();
+    let (planets, wrap_bounds) = (&self.planets, self.wrap_bounds);
+    self.bonds.retain(|bond| match (planets.get(&bond.a), planets.get(&bond.b)) {
+      (Some(a), Some(b)) => soft_body::pull(&mut a.borrow_mut(), &mut b.borrow_mut(), bond.rest_length, &config, dt, wrap_bounds),
+      _ => false,
+    });
+  }
+
+  pub fn bonds(&self) -> &[Bond] {
+    &self.bonds
+  }
+
+  // Two touching planets merge, or with may_break, shatter or graze if the impact calls for it.
+  // Planets that are gone afterwards are added to planets_to_remove, and any fragments to new_planets.
+  fn collide_pair(&mut self, id1: usize, id2: usize, may_break: bool, planets_to_remove: &mut Vec<usize>, new_planets: &mut Vec<Planet>) {
+    let pl1 = &self.planets[&id1];
+    let pl2 = &self.planets[&id2];
+
+    let mut impact = Self::impact(&pl1.borrow(), &pl2.borrow());
+
+    let fragments = self.fragmentation_threshold
+      .filter(|threshold| may_break && impact.energy > *threshold)
+      .and_then(|_| Self::fragment(&mut pl1.borrow_mut(), &mut pl2.borrow_mut(), &mut self.rng));
+
+    let graze = self.mass_transfer.filter(|fraction| may_break && Self::is_graze(&pl1.borrow(), &pl2.borrow(), *fraction));
+
+    if let Some((broken_id, mut fragments)) = fragments {
+      self.events.emit(self.time, EventKind::Fragmented { id: broken_id, pieces: fragments.len() });
+      planets_to_remove.push(broken_id);
+      new_planets.append(&mut fragments);
+    } else if let Some(fraction) = graze {
+      let (mut bpl1, mut bpl2) = (pl1.borrow_mut(), pl2.borrow_mut());
+      let (from, to) = if bpl1.mass >= bpl2.mass { (id2, id1) } else { (id1, id2) };
+      let mass = tools::transfer_mass(&mut bpl1, &mut bpl2, fraction, GRAZE_RESTITUTION);
+      self.events.emit(self.time, EventKind::MassTransferred { from, to, mass });
+    } else {
+      self.events.emit(self.time, EventKind::Merged {
+        into: id1,
+        absorbed: id2,
+        masses: (pl1.borrow().mass, pl2.borrow().mass),
+      });
+      impact.merge = Some(Merge {
+        into: id1,
+        bodies: [MergedBody::from(&*pl1.borrow()), MergedBody::from(&*pl2.borrow())],
+      });
+      Self::collide_planets(&mut pl1.borrow_mut(), &pl2.borrow(), self.config.merge_geometry);
+      planets_to_remove.push(id2)
+    }
+
+    if self.impacts.len() < MAX_STORED_IMPACTS {
+      self.impacts.push(impact);
+    }
+  }
+
+  // Tears apart bodies inside the Roche limit of a much heavier body, spreading the pieces along their orbit
+  fn disrupt_within_roche_limits(&mut self) {
+    let mut keys: Vec<usize> = self.planets.keys().copied().collect();
+    keys.sort_unstable();
+    let bounds: Vec<(Point2<f32>, f32)> = keys.iter()
+      .map(|id| {
+        let pl = self.planets[id].borrow();
+        (pl.position, pl.radius * ROCHE_SEARCH_RADII)
+      })
+      .collect();
+
+    let mut disrupted = HashSet::new();
+    let mut new_planets = Vec::new();
+
+    for (i, j) in SpatialHash::new(&bounds).candidate_pairs() {
+      let (pl1, pl2) = (self.planets[&keys[i]].borrow(), self.planets[&keys[j]].borrow());
+      let (host, satellite) = if pl1.mass >= pl2.mass { (pl1, pl2) } else { (pl2, pl1) };
+      if disrupted.contains(&satellite.id) || satellite.has_spawn_protection() ||
+        satellite.mass > host.mass * ROCHE_MASS_RATIO { continue }
+
+      let roche_limit = tools::roche_limit(host.radius, host.density(), satellite.density());
+      if (satellite.position - host.position).magnitude_squared() >= roche_limit * roche_limit { continue }
+
+      if let Some(mut fragments) = Self::tidal_fragments(&host, &satellite) {
+        self.events.emit(self.time, EventKind::TidallyDisrupted { id: satellite.id, host: host.id, pieces: fragments.len() });
+        disrupted.insert(satellite.id);
+        new_planets.append(&mut fragments);
+      }
+    }
+
+    self.planets.retain(|id, _| !disrupted.contains(id));
+    for planet in new_planets {
+      self.add_planet_raw(planet);
+    }
+  }
+
+  // Pieces of a satellite strung out along its orbit around the host. Outer pieces drift ahead and behind, with
+  // symmetric velocities so momentum is conserved. None if the pieces would be too small.
+  fn tidal_fragments(host: &Planet, satellite: &Planet) -> Option<Vec<Planet>> {
+    let fragment_mass = satellite.mass/ROCHE_FRAGMENT_COUNT as f32;
+    let fragment_radius = tools::inverse_volume_of_sphere(fragment_mass/satellite.density());
+    if fragment_radius < MIN_FRAGMENT_RADIUS { return None }
+
+    let relative_velocity = satellite.velocity - host.velocity;
+    let along_orbit = if relative_velocity.magnitude_squared() > 0.0 {
+      relative_velocity.normalize()
+    } else {
+      let out = satellite.position - host.position;
+      Vector2::new(-out.y, out.x).normalize()
+    };
+    let shear_speed = relative_velocity.magnitude() * TIDAL_SHEAR;
+
+    Some((0..ROCHE_FRAGMENT_COUNT)
+      .map(|k| {
+        let offset = k as f32 - (ROCHE_FRAGMENT_COUNT - 1) as f32/2.0;
+        let mut fragment = Planet::new_with_kind(
+          BodyKind::Asteroid,
+          0,
+          satellite.position + along_orbit * offset * fragment_radius * 2.2,
+          Some(satellite.velocity + along_orbit * offset * shear_speed),
+          Some(fragment_mass),
+          fragment_radius,
+          Some(FRAGMENT_SPAWN_PROTECTION),
+        );
+        fragment.color = satellite.color;
+        fragment
+      })
+      .collect())
+  }
+
+  // Breaks the smaller of two colliding planets into fragments thrown off the impact point, in roughly the direction
+  // it would have bounced. The larger planet's velocity is adjusted so momentum is conserved.
+  // Returns the id of the broken planet and the fragments, or None if the fragments would be too small.
+  fn fragment(pl1: &mut Planet, pl2: &mut Planet, rng: &mut StdRng) -> Option<(usize, Vec<Planet>)> {
+    let (big, small) = if pl1.mass >= pl2.mass { (pl1, pl2) } else { (pl2, pl1) };
+
+    let density = small.mass/tools::volume_of_sphere(small.radius);
+    let fragment_mass = small.mass/FRAGMENT_COUNT as f32;
+    let fragment_radius = tools::inverse_volume_of_sphere(fragment_mass/density);
+    if fragment_radius < MIN_FRAGMENT_RADIUS { return None }
+
+    let total_momentum = big.velocity * big.mass + small.velocity * small.mass;
+    let com_velocity = total_momentum/(big.mass + small.mass);
+
+    let dist_vec = small.position - big.position;
+    let normal = if dist_vec.magnitude_squared() > 0.0 { dist_vec.normalize() } else { Vector2::new(1.0, 0.0) };
+    let tangent = Vector2::new(-normal.y, normal.x);
+
+    // Velocity of the smaller planet relative to the centre of mass, reflected off the impact surface
+    let relative_velocity = small.velocity - com_velocity;
+    let rebound = relative_velocity - normal * (2.0 * relative_velocity.dot(&normal));
+    let rebound_speed = rebound.magnitude() * FRAGMENT_RESTITUTION;
+    let rebound_angle = tools::get_angle(rebound);
+
+    let impact_point = big.position + normal * big.radius;
+    let mut fragments = Vec::with_capacity(FRAGMENT_COUNT);
+    let mut fragment_momentum = Vector2::new(0.0, 0.0);
+
+    for k in 0..FRAGMENT_COUNT {
+      let velocity = com_velocity + tools::get_components(
+        rebound_speed * rng.gen_range(0.5..1.0),
+        rebound_angle + rng.gen_range(-FRAGMENT_SPREAD..FRAGMENT_SPREAD),
+      );
+      // Lined up along the surface, just outside the larger planet
+      let offset = (k as f32 - (FRAGMENT_COUNT - 1) as f32/2.0) * fragment_radius * 2.2;
+      let position = impact_point + normal * (fragment_radius * 1.1) + tangent * offset;
+
+      let mut fragment = Planet::new_with_kind(BodyKind::Asteroid, 0, position, Some(velocity), Some(fragment_mass), fragment_radius, Some(FRAGMENT_SPAWN_PROTECTION));
+      fragment.color = small.color;
+      fragment_momentum += velocity * fragment_mass;
+      fragments.push(fragment);
+    }
+
+    big.velocity = (total_momentum - fragment_momentum)/big.mass;
+    Some((small.id, fragments))
+  }
+
+  // Glancing enough to transfer mass, and the lighter body would be left big enough to keep
+  fn is_graze(pl1: &Planet, pl2: &Planet, fraction: f32) -> bool {
+    let small = if pl1.mass < pl2.mass { pl1 } else { pl2 };
+    let remaining_radius = tools::inverse_volume_of_sphere(small.mass * (1.0 - fraction)/small.density());
+    tools::impact_obliquity(pl1, pl2) > GRAZE_OBLIQUITY && remaining_radius >= MIN_FRAGMENT_RADIUS
+  }
+
+  fn impact(pl1: &Planet, pl2: &Planet) -> Impact {
+    let dist_vec = pl2.position - pl1.position;
+    let normal = if dist_vec.magnitude_squared() > 0.0 { dist_vec.normalize() } else { Vector2::new(1.0, 0.0) };
+    Impact {
+      position: pl1.position + normal * pl1.radius,
+      velocity: (pl1.velocity * pl1.mass + pl2.velocity * pl2.mass)/(pl1.mass + pl2.mass),
+      energy: tools::specific_impact_energy(pl1, pl2),
+      merge: None,
+    }
+  }
+
+  fn collide_planets(pl1: &mut Planet, pl2: &Planet, geometry: MergeGeometry) {  // Makes pl1 the new planet
+    // Conservation of momentum
+    let total_mass = pl1.mass + pl2.mass;
+    let total_momentum = pl1.mass * pl1.velocity + pl2.mass * pl2.velocity;
+    let com_velocity = total_momentum/total_mass;
+    // Use centre of mass as new position
+    let com = Point2::new(
+      (pl1.position.x * pl1.mass + pl2.position.x * pl2.mass)/total_mass,
+      (pl1.position.y * pl1.mass + pl2.position.y * pl2.mass)/total_mass
+    );
+
+    // Angular momentum about the centre of mass: both spins, plus the orbital part of each body, L = m (r × v)
+    let angular_momentum = pl1.spin_angular_momentum() + pl2.spin_angular_momentum() +
+      pl1.mass * tools::cross(pl1.position - com, pl1.velocity - com_velocity) +
+      pl2.mass * tools::cross(pl2.position - com, pl2.velocity - com_velocity);
+
+    // A static body stays put and swallows whatever hits it
+    let anchor = [&*pl1, pl2].iter().find(|pl| pl.is_static).map(|pl| pl.position);
+    let (com, com_velocity) = match anchor {
+      Some(position) => (position, Vector2::new(0.0, 0.0)),
+      None => (com, com_velocity),
+    };
+    pl1.is_static |= pl2.is_static;
+    pl1.ignores_gravity &= pl2.ignores_gravity;
+    pl1.exerts_no_gravity &= pl2.exerts_no_gravity;
+    pl1.tracked |= pl2.tracked;
+
+    pl1.kind = pl1.kind.merged_with(pl2.kind);
+    // Stars stay star coloured, anything else takes on some of the colour of what it absorbed
+    pl1.color = if pl1.kind == BodyKind::Star {
+      BodyKind::Star.base_color()
+    } else {
+      tools::blend_colors(pl1.color, pl1.mass, pl2.color, pl2.mass)
+    };
+    // Keep the heavier body's name, then the lighter's, otherwise name it after both
+    let (heavier, lighter) = if pl1.mass >= pl2.mass { (&pl1.name, &pl2.name) } else { (&pl2.name, &pl1.name) };
+    pl1.name = Some(heavier.clone().or_else(|| lighter.clone())
+      .unwrap_or_else(|| format!("{} {}+{}", pl1.kind, pl1.id, pl2.id)));
+    // Spheres take the mass-weighted density of the two, discs keep their total area and the density follows
+    let density = (pl1.mass * pl1.density() + pl2.mass * pl2.density())/total_mass;
+    pl1.radius = match geometry {
+      MergeGeometry::Volume => tools::inverse_volume_of_sphere(total_mass/density),
+      MergeGeometry::Area => tools::inverse_area_of_circle(tools::area_of_circle(pl1.radius) + tools::area_of_circle(pl2.radius)),
+    };
+    pl1.position = com;
+    pl1.previous_position = pl1.position;
+    pl1.velocity = com_velocity;   // Inelastic collision
+    pl1.mass = total_mass;
+    pl1.angular_velocity = angular_momentum/pl1.moment_of_inertia();
+  }
+
+  // Moves all planets forward by dt using the selected integrator, with gravity from a Barnes–Hut quadtree.
+  // Planet state is copied into flat buffers, stepped, then written back, so planets are only mutated once.
+  fn integrate(&mut self, dt: f32, dt_duration: &Duration) {
+    // Sorted so the tree (and float rounding) is the same every run
+    let mut ids: Vec<usize> = self.planets.keys().copied().collect();
+    ids.sort_unstable();
+    let mut bodies: Vec<TreeBody> = Vec::with_capacity(ids.len());
+    let mut positions: Vec<Point2<f32>> = Vec::with_capacity(ids.len());
+    let mut velocities: Vec<Vector2<f32>> = Vec::with_capacity(ids.len());
+    let mut pulled: Vec<bool> = Vec::with_capacity(ids.len());   // False for bodies gravity doesn't move
+
+    for id in ids.iter() {
+      let pl = self.planets[id].borrow();
+      bodies.push(TreeBody { position: pl.position, mass: self.attracting_mass(&pl), radius: pl.radius });
+      positions.push(pl.position);
+      velocities.push(if pl.is_static { Vector2::new(0.0, 0.0) } else { pl.velocity });
+      pulled.push(!pl.is_static && !pl.ignores_gravity);
+    }
+
+    let (theta, g, softening, wrap_bounds) = (self.barnes_hut_theta, self.config.g, self.config.softening, self.wrap_bounds);
+    #[cfg(feature = "gpu")]
+    let gpu = if self.use_gpu { crate::gpu::shared() } else { None };
+    let force_time = Cell::new(Duration::ZERO);
+    let accelerations = self.integrator.step(&mut positions, &mut velocities, dt, |positions| {
+      let start = Instant::now();
+      let moved: Vec<TreeBody> = bodies.iter().zip(positions.iter())
+        .map(|(b, pos)| TreeBody { position: *pos, ..*b })
+        .collect();
+      #[cfg(feature = "gpu")]
+      let mut accelerations = match gpu {
+        Some(gpu) => gpu.accelerations(&moved, g, softening, wrap_bounds),
+        None => quadtree::accelerations(&moved, theta, g, softening, wrap_bounds),
+      };
+      #[cfg(not(feature = "gpu"))]
+      let mut accelerations = quadtree::accelerations(&moved, theta, g, softening, wrap_bounds);
+      for (acceleration, pulled) in accelerations.iter_mut().zip(pulled.iter()) {
+        if !pulled {
+          *acceleration = Vector2::new(0.0, 0.0);
+        }
+      }
+      force_time.set(force_time.get() + start.elapsed());
+      accelerations
+    });
+    self.timings.forces += force_time.get();
+
+    for (i, id) in ids.iter().enumerate() {
+      let mut pl = self.planets[id].borrow_mut();
+      pl.previous_position = pl.position;
+      pl.position = positions[i];
+      pl.velocity = velocities[i];
+      pl.resultant_force = accelerations[i] * pl.mass;
+      if pl.update(dt_duration, self.wrap_bounds) {
+        self.events.emit(self.time, EventKind::CrossedBoundary { id: *id });
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn momentum(world: &World) -> Vector2<f32> {
+    world.planets.values()
+      .map(|pl| {
+        let pl = pl.borrow();
+        pl.velocity * pl.mass
+      })
+      .sum()
+  }
+
+  // Merges only, so the outcome doesn't depend on the rng
+  fn merging_world() -> World {
+    let mut world = World::new();
+    world.fragmentation_threshold = None;
+    world
+  }
+
+  #[test]
+  fn merging_conserves_momentum() {
+    let mut pl1 = Planet::new(0, Point2::new(0.0, 0.0), Some(Vector2::new(3.0, -1.0)), Some(2e6), 10.0, None);
+    let pl2 = Planet::new(1, Point2::new(12.0, 4.0), Some(Vector2::new(-20.0, 7.5)), Some(5e5), 6.0, None);
+    let before = pl1.velocity * pl1.mass + pl2.velocity * pl2.mass;
+    let total_mass = pl1.mass + pl2.mass;
+
+    World::collide_planets(&mut pl1, &pl2, MergeGeometry::default());
+
+    assert!((pl1.mass - total_mass).abs() <= 1e-6 * total_mass);
+    assert!((pl1.velocity * pl1.mass - before).norm() <= 1e-4 * before.norm());
+  }
+
+  #[test]
+  fn touching_bodies_merge_into_one() {
+    let mut world = merging_world();
+    world.add_planet(Point2::new(0.0, 0.0), Some(Vector2::new(2.0, 1.0)), None, 10.0, None);
+    world.add_planet(Point2::new(15.0, 0.0), Some(Vector2::new(-6.0, 0.5)), None, 6.0, None);
+    let before = momentum(&world);
+
+    world.resolve_collisions();
+
+    assert_eq!(world.planets.len(), 1);
+    assert!((momentum(&world) - before).norm() <= 1e-4 * before.norm());
+  }
+
+  // A touches B and B touches C, but A and C are apart. All three still end up as one body.
+  #[test]
+  fn overlaps_through_a_middle_body_merge_together() {
+    let mut world = merging_world();
+    world.add_planet(Point2::new(0.0, 0.0), Some(Vector2::new(1.0, 0.0)), None, 10.0, None);
+    world.add_planet(Point2::new(18.0, 0.0), Some(Vector2::new(0.0, 2.0)), None, 10.0, None);
+    world.add_planet(Point2::new(36.0, 0.0), Some(Vector2::new(-1.0, 0.0)), None, 10.0, None);
+    let total_mass: f32 = world.planets.values().map(|pl| pl.borrow().mass).sum();
+    let before = momentum(&world);
+
+    world.resolve_collisions();
+
+    assert_eq!(world.planets.len(), 1);
+    let merged = world.planets.values().next().unwrap().borrow();
+    assert!((merged.mass - total_mass).abs() <= 1e-6 * total_mass);
+    assert!((merged.velocity * merged.mass - before).norm() <= 1e-4 * before.norm());
+  }
+
+  // A row of bodies, each only touching its neighbours, all hitting in the same step becomes one body
+  #[test]
+  fn simultaneous_chain_collision_conserves_mass_and_momentum() {
+    let mut world = merging_world();
+    for (n, radius) in [6.0, 10.0, 4.0, 8.0, 5.0].iter().enumerate() {
+      let velocity = Vector2::new(n as f32 - 2.0, (n * n) as f32 * 0.5);
+      world.add_planet(Point2::new(0.0, 0.0), Some(velocity), None, *radius, None);
+    }
+    // Laid out so neighbours overlap by a pixel and nothing else touches
+    let mut ids: Vec<usize> = world.planets.keys().copied().collect();
+    ids.sort_unstable();
+    let mut x = 0.0;
+    for pair in ids.windows(2) {
+      let (r1, r2) = (world.planets[&pair[0]].borrow().radius, world.planets[&pair[1]].borrow().radius);
+      x += r1 + r2 - 1.0;
+      world.planets[&pair[1]].borrow_mut().position.x = x;
+    }
+    let total_mass: f32 = world.planets.values().map(|pl| pl.borrow().mass).sum();
+    let before = momentum(&world);
+
+    world.step(&Duration::from_millis(16));
+
+    assert_eq!(world.planets.len(), 1);
+    let merged = world.planets.values().next().unwrap().borrow();
+    assert!((merged.mass - total_mass).abs() <= 1e-5 * total_mass);
+    assert!((momentum(&world) - before).norm() <= 1e-3 * before.norm());
+  }
+}
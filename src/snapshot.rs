@@ -0,0 +1,110 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use orbits::simulation::World;
+
+use crate::save::SaveState;
+
+pub const SNAPSHOT_DIR: &str = "./orbits_snapshots";
+pub const DEFAULT_AUTOSAVE_INTERVAL: f32 = 60.0;   // Seconds of real time
+pub const DEFAULT_SNAPSHOT_SLOTS: usize = 3;
+
+const RUNNING_MARKER: &str = "running";   // Exists while the app is open, so a crash leaves it behind
+
+// Writes the world to a rotating set of files (snapshot_0.ron, snapshot_1.ron, ...) every so often, overwriting
+// the oldest. A marker file is kept in the same directory while running and removed on a clean exit, so if it is
+// still there at startup the last run crashed and the newest snapshot can be offered back.
+pub struct Snapshots {
+  dir: PathBuf,
+  interval: Option<Duration>,   // None turns autosaving off
+  slots: usize,
+  next_slot: usize,
+  last_write: Instant,
+}
+
+impl Snapshots {
+  // interval is in seconds, 0 or less turns autosaving off
+  pub fn new(dir: impl Into<PathBuf>, interval: f32, slots: usize) -> Self {
+    let dir = dir.into();
+    let slots = slots.max(1);
+    // Start after the newest snapshot, so it survives until it has been offered for recovery
+    let next_slot = Self::newest_slot(&dir, slots).map_or(0, |slot| (slot + 1) % slots);
+    Self {
+      dir,
+      interval: if interval > 0.0 { Some(Duration::from_secs_f32(interval)) } else { None },
+      slots,
+      next_slot,
+      last_write: Instant::now(),
+    }
+  }
+
+  fn slot_path(dir: &Path, slot: usize) -> PathBuf {
+    dir.join(format!("snapshot_{}.ron", slot))
+  }
+
+  // Most recently written slot, by modification time
+  fn newest_slot(dir: &Path, slots: usize) -> Option<usize> {
+    (0..slots)
+      .filter_map(|slot| {
+        let modified = fs::metadata(Self::slot_path(dir, slot)).and_then(|m| m.modified()).ok()?;
+        Some((slot, modified))
+      })
+      .max_by_key(|(_, modified)| *modified)
+      .map(|(slot, _)| slot)
+  }
+
+  pub fn newest(&self) -> Option<PathBuf> {
+    Self::newest_slot(&self.dir, self.slots).map(|slot| Self::slot_path(&self.dir, slot))
+  }
+
+  // The snapshot to offer back if the last run didn't exit cleanly. Marks this run as running either way.
+  pub fn start(&self) -> Option<PathBuf> {
+    let marker = self.dir.join(RUNNING_MARKER);
+    let crashed = marker.exists();
+    if let Err(e) = fs::create_dir_all(&self.dir).and_then(|_| fs::write(&marker, "")) {
+      println!("WARNING: Could not write {}: {}", marker.display(), e);
+    }
+    if crashed { self.newest() } else { None }
+  }
+
+  // Call on a clean exit
+  pub fn finish(&self) {
+    match fs::remove_file(self.dir.join(RUNNING_MARKER)) {
+      Ok(()) => (),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+      Err(e) => println!("WARNING: Could not remove {}: {}", self.dir.join(RUNNING_MARKER).display(), e),
+    }
+  }
+
+  pub fn due(&self) -> bool {
+    self.interval.map_or(false, |interval| self.last_write.elapsed() >= interval)
+  }
+
+  // Writes the world to the next slot
+  pub fn write(&mut self, world: &World) -> io::Result<PathBuf> {
+    self.last_write = Instant::now();
+    fs::create_dir_all(&self.dir)?;
+    let path = Self::slot_path(&self.dir, self.next_slot);
+    SaveState { world }.write_to_file(&path.to_string_lossy())?;
+    self.next_slot = (self.next_slot + 1) % self.slots;
+    Ok(path)
+  }
+}
+
+// How long ago a file was written, for the recovery prompt
+pub fn age(path: &Path) -> Option<Duration> {
+  let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+  SystemTime::now().duration_since(modified).ok()
+}
+
+// Points at the snapshots when the app panics, on top of the usual message. The running marker is left behind,
+// so the next start offers to restore.
+pub fn install_panic_hook() {
+  let default_hook = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    default_hook(info);
+    eprintln!("orbits crashed. The latest autosave is in {} and will be offered back on the next start.", SNAPSHOT_DIR);
+  }));
+}
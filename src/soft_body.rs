@@ -0,0 +1,131 @@
+use nalgebra::{Vector2, Point2};
+use serde::{Serialize, Deserialize};
+
+use std::f32::consts::PI;
+
+use crate::planet::Planet;
+use crate::tools;
+
+const BOND_RANGE: f32 = 1.1;            // Particles closer than this many lattice spacings are bonded
+const MIN_PARTICLE_RADIUS: f32 = 0.3;   // Bodies that would split into smaller particles than this merge instead
+
+// Experimental: when a body is hit it is swapped for a clump of small particles on a hexagonal lattice, each bonded
+// to its neighbours by a damped spring. Collisions then squash and splash rather than merging straight away, and
+// bonds stretched too far snap, so hard enough impacts break clumps apart.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoftBodyConfig {
+  pub particles_per_body: usize,  // Roughly, the lattice fills the body's disc as closely as it can
+  pub stiffness: f32,     // Spring constant per unit reduced mass, 1/s^2. Bonds oscillate at sqrt(stiffness) rad/s.
+  pub damping: f32,       // Per unit reduced mass, 1/s
+  pub break_stretch: f32, // Bonds snap when stretched past this multiple of their rest length
+  pub restitution: f32,   // Of particles bouncing off each other
+}
+
+impl Default for SoftBodyConfig {
+  fn default() -> Self {
+    Self {
+      particles_per_body: 40,
+      stiffness: 400.0,
+      damping: 4.0,
+      break_stretch: 1.5,
+      restitution: 0.2,
+    }
+  }
+}
+
+// A spring between two particles, by id
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Bond {
+  pub a: usize,
+  pub b: usize,
+  pub rest_length: f32,
+}
+
+// Distance between neighbouring particles when a body of this radius is split into about count of them.
+// A hexagonal lattice with spacing d has (√3/2)d² of area per point, so d = R sqrt(2π/(√3 N)).
+pub fn lattice_spacing(radius: f32, count: usize) -> f32 {
+  radius * (2.0 * PI/(3f32.sqrt() * count.max(1) as f32)).sqrt()
+}
+
+// Whether a body can be split into particles, rather than merging as usual
+pub fn can_split(planet: &Planet, config: &SoftBodyConfig) -> bool {
+  !planet.is_static && config.particles_per_body >= 2 &&
+    lattice_spacing(planet.radius, config.particles_per_body)/2.0 >= MIN_PARTICLE_RADIUS
+}
+
+// The particles a body breaks into, sharing its mass equally and moving with it (including its spin), and the
+// lattice spacing between them. Ids are left at 0 for the world to fill in. None if there would be fewer than two.
+pub fn split(planet: &Planet, config: &SoftBodyConfig) -> Option<(Vec<Planet>, f32)> {
+  if !can_split(planet, config) { return None }
+
+  let spacing = lattice_spacing(planet.radius, config.particles_per_body);
+  let particle_radius = spacing/2.0;
+  let row_height = spacing * 3f32.sqrt()/2.0;
+  let reach = (planet.radius/row_height).ceil() as i32 + 1;
+  let max_distance = planet.radius - particle_radius;
+
+  let offsets: Vec<Vector2<f32>> = (-reach..=reach)
+    .flat_map(|j| (-reach..=reach).map(move |i| (i, j)))
+    .map(|(i, j)| Vector2::new((i as f32 + j as f32/2.0) * spacing, j as f32 * row_height))
+    .filter(|offset| offset.magnitude() <= max_distance + spacing * 1e-3)
+    .collect();
+  if offsets.len() < 2 { return None }
+
+  let mass = planet.mass/offsets.len() as f32;
+  let particles = offsets.into_iter()
+    .map(|offset| {
+      // Positive spin is clockwise on screen, where y points down
+      let spin_velocity = Vector2::new(-offset.y, offset.x) * planet.angular_velocity;
+      let mut particle = Planet::new_with_kind(
+        planet.kind,
+        0,
+        planet.position + offset,
+        Some(planet.velocity + spin_velocity),
+        Some(mass),
+        particle_radius,
+        None,
+      );
+      particle.color = planet.color;
+      particle.particle = true;
+      particle
+    })
+    .collect();
+  Some((particles, spacing))
+}
+
+// Bonds between every pair of particles that are lattice neighbours
+pub fn bond_neighbours(particles: &[(usize, Point2<f32>)], spacing: f32) -> Vec<Bond> {
+  let range = spacing * BOND_RANGE;
+  let mut bonds = Vec::new();
+  for (k, (a, position_a)) in particles.iter().enumerate() {
+    for (b, position_b) in particles[k + 1..].iter() {
+      let rest_length = (position_b - position_a).magnitude();
+      if rest_length <= range {
+        bonds.push(Bond { a: *a, b: *b, rest_length });
+      }
+    }
+  }
+  bonds
+}
+
+// Applies a bond's spring and damping to the two particles' velocities over dt, equally and oppositely.
+// F = μ(k (d - L) + c v_sep), where μ is the reduced mass and v_sep how fast they are moving apart.
+// Returns false if the bond is stretched too far and snaps.
+pub fn pull(a: &mut Planet, b: &mut Planet, rest_length: f32, config: &SoftBodyConfig, dt: f32, wrap_bounds: Option<(f32, f32)>) -> bool {
+  let dist_vec = tools::minimum_image(b.position - a.position, wrap_bounds);
+  let dist = dist_vec.magnitude();
+  if dist > rest_length * config.break_stretch { return false }
+  if dist <= 0.0 { return true }
+
+  let (inv_mass_a, inv_mass_b) = (a.inverse_mass(), b.inverse_mass());
+  let inv_mass_sum = inv_mass_a + inv_mass_b;
+  if inv_mass_sum <= 0.0 { return true }
+
+  let normal = dist_vec/dist;
+  let separating_speed = (b.velocity - a.velocity).dot(&normal);
+  let force = (config.stiffness * (dist - rest_length) + config.damping * separating_speed)/inv_mass_sum;
+  a.velocity += normal * (force * inv_mass_a * dt);
+  b.velocity -= normal * (force * inv_mass_b * dt);
+  true
+}
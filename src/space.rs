@@ -0,0 +1,163 @@
+use nalgebra::{Point2, Point3, Rotation3, Vector3};
+use rayon::prelude::*;
+
+use crate::config::SimConfig;
+use crate::integrator::Integrator;
+use crate::planet::BodyKind;
+use crate::tools;
+
+// A simpler world in three dimensions, for inclined orbits that can't happen in the plane.
+// Forces are summed directly (no octree) and touching bodies always merge, so it's meant for small systems.
+// Uses the same config and integrators as the 2D World.
+
+#[derive(Clone, Debug)]
+pub struct Body3 {
+  pub id: usize,
+  pub position: Point3<f32>,
+  pub velocity: Vector3<f32>,
+  pub mass: f32,
+  pub radius: f32,
+  pub kind: BodyKind,
+}
+
+impl Body3 {
+  pub fn color(&self) -> [f32; 4] {
+    self.kind.base_color()
+  }
+
+  // Orthographic projection after rotating by `view` about `centre`. Gives the position on the
+  // projection plane and the depth, which is larger further from the viewer.
+  pub fn project(&self, view: &Rotation3<f32>, centre: Point3<f32>) -> (Point2<f32>, f32) {
+    let rotated = view * (self.position - centre);
+    (Point2::new(centre.x + rotated.x, centre.y + rotated.y), rotated.z)
+  }
+}
+
+pub struct World3 {
+  pub bodies: Vec<Body3>,
+  pub config: SimConfig,
+  pub integrator: Integrator,
+  body_id_count: usize,
+}
+
+impl World3 {
+  pub fn new() -> Self {
+    Self {
+      bodies: Vec::new(),
+      config: SimConfig::default(),
+      integrator: Integrator::default(),
+      body_id_count: 0,
+    }
+  }
+
+  pub fn add_body(&mut self, kind: BodyKind, position: Point3<f32>, velocity: Vector3<f32>, radius: f32) -> usize {
+    let id = self.body_id_count;
+    self.body_id_count += 1;
+    self.bodies.push(Body3 {
+      id,
+      position,
+      velocity,
+      mass: tools::volume_of_sphere(radius) * kind.density(),
+      radius,
+      kind,
+    });
+    id
+  }
+
+  pub fn clear(&mut self) {
+    self.bodies.clear();
+  }
+
+  pub fn step(&mut self, dt: f32) {
+    self.merge_touching();
+
+    let mut positions: Vec<Point3<f32>> = self.bodies.iter().map(|b| b.position).collect();
+    let mut velocities: Vec<Vector3<f32>> = self.bodies.iter().map(|b| b.velocity).collect();
+    let masses: Vec<f32> = self.bodies.iter().map(|b| b.mass).collect();
+    let (g, softening) = (self.config.g, self.config.softening);
+
+    self.integrator.step(&mut positions, &mut velocities, dt, |positions| accelerations(positions, &masses, g, softening));
+
+    for (body, (position, velocity)) in self.bodies.iter_mut().zip(positions.into_iter().zip(velocities)) {
+      body.position = position;
+      body.velocity = velocity;
+    }
+  }
+
+  // Overlapping bodies become one, conserving mass, momentum and volume
+  fn merge_touching(&mut self) {
+    let mut i = 0;
+    while i < self.bodies.len() {
+      let mut j = i + 1;
+      while j < self.bodies.len() {
+        let (a, b) = (&self.bodies[i], &self.bodies[j]);
+        if (a.position - b.position).magnitude_squared() < (a.radius + b.radius).powi(2) {
+          let other = self.bodies.swap_remove(j);
+          let body = &mut self.bodies[i];
+          let total_mass = body.mass + other.mass;
+          body.position = Point3::from((body.position.coords * body.mass + other.position.coords * other.mass)/total_mass);
+          body.velocity = (body.velocity * body.mass + other.velocity * other.mass)/total_mass;
+          body.radius = tools::inverse_volume_of_sphere(tools::volume_of_sphere(body.radius) + tools::volume_of_sphere(other.radius));
+          body.kind = body.kind.merged_with(other.kind);
+          body.mass = total_mass;
+          // Check the merged body against everything again
+          j = i + 1;
+        } else {
+          j += 1;
+        }
+      }
+      i += 1;
+    }
+  }
+
+  pub fn total_mass(&self) -> f32 {
+    self.bodies.iter().map(|b| b.mass).sum()
+  }
+
+  pub fn centre_of_mass(&self) -> Option<Point3<f32>> {
+    let total_mass = self.total_mass();
+    if total_mass <= 0.0 { return None }
+    let weighted: Vector3<f32> = self.bodies.iter().map(|b| b.position.coords * b.mass).sum();
+    Some(Point3::from(weighted/total_mass))
+  }
+
+  // A star with planets on orbits tilted out of the plane by different amounts, around `centre`
+  pub fn inclined_orbits(&mut self, centre: Point3<f32>) {
+    const STAR_RADIUS: f32 = 25.0;
+    // (orbit radius, body radius, inclination in degrees, longitude of the ascending node in degrees)
+    const ORBITS: [(f32, f32, f32, f32); 5] = [
+      (90.0, 3.0, 0.0, 0.0),
+      (150.0, 5.0, 15.0, 40.0),
+      (220.0, 6.0, 35.0, 120.0),
+      (300.0, 4.0, 60.0, 200.0),
+      (380.0, 7.0, 85.0, 300.0),
+    ];
+
+    self.add_body(BodyKind::Star, centre, Vector3::zeros(), STAR_RADIUS);
+    let star_mass = self.bodies.last().map(|b| b.mass).unwrap_or(0.0);
+    for (orbit_radius, radius, inclination, node) in ORBITS {
+      // Circular orbit in the xy plane, tilted about the x axis then turned about z
+      let tilt = Rotation3::from_axis_angle(&Vector3::z_axis(), node.to_radians())
+        * Rotation3::from_axis_angle(&Vector3::x_axis(), inclination.to_radians());
+      let speed = (self.config.g * star_mass/orbit_radius).sqrt();
+      let offset = tilt * Vector3::new(orbit_radius, 0.0, 0.0);
+      let velocity = tilt * Vector3::new(0.0, speed, 0.0);
+      self.add_body(BodyKind::Planet, centre + offset, velocity, radius);
+    }
+  }
+}
+
+// Direct sum over every pair, one body per thread
+fn accelerations(positions: &[Point3<f32>], masses: &[f32], g: f32, softening: f32) -> Vec<Vector3<f32>> {
+  positions.par_iter().enumerate()
+    .map(|(i, position)| {
+      positions.iter().zip(masses.iter()).enumerate()
+        .filter(|(j, _)| *j != i)
+        .map(|(_, (other, mass))| {
+          let dist_vec = other - position;
+          tools::grav_force(g, softening, 1.0, *mass, dist_vec.magnitude_squared(), dist_vec)
+        })
+        .sum()
+    })
+    .collect()
+}
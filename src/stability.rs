@@ -0,0 +1,147 @@
+use std::fmt;
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::simulation::World;
+use crate::events::EventKind;
+
+const EJECTION_DISTANCE: f32 = 10.0;    // Unbound bodies this many starting distances from the primary count as ejected
+const MAX_STEPS: usize = 200_000;       // Whatever the orbit count, so a slow orbit can't run forever
+
+// How far ahead to look, see analyse
+#[derive(Clone, Copy, Debug)]
+pub struct StabilityConfig {
+  pub orbits: f32,        // Of the body's starting osculating orbit
+  pub dt: f32,            // Step length, in sim seconds
+  pub open_orbit_time: f32,   // Sim seconds to run for if the body isn't on a closed orbit to begin with
+}
+
+impl Default for StabilityConfig {
+  fn default() -> Self {
+    Self {
+      orbits: 10.0,
+      dt: 1.0/120.0,
+      open_orbit_time: 60.0,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Verdict {
+  Bound,                          // Still orbiting the primary at the end
+  Collided { with: usize, time: f32 },   // Merged or broke up, time is sim seconds from the start of the analysis
+  Ejected { time: f32 },          // Escaped the primary, or left the system
+  PrimaryLost { time: f32 },      // The primary merged into something else, so there's nothing to measure against
+  NoPrimary,                      // Nothing heavier to orbit
+}
+
+impl fmt::Display for Verdict {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Verdict::Bound => write!(f, "Stays bound"),
+      Verdict::Collided { with, time } => write!(f, "Collides with body {} after {:.1}s", with, time),
+      Verdict::Ejected { time } => write!(f, "Ejected after {:.1}s", time),
+      Verdict::PrimaryLost { time } => write!(f, "Primary lost after {:.1}s", time),
+      Verdict::NoPrimary => write!(f, "Nothing heavier to orbit"),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StabilityReport {
+  pub id: usize,
+  pub primary: Option<usize>,
+  pub verdict: Verdict,
+  pub min_distance: f32,    // From the primary, centre to centre
+  pub max_distance: f32,
+  pub simulated: f32,       // Sim seconds run
+}
+
+// Runs a copy of the world forward to see what becomes of body id: whether it stays bound to its dominant attractor
+// (the primary) for config.orbits orbits, collides with something or is ejected. Meant to be run off the main thread,
+// on a copy taken with World::clone, as it can take a while. Scenario ticks aren't included.
+// Gives up and returns None once cancel is set, checked every step.
+pub fn analyse(mut world: World, id: usize, config: &StabilityConfig, cancel: &AtomicBool) -> Option<StabilityReport> {
+  world.use_gpu = false;    // The GPU belongs to the main thread
+  world.take_events();
+  world.take_impacts();
+
+  let mut report = StabilityReport {
+    id,
+    primary: None,
+    verdict: Verdict::NoPrimary,
+    min_distance: f32::INFINITY,
+    max_distance: 0.0,
+    simulated: 0.0,
+  };
+  let primary = match world.dominant_attractor(id) {
+    Some(primary) => primary,
+    None => return Some(report),
+  };
+  report.primary = Some(primary);
+  // Radial and degenerate paths have no elements, but can still be followed for open_orbit_time
+  let period = world.osculating_orbit(id).and_then(|(_, elements)| elements.period);
+
+  let duration = period.map_or(config.open_orbit_time, |period| period * config.orbits);
+  let steps = ((duration/config.dt).ceil() as usize).min(MAX_STEPS);
+  let dt = Duration::from_secs_f32(config.dt);
+  let start_distance = distance(&world, id, primary).unwrap_or(0.0);
+
+  report.verdict = Verdict::Bound;
+  for _ in 0..steps {
+    if cancel.load(Ordering::Relaxed) { return None }
+    world.step(&dt);
+    report.simulated += config.dt;
+
+    for event in world.take_events() {
+      let verdict = match event.kind {
+        EventKind::Merged { into, absorbed, .. } if absorbed == id => Some(Verdict::Collided { with: into, time: report.simulated }),
+        EventKind::Merged { into, absorbed, .. } if into == id => Some(Verdict::Collided { with: absorbed, time: report.simulated }),
+        EventKind::Merged { absorbed, .. } if absorbed == primary => Some(Verdict::PrimaryLost { time: report.simulated }),
+        EventKind::Fragmented { id: broken, .. } | EventKind::TidallyDisrupted { id: broken, .. } | EventKind::Softened { id: broken, .. }
+          if broken == id => Some(Verdict::Collided { with: primary, time: report.simulated }),
+        EventKind::Despawned { id: gone, .. } if gone == id => Some(Verdict::Ejected { time: report.simulated }),
+        EventKind::Despawned { id: gone, .. } | EventKind::Removed { id: gone } if gone == primary =>
+          Some(Verdict::PrimaryLost { time: report.simulated }),
+        _ => None,
+      };
+      if let Some(verdict) = verdict {
+        report.verdict = verdict;
+        return Some(report)
+      }
+    }
+
+    let d = match distance(&world, id, primary) {
+      Some(d) => d,
+      None => {
+        report.verdict = Verdict::PrimaryLost { time: report.simulated };
+        return Some(report)
+      },
+    };
+    report.min_distance = report.min_distance.min(d);
+    report.max_distance = report.max_distance.max(d);
+
+    if d > start_distance * EJECTION_DISTANCE && !is_bound(&world, id, primary) {
+      report.verdict = Verdict::Ejected { time: report.simulated };
+      return Some(report)
+    }
+  }
+  Some(report)
+}
+
+fn distance(world: &World, id: usize, primary: usize) -> Option<f32> {
+  let planet = world.planets.get(&id)?.borrow();
+  let host = world.planets.get(&primary)?.borrow();
+  Some((planet.position - host.position).magnitude())
+}
+
+// Two-body energy relative to the primary is negative: v²/2 - G(M + m)/r < 0
+fn is_bound(world: &World, id: usize, primary: usize) -> bool {
+  let (planet, host) = match (world.planets.get(&id), world.planets.get(&primary)) {
+    (Some(planet), Some(host)) => (planet.borrow(), host.borrow()),
+    _ => return false,
+  };
+  let r = (planet.position - host.position).magnitude();
+  let v = planet.velocity - host.velocity;
+  v.magnitude_squared()/2.0 - world.config.g * (host.mass + planet.mass)/r < 0.0
+}
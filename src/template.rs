@@ -0,0 +1,45 @@
+use serde::{Serialize, Deserialize};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use orbits::planet::BodyKind;
+use orbits::config::TrailConfig;
+use orbits::tools;
+
+pub const TEMPLATE_FILE: &str = "templates.ron";    // In the resources directory
+
+// A kind of body to spawn, picked from the palette instead of setting kind, size and density by hand
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Template {
+  pub name: String,
+  pub kind: BodyKind,
+  pub mass: f32,
+  pub density: f32,
+  #[serde(default)]
+  pub color: Option<[f32; 4]>,    // None for the config's colour for the kind
+  #[serde(default)]
+  pub trail: Option<TrailConfig>, // None for the default trail
+}
+
+impl Template {
+  pub fn radius(&self) -> f32 {
+    tools::inverse_volume_of_sphere(self.mass/self.density)
+  }
+}
+
+// Templates with a zero or negative mass or density are left out, with a warning
+pub fn load(path: &Path) -> io::Result<Vec<Template>> {
+  let data = fs::read_to_string(path)?;
+  let templates: Vec<Template> = ron::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+  Ok(templates.into_iter()
+    .filter(|template| {
+      let valid = template.mass > 0.0 && template.density > 0.0;
+      if !valid {
+        println!("WARNING: Template \"{}\" needs a mass and density above zero, skipping it", template.name);
+      }
+      valid
+    })
+    .collect())
+}
@@ -1,10 +1,7 @@
-use ggez::graphics::{self, Mesh, Canvas, DrawMode, DrawParam};
-use ggez::{Context, GameResult};
-
-use nalgebra::{Vector2, Point2};
+use nalgebra::{Vector2, Point2, SVector};
 
 use std::f32::consts::PI;
-use crate::{G, planet::Planet};
+use crate::planet::Planet;
 
 pub fn volume_of_sphere(radius: f32) -> f32 {
   (4.0/3.0) * PI * radius.powi(3)
@@ -14,6 +11,53 @@ pub fn inverse_volume_of_sphere(volume: f32) -> f32 {
   ((3.0 * volume)/(4.0 * PI)).powf(1.0/3.0)
 }
 
+pub fn area_of_circle(radius: f32) -> f32 {
+  PI * radius.powi(2)
+}
+
+pub fn inverse_area_of_circle(area: f32) -> f32 {
+  (area/PI).sqrt()
+}
+
+// Mass weighted average of two colours, for merged bodies
+pub fn blend_colors(c1: [f32; 4], m1: f32, c2: [f32; 4], m2: f32) -> [f32; 4] {
+  let total = m1 + m2;
+  if total <= 0.0 { return c1 }
+  let mut blended = [0.0; 4];
+  for i in 0..4 {
+    blended[i] = (c1[i] * m1 + c2[i] * m2)/total;
+  }
+  blended
+}
+
+// Centre of mass and its velocity, or None if there is no mass
+pub fn barycenter<'a, I: IntoIterator<Item = &'a Planet>>(planets: I) -> Option<(Point2<f32>, Vector2<f32>)> {
+  let mut total_mass = 0.0;
+  let mut weighted_position = Vector2::new(0.0, 0.0);
+  let mut momentum = Vector2::new(0.0, 0.0);
+  for pl in planets {
+    total_mass += pl.mass;
+    weighted_position += pl.position.coords * pl.mass;
+    momentum += pl.velocity * pl.mass;
+  }
+
+  if total_mass > 0.0 {
+    Some((Point2::from(weighted_position/total_mass), momentum/total_mass))
+  } else {
+    None
+  }
+}
+
+// Solid sphere about its centre: I = (2/5) m r^2
+pub fn moment_of_inertia(mass: f32, radius: f32) -> f32 {
+  0.4 * mass * radius * radius
+}
+
+// z component of the 2D cross product a × b
+pub fn cross(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+  a.x * b.y - a.y * b.x
+}
+
 pub fn get_angle(vec: Vector2<f32>) -> f32 {
   vec.y.atan2(vec.x)
 }
@@ -25,19 +69,302 @@ pub fn get_components(magnitude: f32, angle: f32) -> Vector2<f32> {
 // F = (GMm/|r|^2) * r_norm
 //   = (GMm/|r|^2) * r * 1/|r|
 //   = (GMm/|r|^3) * r
-pub fn newtonian_grav(pl1: &mut Planet, pl2: &mut Planet, dist_squared: f32, dist_vec: Vector2<f32>) {
-  let force_vec = dist_vec * (G * pl1.mass * pl2.mass/dist_squared.sqrt().powi(3));
+// Softened by ε: F = GMm * r/(|r|^2 + ε^2)^(3/2), which stops close encounters flinging bodies away
+pub fn newtonian_grav(g: f32, softening: f32, pl1: &mut Planet, pl2: &mut Planet, dist_squared: f32, dist_vec: Vector2<f32>) {
+  let force_vec = grav_force(g, softening, pl1.mass, pl2.mass, dist_squared, dist_vec);
 
   pl1.resultant_force += force_vec;
   pl2.resultant_force -= force_vec;
 }
 
+// Force on body 1 due to body 2, where dist_vec points from body 1 to body 2. In 2D or 3D.
+pub fn grav_force<const D: usize>(g: f32, softening: f32, m1: f32, m2: f32, dist_squared: f32, dist_vec: SVector<f32, D>) -> SVector<f32, D> {
+  dist_vec * (g * m1 * m2/(dist_squared + softening * softening).sqrt().powi(3))
+}
+
+// Shortest vector between two points in a world that wraps around at bounds (minimum image convention).
+// Unchanged if the world doesn't wrap.
+pub fn minimum_image(dist_vec: Vector2<f32>, bounds: Option<(f32, f32)>) -> Vector2<f32> {
+  match bounds {
+    Some((width, height)) => Vector2::new(
+      dist_vec.x - width * (dist_vec.x/width).round(),
+      dist_vec.y - height * (dist_vec.y/height).round(),
+    ),
+    None => dist_vec,
+  }
+}
+
+// Potential per unit mass at point, -Σ GM/sqrt(r^2 + ε^2), and the field (acceleration of a test mass) there,
+// due to bodies given as (position, mass)
+pub fn sample_field(g: f32, softening: f32, bodies: &[(Point2<f32>, f32)], point: Point2<f32>, wrap_bounds: Option<(f32, f32)>) -> (f32, Vector2<f32>) {
+  let softening_squared = softening * softening;
+  bodies.iter()
+    .fold((0.0, Vector2::new(0.0, 0.0)), |(potential, field), (position, mass)| {
+      let dist_vec = minimum_image(position - point, wrap_bounds);
+      let dist_squared = dist_vec.magnitude_squared() + softening_squared;
+      if dist_squared <= 0.0 { return (potential, field) }
+      let dist = dist_squared.sqrt();
+      (potential - g * mass/dist, field + dist_vec * (g * mass/(dist_squared * dist)))
+    })
+}
+
+// AABB then circle collision
+pub fn check_collision(pl1: &Planet, pl2: &Planet) -> bool {
+  let dist_vec = pl2.position - pl1.position;
+  let min_dist = pl1.radius + pl2.radius;
+  dist_vec.x.abs() <= min_dist && dist_vec.y.abs() <= min_dist &&
+    dist_vec.x.powi(2) + dist_vec.y.powi(2) <= min_dist.powi(2)
+}
+
+// Impulse-based collision response for two touching planets.
+// j = -(1 + e)(v_rel . n)/(1/m1 + 1/m2), applied along the collision normal n.
+// Planets are also pushed apart (weighted by inverse mass) so they don't stay overlapping.
+pub fn bounce(pl1: &mut Planet, pl2: &mut Planet, restitution: f32) {
+  let dist_vec = pl2.position - pl1.position;
+  let dist = dist_vec.magnitude();
+  if dist <= 0.0 { return }
+
+  let normal = dist_vec/dist;
+  let (inv_mass1, inv_mass2) = (pl1.inverse_mass(), pl2.inverse_mass());
+  let inv_mass_sum = inv_mass1 + inv_mass2;
+  if inv_mass_sum <= 0.0 { return }   // Both static
+
+  let approach_speed = (pl2.velocity - pl1.velocity).dot(&normal);
+  if approach_speed < 0.0 {   // Only if moving towards each other
+    let impulse = -(1.0 + restitution) * approach_speed/inv_mass_sum;
+    pl1.velocity -= normal * (impulse * inv_mass1);
+    pl2.velocity += normal * (impulse * inv_mass2);
+  }
+
+  let overlap = pl1.radius + pl2.radius - dist;
+  if overlap > 0.0 {
+    let correction = normal * (overlap/inv_mass_sum);
+    pl1.position -= correction * inv_mass1;
+    pl2.position += correction * inv_mass2;
+  }
+}
+
+// Angle between the relative velocity and the line between the centres: 0 for head on, PI/2 for a glancing touch
+pub fn impact_obliquity(pl1: &Planet, pl2: &Planet) -> f32 {
+  let dist_vec = pl2.position - pl1.position;
+  let relative_velocity = pl1.velocity - pl2.velocity;
+  if dist_vec.magnitude_squared() <= 0.0 || relative_velocity.magnitude_squared() <= 0.0 { return 0.0 }
+  dist_vec.angle(&relative_velocity)
+}
+
+// Grazing impact where the lighter body loses `fraction` of its mass to the heavier one, then both bounce apart.
+// The transferred mass keeps its momentum, and the bounce conserves momentum too. Each body keeps its density.
+pub fn transfer_mass(pl1: &mut Planet, pl2: &mut Planet, fraction: f32, restitution: f32) -> f32 {
+  let (big, small) = if pl1.mass >= pl2.mass { (&mut *pl1, &mut *pl2) } else { (&mut *pl2, &mut *pl1) };
+  let transferred = small.mass * fraction;
+
+  big.velocity = (big.velocity * big.mass + small.velocity * transferred)/(big.mass + transferred);
+  big.color = blend_colors(big.color, big.mass, small.color, transferred);
+  big.set_mass(big.mass + transferred);
+  small.set_mass(small.mass - transferred);
+
+  bounce(pl1, pl2, restitution);
+  transferred
+}
+
+// Impact energy per unit mass of the smaller planet.
+// Q = (1/2) μ v^2 / m_small, where μ = m1 m2/(m1 + m2) is the reduced mass and v the relative speed.
+pub fn specific_impact_energy(pl1: &Planet, pl2: &Planet) -> f32 {
+  let reduced_mass = pl1.mass * pl2.mass/(pl1.mass + pl2.mass);
+  0.5 * reduced_mass * (pl2.velocity - pl1.velocity).magnitude_squared()/pl1.mass.min(pl2.mass)
+}
+
 // Returns the magnitude of the velocity (speed) needed for a circular orbit around another planet
 // Orbit is circular when the kinetic energy does not change.
 // K = GMm/2r  -- Derived from centripetal force (in circular motion) = gravitational force
 // GMm/2r = 1/2 mv^2
 // GM/2r = 1/2 v^2
 // sqrt(GM/r) = v
-pub fn circular_orbit_speed(host_mass: f32, radius: f32) -> f32 {
-  (G * host_mass/radius).sqrt()
-}
\ No newline at end of file
+pub fn circular_orbit_speed(g: f32, host_mass: f32, radius: f32) -> f32 {
+  (g * host_mass/radius).sqrt()
+}
+
+// Speeds of the two bodies of a circular binary about their barycentre, a distance d apart.
+// Their relative speed is the two-body circular orbit speed, v = sqrt(G(m1 + m2)/d), and each body's share is
+// set by the barycentre staying put: m1 v1 = m2 v2, so v1 = v m2/(m1 + m2) and v2 = v m1/(m1 + m2).
+pub fn binary_orbit_speeds(g: f32, m1: f32, m2: f32, separation: f32) -> (f32, f32) {
+  let total = m1 + m2;
+  let relative_speed = circular_orbit_speed(g, total, separation);
+  (relative_speed * m2/total, relative_speed * m1/total)
+}
+
+// Speed needed to escape to infinity from a distance r, where kinetic energy cancels potential energy:
+// 1/2 v^2 = GM/r
+// v = sqrt(2GM/r)
+pub fn escape_velocity(g: f32, host_mass: f32, radius: f32) -> f32 {
+  (2.0 * g * host_mass/radius).sqrt()
+}
+
+// Radius within which a body's gravity beats the tidal pull of its host: r_H = d * cbrt(m/3M).
+// d is the distance to the host, usually taken at periapsis.
+pub fn hill_radius(distance: f32, mass: f32, host_mass: f32) -> f32 {
+  distance * (mass/(3.0 * host_mass)).cbrt()
+}
+
+// Distance from a host's centre inside which its tides pull apart a rigid satellite held together only by its own gravity:
+// d = R_M * cbrt(2 ρ_M/ρ_m)
+pub fn roche_limit(host_radius: f32, host_density: f32, satellite_density: f32) -> f32 {
+  host_radius * (2.0 * host_density/satellite_density).cbrt()
+}
+
+// Laplace sphere of influence: r_SOI = d * (m/M)^(2/5). Inside it, orbits are better described as around the body than its host.
+pub fn sphere_of_influence(distance: f32, mass: f32, host_mass: f32) -> f32 {
+  distance * (mass/host_mass).powf(0.4)
+}
+// Two-body orbital elements of a body relative to an attractor, in the plane of the simulation
+#[derive(Clone, Copy, Debug)]
+pub struct OrbitalElements {
+  pub eccentricity: f32,
+  pub semi_major_axis: f32,           // Negative for hyperbolic orbits
+  pub semi_latus_rectum: f32,         // Orbit radius is p/(1 + e cos(true anomaly))
+  pub argument_of_periapsis: f32,     // Angle of periapsis from the x axis
+  pub periapsis: f32,                 // Closest distance to the attractor
+  pub apoapsis: Option<f32>,          // Furthest distance, None if the orbit is open
+  pub true_anomaly: f32,              // Angle of the body past periapsis, in the direction it orbits. Negative on the way in.
+  pub period: Option<f32>,            // Sim seconds per orbit, None if the orbit is open
+  pub time_to_periapsis: Option<f32>, // None if the orbit is open and the body is already past periapsis
+}
+
+// Osculating elements from relative position r and velocity v, where mu = G(M + m).
+// e = ((v^2 - mu/|r|) r - (r.v) v)/mu, a = -mu/2E where E = v^2/2 - mu/|r|, p = h^2/mu
+// Returns None for radial (h = 0) paths, which have no conic to draw.
+pub fn orbital_elements(mu: f32, r: Vector2<f32>, v: Vector2<f32>) -> Option<OrbitalElements> {
+  let r_mag = r.magnitude();
+  if mu <= 0.0 || r_mag <= 0.0 { return None }
+
+  let h = r.x * v.y - r.y * v.x;
+  let semi_latus_rectum = h * h/mu;
+  if semi_latus_rectum <= f32::EPSILON { return None }
+
+  let e_vec = (r * (v.magnitude_squared() - mu/r_mag) - v * r.dot(&v))/mu;
+  let eccentricity = e_vec.magnitude();
+  let specific_energy = v.magnitude_squared()/2.0 - mu/r_mag;
+  let semi_major_axis = -mu/(2.0 * specific_energy);
+
+  // Measured from periapsis (or the x axis for circles), turning the same way as the body
+  let periapsis_direction = if eccentricity > 0.0 { e_vec/eccentricity } else { Vector2::new(1.0, 0.0) };
+  let true_anomaly = cross(periapsis_direction, r).atan2(periapsis_direction.dot(&r)) * h.signum();
+  let (period, time_to_periapsis) = orbit_timing(mu, eccentricity, semi_major_axis, true_anomaly);
+
+  Some(OrbitalElements {
+    eccentricity,
+    semi_major_axis,
+    semi_latus_rectum,
+    argument_of_periapsis: if eccentricity > 0.0 { get_angle(e_vec) } else { 0.0 },
+    periapsis: semi_latus_rectum/(1.0 + eccentricity),
+    apoapsis: if eccentricity < 1.0 { Some(semi_latus_rectum/(1.0 - eccentricity)) } else { None },
+    true_anomaly,
+    period,
+    time_to_periapsis,
+  })
+}
+
+// Period and time until the next periapsis, from Kepler's equation.
+// Ellipse: tan(E/2) = sqrt((1 - e)/(1 + e)) tan(ν/2), M = E - e sin E, n = sqrt(mu/a^3)
+// Hyperbola: tanh(F/2) = sqrt((e - 1)/(e + 1)) tan(ν/2), M = e sinh F - F, n = sqrt(mu/(-a)^3)
+// Parabolic orbits (e = 1 exactly) are treated as the nearest hyperbola, which is close enough for a readout.
+fn orbit_timing(mu: f32, eccentricity: f32, semi_major_axis: f32, true_anomaly: f32) -> (Option<f32>, Option<f32>) {
+  let e = eccentricity;
+  let half_tan = (true_anomaly/2.0).tan();
+  if e < 1.0 {
+    let mean_motion = (mu/semi_major_axis.powi(3)).sqrt();
+    let eccentric_anomaly = 2.0 * (((1.0 - e)/(1.0 + e)).sqrt() * half_tan).atan();
+    let mean_anomaly = eccentric_anomaly - e * eccentric_anomaly.sin();
+    let period = 2.0 * PI/mean_motion;
+    let time_to_periapsis = if mean_anomaly <= 0.0 { -mean_anomaly/mean_motion } else { period - mean_anomaly/mean_motion };
+    (Some(period), Some(time_to_periapsis))
+  } else {
+    let mean_motion = (mu/(-semi_major_axis).powi(3)).sqrt();
+    let hyperbolic_anomaly = 2.0 * (((e - 1.0)/(e + 1.0)).sqrt() * half_tan).atanh();
+    let mean_anomaly = e * hyperbolic_anomaly.sinh() - hyperbolic_anomaly;
+    let time_to_periapsis = if mean_anomaly < 0.0 && mean_motion.is_finite() { Some(-mean_anomaly/mean_motion) } else { None };
+    (None, time_to_periapsis)
+  }
+}
+
+// Points along the conic described by the elements, relative to the attractor.
+// Open orbits are cut off at max_radius.
+pub fn orbit_path(elements: &OrbitalElements, points: usize, max_radius: f32) -> Vec<Vector2<f32>> {
+  let e = elements.eccentricity;
+  let max_anomaly = if e < 1.0 {
+    PI
+  } else {
+    // True anomaly where r = max_radius, which is always before the asymptote at acos(-1/e)
+    ((elements.semi_latus_rectum/max_radius - 1.0)/e).max(-1.0).min(1.0).acos()
+  };
+
+  (0..=points)
+    .map(|i| -max_anomaly + 2.0 * max_anomaly * i as f32/points as f32)
+    .map(|anomaly| {
+      let radius = (elements.semi_latus_rectum/(1.0 + e * anomaly.cos())).min(max_radius);
+      get_components(radius, elements.argument_of_periapsis + anomaly)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::integrator::Integrator;
+
+  const EPSILON: f32 = 1e-4;
+
+  fn close(a: f32, b: f32, tolerance: f32) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0)
+  }
+
+  #[test]
+  fn volume_round_trips() {
+    for radius in [0.5, 1.0, 8.0, 150.0, 2000.0] {
+      assert!(close(inverse_volume_of_sphere(volume_of_sphere(radius)), radius, EPSILON), "radius {}", radius);
+      assert!(close(inverse_area_of_circle(area_of_circle(radius)), radius, EPSILON), "radius {}", radius);
+    }
+  }
+
+  #[test]
+  fn gravity_obeys_newtons_third_law() {
+    let (p1, p2) = (Point2::new(10.0, -4.0), Point2::new(-35.0, 60.0));
+    let (m1, m2) = (3e6, 7e4);
+    for softening in [0.0, 5.0] {
+      let dist_vec = p2 - p1;
+      let on_1 = grav_force(0.0001, softening, m1, m2, dist_vec.norm_squared(), dist_vec);
+      let on_2 = grav_force(0.0001, softening, m2, m1, dist_vec.norm_squared(), -dist_vec);
+
+      assert!((on_1 + on_2).norm() <= EPSILON * on_1.norm());
+      assert!(on_1.dot(&dist_vec) > 0.0);   // Attractive
+    }
+  }
+
+  // A test particle launched at the circular orbit speed comes back to where it started after one period
+  #[test]
+  fn circular_orbit_closes() {
+    let (g, host_mass, radius) = (1.0, 1000.0, 100.0);
+    let speed = circular_orbit_speed(g, host_mass, radius);
+    let period = 2.0 * PI * radius/speed;
+    let steps = 4000;
+    let dt = period/steps as f32;
+
+    let start = Point2::new(radius, 0.0);
+    let mut positions = [start];
+    let mut velocities = [Vector2::new(0.0, speed)];
+    let acceleration = |positions: &[Point2<f32>]| -> Vec<Vector2<f32>> {
+      positions.iter()
+        .map(|p| {
+          let dist_vec = Point2::origin() - p;
+          grav_force(g, 0.0, 1.0, host_mass, dist_vec.norm_squared(), dist_vec)
+        })
+        .collect()
+    };
+
+    for _ in 0..steps {
+      Integrator::VelocityVerlet.step(&mut positions, &mut velocities, dt, acceleration);
+      assert!(close(positions[0].coords.norm(), radius, 0.01));
+    }
+    assert!((positions[0] - start).norm() < 0.01 * radius);
+  }
+}
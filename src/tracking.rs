@@ -0,0 +1,68 @@
+use nalgebra::{Vector2, Point2};
+
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+
+use crate::simulation::World;
+
+// State of a tracked body at one moment
+#[derive(Clone, Copy, Debug)]
+pub struct TrajectorySample {
+  pub t: f32,
+  pub id: usize,
+  pub position: Point2<f32>,
+  pub velocity: Vector2<f32>,
+}
+
+// Trajectories of the bodies with their `tracked` flag set, kept in memory until written out as CSV.
+// Good for plotting orbital decay, or checking periods and eccentricities with other tools.
+#[derive(Default)]
+pub struct TrajectoryLog {
+  samples: Vec<TrajectorySample>,
+  time: f32,    // Sim time since the log was started or cleared
+}
+
+impl TrajectoryLog {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // Call after every physics step of dt
+  pub fn record(&mut self, world: &World, dt: f32) {
+    self.time += dt;
+    let mut tracked: Vec<TrajectorySample> = world.planets.values()
+      .map(|pl| pl.borrow())
+      .filter(|pl| pl.tracked)
+      .map(|pl| TrajectorySample { t: self.time, id: pl.id, position: pl.position, velocity: pl.velocity })
+      .collect();
+    tracked.sort_unstable_by_key(|sample| sample.id);
+    self.samples.append(&mut tracked);
+  }
+
+  pub fn len(&self) -> usize {
+    self.samples.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.samples.is_empty()
+  }
+
+  pub fn clear(&mut self) {
+    self.samples.clear();
+    self.time = 0.0;
+  }
+
+  pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "t,id,x,y,vx,vy")?;
+    for sample in self.samples.iter() {
+      writeln!(
+        out,
+        "{},{},{},{},{},{}",
+        sample.t, sample.id, sample.position.x, sample.position.y, sample.velocity.x, sample.velocity.y
+      )?;
+    }
+    out.flush()
+  }
+}
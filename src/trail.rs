@@ -0,0 +1,304 @@
+use ggez::graphics::{MeshBuilder, DrawMode};
+use ggez::GameResult;
+
+use nalgebra::{Point2, Vector2};
+use serde::{Serialize, Deserialize};
+
+use std::fmt;
+use std::time::Duration;
+use std::collections::VecDeque;
+
+use orbits::config::TrailConfig;
+use orbits::planet::Planet;
+
+use crate::SCREEN_DIMS;
+
+const TRAIL_DOT_RADIUS: f32 = 0.8;
+const SLOW_COLOR: [f32; 3] = [0.1, 0.4, 1.0];
+const FAST_COLOR: [f32; 3] = [1.0, 0.15, 0.1];
+const NODE_CAPACITY: usize = 36;    // Nodes a new trail buffer has room for before growing
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum TrailStyle {
+  Line,   // Fading line through past positions
+  Dots,   // A fading dot at each past position
+}
+
+impl TrailStyle {
+  pub fn next(self) -> Self {
+    match self {
+      TrailStyle::Line => TrailStyle::Dots,
+      TrailStyle::Dots => TrailStyle::Line,
+    }
+  }
+}
+
+// What decides the colour of each trail node
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum TrailColoring {
+  Config,         // The trail config's colour, or the planet's
+  Speed,          // Blue when the planet was slowest over the trail, red when fastest
+  Acceleration,   // Same, for the magnitude of the planet's acceleration
+}
+
+impl TrailColoring {
+  pub fn next(self) -> Self {
+    match self {
+      TrailColoring::Config => TrailColoring::Speed,
+      TrailColoring::Speed => TrailColoring::Acceleration,
+      TrailColoring::Acceleration => TrailColoring::Config,
+    }
+  }
+}
+
+impl fmt::Display for TrailColoring {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", match self {
+      TrailColoring::Config => "Trail colour",
+      TrailColoring::Speed => "Speed",
+      TrailColoring::Acceleration => "Acceleration",
+    })
+  }
+}
+
+// The state of a trail's planet this frame
+pub struct TrailSource {
+  pub position: Point2<f32>,
+  pub color: [f32; 4],
+  pub speed: f32,
+  pub acceleration: f32,
+}
+
+impl From<&Planet> for TrailSource {
+  fn from(planet: &Planet) -> Self {
+    Self {
+      position: planet.position,
+      color: planet.color,
+      speed: planet.velocity.magnitude(),
+      acceleration: planet.resultant_force.magnitude()/planet.mass,
+    }
+  }
+}
+
+impl TrailSource {
+  // Position measured from a reference point, for trails drawn in a moving body's frame
+  pub fn relative_to(self, origin: Point2<f32>) -> Self {
+    Self { position: Point2::from(self.position - origin), ..self }
+  }
+}
+
+// Node buffers from dead trails, handed to new ones so trails coming and going (e.g. many merges) doesn't allocate.
+// Buffers keep the capacity they grew to.
+#[derive(Default)]
+pub struct TrailPool {
+  free: Vec<VecDeque<PlanetTrailNode>>,
+  allocated: usize,   // Buffers created in total, in use or free
+}
+
+impl TrailPool {
+  fn take(&mut self) -> VecDeque<PlanetTrailNode> {
+    self.free.pop().unwrap_or_else(|| {
+      self.allocated += 1;
+      VecDeque::with_capacity(NODE_CAPACITY)
+    })
+  }
+
+  pub fn recycle(&mut self, trail: PlanetTrail) {
+    let mut nodes = trail.nodes;
+    nodes.clear();
+    self.free.push(nodes);
+  }
+
+  pub fn free_count(&self) -> usize {
+    self.free.len()
+  }
+
+  pub fn allocated_count(&self) -> usize {
+    self.allocated
+  }
+}
+
+#[derive(Clone)]
+pub struct PlanetTrail {
+  nodes: VecDeque<PlanetTrailNode>,
+  node_placement_timer: Duration,
+  time: Duration,     // Simulation time elapsed since trail creation, used to age nodes
+  has_parent: bool,
+  parent_color: [f32; 4],
+  pub style: Option<TrailStyle>,    // Overrides the global style if set
+  pub config: Option<TrailConfig>,  // Overrides the global config if set
+}
+
+impl PlanetTrail {
+  pub fn new(start: &TrailSource, pool: &mut TrailPool) -> Self {
+    let mut nodes = pool.take();
+    nodes.push_front(PlanetTrailNode::new(start.position, Duration::ZERO, start.speed, start.acceleration, false));
+
+    Self {
+      nodes,
+      node_placement_timer: Duration::new(0, 0),
+      time: Duration::ZERO,
+      has_parent: true,
+      parent_color: [1.0, 1.0, 1.0, 1.0],
+      style: None,
+      config: None,
+    }
+  }
+
+  // parent is the state of the planet, if it still exists.
+  // default_config is used unless this trail has its own config. Nodes placed while reversed are drawn dashed.
+  pub fn update(&mut self, dt_duration: &Duration, parent: Option<TrailSource>, default_config: &TrailConfig, reversed: bool) {
+    let config = self.config.unwrap_or(*default_config);
+    self.time += *dt_duration;
+    self.kill_dead_nodes(config.lifetime);
+
+    if let Some(parent) = parent {
+      self.has_parent = true;
+      self.parent_color = parent.color;
+      self.node_placement_timer += *dt_duration;
+
+      let period = Duration::from_secs_f32(1.0/config.emission_rate.max(1.0));
+      if self.node_placement_timer > period {
+        // Place new node
+        self.add_node(parent.position, parent.speed, parent.acceleration, reversed);
+        self.node_placement_timer -= period;
+      }
+    } else {
+      self.has_parent = false;
+    }
+  }
+
+  // Returns if anything was drawn. The defaults are used unless this trail has its own style/config.
+  // origin is added to every node, to draw trails recorded relative to a body (see TrailSource::relative_to) around it.
+  pub fn draw(&self, mesh: &mut MeshBuilder, default_style: TrailStyle, default_config: &TrailConfig, coloring: TrailColoring, origin: Vector2<f32>) -> GameResult<bool> {
+    let config = self.config.unwrap_or(*default_config);
+    let color_of = self.node_coloring(config.color.unwrap_or(self.parent_color), coloring);
+    let len = self.node_count();
+    let mut drawn = 0;
+
+    match self.style.unwrap_or(default_style) {
+      TrailStyle::Line => if len > 1 {
+        for i in 0..len-1 {
+          if (self.nodes[i].pos.x - self.nodes[i + 1].pos.x).powi(2) +
+            (self.nodes[i].pos.y - self.nodes[i + 1].pos.y).powi(2) <
+            (SCREEN_DIMS.0.min(SCREEN_DIMS.1)/2.0).powi(2) &&  // Make sure line length is less than half the minimum screen dimensions.
+            !(self.nodes[i].reversed && self.nodes[i].odd)   // Dashed while reversed
+          {
+            drawn += 1;
+            let [r, g, b] = color_of(&self.nodes[i]);
+            mesh.line(
+              &[self.nodes[i].pos + origin, self.nodes[i + 1].pos + origin],
+              1.0,
+              [r, g, b, self.node_alpha(&self.nodes[i], config.lifetime)].into()
+            )?;
+          }
+        }
+      },
+      TrailStyle::Dots => {
+        for node in self.nodes.iter() {
+          let [r, g, b] = color_of(node);
+          drawn += 1;
+          mesh.circle(
+            if node.reversed { DrawMode::stroke(0.5) } else { DrawMode::fill() },   // Hollow while reversed
+            node.pos + origin,
+            TRAIL_DOT_RADIUS,
+            0.5,
+            [r, g, b, self.node_alpha(node, config.lifetime)].into()
+          )?;
+        }
+      },
+    }
+
+    Ok(drawn > 0)
+  }
+
+  // Colour of each node. Speed and acceleration are scaled between their lowest and highest values along this trail,
+  // so each trail uses the whole range whatever the scale of its orbit.
+  // Returned as a function of the node rather than a list, so drawing doesn't allocate.
+  fn node_coloring(&self, base_color: [f32; 4], coloring: TrailColoring) -> impl Fn(&PlanetTrailNode) -> [f32; 3] {
+    let (min, max) = self.nodes.iter()
+      .map(|node| node.value(coloring))
+      .fold((f32::MAX, f32::MIN), |(min, max), v| (min.min(v), max.max(v)));
+    let range = (max - min).max(f32::EPSILON);
+
+    move |node| if coloring == TrailColoring::Config {
+      [base_color[0], base_color[1], base_color[2]]
+    } else {
+      let t = (node.value(coloring) - min)/range;
+      [0, 1, 2].map(|i| SLOW_COLOR[i] + (FAST_COLOR[i] - SLOW_COLOR[i]) * t)
+    }
+  }
+
+  // Change transpacency depending on how long the node has been alive.
+  fn node_alpha(&self, node: &PlanetTrailNode, lifetime: f32) -> f32 {
+    let alpha = 1.0 - self.node_age(node).as_secs_f32()/lifetime;
+    alpha.max(0.0).powi(2)
+  }
+
+  fn kill_dead_nodes(&mut self, lifetime: f32) {
+    while let Some(node) = self.nodes.front() {
+      if self.node_age(node).as_secs_f32() >= lifetime {
+        self.nodes.pop_front();
+      } else {
+        break
+      }
+    }
+  }
+
+  fn node_age(&self, node: &PlanetTrailNode) -> Duration {
+    self.time.saturating_sub(node.time_created)
+  }
+
+  pub fn node_count(&self) -> usize {
+    self.nodes.len()
+  }
+
+  pub fn is_dead(&self) -> bool {
+    self.nodes.is_empty() && !self.has_parent
+  }
+
+  pub fn add_node(&mut self, pos: Point2<f32>, speed: f32, acceleration: f32, reversed: bool) {
+    // Make sure distance from last node is a sufficient distance so that line can be drawn without errors
+    let can_place = self.nodes.back()
+      .map_or(true, |last_node| ((pos.x - last_node.pos.x).powi(2) + (pos.y - last_node.pos.y).powi(2)) > 0.1);
+
+    if can_place {
+      let odd = self.nodes.back().map_or(false, |last_node| !last_node.odd);
+      let mut node = PlanetTrailNode::new(pos, self.time, speed, acceleration, reversed);
+      node.odd = odd;
+      self.nodes.push_back(node);
+    }
+  }
+}
+
+#[derive(Clone)]
+struct PlanetTrailNode {
+  pos: Point2<f32>,
+  time_created: Duration,   // Trail time at which the node was placed
+  speed: f32,               // Of the planet when the node was placed
+  acceleration: f32,
+  reversed: bool,           // Placed while time was reversed
+  odd: bool,                // Alternates along the trail, for dashes
+}
+
+impl PlanetTrailNode {
+  fn new(pos: Point2<f32>, time_created: Duration, speed: f32, acceleration: f32, reversed: bool) -> Self {
+    Self {
+      pos,
+      time_created,
+      speed,
+      acceleration,
+      reversed,
+      odd: false,
+    }
+  }
+
+  // What the node is coloured by
+  fn value(&self, coloring: TrailColoring) -> f32 {
+    match coloring {
+      TrailColoring::Config => 0.0,
+      TrailColoring::Speed => self.speed,
+      TrailColoring::Acceleration => self.acceleration,
+    }
+  }
+}
@@ -0,0 +1,73 @@
+// Disjoint sets of the indices 0..n, for grouping bodies that touch each other directly or through a chain.
+// Union by size with path halving, so operations are close to constant time.
+pub struct DisjointSet {
+  parent: Vec<usize>,
+  size: Vec<usize>,
+}
+
+impl DisjointSet {
+  pub fn new(n: usize) -> Self {
+    Self {
+      parent: (0..n).collect(),
+      size: vec![1; n],
+    }
+  }
+
+  // Representative of the set x is in
+  pub fn find(&mut self, mut x: usize) -> usize {
+    while self.parent[x] != x {
+      self.parent[x] = self.parent[self.parent[x]];
+      x = self.parent[x];
+    }
+    x
+  }
+
+  // Joins the sets a and b are in. Returns false if they were already the same set.
+  pub fn union(&mut self, a: usize, b: usize) -> bool {
+    let (mut a, mut b) = (self.find(a), self.find(b));
+    if a == b { return false }
+    if self.size[a] < self.size[b] {
+      std::mem::swap(&mut a, &mut b);
+    }
+    self.parent[b] = a;
+    self.size[a] += self.size[b];
+    true
+  }
+
+  // Every set with more than one member, each sorted, ordered by their smallest member
+  pub fn groups(&mut self) -> Vec<Vec<usize>> {
+    let mut by_root: Vec<Vec<usize>> = vec![Vec::new(); self.parent.len()];
+    for x in 0..self.parent.len() {
+      let root = self.find(x);
+      by_root[root].push(x);
+    }
+    let mut groups: Vec<Vec<usize>> = by_root.into_iter().filter(|group| group.len() > 1).collect();
+    groups.sort_unstable_by_key(|group| group[0]);
+    groups
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn union_is_transitive() {
+    let mut sets = DisjointSet::new(5);
+    assert!(sets.union(0, 1));
+    assert!(sets.union(1, 2));
+    assert_eq!(sets.find(0), sets.find(2));
+    assert!(!sets.union(2, 0));   // Already joined through 1
+    assert_ne!(sets.find(0), sets.find(3));
+  }
+
+  #[test]
+  fn groups_follow_chains() {
+    let mut sets = DisjointSet::new(7);
+    sets.union(5, 3);
+    sets.union(0, 4);
+    sets.union(3, 1);
+    sets.union(6, 1);
+    assert_eq!(sets.groups(), vec![vec![0, 4], vec![1, 3, 5, 6]]);   // 2 is on its own, so not a group
+  }
+}
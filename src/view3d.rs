@@ -0,0 +1,122 @@
+use ggez::event;
+use ggez::graphics::{self, Canvas, Color, DrawMode, DrawParam, MeshBuilder, Mesh};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use ggez::{Context, GameResult};
+
+use nalgebra::{Point3, Rotation3, Vector3};
+
+use orbits::space::World3;
+
+use crate::SCREEN_DIMS;
+
+const TILT_SPEED: f32 = 1.0;       // Radians per second while an arrow key is held
+const DEPTH_RANGE: f32 = 800.0;    // Depth over which bodies shrink and fade, in pixels
+const DEPTH_SIZE_RANGE: (f32, f32) = (0.5, 1.5);  // Size multiplier at the back and front
+const DEPTH_ALPHA_RANGE: (f32, f32) = (0.3, 1.0); // Opacity at the back and front
+
+// Viewer for the 3D world, started with --3d. Draws an orthographic projection, with nearer bodies bigger and
+// brighter so depth can still be told apart. The arrow keys turn the view, and space pauses.
+pub struct View3State {
+  world: World3,
+  tilt: f32,        // About the screen's x axis
+  turn: f32,        // About the screen's y axis
+  turning: (f32, f32),  // Held arrow keys, as (tilt, turn) directions
+  paused: bool,
+}
+
+impl View3State {
+  pub fn new() -> Self {
+    let mut world = World3::new();
+    world.inclined_orbits(Self::centre());
+    Self {
+      world,
+      tilt: -1.2,     // Start looking at the plane from slightly above, so the inclinations show
+      turn: 0.0,
+      turning: (0.0, 0.0),
+      paused: false,
+    }
+  }
+
+  fn centre() -> Point3<f32> {
+    Point3::new(SCREEN_DIMS.0/2.0, SCREEN_DIMS.1/2.0, 0.0)
+  }
+
+  fn view(&self) -> Rotation3<f32> {
+    Rotation3::from_axis_angle(&Vector3::x_axis(), self.tilt) * Rotation3::from_axis_angle(&Vector3::y_axis(), self.turn)
+  }
+
+  fn reset(&mut self) {
+    self.world.clear();
+    self.world.inclined_orbits(Self::centre());
+  }
+}
+
+impl event::EventHandler for View3State {
+  fn update(&mut self, ctx: &mut Context) -> GameResult {
+    let dt = ctx.time.delta().as_secs_f32();
+    self.tilt += self.turning.0 * TILT_SPEED * dt;
+    self.turn += self.turning.1 * TILT_SPEED * dt;
+    if !self.paused {
+      self.world.step(dt * self.world.config.time_scale);
+    }
+    Ok(())
+  }
+
+  fn draw(&mut self, ctx: &mut Context) -> GameResult {
+    let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
+    // Follow the centre of mass so the system doesn't drift out of the rotation
+    let centre = self.world.centre_of_mass().unwrap_or_else(Self::centre);
+    let view = self.view();
+
+    let mut projected: Vec<_> = self.world.bodies.iter()
+      .map(|body| {
+        let (position, depth) = body.project(&view, centre);
+        (body, position, depth)
+      })
+      .collect();
+    // Furthest first, so nearer bodies are drawn over them
+    projected.sort_unstable_by(|a, b| b.2.total_cmp(&a.2));
+
+    if !projected.is_empty() {
+      let mut mesh = MeshBuilder::new();
+      for (body, position, depth) in projected {
+        let nearness = (0.5 - depth/DEPTH_RANGE).max(0.0).min(1.0);   // 0 at the back, 1 at the front
+        let size = DEPTH_SIZE_RANGE.0 + (DEPTH_SIZE_RANGE.1 - DEPTH_SIZE_RANGE.0) * nearness;
+        let [r, g, b, a] = body.color();
+        let alpha = a * (DEPTH_ALPHA_RANGE.0 + (DEPTH_ALPHA_RANGE.1 - DEPTH_ALPHA_RANGE.0) * nearness);
+        mesh.circle(DrawMode::fill(), position, body.radius * size, 0.1, [r, g, b, alpha].into())?;
+      }
+      canvas.draw(&Mesh::from_data(ctx, mesh.build()), DrawParam::default());
+    }
+
+    let text = graphics::Text::new(format!(
+      "Bodies: {}\nTilt: {:.0}°  Turn: {:.0}°{}\nArrows: turn view, Space: pause, R: reset",
+      self.world.bodies.len(), self.tilt.to_degrees(), self.turn.to_degrees(),
+      if self.paused { "  (Paused)" } else { "" },
+    ));
+    canvas.draw(&text, DrawParam::default().dest([10.0, 10.0]));
+    canvas.finish(ctx)
+  }
+
+  fn key_down_event(&mut self, _ctx: &mut Context, input: KeyInput, _repeat: bool) -> GameResult {
+    match input.keycode {
+      Some(KeyCode::Up) => self.turning.0 = -1.0,
+      Some(KeyCode::Down) => self.turning.0 = 1.0,
+      Some(KeyCode::Left) => self.turning.1 = -1.0,
+      Some(KeyCode::Right) => self.turning.1 = 1.0,
+      Some(KeyCode::Space) => self.paused = !self.paused,
+      Some(KeyCode::R) => self.reset(),
+      _ => (),
+    }
+    Ok(())
+  }
+
+  fn key_up_event(&mut self, _ctx: &mut Context, input: KeyInput) -> GameResult {
+    match input.keycode {
+      Some(KeyCode::Up) | Some(KeyCode::Down) => self.turning.0 = 0.0,
+      Some(KeyCode::Left) | Some(KeyCode::Right) => self.turning.1 = 0.0,
+      _ => (),
+    }
+    Ok(())
+  }
+}
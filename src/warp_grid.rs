@@ -0,0 +1,61 @@
+use ggez::graphics::{Canvas, DrawParam, Mesh, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+
+use nalgebra::{Point2, Vector2};
+
+use orbits::simulation::World;
+
+use crate::SCREEN_DIMS;
+
+const GRID_SCREEN_SPACING: f32 = 40.0;  // Roughly how far apart grid lines are on screen, in pixels
+const MAX_WARP: f32 = 0.8;     // Displacement in the deepest well, in grid spacings
+const GRID_COLOR: [f32; 4] = [0.3, 0.45, 0.7, 0.35];
+const LINE_WIDTH: f32 = 1.0;   // On screen
+
+// Background grid pulled in towards massive bodies, like a rubber sheet. Each point moves along the field by an
+// amount proportional to the potential there, scaled so the deepest well in view moves points MAX_WARP spacings.
+// Lines are fixed in the world at a spacing that doubles or halves with the zoom, so the grid doesn't swim as the
+// camera pans. Purely visual, sampled every frame.
+pub fn draw(world: &World, view: Rect, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+  let zoom = SCREEN_DIMS.0/view.w;
+  // Power of two, so lines stay in place while zooming until the spacing changes
+  let spacing = 2f32.powf((GRID_SCREEN_SPACING/zoom).log2().round());
+  let first = (view.x/spacing).floor() as i64;
+  let top = (view.y/spacing).floor() as i64;
+  let columns = (view.w/spacing).ceil() as usize + 2;
+  let rows = (view.h/spacing).ceil() as usize + 2;
+
+  let points: Vec<Point2<f32>> = (0..rows)
+    .flat_map(|row| (0..columns).map(move |column| (column, row)))
+    .map(|(column, row)| Point2::new((first + column as i64) as f32 * spacing, (top + row as i64) as f32 * spacing))
+    .collect();
+
+  let warped = if world.planets.is_empty() {
+    points
+  } else {
+    let samples = world.field_at(&points);
+    let deepest = samples.iter().map(|(potential, _)| *potential).fold(0.0, f32::min);
+    if deepest < 0.0 {
+      points.iter().zip(samples)
+        .map(|(point, (potential, field))| {
+          let direction = field.try_normalize(f32::EPSILON).unwrap_or_else(|| Vector2::new(0.0, 0.0));
+          point + direction * (potential/deepest * MAX_WARP * spacing)
+        })
+        .collect()
+    } else {
+      points
+    }
+  };
+
+  let mut mesh = MeshBuilder::new();
+  let width = LINE_WIDTH/zoom;
+  for row in 0..rows {
+    mesh.line(&warped[row * columns..(row + 1) * columns], width, GRID_COLOR.into())?;
+  }
+  for column in 0..columns {
+    let line: Vec<Point2<f32>> = (0..rows).map(|row| warped[row * columns + column]).collect();
+    mesh.line(&line, width, GRID_COLOR.into())?;
+  }
+  canvas.draw(&Mesh::from_data(ctx, mesh.build()), DrawParam::default());
+  Ok(())
+}